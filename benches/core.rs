@@ -0,0 +1,177 @@
+// Benchmarks of the core, so changes meant to make it faster have numbers
+// behind them:
+//
+//   cargo bench --bench core
+//
+// and again after the change. Criterion keeps the last run under
+// target/criterion and says how much each benchmark moved since.
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use chip8::asm::assemble;
+use chip8::bundled;
+use chip8::cpu::Chip8;
+use chip8::frame::FramePresenter;
+use chip8::quirks::Quirks;
+
+// instructions run in each iteration of the emulate_cycle benchmarks
+const CYCLES: u64 = 1000;
+
+// Loops of the kinds of instruction roms spend their time on. Each runs
+// forever without waiting for a key or the timers.
+const MIXES: [(&str, &str); 4] = [
+    (
+        "arithmetic",
+        "
+    loop:   ADD V0, 3
+            LD V1, V0
+            ADD V1, V0
+            SUB V1, V2
+            SHR V1
+            XOR V2, V1
+            OR V3, V2
+            AND V3, V0
+            RND V4, 0x0F
+            JP loop
+        ",
+    ),
+    (
+        "jumps, calls and skips",
+        "
+    loop:   CALL sub
+            SE V0, 1
+            SNE V0, 2
+            JP next
+    next:   SKP V1
+            JP loop
+    sub:    ADD V0, 1
+            RET
+        ",
+    ),
+    (
+        "memory",
+        "
+    loop:   LD I, 0x400
+            LD B, V0
+            LD [I], V7
+            LD V7, [I]
+            ADD I, V0
+            LD F, V1
+            ADD V0, 1
+            JP loop
+        ",
+    ),
+    (
+        "drawing",
+        "
+            LD I, sprite
+    loop:   DRW V0, V1, 8
+            ADD V0, 5
+            ADD V1, 3
+            JP loop
+    sprite: DB 0x3C, 0x42, 0x81, 0xA5, 0x81, 0x99, 0x42, 0x3C
+        ",
+    ),
+];
+
+// Draws a sprite as tall as they get, all over the screen so it wraps or is
+// clipped at the edges.
+const LARGE_SPRITES: &str = "
+            LD I, sprite
+    loop:   DRW V0, V1, 15
+            ADD V0, 7
+            ADD V1, 5
+            JP loop
+    sprite: DB 0xFF, 0x81, 0xBD, 0xA5, 0xA5, 0xBD, 0x81, 0xFF
+            DB 0x81, 0xBD, 0xA5, 0xA5, 0xBD, 0x81, 0xFF
+";
+
+fn machine(source: &str, quirks: Quirks) -> Chip8 {
+    let mut emu = Chip8::with_quirks(quirks);
+    let rom = assemble(source).expect("the benchmark roms assemble");
+    emu.load_rom(&rom).expect("the benchmark roms fit in memory");
+    emu
+}
+
+// Runs CYCLES instructions a time, checking the rom didn't stop, which
+// would leave nothing being measured.
+fn bench_cycles(c: &mut Criterion, group: &str, roms: &[(&str, &str, Quirks)]) {
+    let mut group = c.benchmark_group(group);
+    group.throughput(Throughput::Elements(CYCLES));
+    for &(name, source, quirks) in roms {
+        let mut emu = machine(source, quirks);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for _ in 0..CYCLES {
+                    emu.emulate_cycle();
+                }
+            })
+        });
+        assert_eq!(emu.fault(), None, "{} stopped", name);
+    }
+    group.finish();
+}
+
+fn emulate_cycle(c: &mut Criterion) {
+    let roms: Vec<(&str, &str, Quirks)> = MIXES
+        .iter()
+        .map(|&(name, source)| (name, source, Quirks::default()))
+        .collect();
+    bench_cycles(c, "emulate_cycle", &roms);
+}
+
+fn draw(c: &mut Criterion) {
+    let clipping = Quirks {
+        clipping: true,
+        ..Quirks::default()
+    };
+    let roms = [
+        ("wrapping", LARGE_SPRITES, Quirks::default()),
+        ("clipping", LARGE_SPRITES, clipping),
+    ];
+    bench_cycles(c, "draw 15 rows", &roms);
+}
+
+// Whole frames of a bundled rom, timers and all.
+fn run_frame(c: &mut Criterion) {
+    let rom = bundled::find("bundled:bounce")
+        .expect("bounce is bundled")
+        .rom;
+    let mut emu = Chip8::default();
+    emu.load_rom(rom).expect("bounce fits in memory");
+    c.bench_function("run_frame bounce", |b| b.iter(|| emu.run_frame(10)));
+}
+
+fn present(c: &mut Criterion) {
+    // a display with something on it, half of it lit
+    let mut gfx = [[0u8; 64]; 32];
+    for (y, row) in gfx.iter_mut().enumerate() {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            *pixel = ((x / 3 + y) % 2) as u8;
+        }
+    }
+    // and the same as the machine keeps it
+    let planes = gfx.map(|row| row.iter().fold(0, |plane, &pixel| plane << 1 | u64::from(pixel)));
+
+    let mut group = c.benchmark_group("present");
+    for scale in [1, 10] {
+        let mut presenter = FramePresenter::default().scale(scale);
+        let (width, height) = presenter.size(64, 32);
+        group.throughput(Throughput::Bytes((width * height * 4) as u64));
+        group.bench_with_input(BenchmarkId::new("rgba", scale), &gfx, |b, gfx| {
+            b.iter(|| {
+                black_box(presenter.present(gfx));
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("rgba from planes", scale), &planes, |b, planes| {
+            b.iter(|| {
+                black_box(presenter.present_planes(planes));
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, emulate_cycle, draw, run_frame, present);
+criterion_main!(benches);
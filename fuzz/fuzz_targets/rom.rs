@@ -0,0 +1,30 @@
+// Runs arbitrary bytes as a rom, to find instructions that panic instead
+// of faulting. The first byte picks the quirks and the rest is the rom,
+// run for a second of frames with the keys going up and down. With
+// cargo-fuzz installed and a nightly toolchain:
+//
+//   cargo fuzz run rom
+//
+// from the repository's root.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use chip8::cpu::Chip8;
+use chip8::quirks::Quirks;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((quirks, rom)) = data.split_first() else {
+        return;
+    };
+    let mut emu = Chip8::with_quirks(Quirks::from_bits(*quirks));
+    emu.seed_rng(0);
+    emu.load_rom(rom);
+    for frame in 0..60 {
+        emu.set_keys(&[(frame % 2) as u8 * 255; 16]);
+        emu.run_frame(100);
+        if emu.fault().is_some() {
+            break;
+        }
+    }
+});
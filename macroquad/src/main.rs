@@ -0,0 +1,128 @@
+// The emulator as a macroquad app, to show how little a frontend needs
+// besides the library: a texture for the display, the keyboard as an
+// InputSource, and a loop that runs a frame every 60th of a second. The
+// same code builds for wasm32-unknown-unknown and runs in a browser with
+// macroquad's JS loader; see README.md.
+//
+//   cargo run -p chip8-macroquad -- [rom]
+//
+// Without a rom it plays the bundled bounce. It's silent, as macroquad's
+// sound and the library's cpal backend both link ALSA and can't be built in
+// the same workspace.
+use std::io;
+
+use macroquad::prelude::*;
+
+use chip8::bundled;
+use chip8::cpu::Chip8;
+use chip8::frame::FramePresenter;
+use chip8::input_source::InputSource;
+use chip8::metadata::ProgramDatabase;
+use chip8::roms::rom_hash;
+use chip8::speed::MAX_CATCH_UP;
+use chip8::{keypad_key, Color as Chip8Color, DEFAULT_PALETTE};
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+const FRAME: f32 = 1.0 / 60.0;
+// the default 600 instructions a second
+const CYCLES_PER_FRAME: u32 = 10;
+
+// The computer's keyboard, as the library's frontends see other keypads.
+struct Keyboard;
+
+impl InputSource for Keyboard {
+    fn poll(&mut self, keys: &mut [u8; 16]) -> io::Result<()> {
+        for code in get_keys_down() {
+            // macroquad names the keys by what's on them, the digits as Key1
+            // and so on
+            let name = format!("{:?}", code);
+            let name = name.strip_prefix("Key").unwrap_or(&name);
+            let mut chars = name.chars();
+            if let (Some(c), None) = (chars.next(), chars.next()) {
+                if let Some(key) = keypad_key(c) {
+                    keys[usize::from(key)] = 255;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn window_conf() -> Conf {
+    Conf {
+        window_title: "CHIP-8".to_owned(),
+        window_width: 640,
+        window_height: 320,
+        ..Default::default()
+    }
+}
+
+#[macroquad::main(window_conf)]
+async fn main() {
+    let rom = match std::env::args().nth(1) {
+        Some(path) => match load_file(&path).await {
+            Ok(rom) => rom,
+            Err(e) => {
+                eprintln!("❌ Unable to load {}: {}", path, e);
+                std::process::exit(2);
+            }
+        },
+        None => bundled::ROMS[0].rom.to_vec(),
+    };
+    let quirks = ProgramDatabase::embedded()
+        .get(&rom_hash(&rom))
+        .and_then(|metadata| metadata.quirks)
+        .unwrap_or_default();
+    let mut emu = Chip8::with_quirks(quirks);
+    if let Err(e) = emu.load_rom(&rom) {
+        eprintln!("❌ Unable to load the rom: {}", e);
+        std::process::exit(2);
+    }
+
+    let mut presenter = FramePresenter::default();
+    let pixels = presenter.present_planes(&emu.gfx);
+    let texture = Texture2D::from_rgba8(WIDTH as u16, HEIGHT as u16, pixels);
+    texture.set_filter(FilterMode::Nearest);
+
+    let mut keyboard = Keyboard;
+    let mut behind = 0.0;
+    loop {
+        if is_key_pressed(KeyCode::Escape) {
+            break;
+        }
+
+        behind = (behind + get_frame_time()).min(MAX_CATCH_UP as f32 * FRAME);
+        while behind >= FRAME && emu.fault().is_none() {
+            behind -= FRAME;
+            let mut keys = [0u8; 16];
+            let _ = keyboard.poll(&mut keys);
+            emu.set_keys(&keys);
+            emu.run_frame(CYCLES_PER_FRAME);
+            if emu.draw_flag() {
+                let pixels = presenter.present_planes(&emu.gfx);
+                texture.update_from_bytes(WIDTH as u32, HEIGHT as u32, pixels);
+            }
+        }
+
+        clear_background(color(DEFAULT_PALETTE.gutter));
+        // as big as fits, keeping the pixels square
+        let pixel = (screen_width() / WIDTH as f32).min(screen_height() / HEIGHT as f32);
+        let size = vec2(WIDTH as f32, HEIGHT as f32) * pixel;
+        let corner = (vec2(screen_width(), screen_height()) - size) / 2.0;
+        let params = DrawTextureParams {
+            dest_size: Some(size),
+            ..Default::default()
+        };
+        draw_texture_ex(&texture, corner.x, corner.y, WHITE, params);
+        if let Some(fault) = emu.fault() {
+            draw_text(&format!("stopped: {}", fault), 8.0, 24.0, 24.0, RED);
+        }
+
+        next_frame().await;
+    }
+}
+
+fn color(color: Chip8Color) -> Color {
+    Color::from_rgba(color.r, color.g, color.b, 255)
+}
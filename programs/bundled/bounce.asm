@@ -0,0 +1,41 @@
+; Bounce: a ball bouncing around the screen, beeping off the walls.
+;
+; Written for chip-8-emu and dedicated to the public domain.
+
+        LD V0, 10           ; x
+        LD V1, 5            ; y
+        LD V2, 1            ; how far to move across each step
+        LD V3, 1            ; and down
+        LD I, ball
+        DRW V0, V1, 2
+
+loop:   LD V4, 2            ; a step every other frame
+        LD DT, V4
+wait:   LD V4, DT
+        SE V4, 0
+        JP wait
+
+        DRW V0, V1, 2       ; rub the ball out
+        ADD V0, V2
+        ADD V1, V3
+        SNE V0, 0           ; off the left and right walls
+        CALL flip_x
+        SNE V0, 62
+        CALL flip_x
+        SNE V1, 0           ; off the top and bottom
+        CALL flip_y
+        SNE V1, 30
+        CALL flip_y
+        DRW V0, V1, 2
+        JP loop
+
+flip_x: LD V5, 0
+        SUBN V2, V5         ; V2 = 0 - V2
+        JP beep
+flip_y: LD V5, 0
+        SUBN V3, V5
+beep:   LD V5, 2
+        LD ST, V5
+        RET
+
+ball:   DB 0xC0, 0xC0
@@ -0,0 +1,14 @@
+; Keypad: shows each key as it's pressed, with a beep, for checking the
+; keypad and how it's mapped.
+;
+; Written for chip-8-emu and dedicated to the public domain.
+
+        LD V1, 30           ; x
+        LD V2, 13           ; y
+loop:   LD V0, K
+        CLS
+        LD F, V0
+        DRW V1, V2, 5
+        LD V3, 4
+        LD ST, V3
+        JP loop
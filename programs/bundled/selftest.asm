@@ -0,0 +1,177 @@
+; Self-test: checks the instructions every rom relies on, the same way
+; whatever the quirks. Each check puts its number in VE and jumps to fail
+; when something's wrong. At the end the screen says OK, or E and the
+; number of the check that failed, and `result` holds 0xFF for a pass or
+; the number.
+;
+; Written for chip-8-emu and dedicated to the public domain.
+
+        LD VE, 1            ; 6XNN, 3XNN
+        LD V0, 0x2A
+        SE V0, 0x2A
+        JP fail
+
+        LD VE, 2            ; 7XNN wraps around and leaves VF alone
+        LD VF, 0
+        LD V0, 0xFF
+        ADD V0, 2
+        SE V0, 1
+        JP fail
+        SE VF, 0
+        JP fail
+
+        LD VE, 3            ; 8XY4 carries
+        LD V0, 0xF0
+        LD V1, 0x20
+        ADD V0, V1
+        SE V0, 0x10
+        JP fail
+        SE VF, 1
+        JP fail
+
+        LD VE, 4            ; 8XY5 borrows
+        LD V0, 0x10
+        LD V1, 0x20
+        SUB V0, V1
+        SE V0, 0xF0
+        JP fail
+        SE VF, 0
+        JP fail
+
+        LD VE, 5            ; 8XY7
+        LD V0, 0x10
+        LD V1, 0x30
+        SUBN V0, V1
+        SE V0, 0x20
+        JP fail
+        SE VF, 1
+        JP fail
+
+        LD VE, 6            ; 8XY1, 8XY2, 8XY3
+        LD V0, 0x0C
+        LD V1, 0x0A
+        OR V0, V1
+        SE V0, 0x0E
+        JP fail
+        AND V0, V1
+        SE V0, 0x0A
+        JP fail
+        XOR V0, V1
+        SE V0, 0
+        JP fail
+
+        LD VE, 7            ; 8XY6, 8XYE, shifting a register onto itself
+        LD V0, 0x81
+        SHR V0, V0
+        SE V0, 0x40
+        JP fail
+        SE VF, 1
+        JP fail
+        SHL V0, V0
+        SE V0, 0x80
+        JP fail
+        SE VF, 0
+        JP fail
+
+        LD VE, 8            ; 5XY0, 9XY0
+        LD V0, 3
+        LD V1, 3
+        SE V0, V1
+        JP fail
+        LD V1, 4
+        SNE V0, V1
+        JP fail
+
+        LD VE, 9            ; 2NNN, 00EE
+        LD V0, 0
+        CALL set_v0
+        SE V0, 0x55
+        JP fail
+
+        LD VE, 10           ; FX33
+        LD V0, 137
+        LD I, scratch
+        LD B, V0
+        LD I, scratch
+        LD V2, [I]
+        SE V0, 1
+        JP fail
+        SE V1, 3
+        JP fail
+        SE V2, 7
+        JP fail
+
+        LD VE, 11           ; FX55, FX65
+        LD V0, 0xAB
+        LD V1, 0xCD
+        LD I, scratch
+        LD [I], V1
+        LD V0, 0
+        LD V1, 0
+        LD I, scratch
+        LD V1, [I]
+        SE V0, 0xAB
+        JP fail
+        SE V1, 0xCD
+        JP fail
+
+        LD VE, 12           ; FX15, FX07
+        LD V0, 30
+        LD DT, V0
+        LD V0, DT
+        SNE V0, 0
+        JP fail
+
+        LD VE, 13           ; DXYN and collisions
+        CLS
+        LD I, dot
+        LD V0, 0
+        LD V1, 0
+        DRW V0, V1, 1
+        SE VF, 0
+        JP fail
+        DRW V0, V1, 1
+        SE VF, 1
+        JP fail
+
+        LD V0, 0xFF
+        JP done
+fail:   LD V0, VE
+done:   LD I, result
+        LD [I], V0
+        CLS
+        LD V3, 26
+        LD V4, 13
+        SE V0, 0xFF
+        JP failed
+
+        LD I, letter_o
+        DRW V3, V4, 5
+        ADD V3, 6
+        LD I, letter_k
+        DRW V3, V4, 5
+        JP halt
+
+failed: LD V5, 0xE
+        LD F, V5
+        DRW V3, V4, 5
+        LD I, scratch
+        LD B, VE
+        LD I, scratch
+        LD V2, [I]
+        ADD V3, 7
+        LD F, V1
+        DRW V3, V4, 5
+        ADD V3, 5
+        LD F, V2
+        DRW V3, V4, 5
+halt:   JP halt
+
+set_v0: LD V0, 0x55
+        RET
+
+dot:      DB 0x80
+letter_o: DB 0xF0, 0x90, 0x90, 0x90, 0xF0
+letter_k: DB 0x90, 0xA0, 0xC0, 0xA0, 0x90
+scratch:  DB 0, 0, 0
+result:   DB 0
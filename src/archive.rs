@@ -0,0 +1,137 @@
+// Roms packed in zip archives, since rom packs are almost always handed
+// around that way. A rom path naming an archive loads the first .ch8 or .c8
+// file in it, and a rom in it can be picked by name after a colon:
+//
+//   chip8 games.zip
+//   chip8 games.zip:PONG.ch8
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, Read, Seek};
+use std::path::Path;
+
+use zip::ZipArchive;
+
+use crate::bundled;
+use crate::cpu::{check_rom_size, MAX_ROM};
+use crate::library::is_rom;
+
+/// Splits a rom path into the file and, for a rom in an archive, its name
+/// in the archive.
+pub fn split(path: &str) -> (&str, Option<&str>) {
+    // lowercasing ascii leaves every byte where it was
+    match path.to_ascii_lowercase().find(".zip:") {
+        Some(index) => (&path[..index + 4], Some(&path[index + 5..])),
+        None => (path, None),
+    }
+}
+
+/// Reads the rom at `path`, out of an archive if it names one, or built in
+/// if it's a bundled rom, which is borrowed rather than copied. A file too
+/// large to be a rom is an error, found without reading the rest of it.
+pub fn read_rom(path: &str) -> io::Result<Cow<'static, [u8]>> {
+    if let Some(bundled) = bundled::find(path) {
+        return Ok(Cow::Borrowed(bundled.rom));
+    }
+    let (file, name) = split(path);
+    let zipped = Path::new(file)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+    if !zipped {
+        return read_capped(File::open(file)?).map(Cow::Owned);
+    }
+    read_from_archive(File::open(file)?, name).map(Cow::Owned)
+}
+
+// Reads a rom, stopping a byte past the most there's memory for so anything
+// bigger is caught without reading all of it.
+fn read_capped(reader: impl Read) -> io::Result<Vec<u8>> {
+    let mut rom = Vec::new();
+    reader.take(MAX_ROM as u64 + 1).read_to_end(&mut rom)?;
+    check_rom_size(rom.len())?;
+    Ok(rom)
+}
+
+// Reads the rom called `name` from an archive, or the first one in it.
+fn read_from_archive(archive: impl Read + Seek, name: Option<&str>) -> io::Result<Vec<u8>> {
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+    let mut archive = ZipArchive::new(archive).map_err(invalid)?;
+    let index = match name {
+        Some(name) => archive.index_for_name(name),
+        None => (0..archive.len()).find(|&i| {
+            archive
+                .name_for_index(i)
+                .is_some_and(|name| is_rom(Path::new(name)))
+        }),
+    };
+    let index = index.ok_or_else(|| {
+        let missing = match name {
+            Some(name) => format!("no {} in the archive", name),
+            None => "no .ch8 or .c8 rom in the archive".to_string(),
+        };
+        io::Error::new(io::ErrorKind::NotFound, missing)
+    })?;
+
+    let entry = archive.by_index(index).map_err(invalid)?;
+    read_capped(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    fn archive(files: &[(&str, &[u8])]) -> Cursor<Vec<u8>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn paths_name_roms_in_archives() {
+        assert_eq!(split("games.zip:PONG.ch8"), ("games.zip", Some("PONG.ch8")));
+        assert_eq!(split("GAMES.ZIP"), ("GAMES.ZIP", None));
+        assert_eq!(split("C:\\roms\\pong.ch8"), ("C:\\roms\\pong.ch8", None));
+    }
+
+    #[test]
+    fn reads_the_first_or_named_rom() {
+        let files: &[(&str, &[u8])] = &[
+            ("README.txt", b"hello"),
+            ("PONG.ch8", &[0x12, 0x00]),
+            ("MAZE.ch8", &[0x00, 0xE0]),
+        ];
+        assert_eq!(
+            read_from_archive(archive(files), None).unwrap(),
+            [0x12, 0x00]
+        );
+        assert_eq!(
+            read_from_archive(archive(files), Some("MAZE.ch8")).unwrap(),
+            [0x00, 0xE0]
+        );
+        let missing = read_from_archive(archive(files), Some("TETRIS.ch8")).unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn roms_too_large_for_memory_are_errors() {
+        let full = [0x12; MAX_ROM];
+        let files: &[(&str, &[u8])] = &[("FULL.ch8", &full), ("HUGE.ch8", &[0x12; 1 << 20])];
+        assert_eq!(read_from_archive(archive(files), None).unwrap().len(), MAX_ROM);
+        let huge = read_from_archive(archive(files), Some("HUGE.ch8")).unwrap_err();
+        assert_eq!(huge.kind(), io::ErrorKind::InvalidData);
+
+        let path = std::env::temp_dir().join(format!("chip8-huge-{}.ch8", std::process::id()));
+        fs::write(&path, [0x12; MAX_ROM + 1]).unwrap();
+        let error = read_rom(path.to_str().unwrap()).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,235 @@
+// A chip8 assembler, for the mnemonics of Cowgod's technical reference that
+// the `disasm` module writes.
+//
+// Each line holds an instruction, a label, or both, and anything after a ;
+// is a comment:
+//
+//   start:  LD V0, 0x05     ; count down from 5
+//   loop:   ADD V0, 0xFF
+//           SE V0, 0
+//           JP loop
+//   spin:   JP spin
+//           DB 0xF0, 0x90   ; data, a byte at a time
+//
+// Numbers can be decimal, or hex with 0x, # or $ in front. Programs are
+// assembled to run from 0x200.
+use std::collections::HashMap;
+
+// where chip8 programs are loaded
+const START: u16 = 0x200;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operand {
+    V(u16),
+    I,
+    // [I], the memory I points at
+    AtI,
+    DT,
+    ST,
+    K,
+    F,
+    B,
+    Value(u16),
+}
+
+/// Assembles a program into the bytes of a rom, or says which line it
+/// couldn't make sense of.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    // the first pass finds where every label is, so instructions can refer
+    // to labels further down
+    let mut labels = HashMap::new();
+    let mut address = START;
+    for (number, line) in source.lines().enumerate() {
+        let (label, instruction) = split_line(line);
+        if let Some(label) = label {
+            if labels.insert(label.to_lowercase(), address).is_some() {
+                return Err(format!("line {}: '{}' is defined twice", number + 1, label));
+            }
+        }
+        if let Some((mnemonic, operands)) = instruction {
+            address += match mnemonic.to_uppercase().as_str() {
+                "DB" => operands.len() as u16,
+                _ => 2,
+            };
+        }
+    }
+
+    let mut rom = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        if let (_, Some((mnemonic, operands))) = split_line(line) {
+            let operands = operands
+                .iter()
+                .map(|operand| parse_operand(operand, &labels))
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|operands| encode(&mnemonic.to_uppercase(), &operands));
+            match operands {
+                Ok(bytes) => rom.extend(bytes),
+                Err(e) => return Err(format!("line {}: {}", number + 1, e)),
+            }
+        }
+    }
+    Ok(rom)
+}
+
+// Splits a line into its label and its mnemonic and operands.
+fn split_line(line: &str) -> (Option<&str>, Option<(&str, Vec<&str>)>) {
+    let mut line = line.split(';').next().unwrap_or("").trim();
+    let mut label = None;
+    if let Some((name, rest)) = line.split_once(':') {
+        label = Some(name.trim());
+        line = rest.trim();
+    }
+    if line.is_empty() {
+        return (label, None);
+    }
+
+    let (mnemonic, operands) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let operands = operands
+        .split(',')
+        .map(str::trim)
+        .filter(|operand| !operand.is_empty())
+        .collect();
+    (label, Some((mnemonic, operands)))
+}
+
+fn parse_operand(operand: &str, labels: &HashMap<String, u16>) -> Result<Operand, String> {
+    let upper = operand.to_uppercase();
+    let register = upper
+        .strip_prefix('V')
+        .filter(|digit| digit.len() == 1)
+        .and_then(|digit| u16::from_str_radix(digit, 16).ok());
+    if let Some(register) = register {
+        return Ok(Operand::V(register));
+    }
+
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::AtI,
+        "DT" => Operand::DT,
+        "ST" => Operand::ST,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "B" => Operand::B,
+        _ => Operand::Value(parse_value(operand, labels)?),
+    };
+    Ok(operand)
+}
+
+fn parse_value(operand: &str, labels: &HashMap<String, u16>) -> Result<u16, String> {
+    let hex = operand
+        .strip_prefix("0x")
+        .or_else(|| operand.strip_prefix("0X"))
+        .or_else(|| operand.strip_prefix('#'))
+        .or_else(|| operand.strip_prefix('$'));
+    let value = match hex {
+        Some(digits) => u16::from_str_radix(digits, 16).ok(),
+        None => operand.parse().ok(),
+    };
+    value
+        .or_else(|| labels.get(&operand.to_lowercase()).copied())
+        .ok_or_else(|| format!("'{}' isn't a number or a label", operand))
+}
+
+fn encode(mnemonic: &str, operands: &[Operand]) -> Result<Vec<u8>, String> {
+    use Operand::*;
+
+    let address = |value: u16| fits(value, 0xFFF);
+    let byte = |value: u16| fits(value, 0xFF);
+    let xy = |x: u16, y: u16| x << 8 | y << 4;
+
+    let opcode = match (mnemonic, operands) {
+        ("CLS", []) => 0x00E0,
+        ("RET", []) => 0x00EE,
+        ("JP", [Value(nnn)]) => 0x1000 | address(*nnn)?,
+        ("JP", [V(0), Value(nnn)]) => 0xB000 | address(*nnn)?,
+        ("CALL", [Value(nnn)]) => 0x2000 | address(*nnn)?,
+        ("SE", [V(x), Value(nn)]) => 0x3000 | x << 8 | byte(*nn)?,
+        ("SNE", [V(x), Value(nn)]) => 0x4000 | x << 8 | byte(*nn)?,
+        ("SE", [V(x), V(y)]) => 0x5000 | xy(*x, *y),
+        ("LD", [V(x), Value(nn)]) => 0x6000 | x << 8 | byte(*nn)?,
+        ("ADD", [V(x), Value(nn)]) => 0x7000 | x << 8 | byte(*nn)?,
+        ("LD", [V(x), V(y)]) => 0x8000 | xy(*x, *y),
+        ("OR", [V(x), V(y)]) => 0x8001 | xy(*x, *y),
+        ("AND", [V(x), V(y)]) => 0x8002 | xy(*x, *y),
+        ("XOR", [V(x), V(y)]) => 0x8003 | xy(*x, *y),
+        ("ADD", [V(x), V(y)]) => 0x8004 | xy(*x, *y),
+        ("SUB", [V(x), V(y)]) => 0x8005 | xy(*x, *y),
+        ("SHR", [V(x), V(y)]) => 0x8006 | xy(*x, *y),
+        ("SHR", [V(x)]) => 0x8006 | xy(*x, *x),
+        ("SUBN", [V(x), V(y)]) => 0x8007 | xy(*x, *y),
+        ("SHL", [V(x), V(y)]) => 0x800E | xy(*x, *y),
+        ("SHL", [V(x)]) => 0x800E | xy(*x, *x),
+        ("SNE", [V(x), V(y)]) => 0x9000 | xy(*x, *y),
+        ("LD", [I, Value(nnn)]) => 0xA000 | address(*nnn)?,
+        ("RND", [V(x), Value(nn)]) => 0xC000 | x << 8 | byte(*nn)?,
+        ("DRW", [V(x), V(y), Value(n)]) => 0xD000 | xy(*x, *y) | fits(*n, 0xF)?,
+        ("SKP", [V(x)]) => 0xE09E | x << 8,
+        ("SKNP", [V(x)]) => 0xE0A1 | x << 8,
+        ("AUDIO", []) => 0xF002,
+        ("LD", [V(x), DT]) => 0xF007 | x << 8,
+        ("LD", [V(x), K]) => 0xF00A | x << 8,
+        ("LD", [DT, V(x)]) => 0xF015 | x << 8,
+        ("LD", [ST, V(x)]) => 0xF018 | x << 8,
+        ("ADD", [I, V(x)]) => 0xF01E | x << 8,
+        ("LD", [F, V(x)]) => 0xF029 | x << 8,
+        ("LD", [B, V(x)]) => 0xF033 | x << 8,
+        ("PITCH", [V(x)]) => 0xF03A | x << 8,
+        ("LD", [AtI, V(x)]) => 0xF055 | x << 8,
+        ("LD", [V(x), AtI]) => 0xF065 | x << 8,
+        ("SEND", [V(x)]) => 0xF0B0 | x << 8,
+        ("RECV", [V(x)]) => 0xF0B1 | x << 8,
+        ("AVAIL", [V(x)]) => 0xF0B2 | x << 8,
+        ("DW", [Value(word)]) => *word,
+        ("DB", bytes) => {
+            return bytes
+                .iter()
+                .map(|operand| match operand {
+                    Value(value) => Ok(byte(*value)? as u8),
+                    _ => Err("DB takes numbers".to_string()),
+                })
+                .collect();
+        }
+        _ => return Err(format!("'{}' can't take those operands", mnemonic)),
+    };
+    Ok(opcode.to_be_bytes().to_vec())
+}
+
+fn fits(value: u16, max: u16) -> Result<u16, String> {
+    if value > max {
+        return Err(format!("0x{:X} is more than 0x{:X}", value, max));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble_rom;
+
+    #[test]
+    fn assembles_labels_and_data() {
+        let source = "start: LD V0, 5 ; count down\nloop: ADD V0, #FF\n  SE V0, 0\n  JP loop\n  DB 0xF0, 144\n";
+        assert_eq!(
+            assemble(source),
+            Ok(vec![0x60, 0x05, 0x70, 0xFF, 0x30, 0x00, 0x12, 0x02, 0xF0, 0x90])
+        );
+    }
+
+    #[test]
+    fn reports_bad_lines() {
+        assert_eq!(
+            assemble("CLS\nLD V0, nowhere\n"),
+            Err("line 2: 'nowhere' isn't a number or a label".to_string())
+        );
+        assert!(assemble("DRW V0, V1, 16").is_err());
+    }
+
+    #[test]
+    fn round_trips_disassembly() {
+        let rom = [
+            0x00, 0xE0, 0x6A, 0x02, 0xA2, 0x0A, 0xDA, 0xB6, 0xF3, 0x65, 0xF0, 0x02, 0xF4,
+            0xB1, 0x51, 0x21, 0x12, 0x00, 0xFF,
+        ];
+        assert_eq!(assemble(&disassemble_rom(&rom)), Ok(rom.to_vec()));
+    }
+}
@@ -0,0 +1,73 @@
+// The chip8 buzzer, played through SDL audio while the sound timer is set.
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::Sdl;
+
+use chip8::sound::{AudioBackend, Buzzer, Pattern, Tone};
+
+// Feeds the buzzer to SDL's audio callback.
+struct Callback {
+    buzzer: Buzzer,
+}
+
+impl AudioCallback for Callback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.buzzer.fill(out);
+    }
+}
+
+// Plays a tone for as long as the chip8 sound timer is running.
+pub struct Beeper {
+    device: AudioDevice<Callback>,
+    playing: bool,
+    pattern: Option<Pattern>,
+}
+
+impl Beeper {
+    pub fn new(sdl_context: &Sdl, tone: Tone, buffer_size: u16) -> Result<Beeper, String> {
+        let audio_subsystem = sdl_context.audio()?;
+
+        let desired_spec = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(1),
+            samples: Some(buffer_size),
+        };
+
+        let device = audio_subsystem.open_playback(None, &desired_spec, |spec| Callback {
+            buzzer: Buzzer::new(tone, spec.freq as u32),
+        })?;
+        // the device runs the whole time so the buzzer can fade in and out
+        // rather than being cut off
+        device.resume();
+
+        Ok(Beeper {
+            device,
+            playing: false,
+            pattern: None,
+        })
+    }
+}
+
+impl AudioBackend for Beeper {
+    // Starts or stops the tone, only touching the device when that changes.
+    fn set_playing(&mut self, playing: bool) {
+        if playing == self.playing {
+            return;
+        }
+
+        // the callback runs on SDL's audio thread so it has to be locked
+        self.device.lock().buzzer.set_playing(playing);
+        self.playing = playing;
+    }
+
+    // Switches between the plain tone and an XO-CHIP audio pattern.
+    fn set_pattern(&mut self, pattern: Option<Pattern>) {
+        if pattern == self.pattern {
+            return;
+        }
+
+        self.device.lock().buzzer.set_pattern(pattern);
+        self.pattern = pattern;
+    }
+}
@@ -0,0 +1,251 @@
+// A bevy plugin running a chip8 as a resource, so games can have a working
+// machine in them, like an arcade cabinet in a 3D scene:
+//
+//   App::new()
+//       .add_plugins(DefaultPlugins)
+//       .add_plugins(Chip8Plugin::new(rom))
+//
+// The display is drawn to an image, for a sprite, UI node or material:
+//
+//   commands.spawn(Sprite::from_image(machine.image.clone()));
+use bevy::app::{App, Plugin, Update};
+use bevy::asset::{Assets, Handle, RenderAssetUsages};
+use bevy::ecs::prelude::*;
+use bevy::image::{Image, ImageSampler};
+use bevy::input::keyboard::KeyCode;
+use bevy::input::ButtonInput;
+use bevy::time::Time;
+use wgpu_types::{Extent3d, TextureDimension, TextureFormat};
+
+use crate::cpu::{check_rom_size, Chip8};
+use crate::frame::FramePresenter;
+use crate::metadata::ProgramDatabase;
+use crate::quirks::Quirks;
+use crate::roms::rom_hash;
+use crate::speed::MAX_CATCH_UP;
+use crate::{keypad_key_for_code, Palette, DEFAULT_PALETTE};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 32;
+const FRAME: f32 = 1.0 / 60.0;
+
+/// Adds a `Chip8Machine` playing a rom, run 60 frames a second in `Update`
+/// and drawn to its image.
+///
+/// rom: the program to play.
+/// quirks: how it expects the machine to behave.
+/// palette: the colors the display is drawn in.
+/// cycles_per_frame: instructions run each frame.
+/// keyboard: whether the keypad is read from the keyboard.
+pub struct Chip8Plugin {
+    rom: Vec<u8>,
+    quirks: Quirks,
+    palette: &'static Palette,
+    cycles_per_frame: u32,
+    keyboard: bool,
+}
+
+impl Chip8Plugin {
+    /// Plays `rom` with the quirks the chip-8-database says it needs, at
+    /// the default 600 instructions a second, with the keypad on the
+    /// keyboard like the emulator's own window. Panics if the rom is too
+    /// large for the machine's memory.
+    pub fn new(rom: &[u8]) -> Self {
+        if let Err(e) = check_rom_size(rom.len()) {
+            panic!("Chip8Plugin: {}", e);
+        }
+        let quirks = ProgramDatabase::embedded()
+            .get(&rom_hash(rom))
+            .and_then(|metadata| metadata.quirks)
+            .unwrap_or_default();
+        Chip8Plugin {
+            rom: rom.to_vec(),
+            quirks,
+            palette: &DEFAULT_PALETTE,
+            cycles_per_frame: 10,
+            keyboard: true,
+        }
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    pub fn palette(mut self, palette: &'static Palette) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    pub fn cycles_per_frame(mut self, cycles: u32) -> Self {
+        self.cycles_per_frame = cycles;
+        self
+    }
+
+    /// Leaves the keypad to the game, which sets `Chip8Machine::keys`
+    /// itself, e.g. only while the player is stood at the cabinet.
+    pub fn without_keyboard(mut self) -> Self {
+        self.keyboard = false;
+        self
+    }
+}
+
+/// The plugin's systems, run in this order, for ordering others around.
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Chip8Systems {
+    /// Reads the keyboard into the keypad.
+    Input,
+    /// Runs the frames that are due.
+    Step,
+    /// Redraws the image if the display changed.
+    Render,
+}
+
+/// The machine the plugin runs.
+///
+/// emu: the machine itself, for reading its state or loading another rom.
+/// keys: the keypad, as `Chip8::set_keys` takes it.
+/// paused: stops running frames until unset.
+/// image: the 64x32 display.
+#[derive(Resource)]
+pub struct Chip8Machine {
+    pub emu: Chip8,
+    pub keys: [u8; 16],
+    pub paused: bool,
+    pub image: Handle<Image>,
+    presenter: FramePresenter,
+    cycles_per_frame: u32,
+    // seconds of frames that are due
+    behind: f32,
+    redraw: bool,
+}
+
+impl Plugin for Chip8Plugin {
+    fn build(&self, app: &mut App) {
+        let sets = (
+            Chip8Systems::Input,
+            Chip8Systems::Step,
+            Chip8Systems::Render,
+        );
+        app.configure_sets(Update, sets.chain()).add_systems(
+            Update,
+            (
+                step.in_set(Chip8Systems::Step),
+                render.in_set(Chip8Systems::Render),
+            ),
+        );
+        if self.keyboard {
+            app.add_systems(Update, read_keyboard.in_set(Chip8Systems::Input));
+        }
+    }
+
+    // the image is added here rather than in build, once the asset plugins
+    // have been
+    fn finish(&self, app: &mut App) {
+        let mut emu = Chip8::with_quirks(self.quirks);
+        emu.load_rom(&self.rom).expect("the rom's size is checked in new");
+
+        let background = self.palette.background;
+        let mut image = Image::new_fill(
+            Extent3d {
+                width: WIDTH,
+                height: HEIGHT,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[background.r, background.g, background.b, 255],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        );
+        // pixels stay square when scaled up
+        image.sampler = ImageSampler::nearest();
+        let image = app.world_mut().resource_mut::<Assets<Image>>().add(image);
+
+        app.insert_resource(Chip8Machine {
+            emu,
+            keys: [0; 16],
+            paused: false,
+            image,
+            presenter: FramePresenter::new(*self.palette),
+            cycles_per_frame: self.cycles_per_frame,
+            behind: 0.0,
+            redraw: true,
+        });
+    }
+}
+
+fn read_keyboard(keyboard: Option<Res<ButtonInput<KeyCode>>>, mut machine: ResMut<Chip8Machine>) {
+    // without bevy's input plugin there's no keyboard to read
+    let Some(keyboard) = keyboard else {
+        return;
+    };
+    machine.keys = [0; 16];
+    // bevy's key codes are named after KeyboardEvent.code's
+    for code in keyboard.get_pressed() {
+        if let Some(key) = keypad_key_for_code(&format!("{:?}", code)) {
+            machine.keys[usize::from(key)] = 255;
+        }
+    }
+}
+
+fn step(time: Res<Time>, mut machine: ResMut<Chip8Machine>) {
+    let machine = &mut *machine;
+    if machine.paused {
+        return;
+    }
+    machine.behind = (machine.behind + time.delta_secs()).min(MAX_CATCH_UP as f32 * FRAME);
+    while machine.behind >= FRAME && machine.emu.fault().is_none() {
+        machine.behind -= FRAME;
+        machine.emu.set_keys(&machine.keys);
+        machine.emu.run_frame(machine.cycles_per_frame);
+        machine.redraw |= machine.emu.draw_flag();
+    }
+}
+
+fn render(mut machine: ResMut<Chip8Machine>, mut images: ResMut<Assets<Image>>) {
+    if !machine.redraw {
+        return;
+    }
+    machine.redraw = false;
+    let Some(image) = images.get_mut(&machine.image) else {
+        return;
+    };
+    if let Some(data) = image.data.as_mut() {
+        machine.presenter.present_planes_into(&machine.emu.gfx, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn frames_run_as_time_passes_and_are_drawn() {
+        // draws the font's 0 in the corner and loops
+        let rom = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06];
+        let mut app = App::new();
+        app.init_resource::<Assets<Image>>()
+            .init_resource::<Time>()
+            .add_plugins(Chip8Plugin::new(&rom).without_keyboard());
+        app.finish();
+
+        let machine = app.world().resource::<Chip8Machine>();
+        let image = app.world().resource::<Assets<Image>>().get(&machine.image);
+        let background = DEFAULT_PALETTE.background;
+        let pixel = [background.r, background.g, background.b, 255];
+        assert_eq!(image.unwrap().data.as_ref().unwrap()[..4], pixel);
+
+        app.world_mut()
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_millis(20));
+        app.update();
+
+        let machine = app.world().resource::<Chip8Machine>();
+        assert!(machine.emu.pixel(0, 0));
+        let image = app.world().resource::<Assets<Image>>().get(&machine.image);
+        let foreground = DEFAULT_PALETTE.foreground;
+        let pixel = [foreground.r, foreground.g, foreground.b, 255];
+        assert_eq!(image.unwrap().data.as_ref().unwrap()[..4], pixel);
+    }
+}
@@ -0,0 +1,84 @@
+// Roms built into the emulator, so a fresh build has something to play and
+// a way to check itself. Their sources are in programs/bundled, written for
+// the `asm` module and dedicated to the public domain. They're played as
+// bundled:NAME, e.g. `chip8 bundled:bounce`, or picked in the launcher.
+
+/// What bundled rom paths start with.
+pub const PREFIX: &str = "bundled:";
+
+/// A rom built into the emulator.
+///
+/// name: what it's played as, after bundled:.
+/// description: a few words on what it does, for the launcher.
+/// rom: the rom itself.
+pub struct BundledRom {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub rom: &'static [u8],
+}
+
+impl BundledRom {
+    /// The rom path to play it as.
+    pub fn path(&self) -> String {
+        format!("{}{}", PREFIX, self.name)
+    }
+}
+
+pub const ROMS: [BundledRom; 3] = [
+    BundledRom {
+        name: "bounce",
+        description: "a ball bouncing around",
+        rom: include_bytes!("../programs/bundled/bounce.ch8"),
+    },
+    BundledRom {
+        name: "keypad",
+        description: "shows the keys pressed",
+        rom: include_bytes!("../programs/bundled/keypad.ch8"),
+    },
+    BundledRom {
+        name: "selftest",
+        description: "checks the emulator works",
+        rom: include_bytes!("../programs/bundled/selftest.ch8"),
+    },
+];
+
+/// The bundled rom a rom path names, if it names one.
+pub fn find(path: &str) -> Option<&'static BundledRom> {
+    let name = path.strip_prefix(PREFIX)?;
+    ROMS.iter().find(|rom| rom.name.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::cpu::Chip8;
+    use crate::quirks::Quirks;
+
+    #[test]
+    fn roms_match_their_sources() {
+        let sources = [
+            include_str!("../programs/bundled/bounce.asm"),
+            include_str!("../programs/bundled/keypad.asm"),
+            include_str!("../programs/bundled/selftest.asm"),
+        ];
+        for (rom, source) in ROMS.iter().zip(sources) {
+            assert_eq!(assemble(source).as_deref(), Ok(rom.rom), "{}", rom.name);
+        }
+    }
+
+    #[test]
+    fn selftest_passes_with_any_quirks() {
+        let selftest = find("bundled:selftest").unwrap();
+        // the result is the last byte of the rom
+        let result = 0x200 + selftest.rom.len() as u16 - 1;
+        for bits in 0..32 {
+            let mut emu = Chip8::with_quirks(Quirks::from_bits(bits));
+            emu.load_game(&selftest.path()).unwrap();
+            for _ in 0..60 {
+                emu.run_frame(10);
+            }
+            assert_eq!(emu.peek(result), 0xFF, "quirks {:05b}", bits);
+        }
+    }
+}
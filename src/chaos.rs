@@ -0,0 +1,114 @@
+// Chaos mode: hardware faults on purpose, to see how roms and the emulator
+// cope with them. A bit of memory can flip now and then, the way a stray
+// cosmic ray or a failing RAM chip would do it, and key presses can go
+// missing like on a worn keypad. Everything's drawn from a seeded random
+// number generator, so a run that turned up something can be had again.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::cpu::Chip8;
+
+/// What chaos to cause, given with --flip-bits, --drop-keys and
+/// --chaos-seed.
+///
+/// flip_bits: the chance each frame of a random bit of memory flipping,
+/// from 0 to 1.
+/// drop_keys: the chance of each key being pressed or let go not reaching
+/// the rom, from 0 to 1.
+/// seed: where the random numbers start, the same seed making the same
+/// faults.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChaosSettings {
+    pub flip_bits: f64,
+    pub drop_keys: f64,
+    pub seed: u64,
+}
+
+/// Causes the faults ChaosSettings asks for, keeping count of them.
+pub struct Chaos {
+    settings: ChaosSettings,
+    rng: StdRng,
+    // the keys as the rom last saw them
+    keys: [u8; 16],
+    pub flipped: u64,
+    pub dropped: u64,
+}
+
+impl Chaos {
+    pub fn new(settings: ChaosSettings) -> Chaos {
+        Chaos {
+            rng: StdRng::seed_from_u64(settings.seed),
+            settings,
+            keys: [0; 16],
+            flipped: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Flips a random bit of memory, or doesn't, once a frame. Returns the
+    /// address and which bit, if one flipped.
+    pub fn frame(&mut self, emu: &mut Chip8) -> Option<(u16, u8)> {
+        if !self.rng.gen_bool(self.settings.flip_bits) {
+            return None;
+        }
+        let address = self.rng.gen_range(0..0x1000);
+        let bit = self.rng.gen_range(0..8);
+        emu.poke(address, emu.peek(address) ^ (1 << bit));
+        self.flipped += 1;
+        Some((address, bit))
+    }
+
+    /// Drops some of the changes to `keys` since last time, leaving those
+    /// keys as they were. A key that's still held the next frame gets
+    /// another chance, so it's quick taps that go missing.
+    pub fn keys(&mut self, keys: &mut [u8; 16]) {
+        for (key, last) in keys.iter_mut().zip(&self.keys) {
+            if (*key != 0) != (*last != 0) && self.rng.gen_bool(self.settings.drop_keys) {
+                *key = *last;
+                self.dropped += 1;
+            }
+        }
+        self.keys = *keys;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chaos(flip_bits: f64, drop_keys: f64) -> Chaos {
+        Chaos::new(ChaosSettings {
+            flip_bits,
+            drop_keys,
+            seed: 7,
+        })
+    }
+
+    #[test]
+    fn bits_flip_where_the_seed_says() {
+        let mut emu = Chip8::default();
+        let (address, bit) = chaos(1.0, 0.0).frame(&mut emu).unwrap();
+        assert_eq!(
+            emu.peek(address),
+            Chip8::default().peek(address) ^ (1 << bit)
+        );
+        assert_eq!(chaos(1.0, 0.0).frame(&mut emu), Some((address, bit)));
+        assert_eq!(chaos(0.0, 0.0).frame(&mut emu), None);
+    }
+
+    #[test]
+    fn dropped_keys_stay_as_they_were() {
+        let mut dropping = chaos(0.0, 1.0);
+        let mut keys = [0; 16];
+        keys[5] = 255;
+        dropping.keys(&mut keys);
+        assert_eq!(keys, [0; 16]);
+        assert_eq!(dropping.dropped, 1);
+
+        let mut keeping = chaos(0.0, 0.0);
+        keys[5] = 255;
+        keeping.keys(&mut keys);
+        assert_eq!(keys[5], 255);
+        assert_eq!(keeping.dropped, 0);
+    }
+}
@@ -0,0 +1,250 @@
+// The command line, split into subcommands that share the library core:
+//
+//   chip8 run pong.ch8 --speed 900
+//   chip8 disasm pong.ch8 > pong.asm
+//
+// A rom on its own, `chip8 pong.ch8`, still runs it.
+use std::iter;
+use std::path::PathBuf;
+
+use crate::library;
+use crate::corpus::MANIFEST;
+use crate::lockstep::Profile;
+use crate::Config;
+
+/// How to use the subcommands, for `chip8 help`.
+pub const USAGE: &str = "\
+Usage: chip8 <command> [arguments]
+
+Commands:
+  run ROM [options]             play a rom in a window
+  record ROM MOVIE [options]    play a rom, recording a movie of the run
+  play ROM MOVIE [options]      play back a movie of a rom
+  disasm ROM                    print a rom as assembly
+  asm SOURCE [-o ROM]           assemble a program into a rom
+  info ROM                      show a rom's hash and remembered settings
+  test ROM [--frames N] [options]
+                                run a rom without a window and print the display,
+                                or the results of a test rom
+  test --corpus DIR [--frames N] [--update] [--manifest FILE] [options]
+                                run every rom in a directory and compare how they
+                                end up with the last time
+  compare ROM PROFILE PROFILE [--frames N] [options]
+                                run a rom under two sets of quirks side by side
+                                and say where they first differ
+  --recent [N] [options]        list the roms played recently, or play the Nth
+  help                          show this message
+
+`chip8 ROM [options]` is short for `chip8 run ROM [options]`, and `chip8` on
+its own opens a launcher listing the roms in the [library] directory. The
+roms built in are played as bundled:bounce, bundled:keypad and
+bundled:selftest. A directory or .m3u playlist can be run in place of a rom,
+taking turns through its roms with F10 or every --rotate SECONDS. The
+options are described in the README.";
+
+// frames `test` and `compare` run for unless told otherwise, ten seconds
+const TEST_FRAMES: u64 = 600;
+
+/// What the emulator was asked to do.
+///
+/// Run: play a rom, with the config covering movies and video export too.
+/// Disasm: print a rom as assembly.
+/// Asm: assemble `source` into a rom at `output`.
+/// Info: describe a rom.
+/// Test: run a rom headless for `frames` frames and print the display, or
+/// for a test rom the emulator knows, which of its checks passed.
+/// Corpus: run every rom in a directory headless for `frames` frames,
+/// comparing how each ends up with the `manifest`, or writing it if there
+/// isn't one yet or `update` asked to.
+/// Compare: run a rom under two quirk profiles side by side for up to
+/// `frames` frames, saying where the machines first differ.
+/// Recent: list the roms played recently.
+/// Launch: pick a rom to run from a list, as nothing was given.
+pub enum Command {
+    Run(Config),
+    Disasm { rom: PathBuf },
+    Asm { source: PathBuf, output: PathBuf },
+    Info { rom: PathBuf },
+    Test { config: Config, frames: u64 },
+    Corpus { config: Config, frames: u64, manifest: PathBuf, update: bool },
+    Compare { config: Config, profiles: [Profile; 2], frames: u64 },
+    Recent,
+    Launch,
+    Help,
+}
+
+impl Command {
+    /// Works out the command from env::Args.
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Command, String> {
+        let args: Vec<String> = args.collect();
+        let (program, rest) = match args.split_first() {
+            Some((program, rest)) => (program.clone(), rest),
+            None => ("chip8".to_string(), &args[..]),
+        };
+        // everything after the subcommand, ready for Config::new
+        let config = |args: Vec<String>| Config::new(iter::once(program.clone()).chain(args));
+
+        let command = match rest.first().map(String::as_str) {
+            Some("run") => Command::Run(config(rest[1..].to_vec())?),
+            Some(name @ ("record" | "play")) => {
+                let (rom, movie, options) = match &rest[1..] {
+                    [rom, movie, options @ ..] => (rom, movie, options),
+                    _ => return Err(format!("{} needs a rom and a movie.", name)),
+                };
+                let flag = format!("--{}-movie", name);
+                let args = [rom.clone(), flag, movie.clone()];
+                Command::Run(config(args.into_iter().chain(options.to_vec()).collect())?)
+            }
+            Some("disasm") => Command::Disasm {
+                rom: only_path("disasm", "a rom", &rest[1..])?,
+            },
+            Some("asm") => {
+                let (source, output) = match &rest[1..] {
+                    [source] => (source, None),
+                    [source, flag, output] if flag == "-o" => (source, Some(output)),
+                    _ => return Err("asm needs a source file, and optionally -o ROM.".to_string()),
+                };
+                let source = PathBuf::from(source);
+                let output = output.map_or_else(|| source.with_extension("ch8"), PathBuf::from);
+                Command::Asm { source, output }
+            }
+            Some("info") => Command::Info {
+                rom: only_path("info", "a rom", &rest[1..])?,
+            },
+            Some("test") => {
+                let mut frames = TEST_FRAMES;
+                let mut corpus: Option<PathBuf> = None;
+                let mut manifest: Option<PathBuf> = None;
+                let mut update = false;
+                let mut options = Vec::new();
+                let mut args = rest[1..].iter().cloned();
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "--frames" => frames = crate::parse_value(&arg, args.next())?,
+                        "--corpus" => corpus = Some(crate::parse_value(&arg, args.next())?),
+                        "--manifest" => manifest = Some(crate::parse_value(&arg, args.next())?),
+                        "--update" => update = true,
+                        _ => options.push(arg),
+                    }
+                }
+                match corpus {
+                    Some(dir) => {
+                        if !dir.is_dir() {
+                            let dir = dir.display();
+                            return Err(format!("--corpus needs a directory, not {}.", dir));
+                        }
+                        let manifest = manifest.unwrap_or_else(|| dir.join(MANIFEST));
+                        options.insert(0, dir.to_string_lossy().into_owned());
+                        Command::Corpus {
+                            config: config(options)?,
+                            frames,
+                            manifest,
+                            update,
+                        }
+                    }
+                    None if manifest.is_some() || update => {
+                        return Err("--manifest and --update need --corpus.".to_string())
+                    }
+                    None => Command::Test {
+                        config: config(options)?,
+                        frames,
+                    },
+                }
+            }
+            Some("compare") => {
+                let (rom, a, b, rest) = match &rest[1..] {
+                    [rom, a, b, rest @ ..] => (rom, a, b, rest),
+                    _ => return Err("compare needs a rom and two quirk profiles.".to_string()),
+                };
+                let profiles = [a.parse()?, b.parse()?];
+                let mut frames = TEST_FRAMES;
+                let mut options = vec![rom.clone()];
+                let mut args = rest.iter().cloned();
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "--frames" => frames = crate::parse_value(&arg, args.next())?,
+                        _ => options.push(arg),
+                    }
+                }
+                Command::Compare {
+                    config: config(options)?,
+                    profiles,
+                    frames,
+                }
+            }
+            Some("--recent") => match rest.get(1) {
+                Some(number) => {
+                    let index: usize = crate::parse_value("--recent", Some(number.clone()))?;
+                    let recent = library::recent();
+                    let rom = match index.checked_sub(1).and_then(|index| recent.get(index)) {
+                        Some(rom) => rom.to_string_lossy().into_owned(),
+                        None => return Err(format!("There are {} recent roms.", recent.len())),
+                    };
+                    let args = iter::once(rom).chain(rest[2..].iter().cloned());
+                    Command::Run(config(args.collect())?)
+                }
+                None => Command::Recent,
+            },
+            Some("help" | "--help" | "-h") => Command::Help,
+            None => Command::Launch,
+            _ => Command::Run(config(rest.to_vec())?),
+        };
+        Ok(command)
+    }
+}
+
+// The single path a subcommand takes.
+fn only_path(command: &str, what: &str, args: &[String]) -> Result<PathBuf, String> {
+    match args {
+        [path] => Ok(PathBuf::from(path)),
+        _ => Err(format!("{} needs {}.", command, what)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn parse(args: &str) -> Result<Command, String> {
+        Command::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn tools_take_paths() {
+        assert!(matches!(
+            parse("chip8 disasm pong.ch8"),
+            Ok(Command::Disasm { rom }) if rom == Path::new("pong.ch8")
+        ));
+        assert!(matches!(
+            parse("chip8 asm pong.asm"),
+            Ok(Command::Asm { output, .. }) if output == Path::new("pong.ch8")
+        ));
+        assert!(matches!(
+            parse("chip8 asm pong.asm -o out.c8"),
+            Ok(Command::Asm { output, .. }) if output == Path::new("out.c8")
+        ));
+        assert!(matches!(parse("chip8 help"), Ok(Command::Help)));
+        assert!(matches!(parse("chip8"), Ok(Command::Launch)));
+    }
+
+    #[test]
+    fn missing_arguments_are_errors() {
+        assert_eq!(
+            parse("chip8 info").err().as_deref(),
+            Some("info needs a rom.")
+        );
+        assert_eq!(
+            parse("chip8 record pong.ch8").err().as_deref(),
+            Some("record needs a rom and a movie.")
+        );
+        assert_eq!(
+            parse("chip8 compare pong.ch8 superchip").err().as_deref(),
+            Some("compare needs a rom and two quirk profiles.")
+        );
+        assert_eq!(
+            parse("chip8 test pong.ch8 --update").err().as_deref(),
+            Some("--manifest and --update need --corpus.")
+        );
+    }
+}
@@ -0,0 +1,68 @@
+// Checking that a save state or movie belongs with the rom it's loaded
+// for. One from another rom, or run with different quirks, doesn't fail to
+// load, it just goes wrong in confusing ways.
+use crate::quirks::Quirks;
+
+/// The version of the emulator, written into save states and movies.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// What a save state or movie was made with.
+///
+/// version: the emulator's version, if it was recorded.
+/// rom_hash: the SHA-1 of the rom, if it was known.
+/// quirks: the quirks it ran with.
+#[derive(Clone, Copy, Debug)]
+pub struct Origin<'a> {
+    pub version: Option<&'a str>,
+    pub rom_hash: Option<&'a str>,
+    pub quirks: Quirks,
+}
+
+impl Origin<'_> {
+    /// Compares the origin with the rom that's running and the quirks it's
+    /// configured with. The wrong rom is an error, anything else that could
+    /// make a difference comes back as a warning.
+    pub fn check(&self, rom_hash: Option<&str>, quirks: Quirks) -> Result<Vec<String>, String> {
+        // a hash that couldn't be worked out can't be compared
+        if let (Some(saved), Some(running)) = (self.rom_hash, rom_hash) {
+            if saved != running {
+                return Err("it was made with a different rom".to_string());
+            }
+        }
+
+        let mut warnings = Vec::new();
+        match self.version {
+            Some(version) if version != VERSION => warnings.push(format!(
+                "it was made by version {} of the emulator and this is {}, it might not play out the same",
+                version, VERSION
+            )),
+            _ => {}
+        }
+        if self.quirks != quirks {
+            warnings.push("it was made with different quirks, which are used instead".to_string());
+        }
+        Ok(warnings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrong_rom_is_an_error_and_the_rest_warnings() {
+        let origin = Origin {
+            version: Some("0.0.1"),
+            rom_hash: Some("abc"),
+            quirks: Quirks::default(),
+        };
+        assert!(origin.check(Some("def"), Quirks::default()).is_err());
+
+        let clipping = Quirks {
+            clipping: true,
+            ..Quirks::default()
+        };
+        assert_eq!(origin.check(Some("abc"), clipping).unwrap().len(), 2);
+        assert_eq!(origin.check(None, Quirks::default()).unwrap().len(), 1);
+    }
+}
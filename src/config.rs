@@ -0,0 +1,393 @@
+// The optional config file, loaded from the user's config directory.
+//
+// Anything left out of the file keeps its default and anything given on
+// the command line overrides the file. The first time the emulator runs it
+// writes a commented template there to start from.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::quirks::Quirks;
+use crate::sound::Tone;
+
+/// The contents of config.toml.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigFile {
+    pub display: DisplaySettings,
+    pub emulation: EmulationSettings,
+    pub audio: AudioSettings,
+    pub keymap: KeymapSettings,
+    pub controller: ControllerSettings,
+    pub accessibility: AccessibilitySettings,
+    pub autosave: AutosaveSettings,
+    pub library: LibrarySettings,
+    pub gpio: Option<GpioSettings>,
+    pub quirks: Quirks,
+    // keyboard key names bound to each emulator action, e.g. pause = ["P"]
+    pub hotkeys: BTreeMap<String, Vec<String>>,
+}
+
+/// The template written on first run, every setting commented out at its
+/// default.
+pub const TEMPLATE: &str = include_str!("config.template.toml");
+
+/// The [display] section.
+///
+/// palette: the colors to start with.
+/// width, height: the size of the window when it opens.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct DisplaySettings {
+    pub palette: PaletteName,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            palette: PaletteName::Default,
+            width: 768,
+            height: 384,
+        }
+    }
+}
+
+/// The built-in palettes, by name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PaletteName {
+    #[default]
+    Default,
+    Bitbee,
+    NeutralGreen,
+    MacPaint,
+    PaperBack,
+}
+
+impl PaletteName {
+    /// Every palette, in the same order as PALETTES.
+    pub const ALL: [PaletteName; 5] = [
+        PaletteName::Default,
+        PaletteName::Bitbee,
+        PaletteName::NeutralGreen,
+        PaletteName::MacPaint,
+        PaletteName::PaperBack,
+    ];
+
+    /// The palette's place in PALETTES.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl FromStr for PaletteName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "default" => Ok(PaletteName::Default),
+            "bitbee" => Ok(PaletteName::Bitbee),
+            "neutral-green" => Ok(PaletteName::NeutralGreen),
+            "mac-paint" => Ok(PaletteName::MacPaint),
+            "paper-back" => Ok(PaletteName::PaperBack),
+            _ => Err(format!(
+                "Unknown palette '{}', expected default, bitbee, neutral-green, mac-paint or paper-back.",
+                s
+            )),
+        }
+    }
+}
+
+/// The [emulation] section.
+///
+/// speed: instructions run a second.
+/// start_paused: open the window paused before the rom's first instruction,
+/// for stepping through it from the start.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct EmulationSettings {
+    pub speed: u32,
+    pub start_paused: bool,
+}
+
+impl Default for EmulationSettings {
+    fn default() -> Self {
+        EmulationSettings {
+            speed: 600,
+            start_paused: false,
+        }
+    }
+}
+
+/// The [audio] section.
+///
+/// tone: what the buzzer sounds like.
+/// muted: whether to start with sound off.
+/// buffer_size: samples per audio buffer, smaller is less laggy but more
+/// likely to crackle. Left out the platform default is used.
+/// visual_beep: flash the window border while the buzzer sounds, for
+/// players who can't hear it.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    #[serde(flatten)]
+    pub tone: Tone,
+    pub muted: bool,
+    pub buffer_size: Option<u16>,
+    pub visual_beep: bool,
+}
+
+impl AudioSettings {
+    /// The buffer size to open the audio device with.
+    pub fn buffer_size(&self) -> u16 {
+        self.buffer_size.unwrap_or(if cfg!(target_os = "macos") {
+            // CoreAudio copes fine with small buffers
+            512
+        } else if cfg!(any(target_os = "linux", target_os = "windows")) {
+            // small enough that the beep doesn't lag behind the game
+            // without PulseAudio or WASAPI underrunning
+            1024
+        } else {
+            2048
+        })
+    }
+}
+
+/// The [keymap] section.
+///
+/// preset: the built-in layout to start from.
+/// bindings: keyboard key names bound to each keypad key on top of the
+/// preset, e.g. 5 = ["W", "Up"].
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KeymapSettings {
+    pub preset: KeymapPreset,
+    #[serde(flatten)]
+    pub bindings: BTreeMap<String, Vec<String>>,
+}
+
+/// The built-in keyboard layouts for the keypad.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeymapPreset {
+    /// 1234/QWER/ASDF/ZXCV by key position, whatever the layout.
+    #[default]
+    Grid,
+    /// The same grid by the characters on a QWERTY keyboard.
+    Qwerty,
+    /// The grid by the characters on an AZERTY keyboard: AZER/QSDF/WXCV.
+    Azerty,
+    /// The grid by the characters on a Dvorak keyboard: ',.P/AOEU/;QJK.
+    Dvorak,
+    /// 7890/UIOP/JKL;/M,./ by position, leaving the left hand on the mouse.
+    LeftHanded,
+    /// The number pad.
+    Numpad,
+    /// The left half of the keypad on 12/QW/AS/ZX and the right half on the
+    /// number pad, for two players sharing a full-size keyboard.
+    TwoPlayer,
+}
+
+impl KeymapPreset {
+    /// Every preset, in the order they're listed.
+    pub const ALL: [KeymapPreset; 7] = [
+        KeymapPreset::Grid,
+        KeymapPreset::Qwerty,
+        KeymapPreset::Azerty,
+        KeymapPreset::Dvorak,
+        KeymapPreset::LeftHanded,
+        KeymapPreset::Numpad,
+        KeymapPreset::TwoPlayer,
+    ];
+}
+
+impl FromStr for KeymapPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "grid" => Ok(KeymapPreset::Grid),
+            "qwerty" => Ok(KeymapPreset::Qwerty),
+            "azerty" => Ok(KeymapPreset::Azerty),
+            "dvorak" => Ok(KeymapPreset::Dvorak),
+            "left-handed" => Ok(KeymapPreset::LeftHanded),
+            "numpad" => Ok(KeymapPreset::Numpad),
+            "two-player" => Ok(KeymapPreset::TwoPlayer),
+            _ => Err(format!(
+                "Unknown keymap '{}', expected grid, qwerty, azerty, dvorak, left-handed, numpad or two-player.",
+                s
+            )),
+        }
+    }
+}
+
+/// The [controller] section.
+///
+/// deadzone: how far the analog stick has to be pushed, as a fraction of
+/// its travel, before it presses a direction key.
+/// pause_on_disconnect: pause when a controller is unplugged mid-game.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ControllerSettings {
+    pub deadzone: f32,
+    pub pause_on_disconnect: bool,
+}
+
+impl Default for ControllerSettings {
+    fn default() -> Self {
+        ControllerSettings {
+            deadzone: 0.25,
+            pause_on_disconnect: true,
+        }
+    }
+}
+
+/// The [accessibility] section.
+///
+/// sticky_keys: tapping a keypad key latches it down until it's tapped
+/// again, for players who can't hold keys or hold several at once.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AccessibilitySettings {
+    pub sticky_keys: bool,
+}
+
+/// The [autosave] section.
+///
+/// enabled: save the machine when quitting, so closing the window by
+/// accident doesn't lose the game.
+/// resume: what to do with that save the next time the rom is started.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct AutosaveSettings {
+    pub enabled: bool,
+    pub resume: Resume,
+}
+
+impl Default for AutosaveSettings {
+    fn default() -> Self {
+        AutosaveSettings {
+            enabled: true,
+            resume: Resume::Ask,
+        }
+    }
+}
+
+/// Whether to pick up where the rom was left last time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resume {
+    /// Start paused and ask.
+    #[default]
+    Ask,
+    Always,
+    Never,
+}
+
+/// The [library] section.
+///
+/// directory: where the launcher looks for roms when none is given, the
+/// current directory unless set.
+/// database: the `database` directory of a copy of the chip-8-database, for
+/// the titles and quirks of roms. Only the bundled roms are known without it.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct LibrarySettings {
+    pub directory: Option<PathBuf>,
+    pub database: Option<PathBuf>,
+}
+
+/// The [gpio] section, for a 4x4 matrix keypad wired to a Raspberry Pi.
+///
+/// rows: the GPIO numbers of the row wires, top to bottom.
+/// columns: the GPIO numbers of the column wires, left to right. They need
+/// pull-ups, e.g. `gpio=5,6,13,19=ip,pu` in config.txt.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct GpioSettings {
+    pub rows: [u32; 4],
+    pub columns: [u32; 4],
+}
+
+/// Where the config file lives, e.g. ~/.config/chip8/config.toml on Linux.
+pub fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("chip8").join("config.toml"))
+}
+
+impl ConfigFile {
+    /// Loads the config file, falling back to the defaults when there isn't
+    /// one and writing the template in its place.
+    pub fn load() -> Result<ConfigFile, String> {
+        let path = match config_path() {
+            Some(path) => path,
+            None => return Ok(ConfigFile::default()),
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => ConfigFile::parse(&contents)
+                .map_err(|e| format!("Invalid config file {}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                // the template is only a convenience, a config directory
+                // that can't be written to isn't worth stopping for
+                if let Some(dir) = path.parent() {
+                    let _ = fs::create_dir_all(dir).and_then(|()| fs::write(&path, TEMPLATE));
+                }
+                Ok(ConfigFile::default())
+            }
+            Err(e) => Err(format!("Unable to read {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<ConfigFile, toml::de::Error> {
+        toml::from_str(contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::Waveform;
+
+    #[test]
+    fn missing_keys_keep_defaults() {
+        let file = ConfigFile::parse("[audio]\nwaveform = \"sine\"\nmuted = true\n").unwrap();
+        assert_eq!(file.audio.tone.waveform, Waveform::Sine);
+        assert_eq!(file.audio.tone.frequency, Tone::default().frequency);
+        assert!(file.audio.muted);
+    }
+
+    #[test]
+    fn template_settings_are_the_defaults() {
+        // uncomment the settings, leaving the descriptions alone
+        let uncommented: String = TEMPLATE
+            .lines()
+            .filter(|line| !line.starts_with("#5 ") && !line.starts_with("#pause "))
+            .map(|line| match line.strip_prefix('#') {
+                Some(setting) if !setting.starts_with(' ') && !setting.is_empty() => setting,
+                _ => line,
+            })
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let file = ConfigFile::parse(&uncommented).unwrap();
+        let defaults = ConfigFile::default();
+
+        assert_eq!(file.display, defaults.display);
+        assert_eq!(file.emulation, defaults.emulation);
+        assert_eq!(file.audio.tone, defaults.audio.tone);
+        assert_eq!(file.keymap, defaults.keymap);
+        assert_eq!(file.controller, defaults.controller);
+        assert_eq!(file.quirks, defaults.quirks);
+    }
+
+    #[test]
+    fn keymap_preset_and_bindings() {
+        let file = ConfigFile::parse("[keymap]\npreset = \"left-handed\"\n5 = [\"Up\"]\n").unwrap();
+        assert_eq!(file.keymap.preset, KeymapPreset::LeftHanded);
+        assert_eq!(file.keymap.bindings["5"], ["Up"]);
+    }
+}
@@ -0,0 +1,120 @@
+// Game controller input. The left stick stands in for the 2/4/6/8 keys most
+// roms use as directions, so Pong, Brix and friends play naturally on a pad.
+use sdl2::controller::{Axis, GameController};
+use sdl2::GameControllerSubsystem;
+
+// The keypad keys pressed by pushing the stick each way.
+const UP: usize = 0x2;
+const LEFT: usize = 0x4;
+const RIGHT: usize = 0x6;
+const DOWN: usize = 0x8;
+
+// Keeps the connected controllers open so SDL sends their events.
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    open: Vec<GameController>,
+}
+
+impl Controllers {
+    pub fn new(subsystem: GameControllerSubsystem) -> Controllers {
+        Controllers {
+            subsystem,
+            open: Vec::new(),
+        }
+    }
+
+    // Opens a controller that's been plugged in. SDL reports the ones
+    // already connected at startup the same way.
+    pub fn add(&mut self, index: u32) {
+        if !self.subsystem.is_game_controller(index) {
+            return;
+        }
+
+        match self.subsystem.open(index) {
+            Ok(controller) => {
+                let id = controller.instance_id();
+                if !self.open.iter().any(|open| open.instance_id() == id) {
+                    self.open.push(controller);
+                }
+            }
+            Err(e) => eprintln!("⚠️ Unable to open controller {}: {}", index, e),
+        }
+    }
+
+    // Closes a controller that's been unplugged, returning whether it was
+    // one of ours.
+    pub fn remove(&mut self, instance_id: u32) -> bool {
+        let before = self.open.len();
+        self.open.retain(|open| open.instance_id() != instance_id);
+        self.open.len() != before
+    }
+}
+
+// Where the left stick is pointing.
+pub struct Stick {
+    // how far the stick has to move from the center before it counts, in
+    // axis units
+    deadzone: i16,
+    x: i16,
+    y: i16,
+}
+
+impl Stick {
+    // `deadzone` is a fraction of the stick's full travel.
+    pub fn new(deadzone: f32) -> Stick {
+        Stick {
+            deadzone: (deadzone * i16::MAX as f32) as i16,
+            x: 0,
+            y: 0,
+        }
+    }
+
+    // Lets go of the stick, for when its controller goes away.
+    pub fn center(&mut self) {
+        self.x = 0;
+        self.y = 0;
+    }
+
+    // Records an axis moving, ignoring all but the left stick.
+    pub fn set_axis(&mut self, axis: Axis, value: i16) {
+        match axis {
+            Axis::LeftX => self.x = value,
+            Axis::LeftY => self.y = value,
+            _ => {}
+        }
+    }
+
+    // Presses the direction keys the stick is pushed towards.
+    pub fn apply(&self, keys: &mut [u8; 16]) {
+        if self.x < -self.deadzone {
+            keys[LEFT] = 255;
+        } else if self.x > self.deadzone {
+            keys[RIGHT] = 255;
+        }
+
+        // SDL's y axis points down
+        if self.y < -self.deadzone {
+            keys[UP] = 255;
+        } else if self.y > self.deadzone {
+            keys[DOWN] = 255;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deadzone_ignores_small_movements() {
+        let mut stick = Stick::new(0.25);
+        stick.set_axis(Axis::LeftX, 4000);
+        stick.set_axis(Axis::LeftY, -20000);
+
+        let mut keys = [0; 16];
+        stick.apply(&mut keys);
+        assert_eq!(keys[RIGHT], 0);
+        assert_eq!(keys[UP], 255);
+        assert_eq!(keys.iter().filter(|key| **key != 0).count(), 1);
+    }
+}
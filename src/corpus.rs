@@ -0,0 +1,178 @@
+// Regression runs over a whole library of roms. `chip8 test --corpus DIR`
+// runs every rom in the directory for the same number of frames and hashes
+// the machine each one ends up as, display and all, into a manifest. Later
+// runs compare against it, so a change to the emulator that makes any rom
+// end up differently shows up by name.
+//
+// The manifest is plain text to diff and check in, a line for each rom:
+//
+//   frames 600
+//   3f2a9c01  pong.ch8
+//   91bb0e7d  tetris.ch8
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::cpu::Chip8;
+use crate::quirks::Quirks;
+use crate::speed::Speed;
+
+/// What the manifest's called when it's kept with the roms.
+pub const MANIFEST: &str = "chip8-corpus.txt";
+
+/// How each rom in a corpus ended up.
+///
+/// frames: how long each rom ran for.
+/// hashes: the checksum of the machine at the end, by the rom's file name.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Manifest {
+    pub frames: u64,
+    pub hashes: BTreeMap<String, u32>,
+}
+
+/// How a rom compared with the manifest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    Same,
+    // it ended up differently, as it did in the manifest
+    Changed { was: u32 },
+    // it isn't in the manifest
+    New,
+    // it's in the manifest but not the corpus
+    Missing,
+}
+
+impl Manifest {
+    pub fn parse(contents: &str) -> Result<Manifest, String> {
+        let mut lines = contents.lines().map(str::trim).filter(|line| !line.is_empty());
+        let frames = lines
+            .next()
+            .and_then(|line| line.strip_prefix("frames "))
+            .and_then(|frames| frames.trim().parse().ok())
+            .ok_or("expected the frames on the first line")?;
+        let mut hashes = BTreeMap::new();
+        for line in lines {
+            let (hash, rom) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| format!("expected a hash and a rom in {:?}", line))?;
+            let hash = u32::from_str_radix(hash, 16)
+                .map_err(|_| format!("expected a hash in {:?}", line))?;
+            hashes.insert(rom.trim().to_string(), hash);
+        }
+        Ok(Manifest { frames, hashes })
+    }
+
+    /// Reads the manifest at `path`, if there is one yet.
+    pub fn load(path: &Path) -> Result<Option<Manifest>, String> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Manifest::parse(&contents)
+                .map(Some)
+                .map_err(|e| format!("Invalid manifest {}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Unable to read {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_string())
+            .map_err(|e| format!("Unable to write {}: {}", path.display(), e))
+    }
+
+    /// How each rom, in this manifest or the `previous` one, compares with
+    /// how it was, by name.
+    pub fn compare(&self, previous: &Manifest) -> Vec<(String, Change)> {
+        let mut roms: Vec<(String, Change)> = self
+            .hashes
+            .iter()
+            .map(|(rom, hash)| {
+                let change = match previous.hashes.get(rom) {
+                    Some(was) if was == hash => Change::Same,
+                    Some(&was) => Change::Changed { was },
+                    None => Change::New,
+                };
+                (rom.clone(), change)
+            })
+            .collect();
+        for rom in previous.hashes.keys() {
+            if !self.hashes.contains_key(rom) {
+                roms.push((rom.clone(), Change::Missing));
+            }
+        }
+        roms.sort_by(|(a, _), (b, _)| a.cmp(b));
+        roms
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "frames {}", self.frames)?;
+        for (rom, hash) in &self.hashes {
+            writeln!(f, "{:08x}  {}", hash, rom)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `rom` for `frames` frames from power on, with the random numbers
+/// seeded so every run is the same, and hashes the machine it ends up as.
+pub fn run(rom: &[u8], quirks: Quirks, frames: u64, speed: Speed) -> io::Result<u32> {
+    let mut emu = Chip8::with_quirks(quirks);
+    emu.seed_rng(0);
+    emu.load_rom(rom)?;
+    for _ in 0..frames {
+        emu.run_frame(speed.cycles_in(emu.frames()));
+    }
+    Ok(emu.checksum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundled;
+
+    fn manifest(hashes: &[(&str, u32)]) -> Manifest {
+        Manifest {
+            frames: 600,
+            hashes: hashes
+                .iter()
+                .map(|(rom, hash)| (rom.to_string(), *hash))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn manifests_round_trip() {
+        let manifest = manifest(&[("pong.ch8", 0x3f2a9c01), ("tetris.ch8", 7)]);
+        let text = manifest.to_string();
+        assert_eq!(text, "frames 600\n3f2a9c01  pong.ch8\n00000007  tetris.ch8\n");
+        assert_eq!(Manifest::parse(&text).unwrap(), manifest);
+        assert!(Manifest::parse("3f2a9c01  pong.ch8\n").is_err());
+        assert!(Manifest::parse("frames 600\npong.ch8\n").is_err());
+    }
+
+    #[test]
+    fn changes_are_found_by_name() {
+        let previous = manifest(&[("a.ch8", 1), ("b.ch8", 2), ("c.ch8", 3)]);
+        let now = manifest(&[("a.ch8", 1), ("b.ch8", 5), ("d.ch8", 4)]);
+        assert_eq!(
+            now.compare(&previous),
+            [
+                ("a.ch8".to_string(), Change::Same),
+                ("b.ch8".to_string(), Change::Changed { was: 2 }),
+                ("c.ch8".to_string(), Change::Missing),
+                ("d.ch8".to_string(), Change::New),
+            ]
+        );
+    }
+
+    #[test]
+    fn runs_repeat() {
+        let rom = bundled::find("bundled:bounce").unwrap().rom;
+        let run = |frames| run(rom, Quirks::default(), frames, Speed::default()).unwrap();
+        let hash = run(60);
+        assert_eq!(run(60), hash);
+        assert_ne!(run(61), hash);
+    }
+}
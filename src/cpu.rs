@@ -1,9 +1,27 @@
 // A chip8 emulator
 use core::fmt;
-use rand::Rng;
-use std::fs::File;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use std::io;
-use std::io::Read;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+use crate::archive::read_rom;
+use crate::peripheral::Peripheral;
+use crate::quirks::Quirks;
+use crate::speed::Speed;
+
+/// The biggest rom there's memory for, from 0x200 to the end: 3584 bytes.
+pub const MAX_ROM: usize = 0x1000 - 0x200;
+
+/// The error for a rom of `size` bytes, if it's bigger than MAX_ROM.
+pub fn check_rom_size(size: usize) -> io::Result<()> {
+    if size > MAX_ROM {
+        let error = format!("the rom is too large, it has to fit in {} bytes", MAX_ROM);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+    }
+    Ok(())
+}
 
 #[derive(Debug)]
 pub struct Chip8 {
@@ -16,7 +34,9 @@ pub struct Chip8 {
     v: [u8; 16],             // CPU registers
     i: u16,                  // index register
     pc: u16,                 // program counter
-    pub gfx: [[u8; 64]; 32], // gfx: the screen
+    // gfx: the screen, a u64 to a row with the leftmost pixel in the top
+    // bit, so a row of a sprite is drawn with a shift and an XOR
+    pub gfx: [u64; 32],
     // timers (60hz) when set >0 they will count down to 0
     delay_timer: u8,
     sound_timer: u8,  // system buzzer makes sound when sound timer reaches 0
@@ -24,6 +44,106 @@ pub struct Chip8 {
     sp: u8,           // the stack pointer
     keys: [u8; 16],   // the 16 keys that can control the system
     screen_updated: bool,
+    waiting_for_key: bool, // blocked on FX0A until a key is pressed
+    // XO-CHIP audio: a 1-bit sample played instead of the plain beep
+    // once a rom loads one with F002
+    audio_pattern: [u8; 16],
+    pitch: u8, // playback rate of the pattern, set by FX3A
+    xo_audio: bool,
+    quirks: Quirks, // which variant of the disputed instructions to run
+    // for CXNN, kept rather than made for each one, and seeded so movies play
+    // back the same. A named generator, since rand's StdRng and SmallRng can
+    // change algorithm from one version to the next
+    rng: ChaCha8Rng,
+    frames: u64,    // timer ticks since power on, 60 a second
+    cycles: u64,    // instructions executed since power on
+    fault: Option<Fault>, // what stopped the machine, if it's stopped
+    // devices answering the FX instructions the machine doesn't know
+    peripherals: Vec<Box<dyn Peripheral>>,
+    // the rom's writes to memory since they were last taken, if watched
+    writes: Option<Vec<(u16, u8)>>,
+    // whether to check the invariants after every instruction
+    checking: bool,
+}
+
+// An instruction the machine can't run, which stops it where it is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fault {
+    pub opcode: u16,
+    pub address: u16,
+    pub kind: FaultKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultKind {
+    // an instruction the machine doesn't know
+    UnknownOpcode,
+    // a call with all 16 levels of the stack already used
+    StackOverflow,
+    // the instruction left the machine in a state it should never be in,
+    // found with the invariants checked
+    BrokenInvariant(Invariant),
+}
+
+// What the machine's up to, for frontends to show.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    Running,
+    // blocked on FX0A until a key is pressed
+    WaitingForKey,
+    // the rom jumped to itself, the way roms end, and only a reset will get
+    // it going again
+    Finished,
+    // stopped at an instruction it couldn't run
+    Stopped(Fault),
+}
+
+// Something that should always be true of the machine between
+// instructions, with what it was instead. Besides a rom jumping or running
+// off the end of memory, or adding to I until it's past the end, these are
+// bugs in the emulator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Invariant {
+    // the program counter past the end of memory
+    PcOutOfMemory(u16),
+    // the program counter moved an odd number of bytes by an instruction
+    // that doesn't jump
+    PcMisaligned(u16),
+    // the stack pointer past the top of the stack
+    StackPointer(u8),
+    // I past the end of memory
+    Index(u16),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            FaultKind::UnknownOpcode => {
+                write!(f, "unknown opcode {:04X} at {:03X}", self.opcode, self.address)
+            }
+            FaultKind::StackOverflow => {
+                write!(f, "stack overflow calling {:04X} at {:03X}", self.opcode, self.address)
+            }
+            FaultKind::BrokenInvariant(invariant) => write!(
+                f,
+                "{} after {:04X} at {:03X}",
+                invariant, self.opcode, self.address
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Invariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Invariant::PcOutOfMemory(pc) => write!(f, "PC {:04X} is past the end of memory", pc),
+            Invariant::PcMisaligned(pc) => {
+                write!(f, "PC {:03X} moved an odd number of bytes without a jump", pc)
+            }
+            Invariant::StackPointer(sp) => write!(f, "SP {} is past the top of the stack", sp),
+            Invariant::Index(i) => write!(f, "I {:04X} is past the end of memory", i),
+        }
+    }
 }
 
 // Formatting for printing a Chip8 used to debug state.
@@ -45,7 +165,7 @@ stack pointer: {}
 }
 
 // Each line represents a character and is annotated accordingly.
-const CHIP8_FONTSET: [u8; 80] = [
+pub(crate) const CHIP8_FONTSET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -74,75 +194,275 @@ impl Default for Chip8 {
             v: [0; 16],
             i: 0,
             pc: 0x200,
-            gfx: [[0; 64]; 32],
+            gfx: [0; 32],
             delay_timer: 0,
             sound_timer: 0,
             stack: [0; 16],
             sp: 0,
             keys: [0; 16],
             screen_updated: false,
+            waiting_for_key: false,
+            audio_pattern: [0; 16],
+            pitch: 64,
+            xo_audio: false,
+            quirks: Quirks::default(),
+            rng: ChaCha8Rng::from_entropy(),
+            frames: 0,
+            cycles: 0,
+            fault: None,
+            peripherals: Vec::new(),
+            writes: None,
+            checking: false,
         };
 
-        for i in 0..80 {
-            c8.memory[i] = CHIP8_FONTSET[i];
-        }
+        c8.memory[..80].copy_from_slice(&CHIP8_FONTSET);
 
         c8
     }
 }
 
 impl Chip8 {
-    // Loads the game from the filesystem into memory
+    pub fn with_quirks(quirks: Quirks) -> Chip8 {
+        Chip8 {
+            quirks,
+            ..Chip8::default()
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    // Makes CXNN's random numbers the same every time, for movies.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    // Loads the game from the filesystem into memory, out of a zip
+    // archive if the path names one
     pub fn load_game(&mut self, file_name: &str) -> Result<(), io::Error> {
         // TODO: check file_name for .ch8 ending
-        let mut file = match File::open(file_name) {
-            Ok(val) => val,
-            Err(e) => return Err(e),
-        };
+        let rom = read_rom(file_name)?;
+        self.load_rom(&rom)
+    }
 
-        // 0x200 -> 0xFFF
-        // 512 -> 4096 = 3584 bytes
-        // read the file into this buffer
+    // Loads a rom that's already in memory, for frontends without a
+    // filesystem. A rom too large for memory is an error rather than cut
+    // short, and the memory past the end of the rom is cleared.
+    pub fn load_rom(&mut self, rom: &[u8]) -> io::Result<()> {
+        check_rom_size(rom.len())?;
+        // 0x200 -> 0xFFF, 3584 bytes
+        let (program, rest) = self.memory[0x200..].split_at_mut(rom.len());
+        program.copy_from_slice(rom);
+        rest.fill(0);
+        Ok(())
+    }
 
-        // read in the file as a byte vector
-        let mut buffer: [u8; 3584] = [0; 3584];
-        let _size = match file.read(&mut buffer) {
-            Ok(val) => val,
-            Err(e) => return Err(e),
+    // Starts the program over from 0x200 with cleared registers, stack,
+    // timers and display. Memory is left alone so the rom doesn't need
+    // loading again.
+    pub fn reset(&mut self) {
+        let peripherals = std::mem::take(&mut self.peripherals);
+        *self = Chip8 {
+            memory: self.memory,
+            screen_updated: true,
+            peripherals,
+            writes: self.writes.take(),
+            checking: self.checking,
+            ..Chip8::with_quirks(self.quirks)
         };
+    }
 
-        // load the game into memory
-        for i in 0..3584 {
-            //print!("0x{:02x}  ", buffer[i]);
-            self.memory[0x200 + i] = buffer[i];
+    // Writes everything about the machine but the quirks and the keys held,
+    // for save states.
+    pub fn write_state(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&self.opcode.to_le_bytes())?;
+        out.write_all(&self.memory)?;
+        out.write_all(&self.v)?;
+        out.write_all(&self.i.to_le_bytes())?;
+        out.write_all(&self.pc.to_le_bytes())?;
+        out.write_all(self.pixels().as_flattened())?;
+        out.write_all(&[self.delay_timer, self.sound_timer])?;
+        for address in &self.stack {
+            out.write_all(&address.to_le_bytes())?;
         }
+        out.write_all(&[self.sp, self.waiting_for_key as u8])?;
+        out.write_all(&self.audio_pattern)?;
+        out.write_all(&[self.pitch, self.xo_audio as u8])?;
+        out.write_all(&self.frames.to_le_bytes())?;
+        out.write_all(&self.cycles.to_le_bytes())
+    }
+
+    // A hash of everything write_state writes, to tell whether two runs
+    // are still in step.
+    pub fn checksum(&self) -> u32 {
+        let mut state = Vec::new();
+        self.write_state(&mut state)
+            .expect("writing to a Vec can't fail");
+        let digest = sha1_smol::Sha1::from(state).digest().bytes();
+        u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]])
+    }
+
+    // Reads back what write_state wrote.
+    pub fn read_state(&mut self, input: &mut impl Read) -> io::Result<()> {
+        fn read_u16(input: &mut impl Read) -> io::Result<u16> {
+            let mut bytes = [0; 2];
+            input.read_exact(&mut bytes)?;
+            Ok(u16::from_le_bytes(bytes))
+        }
+        fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+            let mut bytes = [0; 8];
+            input.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        }
+        fn read_u8(input: &mut impl Read) -> io::Result<u8> {
+            let mut byte = [0];
+            input.read_exact(&mut byte)?;
+            Ok(byte[0])
+        }
+
+        self.opcode = read_u16(input)?;
+        input.read_exact(&mut self.memory)?;
+        input.read_exact(&mut self.v)?;
+        self.i = read_u16(input)?;
+        self.pc = read_u16(input)?;
+        let mut row = [0; 64];
+        for plane in &mut self.gfx {
+            input.read_exact(&mut row)?;
+            *plane = row.iter().fold(0, |plane, &pixel| plane << 1 | u64::from(pixel != 0));
+        }
+        self.delay_timer = read_u8(input)?;
+        self.sound_timer = read_u8(input)?;
+        for address in &mut self.stack {
+            *address = read_u16(input)?;
+        }
+        self.sp = read_u8(input)?;
+        // a stack pointer past the top would panic on the next call or
+        // return, and a program counter or I past the end of memory would
+        // overflow when stepped on from, so a state with one is as good as
+        // corrupt
+        let error = if self.sp as usize >= self.stack.len() {
+            Some(format!("stack pointer {} is past the top of the stack", self.sp))
+        } else if self.pc as usize >= self.memory.len() {
+            Some(format!("program counter {:#06X} is past the end of memory", self.pc))
+        } else if self.i as usize >= self.memory.len() {
+            Some(format!("I {:#06X} is past the end of memory", self.i))
+        } else {
+            None
+        };
+        if let Some(error) = error {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+        }
+        self.waiting_for_key = read_u8(input)? != 0;
+        input.read_exact(&mut self.audio_pattern)?;
+        self.pitch = read_u8(input)?;
+        self.xo_audio = read_u8(input)? != 0;
+        self.frames = read_u64(input)?;
+        self.cycles = read_u64(input)?;
+        self.fault = None;
+        self.screen_updated = true;
 
         Ok(())
     }
 
     // This is the main cycle that consists of three phases
-    // Fetch, Decode, and Execute
-    // is also responsible for updating timers!!
+    // Fetch, Decode, and Execute. Once the machine has come to an
+    // instruction it doesn't know it stays put
     pub fn emulate_cycle(&mut self){
-        // Fetch opcode
-        self.opcode = (self.memory[self.pc as usize] as u16) << 8
-            | self.memory[(self.pc + 1) as usize] as u16;
+        if self.fault.is_some() {
+            return;
+        }
+        // Fetch opcode, running off the end of memory wraps around to
+        // the start like addresses do
+        self.pc %= self.memory.len() as u16;
+        self.opcode = self.next_opcode();
+        self.cycles += 1;
 
         // Decode opcode is done with the match
         // Execute opcode
-        match self.execute_opcode() {
-            Ok(()) => {
-                // update timers
-                if self.delay_timer > 0 {
-                    self.delay_timer -= 1;
-                }
-                if self.sound_timer > 0 {
-                    self.sound_timer -= 1;
-                }
-            },
-            Err(e) => panic!("{}", e),
+        let address = self.pc;
+        if let Err(fault) = self.execute_opcode() {
+            self.fault = Some(fault);
+        } else if self.checking {
+            self.fault = self.broken_invariant(address).map(|invariant| Fault {
+                opcode: self.opcode,
+                address,
+                kind: FaultKind::BrokenInvariant(invariant),
+            });
         }
+    }
 
+    // Stops the machine as soon as an instruction breaks one of the
+    // invariants, instead of carrying on until whatever it broke shows up
+    // somewhere else.
+    pub fn check_invariants(&mut self) {
+        self.checking = true;
+    }
+
+    // The first invariant the instruction that was at `address` broke, if
+    // it broke any.
+    fn broken_invariant(&self, address: u16) -> Option<Invariant> {
+        let jumped = matches!(self.opcode >> 12, 0x1 | 0x2 | 0xB) || self.opcode == 0x00EE;
+        if self.pc as usize >= self.memory.len() {
+            Some(Invariant::PcOutOfMemory(self.pc))
+        } else if !jumped && self.pc % 2 != address % 2 {
+            Some(Invariant::PcMisaligned(self.pc))
+        } else if self.sp as usize >= self.stack.len() {
+            Some(Invariant::StackPointer(self.sp))
+        } else if self.i as usize > self.memory.len() {
+            Some(Invariant::Index(self.i))
+        } else {
+            None
+        }
+    }
+
+    // Runs one 60th of a second: `cycles` instructions, then a timer tick.
+    // The timers tick whether or not the instructions ran, so a rom waiting
+    // on FX0A still stops beeping on time.
+    pub fn run_frame(&mut self, cycles: u32) {
+        for ran in 1..=cycles {
+            self.emulate_cycle();
+            // nothing but a key press can change the machine now and keys
+            // only change between frames
+            if self.waiting_for_key() || self.fault.is_some() {
+                break;
+            }
+            // a finished rom would only jump to itself for the rest of the
+            // frame, so those instructions are counted without running them
+            if self.halted() && self.opcode == self.next_opcode() {
+                self.cycles += u64::from(cycles - ran);
+                break;
+            }
+        }
+        self.tick_timers();
+    }
+
+    // Runs frame after frame at `speed`, each with a timer tick, until
+    // `duration` has gone by, for running faster than the rom would in real
+    // time. The clock is only looked at between frames, so it's overrun by a
+    // frame at most. Returns how many frames ran.
+    pub fn run_for(&mut self, duration: Duration, speed: Speed) -> u64 {
+        let start = Instant::now();
+        let frames = self.frames;
+        loop {
+            self.run_frame(speed.cycles_in(self.frames));
+            // a stopped machine would only be ticking its timers
+            if self.fault.is_some() || start.elapsed() >= duration {
+                return self.frames - frames;
+            }
+        }
+    }
+
+    // Counts the timers down, to be called 60 times a second however many
+    // cycles run in between.
+    pub fn tick_timers(&mut self) {
+        self.frames += 1;
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
     }
 
     // use the vf register to check whether the scene has been updated
@@ -157,15 +477,200 @@ impl Chip8 {
         false
     }
 
+    // Whether the pixel at x, y is lit.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.gfx[y] >> (63 - x) & 1 != 0
+    }
+
+    // The display a byte to a pixel, 1 for lit, for frontends to draw.
+    pub fn pixels(&self) -> [[u8; 64]; 32] {
+        let mut pixels = [[0; 64]; 32];
+        for (row, plane) in pixels.iter_mut().zip(&self.gfx) {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = (plane >> (63 - x)) as u8 & 1;
+            }
+        }
+        pixels
+    }
+
+    // true while an FX0A instruction is blocking for a key press, nothing
+    // but input can change the machine until it's released
+    pub fn waiting_for_key(&self) -> bool {
+        self.waiting_for_key
+    }
+
+    // true once the rom jumps to itself, which is how roms stop when they're
+    // done. Nothing but the timers can change from then on
+    pub fn halted(&self) -> bool {
+        let opcode = self.next_opcode();
+        opcode & 0xF000 == 0x1000 && opcode & 0x0FFF == self.pc
+    }
+
+    pub fn state(&self) -> State {
+        match self.fault {
+            Some(fault) => State::Stopped(fault),
+            None if self.waiting_for_key => State::WaitingForKey,
+            None if self.halted() => State::Finished,
+            None => State::Running,
+        }
+    }
+
+    // the buzzer sounds for as long as the sound timer is counting down
+    pub fn sound_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    // the XO-CHIP sample to play while the buzzer sounds, if the rom has
+    // loaded one
+    pub fn audio_pattern(&self) -> Option<&[u8; 16]> {
+        if self.xo_audio {
+            Some(&self.audio_pattern)
+        } else {
+            None
+        }
+    }
+
+    // the rate in bits per second the audio pattern is played back at,
+    // 4000hz at the default pitch of 64
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
     // Sets the keys for the
     pub fn set_keys(&mut self, keys: &[u8; 16]) {
         self.keys.copy_from_slice(keys);
     }
 
+    // Frames run since power on, counted by timer ticks.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // Instructions executed since power on.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // Where the next instruction is read from.
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    // The instruction the machine stopped on, if it came to one it doesn't
+    // know.
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    // The instruction that runs next.
+    pub fn next_opcode(&self) -> u16 {
+        (self.peek(self.pc) as u16) << 8 | self.peek(self.pc + 1) as u16
+    }
+
+    // Reads a byte of memory, for watching what the rom is up to.
+    pub fn peek(&self, address: u16) -> u8 {
+        self.memory[address as usize % self.memory.len()]
+    }
+
+    // Writes a byte of memory, for cheats and scripts.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        let len = self.memory.len();
+        self.memory[address as usize % len] = value;
+    }
+
+    // Starts keeping the rom's writes to memory, for take_writes to hand
+    // over.
+    pub fn watch_writes(&mut self) {
+        self.writes.get_or_insert_with(Vec::new);
+    }
+
+    // The addresses and values the rom wrote to memory since last time, in
+    // the order it wrote them, if they're being watched.
+    pub fn take_writes(&mut self) -> Vec<(u16, u8)> {
+        self.writes.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    pub fn keys(&self) -> &[u8; 16] {
+        &self.keys
+    }
+
+    // The V registers, for debuggers.
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.v
+    }
+
+    // The index register.
+    pub fn index(&self) -> u16 {
+        self.i
+    }
+
+    // The return addresses of the subroutines being run, innermost last.
+    pub fn stack(&self) -> &[u16] {
+        // calls start filling the stack from 1
+        &self.stack[1..=self.sp as usize]
+    }
+
+    // The delay and sound timers.
+    pub fn timers(&self) -> (u8, u8) {
+        (self.delay_timer, self.sound_timer)
+    }
+
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.v[x % 16] = value;
+    }
+
+    pub fn set_index(&mut self, index: u16) {
+        self.i = index;
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc % self.memory.len() as u16;
+    }
+
+    pub fn set_timers(&mut self, delay: u8, sound: u8) {
+        self.delay_timer = delay;
+        self.sound_timer = sound;
+    }
+
+    // Changes the quirks, from the next instruction on.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    // Wires a peripheral to the machine, to answer FX instructions the
+    // machine doesn't know itself.
+    pub fn attach(&mut self, peripheral: Box<dyn Peripheral>) {
+        self.peripherals.push(peripheral);
+    }
+
+    // Turns this machine into `other`, like a save state or a fresh machine
+    // for another rom, keeping this one's peripherals attached, its writes
+    // watched and its invariants checked.
+    pub fn replace_with(&mut self, other: Chip8) {
+        let peripherals = std::mem::take(&mut self.peripherals);
+        *self = Chip8 {
+            peripherals,
+            writes: self.writes.take(),
+            checking: self.checking,
+            ..other
+        };
+    }
+
+    // A copy of the machine without its peripherals or watched writes, to
+    // show somewhere else while this one carries on.
+    pub fn snapshot(&self) -> Chip8 {
+        Chip8 {
+            rng: self.rng.clone(),
+            peripherals: Vec::new(),
+            writes: None,
+            ..*self
+        }
+    }
+
     // finds the appropriate opcode function to call
     // and executes it.
     // updates the program counter
-    fn execute_opcode(&mut self) -> Result<(), &str> {
+    fn execute_opcode(&mut self) -> Result<(), Fault> {
         // pull out the last three parts of the opcode into an array
         // this will be passed to the opcode functions to reduce
         // code duplication
@@ -180,72 +685,97 @@ impl Chip8 {
         let nn: u8 = (self.opcode & 0x00FF) as u8;
         let nnn: u16 = self.opcode & 0x0FFF;
 
-        match self.opcode & 0xF000 {
-            0x0000 => match self.opcode & 0x00FF {
-                // clear screen
-                0x00E0 => {
-                    self.gfx = [[0; 64]; 32];
-                    self.screen_updated = true;
-                    self.pc += 2;
-                    Ok(())
-                }
-                0x00EE => Ok(self.return_subroutine()),
-                _ => panic!("opcode decoded an unsupported code: 0x{:02x}!", self.opcode),
-            },
+        match OPS[self.opcode as usize] {
+            // clear screen
+            Op::ClearScreen => {
+                self.gfx = [0; 32];
+                self.screen_updated = true;
+                self.pc += 2;
+            }
+            Op::Return => self.return_subroutine(),
             // jump to address NNN
-            0x1000 => {
-                let new_addr = self.opcode & 0x0FFF;
-                self.pc = new_addr;
-                Ok(())
+            Op::Jump => self.pc = nnn,
+            Op::Call => self.call_subroutine_at_nnn(&nnn)?,
+            Op::SkipIfEqual => self.skip_if_vx_equals_nn(&x, &nn),
+            Op::SkipIfNotEqual => self.skip_if_vx_not_equal_nn(&x, &nn),
+            Op::SkipIfRegistersEqual => self.skip_if_vx_equals_vy(&x, &y),
+            Op::Load => self.vx_equals_nn(&x, &nn),
+            Op::Add => self.vx_plus_equals_nn(&x, &nn),
+            Op::Assign => self.vx_assign_vy(&x, &y),
+            Op::Or => self.vx_assign_or_vy(&x, &y),
+            Op::And => self.vx_assign_and_vy(&x, &y),
+            Op::Xor => self.vx_assign_xor_vy(&x, &y),
+            Op::AddRegisters => self.vx_assign_plus_vy(&x, &y),
+            Op::Subtract => self.vx_assign_minus_vy(&x, &y),
+            Op::ShiftRight => {
+                self.load_shift_operand(&x, &y);
+                self.vx_assign_rshift(&x);
+            }
+            Op::SubtractFrom => self.vx_assign_vy_minus_vx(&x, &y),
+            Op::ShiftLeft => {
+                self.load_shift_operand(&x, &y);
+                self.vx_assign_lshift(&x);
             }
-            0x2000 => Ok(self.call_subroutine_at_nnn(&nnn)),
-            0x3000 => Ok(self.skip_if_vx_equals_nn(&x, &nn)),
-            0x4000 => Ok(self.skip_if_vx_not_equal_nn(&x, &nn)),
-            0x5000 => Ok(self.skip_if_vx_equals_vy(&x, &y)),
-            0x6000 => Ok(self.vx_equals_nn(&x, &nn)),
-            0x7000 => Ok(self.vx_plus_equals_nn(&x, &nn)),
-            0x8000 => match self.opcode & 0x000f {
-                0x0000 => Ok(self.vx_assign_vy(&x, &y)),
-                0x0001 => Ok(self.vx_assign_or_vy(&x, &y)),
-                0x0002 => Ok(self.vx_assign_and_vy(&x, &y)),
-                0x0003 => Ok(self.vx_assign_xor_vy(&x, &y)),
-                0x0004 => Ok(self.vx_assign_plus_vy(&x, &y)),
-                0x0005 => Ok(self.vx_assign_minus_vy(&x, &y)),
-                0x0006 => Ok(self.vx_assign_rshift(&x)),
-                0x0007 => Ok(self.vx_assign_vy_minus_vx(&x, &y)),
-                0x000e => Ok(self.vx_assign_lshift(&x)),
-                _ => panic!("opcode decoded an unsupported code: {}!", self.opcode),
-            },
-            0x9000 => Ok(self.skip_if_vx_not_equal_vy()),
+            Op::SkipIfRegistersNotEqual => self.skip_if_vx_not_equal_vy(),
             // set i to addr nnn
-            0xa000 => {
+            Op::LoadIndex => {
                 self.i = nnn;
                 self.pc += 2;
-                Ok(())
             }
-            // pc = v0 + nnn
-            0xb000 => Ok(self.pc = self.v[0] as u16 + nnn),
-            0xc000 => Ok(self.vx_equals_rand(&x, &nn)),
-            0xd000 => Ok(self.draw(&x, &y, &n)),
-            0xe000 => match self.opcode & 0x000f {
-                0x000e => Ok(self.skip_if_key_pressed(&x)),
-                0x0001 => Ok(self.skip_if_key_not_pressed(&x)),
-                _ => panic!("opcode decoded an unsupported code: 0x{:02x}!", self.opcode),
-            },
-            0xf000 => match self.opcode & 0x00ff {
-                0x0007 => Ok(self.vx_assign_delay(&x)),
-                0x000a => Ok(self.vx_assign_key(&x)),
-                0x0015 => Ok(self.set_delay_timer(&x)),
-                0x0018 => Ok(self.set_sound_timer(&x)),
-                0x001e => Ok(self.index_assign_plus_vx(&x)),
-                0x0029 => Ok(self.index_assign_sprite(&x)),
-                0x0033 => Ok(self.set_bcd(&x)),
-                0x0055 => Ok(self.reg_dump(&x)),
-                0x0065 => Ok(self.reg_load(&x)),
-                _ => panic!("opcode decoded an unsupported code: 0x{:02x}!", self.opcode),
-            },
-            _ => panic!("opcode decoded an unsupported code: 0x{:02x}!", self.opcode),
+            // pc = v0 + nnn, or vx + nnn with the jumping quirk
+            Op::JumpWithOffset => {
+                let offset = if self.quirks.jumping { self.v[x as usize] } else { self.v[0] };
+                self.pc = offset as u16 + nnn;
+            }
+            Op::Random => self.vx_equals_rand(&x, &nn),
+            Op::Draw => self.draw(&x, &y, &n),
+            Op::SkipIfKey => self.skip_if_key_pressed(&x),
+            Op::SkipIfNotKey => self.skip_if_key_not_pressed(&x),
+            Op::LoadAudioPattern => self.load_audio_pattern(),
+            Op::LoadDelay => self.vx_assign_delay(&x),
+            Op::WaitForKey => self.vx_assign_key(&x),
+            Op::SetDelay => self.set_delay_timer(&x),
+            Op::SetSound => self.set_sound_timer(&x),
+            Op::AddIndex => self.index_assign_plus_vx(&x),
+            Op::Font => self.index_assign_sprite(&x),
+            Op::Bcd => self.set_bcd(&x),
+            Op::Store => self.reg_dump(&x),
+            Op::Restore => self.reg_load(&x),
+            Op::SetPitch => self.set_pitch(&x),
+            Op::Peripheral if self.run_peripheral(x as usize, nn) => self.pc += 2,
+            Op::Peripheral | Op::Unknown => {
+                return Err(self.fault_with(FaultKind::UnknownOpcode))
+            }
         }
+
+        Ok(())
+    }
+
+    fn fault_with(&self, kind: FaultKind) -> Fault {
+        Fault {
+            opcode: self.opcode,
+            address: self.pc,
+            kind,
+        }
+    }
+
+    // Writes memory for the rom, keeping the write if writes are watched.
+    // Addresses past the end wrap around to the start.
+    #[inline]
+    fn write(&mut self, address: u16, value: u8) {
+        let address = address as usize % self.memory.len();
+        self.memory[address] = value;
+        if let Some(writes) = &mut self.writes {
+            writes.push((address as u16, value));
+        }
+    }
+
+    // Offers FXNN to the peripherals, returning whether one of them ran it.
+    fn run_peripheral(&mut self, x: usize, nn: u8) -> bool {
+        let v = &mut self.v;
+        self.peripherals
+            .iter_mut()
+            .any(|peripheral| peripheral.execute(x, nn, v))
     }
 
     // returns from the subroutine
@@ -261,10 +791,14 @@ impl Chip8 {
 
     // call the subroutine at the memory address nnn in opcode
     #[inline]
-    fn call_subroutine_at_nnn(&mut self, nnn: &u16) {
+    fn call_subroutine_at_nnn(&mut self, nnn: &u16) -> Result<(), Fault> {
+        if self.sp as usize + 1 >= self.stack.len() {
+            return Err(self.fault_with(FaultKind::StackOverflow));
+        }
         self.sp += 1;
         self.stack[self.sp as usize] = self.pc + 2;
         self.pc = *nnn;
+        Ok(())
     }
 
     // skip the next instruction if Vx == NN
@@ -328,6 +862,9 @@ impl Chip8 {
     #[inline]
     fn vx_assign_or_vy(&mut self, x: &u8, y: &u8) {
         self.v[*x as usize] |= self.v[*y as usize];
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
         self.pc += 2;
     }
 
@@ -335,6 +872,9 @@ impl Chip8 {
     #[inline]
     fn vx_assign_and_vy(&mut self, x: &u8, y: &u8) {
         self.v[*x as usize] &= self.v[*y as usize];
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
         self.pc += 2;
     }
 
@@ -342,77 +882,63 @@ impl Chip8 {
     #[inline]
     fn vx_assign_xor_vy(&mut self, x: &u8, y: &u8) {
         self.v[*x as usize] ^= self.v[*y as usize];
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
         self.pc += 2;
     }
 
-    // vx += vy
+    // Writes the result of 8XY4 to 8XYE to vx, then their flag to vf. When
+    // vx is vf the flag wins, and both are worked out from the operands
+    // before either is written, so vy being vf doesn't change the result.
     #[inline]
-    fn vx_assign_plus_vy(&mut self, x: &u8, y: &u8) {
-        if self.v[*x as usize] > (255 - self.v[*y as usize]) {
-            self.v[0xF] = 1; // carry
-            self.v[*x as usize] =
-                ((self.v[*y as usize] as u16 + self.v[*x as usize] as u16) - 256) as u8;
-        } else {
-            self.v[0xF] = 0;
-            self.v[*x as usize] += self.v[*y as usize];
-        }
-
+    fn set_vx_and_flag(&mut self, x: &u8, result: u8, flag: bool) {
+        self.v[*x as usize] = result;
+        self.v[0xF] = flag as u8;
         self.pc += 2;
     }
 
-    // vx -= vy
+    // vx += vy, vf = carry
     #[inline]
-    fn vx_assign_minus_vy(&mut self, x: &u8, y: &u8) {
-        let vx = self.v[*x as usize];
-        let vy = self.v[*y as usize];
-        if vx < vy {
-            self.v[0xF] = 0;
-            self.v[*x as usize] =
-                ((self.v[*x as usize] as u16 + 256) - self.v[*y as usize] as u16) as u8;
-        } else {
-            self.v[0xF] = 1;
-            self.v[*x as usize] -= self.v[*y as usize];
-        }
+    fn vx_assign_plus_vy(&mut self, x: &u8, y: &u8) {
+        let (sum, carry) = self.v[*x as usize].overflowing_add(self.v[*y as usize]);
+        self.set_vx_and_flag(x, sum, carry);
+    }
 
-        //panic!("{} - {} = {}", vx, vy, self.v[*x as usize]);
+    // vx -= vy, vf = not borrow
+    #[inline]
+    fn vx_assign_minus_vy(&mut self, x: &u8, y: &u8) {
+        let (difference, borrow) = self.v[*x as usize].overflowing_sub(self.v[*y as usize]);
+        self.set_vx_and_flag(x, difference, !borrow);
+    }
 
-        self.pc += 2;
+    // without the shifting quirk 8XY6 and 8XYE shift vy into vx
+    #[inline]
+    fn load_shift_operand(&mut self, x: &u8, y: &u8) {
+        if !self.quirks.shifting {
+            self.v[*x as usize] = self.v[*y as usize];
+        }
     }
 
-    // vx >>= 1
+    // vx >>= 1, vf = the bit shifted out
     #[inline]
     fn vx_assign_rshift(&mut self, x: &u8) {
-        self.v[0xF] = self.v[*x as usize] & 1;
-
-        self.v[*x as usize] >>= 1;
-        self.pc += 2;
+        let vx = self.v[*x as usize];
+        self.set_vx_and_flag(x, vx >> 1, vx & 1 != 0);
     }
 
-    // vx = vy - vx
+    // vx = vy - vx, vf = not borrow
     #[inline]
     fn vx_assign_vy_minus_vx(&mut self, x: &u8, y: &u8) {
-        let vx = self.v[*x as usize];
-        let vy = self.v[*y as usize];
-        if vy < vx {
-            self.v[0xF] = 0;
-            self.v[*x as usize] =
-                ((self.v[*y as usize] as u16 + 256) - self.v[*x as usize] as u16) as u8;
-        } else {
-            self.v[0xF] = 1;
-            self.v[*x as usize] = self.v[*y as usize] - self.v[*x as usize];
-        }
-
-        //panic!("{} - {} = {}", vx, vy, self.v[*x as usize]);
-
-        self.pc += 2;
+        let (difference, borrow) = self.v[*y as usize].overflowing_sub(self.v[*x as usize]);
+        self.set_vx_and_flag(x, difference, !borrow);
     }
 
-    // vx <<= 1
+    // vx <<= 1, vf = the bit shifted out
     #[inline]
     fn vx_assign_lshift(&mut self, x: &u8) {
-        self.v[0xF] = self.v[*x as usize] >> 7;
-        self.v[*x as usize] <<= 1;
-        self.pc += 2;
+        let vx = self.v[*x as usize];
+        self.set_vx_and_flag(x, vx << 1, vx >> 7 != 0);
     }
 
     // if (vx != vy)
@@ -429,7 +955,7 @@ impl Chip8 {
     // vx = rand() & nn
     #[inline]
     fn vx_equals_rand(&mut self, x: &u8, nn: &u8) {
-        let r = rand::thread_rng().gen_range(0..=255);
+        let r = self.rng.gen_range(0..=255);
         self.v[*x as usize] = r & *nn;
         self.pc += 2;
     }
@@ -445,21 +971,36 @@ impl Chip8 {
         // we will update this to 1 if the sprite goes off screen
         self.v[0xF] = 0;
 
+        // with the clipping quirk the sprite's position wraps around but
+        // whatever runs off the edge is cut off
+        let (start_x, start_y) = if self.quirks.clipping {
+            (self.v[*x as usize] as u16 % 64, self.v[*y as usize] as u16 % 32)
+        } else {
+            (self.v[*x as usize] as u16, self.v[*y as usize] as u16)
+        };
+
         // Update gfx
         for row in 0..*n {
+            if self.quirks.clipping && start_y + row as u16 >= 32 {
+                break;
+            }
             // dont go off the screen vertically
-            let vy = (self.v[*y as usize] as u16 + row as u16) % 32;
+            let vy = (start_y + row as u16) % 32;
             // grab the sprite from I!
-            let sprite = self.memory[(self.i + row as u16) as usize];
-
-            // Update each pixel
-            for pixel in 0..8 {
-                let vx = (self.v[*x as usize] as u16 + pixel as u16) % 64;
-                // dont go off the screen horizontally
-                let color = (sprite >> (7 - pixel)) & 1;
-                self.v[0xF] |= color & self.gfx[vy as usize][vx as usize];
-                self.gfx[vy as usize][vx as usize] ^= color;
+            let sprite = (self.peek(self.i.wrapping_add(row as u16)) as u64) << 56;
+
+            // line the sprite up with the row, what runs off the right edge
+            // wrapping around to the left or cut off
+            let line = if self.quirks.clipping {
+                sprite >> start_x
+            } else {
+                sprite.rotate_right(start_x as u32 % 64)
+            };
+            let plane = &mut self.gfx[vy as usize];
+            if *plane & line != 0 {
+                self.v[0xF] = 1;
             }
+            *plane ^= line;
         }
 
         // set the draw flags to true so this gets rendered!
@@ -470,7 +1011,7 @@ impl Chip8 {
     // if (key() == vx)
     #[inline]
     fn skip_if_key_pressed(&mut self, x: &u8) {
-        if self.keys[self.v[*x as usize] as usize] != 0 {
+        if self.keys[self.v[*x as usize] as usize & 0xF] != 0 {
             self.pc += 2;
         }
         self.pc += 2;
@@ -479,7 +1020,7 @@ impl Chip8 {
     // if (key() != vx)
     #[inline]
     fn skip_if_key_not_pressed(&mut self, x: &u8) {
-        if self.keys[self.v[*x as usize] as usize] == 0 {
+        if self.keys[self.v[*x as usize] as usize & 0xF] == 0 {
             self.pc += 2;
         }
         self.pc += 2;
@@ -495,9 +1036,10 @@ impl Chip8 {
     // vx = get_key()
     #[inline]
     fn vx_assign_key(&mut self, x: &u8) {
-        if self.keys.contains(&255) {
+        self.waiting_for_key = !self.keys.contains(&255);
+        if !self.waiting_for_key {
             for (i, key) in self.keys.iter().enumerate() {
-                if *key != 0 as u8 {
+                if *key != 0 {
                     self.v[*x as usize] = i as u8;
                     break;
                 }
@@ -522,7 +1064,7 @@ impl Chip8 {
 
     #[inline]
     fn index_assign_plus_vx(&mut self, x: &u8) {
-        self.i += self.v[*x as usize] as u16;
+        self.i = self.i.wrapping_add(self.v[*x as usize] as u16);
         self.pc += 2;
     }
 
@@ -535,9 +1077,10 @@ impl Chip8 {
 
     #[inline]
     fn set_bcd(&mut self, x: &u8) {
-        self.memory[self.i as usize] = self.v[*x as usize] / 100;
-        self.memory[self.i as usize + 1] = (self.v[*x as usize] % 100) / 10;
-        self.memory[self.i as usize + 2] = self.v[*x as usize] % 10;
+        let vx = self.v[*x as usize];
+        self.write(self.i, vx / 100);
+        self.write(self.i.wrapping_add(1), (vx % 100) / 10);
+        self.write(self.i.wrapping_add(2), vx % 10);
 
         self.pc += 2;
     }
@@ -545,25 +1088,166 @@ impl Chip8 {
     #[inline]
     fn reg_dump(&mut self, x: &u8) {
         for reg in 0..=*x {
-            self.memory[self.i as usize + reg as usize] = self.v[reg as usize];
+            self.write(self.i.wrapping_add(reg as u16), self.v[reg as usize]);
+        }
+        if self.quirks.memory {
+            self.i = self.i.wrapping_add(*x as u16 + 1);
         }
 
         self.pc += 2;
     }
 
+    // load the 16 byte audio pattern from I
+    // 0xF002
+    #[inline]
+    fn load_audio_pattern(&mut self) {
+        for (offset, byte) in self.audio_pattern.iter_mut().enumerate() {
+            *byte = self.memory[(self.i as usize + offset) % self.memory.len()];
+        }
+        self.xo_audio = true;
+        self.pc += 2;
+    }
+
+    // pitch = vx
+    // 0xFX3A
+    #[inline]
+    fn set_pitch(&mut self, x: &u8) {
+        self.pitch = self.v[*x as usize];
+        self.pc += 2;
+    }
+
     #[inline]
     fn reg_load(&mut self, x: &u8) {
         for reg in 0..=*x {
-            self.v[reg as usize] = self.memory[self.i as usize + reg as usize];
+            self.v[reg as usize] = self.peek(self.i.wrapping_add(reg as u16));
+        }
+        if self.quirks.memory {
+            self.i = self.i.wrapping_add(*x as u16 + 1);
         }
 
         self.pc += 2;
     }
 }
 
+// The instructions, as told apart by their opcodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    ClearScreen,
+    Return,
+    Jump,
+    Call,
+    SkipIfEqual,
+    SkipIfNotEqual,
+    SkipIfRegistersEqual,
+    Load,
+    Add,
+    Assign,
+    Or,
+    And,
+    Xor,
+    AddRegisters,
+    Subtract,
+    ShiftRight,
+    SubtractFrom,
+    ShiftLeft,
+    SkipIfRegistersNotEqual,
+    LoadIndex,
+    JumpWithOffset,
+    Random,
+    Draw,
+    SkipIfKey,
+    SkipIfNotKey,
+    LoadAudioPattern,
+    LoadDelay,
+    WaitForKey,
+    SetDelay,
+    SetSound,
+    AddIndex,
+    Font,
+    Bcd,
+    Store,
+    Restore,
+    SetPitch,
+    // an FX instruction the machine doesn't know, which a peripheral might
+    Peripheral,
+    Unknown,
+}
+
+// Which instruction every opcode is, worked out once when the emulator's
+// built, so running one is a lookup and a single jump instead of a match on
+// the top nibble and another on the low bits.
+static OPS: [Op; 0x10000] = {
+    let mut ops = [Op::Unknown; 0x10000];
+    let mut opcode = 0;
+    while opcode < ops.len() {
+        ops[opcode] = decode(opcode as u16);
+        opcode += 1;
+    }
+    ops
+};
+
+const fn decode(opcode: u16) -> Op {
+    let x = (opcode & 0x0F00) >> 8;
+    match opcode & 0xF000 {
+        0x0000 => match opcode & 0x00FF {
+            0x00E0 => Op::ClearScreen,
+            0x00EE => Op::Return,
+            _ => Op::Unknown,
+        },
+        0x1000 => Op::Jump,
+        0x2000 => Op::Call,
+        0x3000 => Op::SkipIfEqual,
+        0x4000 => Op::SkipIfNotEqual,
+        0x5000 => Op::SkipIfRegistersEqual,
+        0x6000 => Op::Load,
+        0x7000 => Op::Add,
+        0x8000 => match opcode & 0x000F {
+            0x0000 => Op::Assign,
+            0x0001 => Op::Or,
+            0x0002 => Op::And,
+            0x0003 => Op::Xor,
+            0x0004 => Op::AddRegisters,
+            0x0005 => Op::Subtract,
+            0x0006 => Op::ShiftRight,
+            0x0007 => Op::SubtractFrom,
+            0x000E => Op::ShiftLeft,
+            _ => Op::Unknown,
+        },
+        0x9000 => Op::SkipIfRegistersNotEqual,
+        0xA000 => Op::LoadIndex,
+        0xB000 => Op::JumpWithOffset,
+        0xC000 => Op::Random,
+        0xD000 => Op::Draw,
+        0xE000 => match opcode & 0x000F {
+            0x000E => Op::SkipIfKey,
+            0x0001 => Op::SkipIfNotKey,
+            _ => Op::Unknown,
+        },
+        _ => match opcode & 0x00FF {
+            0x0002 if x == 0 => Op::LoadAudioPattern,
+            0x0007 => Op::LoadDelay,
+            0x000A => Op::WaitForKey,
+            0x0015 => Op::SetDelay,
+            0x0018 => Op::SetSound,
+            0x001E => Op::AddIndex,
+            0x0029 => Op::Font,
+            0x0033 => Op::Bcd,
+            0x0055 => Op::Store,
+            0x0065 => Op::Restore,
+            0x003A => Op::SetPitch,
+            _ => Op::Peripheral,
+        },
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::Chip8;
+    use super::{Chip8, Fault, FaultKind, Invariant, Op, State, MAX_ROM, OPS};
+    use std::io;
+    use std::time::Duration;
+    use crate::quirks::Quirks;
+    use crate::speed::Speed;
+    use proptest::prelude::*;
 
     #[test]
     fn return_subroutine_with_empty_stack() {
@@ -634,4 +1318,987 @@ mod tests {
         assert_eq!(cpu.v[1], 2);
         assert_eq!(cpu.v[2], 3);
     }
+
+    #[test]
+    fn reset_keeps_memory() {
+        let mut cpu = Chip8::default();
+        cpu.memory[0x200] = 0x12;
+        cpu.pc = 0x345;
+        cpu.v[7] = 9;
+        cpu.sp = 2;
+        cpu.gfx[3] = 1 << 59;
+
+        cpu.reset();
+        assert_eq!(cpu.memory[0x200], 0x12);
+        assert_eq!(cpu.pc, 0x200);
+        assert_eq!(cpu.v[7], 0);
+        assert_eq!(cpu.sp, 0);
+        assert!(!cpu.pixel(4, 3));
+        assert!(cpu.draw_flag());
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let mut cpu = Chip8::default();
+        // a frame of jumping on the spot
+        cpu.memory[0x200] = 0x12;
+        cpu.run_frame(3);
+        cpu.memory[0x300] = 0xAB;
+        cpu.v[5] = 7;
+        cpu.pc = 0x234;
+        cpu.stack[1] = 0x456;
+        cpu.gfx[31] = 1;
+        cpu.sound_timer = 30;
+
+        let mut state = Vec::new();
+        cpu.write_state(&mut state).unwrap();
+        let mut loaded = Chip8::default();
+        loaded.read_state(&mut state.as_slice()).unwrap();
+
+        assert_eq!(loaded.memory[0x300], 0xAB);
+        assert_eq!(loaded.v[5], 7);
+        assert_eq!(loaded.pc, 0x234);
+        assert_eq!(loaded.stack[1], 0x456);
+        assert_eq!(loaded.gfx, cpu.gfx);
+        assert!(loaded.sound_active());
+        assert_eq!((loaded.frames(), loaded.cycles()), (1, 3));
+    }
+
+    #[test]
+    fn states_with_pointers_out_of_bounds_are_invalid() {
+        let mut state = Vec::new();
+        Chip8::default().write_state(&mut state).unwrap();
+        // I and the program counter come after the opcode, memory and
+        // registers, and the stack pointer after them, the display, the
+        // timers and the stack
+        let i = 2 + 4096 + 16;
+        let pc = i + 2;
+        let sp = pc + 2 + 64 * 32 + 2 + 16 * 2;
+        for (offset, bytes) in [(sp, &[200][..]), (pc, &[0xFF, 0xFF]), (i, &[0x00, 0x10])] {
+            let mut corrupt = state.clone();
+            corrupt[offset..offset + bytes.len()].copy_from_slice(bytes);
+            let error = Chip8::default().read_state(&mut corrupt.as_slice()).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        }
+
+        state[sp] = 15;
+        state[pc..pc + 2].copy_from_slice(&[0xFF, 0x0F]);
+        state[i..i + 2].copy_from_slice(&[0xFF, 0x0F]);
+        Chip8::default().read_state(&mut state.as_slice()).unwrap();
+    }
+
+    #[test]
+    fn quirks_change_shifting_and_clipping() {
+        let mut cpu = Chip8::with_quirks(Quirks {
+            shifting: false,
+            clipping: true,
+            ..Quirks::default()
+        });
+        cpu.v[1] = 0b11;
+        cpu.opcode = 0x8016;
+        cpu.execute_opcode().unwrap();
+        assert_eq!(cpu.v[0], 0b1);
+
+        // a full row of pixels drawn 4 from the right edge
+        cpu.memory[0x300] = 0xFF;
+        cpu.i = 0x300;
+        cpu.v[2] = 60;
+        cpu.v[3] = 0;
+        cpu.opcode = 0xD231;
+        cpu.execute_opcode().unwrap();
+        assert_eq!(cpu.gfx[0], 0xF);
+    }
+
+    #[test]
+    fn seeded_random_numbers_repeat() {
+        let rolls = |seed| {
+            let mut cpu = Chip8::default();
+            cpu.seed_rng(seed);
+            (0..8)
+                .map(|_| {
+                    cpu.vx_equals_rand(&0, &0xFF);
+                    cpu.v[0]
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(rolls(7), rolls(7));
+        assert_ne!(rolls(7), rolls(8));
+        // movies play back with these, so they mustn't change with a new
+        // version of rand or anything else
+        assert_eq!(rolls(0), [108, 103, 55, 95, 230, 50, 124, 164]);
+    }
+
+    #[test]
+    fn frames_run_until_the_time_is_up() {
+        let mut cpu = Chip8::default();
+        // loops forever
+        cpu.load_rom(&[0x12, 0x00]).unwrap();
+        let frames = cpu.run_for(Duration::from_millis(5), Speed::default());
+        assert!(frames > 0);
+        assert_eq!(cpu.frames(), frames);
+        assert_eq!(cpu.cycles(), frames * 10);
+
+        // it runs a frame however short the time, and no more once stopped
+        let mut cpu = Chip8::default();
+        cpu.load_rom(&[0xFF, 0xFF]).unwrap();
+        assert_eq!(cpu.run_for(Duration::ZERO, Speed::default()), 1);
+        assert_eq!(cpu.run_for(Duration::from_secs(1), Speed::default()), 1);
+    }
+
+    #[test]
+    fn key_wait_blocks_until_pressed() {
+        let mut cpu = Chip8::default();
+        cpu.vx_assign_key(&3);
+        assert!(cpu.waiting_for_key());
+        assert_eq!(cpu.pc, 0x200);
+
+        let mut keys = [0; 16];
+        keys[0xB] = 255;
+        cpu.set_keys(&keys);
+        cpu.vx_assign_key(&3);
+        assert!(!cpu.waiting_for_key());
+        assert_eq!(cpu.v[3], 0xB);
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn timers_count_down_while_waiting_for_a_key() {
+        let mut cpu = Chip8::default();
+        // LD V0, 10; LD ST, V0; LD DT, V0; LD V1, K
+        cpu.load_rom(&[0x60, 0x0A, 0xF0, 0x18, 0xF0, 0x15, 0xF1, 0x0A]).unwrap();
+        cpu.run_frame(10);
+        assert!(cpu.waiting_for_key());
+        assert_eq!(cpu.timers(), (9, 9));
+
+        for _ in 0..9 {
+            cpu.run_frame(10);
+        }
+        assert!(cpu.waiting_for_key());
+        assert_eq!(cpu.timers(), (0, 0));
+        assert!(!cpu.sound_active());
+        assert_eq!((cpu.pc(), cpu.frames()), (0x206, 10));
+    }
+
+    #[test]
+    fn audio_pattern_loaded_from_index() {
+        let mut cpu = Chip8::default();
+        assert!(cpu.audio_pattern().is_none());
+
+        cpu.i = 0x300;
+        cpu.memory[0x300] = 0xF0;
+        cpu.memory[0x30F] = 0x0F;
+        cpu.load_audio_pattern();
+        let pattern = cpu.audio_pattern().unwrap();
+        assert_eq!(pattern[0], 0xF0);
+        assert_eq!(pattern[15], 0x0F);
+    }
+
+    #[test]
+    fn pitch_sets_playback_rate() {
+        let mut cpu = Chip8::default();
+        assert_eq!(cpu.playback_rate(), 4000.0);
+
+        cpu.v[1] = 112;
+        cpu.set_pitch(&1);
+        assert_eq!(cpu.playback_rate(), 8000.0);
+    }
+
+    #[test]
+    fn jumping_to_itself_halts() {
+        let mut cpu = Chip8::default();
+        cpu.memory[0x200..0x204].copy_from_slice(&[0x12, 0x02, 0x12, 0x02]);
+        assert!(!cpu.halted());
+        cpu.pc = 0x202;
+        assert!(cpu.halted());
+    }
+
+    #[test]
+    fn unknown_opcodes_stop_the_machine() {
+        let mut cpu = Chip8::default();
+        cpu.memory[0x200..0x204].copy_from_slice(&[0x60, 0x01, 0xFF, 0xFF]);
+        cpu.run_frame(10);
+        let fault = cpu.fault().unwrap();
+        assert_eq!((fault.opcode, fault.address), (0xFFFF, 0x202));
+        assert_eq!((cpu.pc, cpu.cycles()), (0x202, 2));
+
+        cpu.reset();
+        assert_eq!(cpu.fault(), None);
+    }
+
+    #[test]
+    fn stack_lists_return_addresses() {
+        let mut cpu = Chip8::default();
+        assert!(cpu.stack().is_empty());
+        // call 0x204, then 0x206 from there
+        cpu.load_rom(&[0x22, 0x04, 0x00, 0x00, 0x22, 0x06]).unwrap();
+        cpu.emulate_cycle();
+        cpu.emulate_cycle();
+        assert_eq!(cpu.stack(), &[0x202, 0x206]);
+    }
+
+    // Sets up a machine, runs instructions on it one at a time and checks
+    // what they left behind:
+    //
+    //   OpcodeTest::new().set_v(1, 5).set_v(2, 7).opcode(0x8124).expect_v(1, 12)
+    //
+    // Each opcode runs as though it was fetched from pc, which starts at
+    // 0x200, and the expectations check the machine as it is after the
+    // opcodes before them.
+    struct OpcodeTest {
+        cpu: Chip8,
+    }
+
+    impl OpcodeTest {
+        fn new() -> OpcodeTest {
+            OpcodeTest::with_quirks(Quirks::default())
+        }
+
+        fn with_quirks(quirks: Quirks) -> OpcodeTest {
+            let mut cpu = Chip8::with_quirks(quirks);
+            cpu.seed_rng(0);
+            OpcodeTest { cpu }
+        }
+
+        fn set_v(mut self, x: usize, value: u8) -> OpcodeTest {
+            self.cpu.v[x] = value;
+            self
+        }
+
+        fn set_i(mut self, value: u16) -> OpcodeTest {
+            self.cpu.i = value;
+            self
+        }
+
+        fn set_memory(mut self, address: usize, bytes: &[u8]) -> OpcodeTest {
+            self.cpu.memory[address..address + bytes.len()].copy_from_slice(bytes);
+            self
+        }
+
+        fn set_pixel(mut self, x: usize, y: usize) -> OpcodeTest {
+            self.cpu.gfx[y] |= 1 << (63 - x);
+            self
+        }
+
+        fn press(mut self, key: usize) -> OpcodeTest {
+            self.cpu.keys[key] = 255;
+            self
+        }
+
+        fn set_timers(mut self, delay: u8, sound: u8) -> OpcodeTest {
+            self.cpu.set_timers(delay, sound);
+            self
+        }
+
+        #[track_caller]
+        fn opcode(mut self, opcode: u16) -> OpcodeTest {
+            self.cpu.opcode = opcode;
+            if let Err(fault) = self.cpu.execute_opcode() {
+                panic!("{:04X} stopped the machine: {}", opcode, fault);
+            }
+            self
+        }
+
+        #[track_caller]
+        fn expect_fault(mut self, opcode: u16) -> OpcodeTest {
+            let address = self.cpu.pc;
+            self.cpu.opcode = opcode;
+            let kind = FaultKind::UnknownOpcode;
+            assert_eq!(self.cpu.execute_opcode(), Err(Fault { opcode, address, kind }));
+            self
+        }
+
+        #[track_caller]
+        fn expect_v(self, x: usize, value: u8) -> OpcodeTest {
+            assert_eq!(self.cpu.v[x], value, "V{:X}", x);
+            self
+        }
+
+        #[track_caller]
+        fn expect_i(self, value: u16) -> OpcodeTest {
+            assert_eq!(self.cpu.i, value, "I");
+            self
+        }
+
+        #[track_caller]
+        fn expect_pc(self, value: u16) -> OpcodeTest {
+            assert_eq!(self.cpu.pc, value, "pc");
+            self
+        }
+
+        #[track_caller]
+        fn expect_stack(self, stack: &[u16]) -> OpcodeTest {
+            assert_eq!(self.cpu.stack(), stack, "stack");
+            self
+        }
+
+        #[track_caller]
+        fn expect_memory(self, address: usize, bytes: &[u8]) -> OpcodeTest {
+            let memory = &self.cpu.memory[address..address + bytes.len()];
+            assert_eq!(memory, bytes, "memory at {:03X}", address);
+            self
+        }
+
+        #[track_caller]
+        fn expect_pixel(self, x: usize, y: usize, on: bool) -> OpcodeTest {
+            assert_eq!(self.cpu.pixel(x, y), on, "pixel at {}, {}", x, y);
+            self
+        }
+
+        #[track_caller]
+        fn expect_lit(self, count: usize) -> OpcodeTest {
+            let lit: u32 = self.cpu.gfx.iter().map(|plane| plane.count_ones()).sum();
+            assert_eq!(lit as usize, count, "lit pixels");
+            self
+        }
+
+        #[track_caller]
+        fn expect_timers(self, delay: u8, sound: u8) -> OpcodeTest {
+            assert_eq!(
+                (self.cpu.delay_timer, self.cpu.sound_timer),
+                (delay, sound),
+                "timers"
+            );
+            self
+        }
+
+        #[track_caller]
+        fn expect_waiting(self, waiting: bool) -> OpcodeTest {
+            assert_eq!(self.cpu.waiting_for_key(), waiting, "waiting for a key");
+            self
+        }
+    }
+
+    #[test]
+    fn opcodes_clear_the_screen() {
+        OpcodeTest::new()
+            .set_pixel(0, 0)
+            .set_pixel(63, 31)
+            .opcode(0x00E0)
+            .expect_lit(0)
+            .expect_pc(0x202);
+    }
+
+    #[test]
+    fn opcodes_jump_call_and_return() {
+        OpcodeTest::new()
+            .opcode(0x1ABC)
+            .expect_pc(0xABC)
+            .expect_stack(&[]);
+        OpcodeTest::new()
+            .opcode(0x2300)
+            .expect_pc(0x300)
+            .expect_stack(&[0x202])
+            .opcode(0x2400)
+            .expect_stack(&[0x202, 0x302])
+            .opcode(0x00EE)
+            .expect_pc(0x302)
+            .opcode(0x00EE)
+            .expect_pc(0x202)
+            .expect_stack(&[]);
+    }
+
+    #[test]
+    fn opcodes_skip() {
+        // opcode, V1, V2, whether the next instruction's skipped
+        let cases = [
+            (0x3142, 0x42, 0, true),
+            (0x3142, 0x41, 0, false),
+            (0x4142, 0x42, 0, false),
+            (0x4142, 0x41, 0, true),
+            (0x5120, 7, 7, true),
+            (0x5120, 7, 8, false),
+            (0x9120, 7, 7, false),
+            (0x9120, 7, 8, true),
+        ];
+        for (opcode, v1, v2, skipped) in cases {
+            let pc = if skipped { 0x204 } else { 0x202 };
+            OpcodeTest::new()
+                .set_v(1, v1)
+                .set_v(2, v2)
+                .opcode(opcode)
+                .expect_pc(pc);
+        }
+    }
+
+    #[test]
+    fn opcodes_load_and_add_constants() {
+        OpcodeTest::new()
+            .opcode(0x6A42)
+            .expect_v(0xA, 0x42)
+            .expect_pc(0x202);
+        // 7XNN wraps around without touching VF
+        OpcodeTest::new()
+            .set_v(3, 0xF0)
+            .opcode(0x7320)
+            .expect_v(3, 0x10)
+            .expect_v(0xF, 0);
+        OpcodeTest::new().set_v(3, 1).opcode(0x7302).expect_v(3, 3);
+    }
+
+    #[test]
+    fn opcodes_do_arithmetic() {
+        // opcode, V1, V2, V1 after, VF after; VF starts at 0xAA to show
+        // which instructions leave it alone
+        let cases = [
+            (0x8120, 0x12, 0x34, 0x34, 0xAA),
+            (0x8121, 0b1100, 0b1010, 0b1110, 0xAA),
+            (0x8122, 0b1100, 0b1010, 0b1000, 0xAA),
+            (0x8123, 0b1100, 0b1010, 0b0110, 0xAA),
+            (0x8124, 5, 7, 12, 0),
+            (0x8124, 0xFF, 1, 0, 1),
+            (0x8124, 0xF0, 0x20, 0x10, 1),
+            (0x8125, 7, 5, 2, 1),
+            (0x8125, 5, 5, 0, 1),
+            (0x8125, 5, 7, 0xFE, 0),
+            (0x8127, 5, 7, 2, 1),
+            (0x8127, 5, 5, 0, 1),
+            (0x8127, 7, 5, 0xFE, 0),
+            (0x8126, 0b101, 0xFF, 0b10, 1),
+            (0x8126, 0b100, 0xFF, 0b10, 0),
+            (0x812E, 0x81, 0xFF, 0x02, 1),
+            (0x812E, 0x41, 0xFF, 0x82, 0),
+        ];
+        for (opcode, v1, v2, result, vf) in cases {
+            OpcodeTest::new()
+                .set_v(1, v1)
+                .set_v(2, v2)
+                .set_v(0xF, 0xAA)
+                .opcode(opcode)
+                .expect_v(1, result)
+                .expect_v(2, v2)
+                .expect_v(0xF, vf)
+                .expect_pc(0x202);
+        }
+    }
+
+    // What 8XY4, 8XY5, 8XY6, 8XY7 and 8XYE should leave in VX and VF, worked
+    // out from the operands alone.
+    fn alu_model(n: u16, vx: u8, vy: u8, quirks: Quirks) -> (u8, u8) {
+        let shifted = if quirks.shifting { vx } else { vy };
+        match n {
+            0x4 => {
+                let (sum, carry) = vx.overflowing_add(vy);
+                (sum, carry as u8)
+            }
+            0x5 => (vx.wrapping_sub(vy), (vx >= vy) as u8),
+            0x6 => (shifted >> 1, shifted & 1),
+            0x7 => (vy.wrapping_sub(vx), (vy >= vx) as u8),
+            0xE => (shifted << 1, shifted >> 7),
+            _ => unreachable!("8XY{:X} doesn't set a flag", n),
+        }
+    }
+
+    fn alu_ops() -> impl Strategy<Value = u16> {
+        prop::sample::select(vec![0x4, 0x5, 0x6, 0x7, 0xE])
+    }
+
+    proptest! {
+        #[test]
+        fn alu_ops_match_the_model(
+            n in alu_ops(),
+            x in 0..0xFusize,
+            y in 0..0xFusize,
+            vx: u8,
+            vy: u8,
+            shifting: bool,
+        ) {
+            let quirks = Quirks { shifting, ..Quirks::default() };
+            // the same register twice is both operands
+            let vy = if x == y { vx } else { vy };
+            let (result, flag) = alu_model(n, vx, vy, quirks);
+            OpcodeTest::with_quirks(quirks)
+                .set_v(y, vy)
+                .set_v(x, vx)
+                .set_v(0xF, 0xAA)
+                .opcode(0x8000 | (x as u16) << 8 | (y as u16) << 4 | n)
+                .expect_v(x, result)
+                .expect_v(0xF, flag)
+                .expect_pc(0x202);
+        }
+
+        // With VF as VX the flag is written over the result, and as VY it's
+        // read before it's changed.
+        #[test]
+        fn alu_ops_with_vf_as_an_operand_match_the_model(
+            n in alu_ops(),
+            other in 0..=0xFusize,
+            vf_is_x: bool,
+            v: u8,
+            vf: u8,
+            shifting: bool,
+        ) {
+            let quirks = Quirks { shifting, ..Quirks::default() };
+            let v = if other == 0xF { vf } else { v };
+            let (x, y, vx, vy) = if vf_is_x { (0xF, other, vf, v) } else { (other, 0xF, v, vf) };
+            let (result, flag) = alu_model(n, vx, vy, quirks);
+            let test = OpcodeTest::with_quirks(quirks)
+                .set_v(other, v)
+                .set_v(0xF, vf)
+                .opcode(0x8000 | (x as u16) << 8 | (y as u16) << 4 | n)
+                .expect_v(0xF, flag);
+            if x != 0xF {
+                test.expect_v(x, result);
+            }
+        }
+
+        #[test]
+        fn add_constant_wraps_and_leaves_vf_alone(
+            x in 0..=0xFusize,
+            vx: u8,
+            nn: u8,
+            vf: u8,
+        ) {
+            let vf = if x == 0xF { vx.wrapping_add(nn) } else { vf };
+            OpcodeTest::new()
+                .set_v(0xF, vf)
+                .set_v(x, vx)
+                .opcode(0x7000 | (x as u16) << 8 | nn as u16)
+                .expect_v(x, vx.wrapping_add(nn))
+                .expect_v(0xF, vf);
+        }
+    }
+
+    #[test]
+    fn vf_as_an_operand_gets_the_flag_last() {
+        let shifting = Quirks {
+            shifting: false,
+            ..Quirks::default()
+        };
+        // quirks, opcode, V1, VF, V1 after, VF after
+        let cases = [
+            // VF as VX: the flag's written over the result
+            (Quirks::default(), 0x8F14, 0x20, 0xF0, 0x20, 1),
+            (Quirks::default(), 0x8F14, 0x20, 0x10, 0x20, 0),
+            (Quirks::default(), 0x8F15, 0x20, 0x30, 0x20, 1),
+            (Quirks::default(), 0x8F15, 0x20, 0x10, 0x20, 0),
+            (Quirks::default(), 0x8F17, 0x20, 0x10, 0x20, 1),
+            (Quirks::default(), 0x8F17, 0x20, 0x30, 0x20, 0),
+            (Quirks::default(), 0x8F06, 0, 0x03, 0, 1),
+            (Quirks::default(), 0x8F06, 0, 0x02, 0, 0),
+            (Quirks::default(), 0x8F0E, 0, 0x81, 0, 1),
+            (Quirks::default(), 0x8F0E, 0, 0x41, 0, 0),
+            (shifting, 0x8F16, 0x03, 0xAA, 0x03, 1),
+            (shifting, 0x8F1E, 0x41, 0xAA, 0x41, 0),
+            // VF as VY: it's read before the flag's written
+            (Quirks::default(), 0x81F4, 0xF0, 0x20, 0x10, 1),
+            (Quirks::default(), 0x81F5, 0x10, 0x20, 0xF0, 0),
+            (Quirks::default(), 0x81F7, 0x10, 0x20, 0x10, 1),
+            (shifting, 0x81F6, 0xAA, 0x03, 0x01, 1),
+            (shifting, 0x81FE, 0xAA, 0x81, 0x02, 1),
+            // VF as both
+            (Quirks::default(), 0x8FF4, 0, 0x80, 0, 1),
+            (Quirks::default(), 0x8FF5, 0, 0x80, 0, 1),
+            (Quirks::default(), 0x8FF7, 0, 0x80, 0, 1),
+        ];
+        for (quirks, opcode, v1, vf, v1_after, vf_after) in cases {
+            OpcodeTest::with_quirks(quirks)
+                .set_v(1, v1)
+                .set_v(0xF, vf)
+                .opcode(opcode)
+                .expect_v(1, v1_after)
+                .expect_v(0xF, vf_after);
+        }
+    }
+
+    #[test]
+    fn quirks_change_the_logic_ops() {
+        let quirks = Quirks {
+            vf_reset: true,
+            ..Quirks::default()
+        };
+        for (opcode, result) in [(0x8121, 0b1110), (0x8122, 0b1000), (0x8123, 0b0110)] {
+            OpcodeTest::with_quirks(quirks)
+                .set_v(1, 0b1100)
+                .set_v(2, 0b1010)
+                .set_v(0xF, 0xAA)
+                .opcode(opcode)
+                .expect_v(1, result)
+                .expect_v(0xF, 0);
+        }
+    }
+
+    #[test]
+    fn quirks_change_the_shifts() {
+        let quirks = Quirks {
+            shifting: false,
+            ..Quirks::default()
+        };
+        // opcode, V1, V2, V1 after, VF after
+        let cases = [
+            (0x8126, 0xFF, 0b101, 0b10, 1),
+            (0x8126, 0xFF, 0b100, 0b10, 0),
+            (0x812E, 0xFF, 0x81, 0x02, 1),
+            (0x812E, 0xFF, 0x41, 0x82, 0),
+        ];
+        for (opcode, v1, v2, result, vf) in cases {
+            OpcodeTest::with_quirks(quirks)
+                .set_v(1, v1)
+                .set_v(2, v2)
+                .opcode(opcode)
+                .expect_v(1, result)
+                .expect_v(2, v2)
+                .expect_v(0xF, vf);
+        }
+    }
+
+    #[test]
+    fn opcodes_set_and_add_to_the_index() {
+        OpcodeTest::new()
+            .opcode(0xA123)
+            .expect_i(0x123)
+            .expect_pc(0x202);
+        OpcodeTest::new()
+            .set_i(0x123)
+            .set_v(4, 0x10)
+            .opcode(0xF41E)
+            .expect_i(0x133)
+            .expect_v(0xF, 0);
+        // the font's sprites are 5 bytes each from 0, and only VX's low
+        // nibble picks one
+        for (digit, address) in [(0, 0), (0xA, 50), (0xF, 75), (0x1B, 55)] {
+            OpcodeTest::new()
+                .set_v(4, digit)
+                .opcode(0xF429)
+                .expect_i(address);
+        }
+    }
+
+    #[test]
+    fn opcodes_jump_with_an_offset() {
+        let jumping = Quirks {
+            jumping: true,
+            ..Quirks::default()
+        };
+        OpcodeTest::new()
+            .set_v(0, 0x10)
+            .set_v(3, 0x20)
+            .opcode(0xB300)
+            .expect_pc(0x310);
+        OpcodeTest::with_quirks(jumping)
+            .set_v(0, 0x10)
+            .set_v(3, 0x20)
+            .opcode(0xB300)
+            .expect_pc(0x320);
+    }
+
+    #[test]
+    fn opcodes_mask_random_numbers() {
+        OpcodeTest::new()
+            .set_v(5, 0xAA)
+            .opcode(0xC500)
+            .expect_v(5, 0);
+        for _ in 0..32 {
+            let test = OpcodeTest::new().opcode(0xC50F);
+            assert!(test.cpu.v[5] <= 0x0F);
+            test.expect_pc(0x202);
+        }
+    }
+
+    #[test]
+    fn opcodes_draw_sprites() {
+        // a row of alternating pixels
+        let sprite = OpcodeTest::new()
+            .set_memory(0x300, &[0b1010_0000])
+            .set_i(0x300);
+        sprite
+            .set_v(1, 10)
+            .set_v(2, 5)
+            .set_v(0xF, 0xAA)
+            .opcode(0xD121)
+            .expect_pixel(10, 5, true)
+            .expect_pixel(11, 5, false)
+            .expect_pixel(12, 5, true)
+            .expect_lit(2)
+            .expect_v(0xF, 0)
+            .expect_pc(0x202)
+            // drawing it again erases it, which is a collision
+            .opcode(0xD121)
+            .expect_lit(0)
+            .expect_v(0xF, 1);
+
+        // a pixel already on that the sprite doesn't cover isn't one
+        OpcodeTest::new()
+            .set_memory(0x300, &[0b1000_0000])
+            .set_i(0x300)
+            .set_pixel(1, 0)
+            .opcode(0xD001)
+            .expect_lit(2)
+            .expect_v(0xF, 0);
+    }
+
+    #[test]
+    fn quirks_change_drawing_at_the_edges() {
+        let clipping = Quirks {
+            clipping: true,
+            ..Quirks::default()
+        };
+        // a 2x2 square drawn over the bottom right corner
+        let corner = |quirks| {
+            OpcodeTest::with_quirks(quirks)
+                .set_memory(0x300, &[0xC0, 0xC0])
+                .set_i(0x300)
+                .set_v(1, 63)
+                .set_v(2, 31)
+                .opcode(0xD122)
+        };
+        corner(Quirks::default())
+            .expect_pixel(63, 31, true)
+            .expect_pixel(0, 31, true)
+            .expect_pixel(63, 0, true)
+            .expect_pixel(0, 0, true)
+            .expect_lit(4);
+        corner(clipping).expect_pixel(63, 31, true).expect_lit(1);
+
+        // either way a sprite starting off screen wraps to the other side
+        for quirks in [Quirks::default(), clipping] {
+            OpcodeTest::with_quirks(quirks)
+                .set_memory(0x300, &[0x80])
+                .set_i(0x300)
+                .set_v(1, 64 + 3)
+                .set_v(2, 32 + 2)
+                .opcode(0xD121)
+                .expect_pixel(3, 2, true)
+                .expect_lit(1);
+        }
+    }
+
+    #[test]
+    fn opcodes_read_the_keys() {
+        // opcode, key pressed, whether the next instruction's skipped
+        let cases = [
+            (0xE19E, Some(7), true),
+            (0xE19E, Some(8), false),
+            (0xE19E, None, false),
+            (0xE1A1, Some(7), false),
+            (0xE1A1, Some(8), true),
+            (0xE1A1, None, true),
+        ];
+        for (opcode, key, skipped) in cases {
+            let mut test = OpcodeTest::new().set_v(1, 7);
+            if let Some(key) = key {
+                test = test.press(key);
+            }
+            let pc = if skipped { 0x204 } else { 0x202 };
+            test.opcode(opcode).expect_pc(pc);
+        }
+    }
+
+    #[test]
+    fn opcodes_wait_for_a_key() {
+        OpcodeTest::new()
+            .opcode(0xF30A)
+            .expect_waiting(true)
+            .expect_pc(0x200)
+            .press(0xC)
+            .opcode(0xF30A)
+            .expect_waiting(false)
+            .expect_v(3, 0xC)
+            .expect_pc(0x202);
+    }
+
+    #[test]
+    fn opcodes_use_the_timers() {
+        OpcodeTest::new()
+            .set_v(1, 30)
+            .set_v(2, 40)
+            .opcode(0xF115)
+            .opcode(0xF218)
+            .expect_timers(30, 40)
+            .expect_pc(0x204);
+        OpcodeTest::new()
+            .set_timers(25, 0)
+            .opcode(0xF307)
+            .expect_v(3, 25);
+    }
+
+    #[test]
+    fn opcodes_store_decimal_digits() {
+        for (value, digits) in [
+            (0, [0, 0, 0]),
+            (9, [0, 0, 9]),
+            (42, [0, 4, 2]),
+            (255, [2, 5, 5]),
+        ] {
+            OpcodeTest::new()
+                .set_v(6, value)
+                .set_i(0x300)
+                .opcode(0xF633)
+                .expect_memory(0x300, &digits)
+                .expect_i(0x300);
+        }
+    }
+
+    #[test]
+    fn opcodes_store_and_load_registers() {
+        let memory = Quirks {
+            memory: true,
+            ..Quirks::default()
+        };
+        // I is left alone by default and ends up past the last register
+        // with the memory quirk
+        for (quirks, after) in [(Quirks::default(), 0x300), (memory, 0x303)] {
+            OpcodeTest::with_quirks(quirks)
+                .set_v(0, 1)
+                .set_v(1, 2)
+                .set_v(2, 3)
+                .set_v(3, 4)
+                .set_i(0x300)
+                .opcode(0xF255)
+                .expect_memory(0x300, &[1, 2, 3, 0])
+                .expect_i(after);
+            OpcodeTest::with_quirks(quirks)
+                .set_memory(0x300, &[5, 6, 7, 8])
+                .set_i(0x300)
+                .opcode(0xF265)
+                .expect_v(0, 5)
+                .expect_v(1, 6)
+                .expect_v(2, 7)
+                .expect_v(3, 0)
+                .expect_i(after);
+        }
+    }
+
+    #[test]
+    fn unknown_opcodes_are_faults() {
+        for opcode in [0x0123, 0x8128, 0x812F, 0xE1FF, 0xF1FF, 0xF102] {
+            OpcodeTest::new().expect_fault(opcode).expect_pc(0x200);
+        }
+    }
+
+    #[test]
+    fn opcodes_are_decoded_ahead_of_time() {
+        assert_eq!(OPS[0x00E0], Op::ClearScreen);
+        assert_eq!(OPS[0x0123], Op::Unknown);
+        // the low nibble of 5XY0 and 9XY0 isn't looked at
+        assert_eq!(OPS[0x5AB3], Op::SkipIfRegistersEqual);
+        assert_eq!(OPS[0x812E], Op::ShiftLeft);
+        assert_eq!(OPS[0xF002], Op::LoadAudioPattern);
+        assert_eq!(OPS[0xF102], Op::Peripheral);
+        assert_eq!(OPS[0xFFFF], Op::Peripheral);
+    }
+
+    #[test]
+    fn calls_past_the_top_of_the_stack_are_faults() {
+        let mut cpu = Chip8::default();
+        // calls itself forever
+        cpu.load_rom(&[0x22, 0x00]).unwrap();
+        cpu.run_frame(100);
+        let fault = cpu.fault().unwrap();
+        assert_eq!(fault.kind, FaultKind::StackOverflow);
+        assert_eq!((fault.opcode, fault.address), (0x2200, 0x200));
+        assert_eq!(cpu.stack().len(), 15);
+        assert_eq!(fault.to_string(), "stack overflow calling 2200 at 200");
+    }
+
+    #[test]
+    fn addresses_wrap_around_the_end_of_memory() {
+        OpcodeTest::new()
+            .set_i(0xFFFF)
+            .set_v(0, 1)
+            .set_v(1, 2)
+            .opcode(0xF155)
+            .expect_memory(0xFFF, &[1])
+            .expect_memory(0, &[2])
+            .opcode(0xF165)
+            .expect_v(1, 2)
+            .set_v(2, 1)
+            .opcode(0xF21E)
+            .expect_i(0);
+        OpcodeTest::new().set_v(1, 0x17).press(7).opcode(0xE19E).expect_pc(0x204);
+
+        // running off the end starts over from 0, where the font's 0 isn't
+        // an instruction
+        let mut cpu = Chip8::default();
+        cpu.poke(0xFFE, 0x60);
+        cpu.poke(0xFFF, 0x01);
+        cpu.set_pc(0xFFE);
+        cpu.emulate_cycle();
+        cpu.emulate_cycle();
+        let fault = cpu.fault().unwrap();
+        assert_eq!((fault.opcode, fault.address), (0xF090, 0x000));
+    }
+
+    #[test]
+    fn checked_invariants_stop_the_machine() {
+        // LD I, 0xFFF; LD V0, 2; ADD I, V0
+        let rom = [0xAF, 0xFF, 0x60, 0x02, 0xF0, 0x1E];
+        let mut cpu = Chip8::default();
+        cpu.load_rom(&rom).unwrap();
+        cpu.run_frame(3);
+        assert_eq!(cpu.fault(), None);
+
+        let mut cpu = Chip8::default();
+        cpu.check_invariants();
+        cpu.load_rom(&rom).unwrap();
+        cpu.run_frame(3);
+        let fault = cpu.fault().unwrap();
+        assert_eq!(fault.kind, FaultKind::BrokenInvariant(Invariant::Index(0x1001)));
+        assert_eq!(fault.to_string(), "I 1001 is past the end of memory after F01E at 204");
+        // still checked after a reset
+        cpu.reset();
+        cpu.run_frame(3);
+        assert!(cpu.fault().is_some());
+
+        // jumping to an odd address is up to the rom
+        let mut cpu = Chip8::default();
+        cpu.check_invariants();
+        cpu.load_rom(&[0x12, 0x03, 0x00, 0x60, 0x01]).unwrap();
+        cpu.run_frame(2);
+        assert_eq!((cpu.fault(), cpu.pc()), (None, 0x205));
+    }
+
+    #[test]
+    fn roms_fill_program_memory_and_no_further() {
+        let mut cpu = Chip8::default();
+        cpu.load_rom(&[0xAA; MAX_ROM]).unwrap();
+        assert_eq!(cpu.peek(0x200), 0xAA);
+        assert_eq!(cpu.peek(0xFFF), 0xAA);
+        // the font is left alone
+        assert_eq!(cpu.peek(0), 0xF0);
+
+        let error = cpu.load_rom(&[0xBB; MAX_ROM + 1]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(cpu.peek(0x200), 0xAA);
+
+        // a smaller rom leaves nothing of the last one behind
+        cpu.load_rom(&[1, 2]).unwrap();
+        assert_eq!((cpu.peek(0x200), cpu.peek(0x201)), (1, 2));
+        assert_eq!(cpu.peek(0x202), 0);
+        assert_eq!(cpu.peek(0xFFF), 0);
+    }
+
+    #[test]
+    fn finished_roms_count_instructions_without_running_them() {
+        // LD V0, 1; JP 202
+        let mut cpu = Chip8::default();
+        cpu.load_rom(&[0x60, 0x01, 0x12, 0x02]).unwrap();
+        assert_eq!(cpu.state(), State::Running);
+        cpu.run_frame(10);
+        assert_eq!(cpu.state(), State::Finished);
+        assert_eq!((cpu.cycles(), cpu.pc()), (10, 0x202));
+        cpu.run_frame(10);
+        assert_eq!((cpu.cycles(), cpu.pc()), (20, 0x202));
+
+        cpu.reset();
+        cpu.load_rom(&[0xF0, 0x0A]).unwrap();
+        cpu.emulate_cycle();
+        assert_eq!(cpu.state(), State::WaitingForKey);
+    }
+
+    #[test]
+    fn random_roms_never_panic() {
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        for bits in 0..32 {
+            let mut rom = [0; 3584];
+            rng.fill(&mut rom[..]);
+            let mut cpu = Chip8::with_quirks(Quirks::from_bits(bits));
+            cpu.load_rom(&rom).unwrap();
+            for frame in 0..60 {
+                cpu.set_keys(&[(frame % 2) as u8 * 255; 16]);
+                cpu.run_frame(100);
+            }
+        }
+    }
 }
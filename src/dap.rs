@@ -0,0 +1,520 @@
+// A Debug Adapter Protocol server, so VS Code, nvim-dap and other DAP
+// clients can debug a rom running in the window: breakpoints, stepping an
+// instruction at a time, the registers and timers as variables, and memory.
+// Clients attach over TCP to the address given with --dap, one at a time.
+//
+// There's no source to a rom, so its disassembly stands in: memory from 0x200
+// on, a line for every two bytes, so line N is address 0x200 + 2 * (N - 1).
+// Breakpoints go on those lines, and the call stack is the program counter
+// and the return addresses of the subroutines it's in.
+//
+// Like --rpc, requests are answered by the frontend between frames.
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::{json, Value};
+
+use crate::cpu::Chip8;
+use crate::disasm::{disassemble, disassemble_rom};
+
+// there's only the one thread of execution
+const THREAD: u64 = 1;
+// the disassembly, the only source there is
+const SOURCE: u64 = 1;
+// the scopes' variables
+const REGISTERS: u64 = 1;
+const TIMERS: u64 = 2;
+// messages are a request in JSON, anything bigger is a mistake
+const MAX_MESSAGE: usize = 64 * 1024;
+// the most instructions stepping out of a subroutine runs, so one that
+// never returns can't freeze the window
+const MAX_STEP_OUT: u64 = 1_000_000;
+const START: u16 = 0x200;
+const MEMORY_SIZE: usize = 4096;
+
+/// The server, listening for a debugger for the frontend to answer.
+pub struct DapServer {
+    messages: Receiver<Message>,
+    // where responses and events go, once a debugger's attached
+    client: Arc<Mutex<Option<TcpStream>>>,
+    breakpoints: BTreeSet<u16>,
+    // whether the debugger was last told the machine's stopped
+    stopped: bool,
+    // the breakpoint it's stopped at, which it's let past when it carries on
+    stopped_at: Option<u16>,
+    seq: u64,
+}
+
+enum Message {
+    Request(Value),
+    // the debugger went away
+    Gone,
+}
+
+impl DapServer {
+    /// Starts listening on `address`, e.g. 127.0.0.1:4711.
+    pub fn bind(address: &str) -> io::Result<DapServer> {
+        Ok(DapServer::listen(TcpListener::bind(address)?))
+    }
+
+    fn listen(listener: TcpListener) -> DapServer {
+        let client = Arc::new(Mutex::new(None));
+        let attached = Arc::clone(&client);
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let Ok(writer) = stream.try_clone() else {
+                    continue;
+                };
+                // a new debugger takes over from the last one
+                if let Ok(mut client) = attached.lock() {
+                    *client = Some(writer);
+                }
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    if let Err(e) = read_messages(stream, &sender) {
+                        eprintln!("⚠️ Problem reading from the debugger: {}", e);
+                    }
+                    let _ = sender.send(Message::Gone);
+                });
+            }
+        });
+        DapServer {
+            messages,
+            client,
+            breakpoints: BTreeSet::new(),
+            stopped: false,
+            stopped_at: None,
+            seq: 0,
+        }
+    }
+
+    /// Answers the requests that came in since last time, which can pause,
+    /// resume and step `emu`, and tells the debugger when the machine's been
+    /// paused or resumed some other way.
+    pub fn answer(&mut self, emu: &mut Chip8, paused: &mut bool) {
+        let messages: Vec<Message> = self.messages.try_iter().collect();
+        for message in messages {
+            match message {
+                Message::Request(request) => self.respond(&request, emu, paused),
+                // the rom's left to carry on by itself
+                Message::Gone => {
+                    self.breakpoints.clear();
+                    *paused = false;
+                    self.stopped = false;
+                }
+            }
+        }
+        if *paused != self.stopped {
+            if *paused {
+                let reason = if emu.fault().is_some() {
+                    "exception"
+                } else {
+                    "pause"
+                };
+                self.stop(reason, emu);
+            } else {
+                self.stopped = false;
+                self.send_event("continued", json!({ "threadId": THREAD }));
+            }
+        }
+    }
+
+    /// Runs a frame like Chip8::run_frame, but stops before an instruction
+    /// with a breakpoint on it, returning true for the frontend to pause.
+    pub fn run_frame(&mut self, emu: &mut Chip8, cycles: u32) -> bool {
+        let mut resuming = self.stopped_at.take();
+        for _ in 0..cycles {
+            let pc = emu.pc();
+            if self.breakpoints.contains(&pc) && resuming != Some(pc) {
+                self.stop("breakpoint", emu);
+                return true;
+            }
+            resuming = None;
+            emu.emulate_cycle();
+            if emu.waiting_for_key() || emu.fault().is_some() {
+                break;
+            }
+        }
+        emu.tick_timers();
+        false
+    }
+
+    fn respond(&mut self, request: &Value, emu: &mut Chip8, paused: &mut bool) {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsReadMemoryRequest": true,
+            })),
+            // the rom's already running, so there's nothing to launch
+            "launch" | "attach" | "configurationDone" | "setExceptionBreakpoints" => {
+                Ok(Value::Null)
+            }
+            "disconnect" => {
+                self.breakpoints.clear();
+                *paused = false;
+                self.stopped = false;
+                Ok(Value::Null)
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD, "name": "CHIP-8" }] })),
+            "setBreakpoints" => Ok(self.set_breakpoints(arguments)),
+            "stackTrace" => Ok(stack_trace(emu)),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Timers", "variablesReference": TIMERS, "expensive": false },
+            ]})),
+            "variables" => Ok(variables(arguments, emu)),
+            "source" => Ok(json!({ "content": listing(emu), "mimeType": "text/x-chip8" })),
+            "readMemory" => read_memory(arguments, emu),
+            "continue" => {
+                *paused = false;
+                self.stopped = false;
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "pause" => {
+                *paused = true;
+                Ok(Value::Null)
+            }
+            "next" | "stepIn" | "stepOut" if !*paused => Err("Pause before stepping.".to_string()),
+            "next" | "stepIn" => {
+                emu.emulate_cycle();
+                Ok(Value::Null)
+            }
+            "stepOut" => {
+                let depth = emu.stack().len();
+                for _ in 0..MAX_STEP_OUT {
+                    if emu.stack().len() < depth || emu.fault().is_some() {
+                        break;
+                    }
+                    emu.emulate_cycle();
+                }
+                Ok(Value::Null)
+            }
+            _ => Err(format!("There's no request {}.", command)),
+        };
+
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => {}
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response);
+
+        match command {
+            "initialize" => self.send_event("initialized", json!({})),
+            "next" | "stepIn" | "stepOut" if *paused => self.stop("step", emu),
+            _ => {}
+        }
+    }
+
+    // Puts breakpoints on the disassembly's lines, replacing the last ones.
+    fn set_breakpoints(&mut self, arguments: &Value) -> Value {
+        self.breakpoints.clear();
+        let lines = arguments["breakpoints"]
+            .as_array()
+            .map_or(&[][..], Vec::as_slice);
+        let breakpoints: Vec<Value> = lines
+            .iter()
+            .map(|breakpoint| {
+                let line = breakpoint["line"].as_u64().unwrap_or(0);
+                match address(line) {
+                    Some(address) => {
+                        self.breakpoints.insert(address);
+                        json!({ "verified": true, "line": line })
+                    }
+                    None => json!({ "verified": false, "message": "That's not in memory." }),
+                }
+            })
+            .collect();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn stop(&mut self, reason: &str, emu: &Chip8) {
+        self.stopped = true;
+        self.stopped_at = Some(emu.pc());
+        let mut body = json!({ "reason": reason, "threadId": THREAD, "allThreadsStopped": true });
+        if let Some(fault) = emu.fault() {
+            body["text"] = json!(fault.to_string());
+        }
+        self.send_event("stopped", body);
+    }
+
+    fn send_event(&mut self, event: &str, body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn send(&mut self, mut message: Value) {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let message = message.to_string();
+        let Ok(mut client) = self.client.lock() else {
+            return;
+        };
+        let sent = client.as_mut().map(|stream| {
+            write!(
+                stream,
+                "Content-Length: {}\r\n\r\n{}",
+                message.len(),
+                message
+            )
+        });
+        // it's hung up, which its reader finds out too
+        if let Some(Err(_)) = sent {
+            *client = None;
+        }
+    }
+}
+
+// Passes on the debugger's messages until it hangs up.
+fn read_messages(stream: TcpStream, messages: &Sender<Message>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    loop {
+        let mut length = None;
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().ok();
+                }
+            }
+        }
+        let Some(length) = length.filter(|length| *length <= MAX_MESSAGE) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "bad Content-Length",
+            ));
+        };
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        let request = serde_json::from_slice(&body)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if messages.send(Message::Request(request)).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+// The address on a line of the disassembly.
+fn address(line: u64) -> Option<u16> {
+    let address = u64::from(START) + line.checked_sub(1)? * 2;
+    (address < MEMORY_SIZE as u64).then_some(address as u16)
+}
+
+// The line of the disassembly an address is on.
+fn line(address: u16) -> Option<u16> {
+    address.checked_sub(START).map(|offset| offset / 2 + 1)
+}
+
+// The disassembly of memory from 0x200 on.
+fn listing(emu: &Chip8) -> String {
+    let memory: Vec<u8> = (START..MEMORY_SIZE as u16)
+        .map(|address| emu.peek(address))
+        .collect();
+    disassemble_rom(&memory)
+}
+
+fn stack_trace(emu: &Chip8) -> Value {
+    let addresses = std::iter::once(emu.pc()).chain(emu.stack().iter().rev().copied());
+    let frames: Vec<Value> = addresses
+        .enumerate()
+        .map(|(id, address)| {
+            let opcode = u16::from_be_bytes([emu.peek(address), emu.peek(address + 1)]);
+            let name = disassemble(opcode).unwrap_or_else(|| format!("DW 0x{:04X}", opcode));
+            let mut frame = json!({
+                "id": id,
+                "name": format!("{:03X}  {}", address, name),
+                "line": 0,
+                "column": 0,
+                "instructionPointerReference": format!("0x{:03X}", address),
+            });
+            if let Some(line) = line(address) {
+                frame["line"] = json!(line);
+                frame["source"] = json!({ "name": "disassembly", "sourceReference": SOURCE });
+            }
+            frame
+        })
+        .collect();
+    json!({ "stackFrames": frames, "totalFrames": frames.len() })
+}
+
+fn variables(arguments: &Value, emu: &Chip8) -> Value {
+    let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+    let pointer = |name: &str, address: u16| {
+        let reference = format!("0x{:03X}", address);
+        json!({
+            "name": name,
+            "value": reference,
+            "variablesReference": 0,
+            "memoryReference": reference,
+        })
+    };
+    let variables: Vec<Value> = match arguments["variablesReference"].as_u64() {
+        Some(REGISTERS) => {
+            let v = emu.registers().iter().enumerate();
+            let v = v.map(|(x, value)| variable(format!("V{:X}", x), format!("0x{:02X}", value)));
+            v.chain([pointer("I", emu.index()), pointer("PC", emu.pc())])
+                .collect()
+        }
+        Some(TIMERS) => {
+            let (delay, sound) = emu.timers();
+            vec![
+                variable("delay".to_string(), delay.to_string()),
+                variable("sound".to_string(), sound.to_string()),
+            ]
+        }
+        _ => Vec::new(),
+    };
+    json!({ "variables": variables })
+}
+
+fn read_memory(arguments: &Value, emu: &Chip8) -> Result<Value, String> {
+    let reference = arguments["memoryReference"].as_str().unwrap_or_default();
+    let start = match reference.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => reference.parse(),
+    }
+    .map_err(|_| format!("{} isn't an address.", reference))?;
+    let start = start + arguments["offset"].as_i64().unwrap_or(0);
+    let count = arguments["count"].as_i64().unwrap_or(0).max(0);
+    if !(0..MEMORY_SIZE as i64).contains(&start) {
+        return Ok(json!({ "address": format!("0x{:03X}", start), "unreadableBytes": count }));
+    }
+    let end = (start + count).min(MEMORY_SIZE as i64);
+    let bytes: Vec<u8> = (start..end)
+        .map(|address| emu.peek(address as u16))
+        .collect();
+    Ok(json!({
+        "address": format!("0x{:03X}", start),
+        "data": STANDARD.encode(&bytes),
+        "unreadableBytes": count - bytes.len() as i64,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn request(stream: &mut TcpStream, seq: u64, command: &str, arguments: Value) {
+        let request = json!({
+            "seq": seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+        .to_string();
+        write!(
+            stream,
+            "Content-Length: {}\r\n\r\n{}",
+            request.len(),
+            request
+        )
+        .unwrap();
+    }
+
+    fn receive(reader: &mut impl BufRead) -> Value {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        let length: usize = header["Content-Length:".len()..].trim().parse().unwrap();
+        reader.read_line(&mut header).unwrap();
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[test]
+    fn stops_at_breakpoints() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut dap = DapServer::listen(listener);
+        let mut replies = BufReader::new(client.try_clone().unwrap());
+        request(
+            &mut client,
+            1,
+            "initialize",
+            json!({ "adapterID": "chip8" }),
+        );
+        // a breakpoint on the third line, 0x204
+        let source = json!({ "sourceReference": SOURCE });
+        let breakpoints = json!({ "source": source, "breakpoints": [{ "line": 3 }] });
+        request(&mut client, 2, "setBreakpoints", breakpoints);
+
+        let mut emu = Chip8::default();
+        // LD V0, 1; ADD V0, 1; JP 0x204
+        emu.load_rom(&[0x60, 0x01, 0x70, 0x01, 0x12, 0x04]).unwrap();
+        let mut paused = false;
+        let started = Instant::now();
+        while dap.breakpoints.is_empty() {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            dap.answer(&mut emu, &mut paused);
+        }
+        assert!(dap.run_frame(&mut emu, 10));
+        assert_eq!((emu.pc(), emu.registers()[0]), (0x204, 2));
+
+        let initialized = receive(&mut replies);
+        assert_eq!(initialized["body"]["supportsReadMemoryRequest"], true);
+        assert_eq!(receive(&mut replies)["event"], "initialized");
+        let set = receive(&mut replies);
+        assert_eq!(set["request_seq"], 2);
+        assert_eq!(set["body"]["breakpoints"][0]["verified"], true);
+        let stopped = receive(&mut replies);
+        assert_eq!(stopped["event"], "stopped");
+        assert_eq!(stopped["body"]["reason"], "breakpoint");
+
+        // carrying on gets past the breakpoint it stopped at, once
+        let cycles = emu.cycles();
+        assert!(dap.run_frame(&mut emu, 10));
+        assert_eq!(emu.cycles(), cycles + 1);
+    }
+
+    #[test]
+    fn registers_and_memory_are_read() {
+        let mut emu = Chip8::default();
+        emu.load_rom(&[0x60, 0x01, 0xA3, 0x00]).unwrap();
+        emu.emulate_cycle();
+        emu.emulate_cycle();
+
+        let registers = variables(&json!({ "variablesReference": REGISTERS }), &emu);
+        let registers = registers["variables"].as_array().unwrap();
+        assert_eq!(registers[0]["value"], "0x01");
+        assert_eq!(registers[16]["name"], "I");
+        assert_eq!(registers[16]["memoryReference"], "0x300");
+
+        let memory = json!({ "memoryReference": "0x200", "offset": 2, "count": 2 });
+        assert_eq!(read_memory(&memory, &emu).unwrap()["data"], "owA=");
+        let past_the_end = json!({ "memoryReference": "4095", "count": 3 });
+        let memory = read_memory(&past_the_end, &emu).unwrap();
+        assert_eq!(memory["unreadableBytes"], 2);
+    }
+
+    #[test]
+    fn lines_are_addresses() {
+        assert_eq!(address(3), Some(0x204));
+        assert_eq!(address(0), None);
+        assert_eq!(address(5000), None);
+        assert_eq!(line(0x204), Some(3));
+        assert_eq!(line(0x100), None);
+    }
+}
@@ -0,0 +1,108 @@
+// Turning chip8 programs back into assembly, in the mnemonics of Cowgod's
+// technical reference that the `asm` module reads.
+//
+// Each instruction is listed with its address and opcode in a comment, so
+// the listing can be read on its own or assembled straight back into the
+// same rom:
+//
+//   LD VA, 0x02       ; 200  6A02
+//   DRW VA, VB, 0x6   ; 202  DAB6
+
+/// The assembly for a single opcode, or None when it isn't an instruction
+/// this emulator knows.
+pub fn disassemble(opcode: u16) -> Option<String> {
+    let x = (opcode >> 8) & 0xF;
+    let y = (opcode >> 4) & 0xF;
+    let n = opcode & 0xF;
+    let nn = opcode & 0xFF;
+    let nnn = opcode & 0xFFF;
+
+    let text = match (opcode >> 12, x, y, n) {
+        (0x0, 0x0, 0xE, 0x0) => "CLS".to_string(),
+        (0x0, 0x0, 0xE, 0xE) => "RET".to_string(),
+        (0x1, ..) => format!("JP 0x{:03X}", nnn),
+        (0x2, ..) => format!("CALL 0x{:03X}", nnn),
+        (0x3, ..) => format!("SE V{:X}, 0x{:02X}", x, nn),
+        (0x4, ..) => format!("SNE V{:X}, 0x{:02X}", x, nn),
+        (0x5, _, _, 0x0) => format!("SE V{:X}, V{:X}", x, y),
+        (0x6, ..) => format!("LD V{:X}, 0x{:02X}", x, nn),
+        (0x7, ..) => format!("ADD V{:X}, 0x{:02X}", x, nn),
+        (0x8, _, _, 0x0) => format!("LD V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x1) => format!("OR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x2) => format!("AND V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x3) => format!("XOR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x4) => format!("ADD V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x5) => format!("SUB V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x6) => format!("SHR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x7) => format!("SUBN V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0xE) => format!("SHL V{:X}, V{:X}", x, y),
+        (0x9, _, _, 0x0) => format!("SNE V{:X}, V{:X}", x, y),
+        (0xA, ..) => format!("LD I, 0x{:03X}", nnn),
+        (0xB, ..) => format!("JP V0, 0x{:03X}", nnn),
+        (0xC, ..) => format!("RND V{:X}, 0x{:02X}", x, nn),
+        (0xD, ..) => format!("DRW V{:X}, V{:X}, 0x{:X}", x, y, n),
+        (0xE, _, 0x9, 0xE) => format!("SKP V{:X}", x),
+        (0xE, _, 0xA, 0x1) => format!("SKNP V{:X}", x),
+        (0xF, 0x0, 0x0, 0x2) => "AUDIO".to_string(),
+        (0xF, _, 0x0, 0x7) => format!("LD V{:X}, DT", x),
+        (0xF, _, 0x0, 0xA) => format!("LD V{:X}, K", x),
+        (0xF, _, 0x1, 0x5) => format!("LD DT, V{:X}", x),
+        (0xF, _, 0x1, 0x8) => format!("LD ST, V{:X}", x),
+        (0xF, _, 0x1, 0xE) => format!("ADD I, V{:X}", x),
+        (0xF, _, 0x2, 0x9) => format!("LD F, V{:X}", x),
+        (0xF, _, 0x3, 0x3) => format!("LD B, V{:X}", x),
+        (0xF, _, 0x3, 0xA) => format!("PITCH V{:X}", x),
+        (0xF, _, 0x5, 0x5) => format!("LD [I], V{:X}", x),
+        (0xF, _, 0x6, 0x5) => format!("LD V{:X}, [I]", x),
+        // the serial port
+        (0xF, _, 0xB, 0x0) => format!("SEND V{:X}", x),
+        (0xF, _, 0xB, 0x1) => format!("RECV V{:X}", x),
+        (0xF, _, 0xB, 0x2) => format!("AVAIL V{:X}", x),
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// Lists a whole rom, loaded at 0x200, two bytes at a time. Anything that
+/// isn't an instruction, like sprite data, comes out as DW, and an odd byte
+/// at the end as DB.
+pub fn disassemble_rom(rom: &[u8]) -> String {
+    let mut listing = String::new();
+    for (index, bytes) in rom.chunks(2).enumerate() {
+        let address = 0x200 + index * 2;
+        let (text, raw) = match *bytes {
+            [high, low] => {
+                let opcode = u16::from_be_bytes([high, low]);
+                let text = disassemble(opcode).unwrap_or_else(|| format!("DW 0x{:04X}", opcode));
+                (text, format!("{:04X}", opcode))
+            }
+            [byte] => (format!("DB 0x{:02X}", byte), format!("{:02X}", byte)),
+            _ => unreachable!("chunks of two"),
+        };
+        listing.push_str(&format!("{:<18}; {:03X}  {}\n", text, address, raw));
+    }
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassembles_instructions() {
+        assert_eq!(disassemble(0x00E0).as_deref(), Some("CLS"));
+        assert_eq!(disassemble(0x6A02).as_deref(), Some("LD VA, 0x02"));
+        assert_eq!(disassemble(0xDAB6).as_deref(), Some("DRW VA, VB, 0x6"));
+        assert_eq!(disassemble(0xF355).as_deref(), Some("LD [I], V3"));
+        assert_eq!(disassemble(0x5121), None);
+    }
+
+    #[test]
+    fn lists_data_and_odd_bytes() {
+        let listing = disassemble_rom(&[0x12, 0x00, 0x51, 0x21, 0xFF]);
+        assert_eq!(
+            listing,
+            "JP 0x200          ; 200  1200\nDW 0x5121         ; 202  5121\nDB 0xFF           ; 204  FF\n"
+        );
+    }
+}
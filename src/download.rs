@@ -0,0 +1,116 @@
+// Roms given as http:// or https:// URLs, like links into the CHIP-8
+// Archive or raw files on GitHub. They're downloaded into the cache
+// directory once and played from there, e.g.
+// ~/.cache/chip8/downloads/<hash of the URL>/PONG.ch8 on Linux, so save
+// states have somewhere to go and the rom still starts without a network.
+use std::path::PathBuf;
+
+use crate::archive;
+
+// roms are a few kilobytes and archives of them not much more, anything
+// bigger is surely not a rom
+#[cfg(feature = "http")]
+const MAX_SIZE: u64 = 1024 * 1024;
+
+/// Whether a rom path is a URL to download the rom from.
+pub fn is_url(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Where the file at `url` is kept once downloaded.
+pub fn cache_path(url: &str) -> Option<PathBuf> {
+    let hash = sha1_smol::Sha1::from(url).digest().to_string();
+    dirs::cache_dir().map(|dir| {
+        dir.join("chip8")
+            .join("downloads")
+            .join(&hash[..16])
+            .join(file_name(url))
+    })
+}
+
+// The name of the file a URL points at, leaving out any query.
+fn file_name(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    match path.rsplit('/').next() {
+        Some(name) if !name.is_empty() => name,
+        _ => "rom.ch8",
+    }
+}
+
+/// Downloads the rom at `url` unless it's in the cache already, and returns
+/// the rom path to play it from. A rom picked out of an archive by name
+/// stays picked, so `https://example.com/games.zip:PONG.ch8` works too.
+pub fn fetch(url: &str) -> Result<String, String> {
+    let (file, name) = archive::split(url);
+    let path = cache_path(file).ok_or("No cache directory to download the rom to.")?;
+    if !path.is_file() {
+        download(file, &path)?;
+    }
+
+    let path = path.to_string_lossy().into_owned();
+    Ok(match name {
+        Some(name) => format!("{}:{}", path, name),
+        None => path,
+    })
+}
+
+#[cfg(feature = "http")]
+fn download(url: &str, path: &std::path::Path) -> Result<(), String> {
+    use std::fs;
+    use std::io::Read;
+
+    let response = ureq::get(url)
+        .call()
+        .map_err(|e| format!("Unable to download {}: {}", url, e))?;
+    let too_big = || format!("{} is too big to be a rom.", url);
+    let length = response
+        .header("Content-Length")
+        .and_then(|length| length.parse::<u64>().ok());
+    if length.is_some_and(|length| length > MAX_SIZE) {
+        return Err(too_big());
+    }
+
+    // the length isn't always given, or true
+    let mut rom = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_SIZE + 1)
+        .read_to_end(&mut rom)
+        .map_err(|e| format!("Unable to download {}: {}", url, e))?;
+    if rom.len() as u64 > MAX_SIZE {
+        return Err(too_big());
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, rom).map_err(|e| format!("Unable to write {}: {}", path.display(), e))
+}
+
+#[cfg(not(feature = "http"))]
+fn download(url: &str, _path: &std::path::Path) -> Result<(), String> {
+    Err(format!(
+        "Unable to download {}: this build can't, it needs the http feature.",
+        url
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_are_told_apart_from_files() {
+        assert!(is_url("https://example.com/PONG.ch8"));
+        assert!(is_url("HTTP://example.com/PONG.ch8"));
+        assert!(!is_url("roms/PONG.ch8"));
+    }
+
+    #[test]
+    fn downloads_keep_the_file_name() {
+        assert_eq!(file_name("https://example.com/roms/PONG.ch8"), "PONG.ch8");
+        assert_eq!(file_name("https://example.com/games.zip?raw=true"), "games.zip");
+        assert_eq!(file_name("https://example.com/"), "rom.ch8");
+    }
+}
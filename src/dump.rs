@@ -0,0 +1,144 @@
+// Renders a chip8 framebuffer as text, for terminals or as a plain PBM
+// image.
+use std::fmt::Write;
+
+use crate::{Color, Palette};
+
+/// Renders `gfx` as lines of Unicode half blocks, two display rows per line
+/// of text, colored with 24-bit ANSI escapes from `palette`.
+///
+/// The upper pixel of each cell is drawn with the foreground color of `▀`
+/// and the lower pixel with its background color.
+pub fn framebuffer_to_ansi<const W: usize, const H: usize>(
+    gfx: &[[u8; W]; H],
+    palette: &Palette,
+) -> String {
+    let color = |on: u8| {
+        if on != 0 {
+            palette.foreground
+        } else {
+            palette.background
+        }
+    };
+
+    let mut out = String::new();
+    for rows in gfx.chunks(2) {
+        // colors currently set on the terminal so we only emit changes
+        let mut current: Option<(Color, Color)> = None;
+
+        for x in 0..W {
+            let top = color(rows[0][x]);
+            let bottom = color(rows.get(1).map_or(0, |row| row[x]));
+
+            if current != Some((top, bottom)) {
+                let _ = write!(
+                    out,
+                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m",
+                    top.r, top.g, top.b, bottom.r, bottom.g, bottom.b
+                );
+                current = Some((top, bottom));
+            }
+            out.push('▀');
+        }
+        out.push_str("\x1b[0m\n");
+    }
+
+    out
+}
+
+/// Renders `gfx` as lines of Unicode braille, each character a block of two
+/// by four pixels, for terminals too small for half blocks. Braille has no
+/// background to color, so lit pixels are dots in the palette's foreground
+/// and the rest is left to the terminal.
+pub fn framebuffer_to_braille<const W: usize, const H: usize>(
+    gfx: &[[u8; W]; H],
+    palette: &Palette,
+) -> String {
+    let ink = palette.foreground;
+    let mut out = String::new();
+    for y in (0..H).step_by(4) {
+        let _ = write!(out, "\x1b[38;2;{};{};{}m", ink.r, ink.g, ink.b);
+        for x in (0..W).step_by(2) {
+            out.push(braille_cell(gfx, x, y));
+        }
+        out.push_str("\x1b[0m\n");
+    }
+
+    out
+}
+
+// The braille character for the two by four pixels with their top left at
+// `x`, `y`, any past the edge of the display left blank.
+pub(crate) fn braille_cell<const W: usize, const H: usize>(
+    gfx: &[[u8; W]; H],
+    x: usize,
+    y: usize,
+) -> char {
+    // the bit for each dot of a braille cell, by row and then column
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+    let mut cell = 0;
+    for (row, dots) in gfx.iter().skip(y).zip(DOTS) {
+        for (column, dot) in dots.into_iter().enumerate() {
+            if row.get(x + column).is_some_and(|&on| on != 0) {
+                cell |= dot;
+            }
+        }
+    }
+    char::from_u32(0x2800 + cell).expect("braille is in unicode")
+}
+
+/// Renders `gfx` as a plain PBM image, a 1 for each lit pixel, which image
+/// viewers open and diffs read.
+pub fn framebuffer_to_pbm<const W: usize, const H: usize>(gfx: &[[u8; W]; H]) -> String {
+    let mut out = format!("P1\n{} {}\n", W, H);
+    for row in gfx {
+        let pixels: Vec<&str> = row
+            .iter()
+            .map(|&on| if on != 0 { "1" } else { "0" })
+            .collect();
+        out.push_str(&pixels.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DEFAULT_PALETTE;
+
+    #[test]
+    fn two_rows_per_line() {
+        let gfx = [[0u8; 64]; 32];
+        let out = framebuffer_to_ansi(&gfx, &DEFAULT_PALETTE);
+        assert_eq!(out.lines().count(), 16);
+        assert!(out.lines().all(|line| line.matches('▀').count() == 64));
+    }
+
+    #[test]
+    fn only_emits_color_changes() {
+        let mut gfx = [[0u8; 4]; 2];
+        gfx[0][2] = 1;
+        let out = framebuffer_to_ansi(&gfx, &DEFAULT_PALETTE);
+        // blank cells, the lit cell, then blank cells again
+        assert_eq!(out.matches("\x1b[38;2;").count(), 3);
+    }
+
+    #[test]
+    fn braille_packs_eight_pixels_a_character() {
+        let mut gfx = [[0u8; 64]; 32];
+        gfx[0][0] = 1;
+        gfx[3][1] = 1;
+        let out = framebuffer_to_braille(&gfx, &DEFAULT_PALETTE);
+        assert_eq!(out.lines().count(), 8);
+        assert!(out.lines().next().unwrap().contains("\u{2881}\u{2800}"));
+    }
+
+    #[test]
+    fn pbm_has_a_row_a_line() {
+        let mut gfx = [[0u8; 3]; 2];
+        gfx[1][0] = 1;
+        assert_eq!(framebuffer_to_pbm(&gfx), "P1\n3 2\n0 0 0\n1 0 0\n");
+    }
+}
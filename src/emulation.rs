@@ -0,0 +1,264 @@
+// The machine on a thread of its own, for --threaded. The thread paces
+// itself, so a window that's slow to draw or being dragged around doesn't
+// hold the rom up, and turbo runs it flat out without the window going
+// unanswered.
+//
+// The window sends the keys and how to run in, and gets a copy of the
+// machine back after each frame to draw, beep and rewind with. Anything the
+// window does to its copy, like loading a state or resetting, is sent back
+// for the thread to carry on from, and whatever the thread ran in the
+// meantime is dropped.
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chip8::cpu::Chip8;
+use chip8::quirks::Quirks;
+use chip8::speed::Speed;
+
+use crate::pacer::FramePacer;
+
+// How the thread runs the machine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pace {
+    pub paused: bool,
+    // frames run each frame, more than one while fast-forwarding
+    pub frames: u32,
+    pub speed: Speed,
+    // as many frames as it can, for turbo
+    pub uncapped: bool,
+}
+
+enum Command {
+    Keys([u8; 16]),
+    Run(Pace),
+    // a frame while paused
+    Advance,
+    // carry on from this machine instead
+    Replace(Box<Chip8>),
+}
+
+// The window's end of the thread. The machines coming back are stamped with
+// how many times the machine's been replaced, so ones run from a machine
+// that's since been replaced are dropped.
+pub struct EmulationThread {
+    commands: Sender<Command>,
+    frames: Receiver<(u64, Chip8)>,
+    thread: JoinHandle<Chip8>,
+    generation: u64,
+    // the machine the window last had from the thread or sent it, to tell
+    // whether the window has changed its copy since
+    synced: (u32, Quirks),
+    keys: [u8; 16],
+    pace: Pace,
+}
+
+impl EmulationThread {
+    // Starts running `emu` a frame every `frame`, peripherals and all.
+    pub fn spawn(emu: Chip8, frame: Duration, pace: Pace) -> EmulationThread {
+        let (commands, received) = mpsc::channel();
+        let (sender, frames) = mpsc::channel();
+        let synced = (emu.checksum(), emu.quirks());
+        let keys = *emu.keys();
+        let thread = thread::spawn(move || run(emu, frame, pace, received, sender));
+        EmulationThread {
+            commands,
+            frames,
+            thread,
+            generation: 0,
+            synced,
+            keys,
+            pace,
+        }
+    }
+
+    // The machines the thread has run since last time, oldest first.
+    pub fn frames(&mut self) -> Vec<Chip8> {
+        let generation = self.generation;
+        let frames: Vec<Chip8> = self
+            .frames
+            .try_iter()
+            .filter(|(ran_from, _)| *ran_from == generation)
+            .map(|(_, emu)| emu)
+            .collect();
+        if let Some(emu) = frames.last() {
+            self.synced = (emu.checksum(), emu.quirks());
+        }
+        frames
+    }
+
+    // Sends the window's copy of the machine to carry on from, if the
+    // window's changed it since it came from the thread.
+    pub fn sync(&mut self, emu: &Chip8) {
+        let copy = (emu.checksum(), emu.quirks());
+        if copy != self.synced {
+            self.synced = copy;
+            self.generation += 1;
+            self.send(Command::Replace(Box::new(emu.snapshot())));
+        }
+    }
+
+    pub fn set_keys(&mut self, keys: &[u8; 16]) {
+        if *keys != self.keys {
+            self.keys = *keys;
+            self.send(Command::Keys(*keys));
+        }
+    }
+
+    pub fn set_pace(&mut self, pace: Pace) {
+        if pace != self.pace {
+            self.pace = pace;
+            self.send(Command::Run(pace));
+        }
+    }
+
+    // Runs a frame while paused.
+    pub fn advance(&self) {
+        self.send(Command::Advance);
+    }
+
+    // Stops the thread once it's done what it was sent, and hands back the
+    // machine.
+    pub fn stop(self) -> Chip8 {
+        drop(self.commands);
+        self.thread.join().expect("the emulation thread doesn't panic")
+    }
+
+    // The thread only finishes once it's stopped, so there's no one to
+    // tell if it's gone.
+    fn send(&self, command: Command) {
+        let _result = self.commands.send(command);
+    }
+}
+
+fn run(
+    mut emu: Chip8,
+    frame: Duration,
+    mut pace: Pace,
+    commands: Receiver<Command>,
+    frames: Sender<(u64, Chip8)>,
+) -> Chip8 {
+    let mut pacer = FramePacer::new(frame);
+    let mut generation = 0;
+    loop {
+        // paused, there's nothing to do until the window asks for something
+        let mut advance = false;
+        loop {
+            let command = if pace.paused && !advance {
+                match commands.recv() {
+                    Ok(command) => command,
+                    Err(_) => return emu,
+                }
+            } else {
+                match commands.try_recv() {
+                    Ok(command) => command,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return emu,
+                }
+            };
+            match command {
+                Command::Keys(keys) => emu.set_keys(&keys),
+                Command::Run(next) => {
+                    if pace.paused && !next.paused {
+                        pacer.restart();
+                    }
+                    pace = next;
+                }
+                Command::Advance => advance = true,
+                Command::Replace(next) => {
+                    emu.replace_with(*next);
+                    generation += 1;
+                }
+            }
+        }
+
+        if advance {
+            emu.run_frame(pace.speed.cycles_in(emu.frames()));
+        } else if pace.uncapped {
+            // as many frames as fit in one, the window getting no more
+            // machines than it could show
+            emu.run_for(frame, pace.speed);
+        } else {
+            for _ in 0..pace.frames {
+                emu.run_frame(pace.speed.cycles_in(emu.frames()));
+            }
+        }
+        if frames.send((generation, emu.snapshot())).is_err() {
+            return emu;
+        }
+        // the copy has the draw flag now
+        emu.draw_flag();
+
+        if pace.uncapped {
+            pacer.restart();
+        } else if !pace.paused {
+            pacer.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(1);
+
+    fn running(frames: u32) -> Pace {
+        Pace {
+            paused: false,
+            frames,
+            speed: Speed::default(),
+            uncapped: false,
+        }
+    }
+
+    // waits for the thread to send back a machine
+    fn next_frame(emulation: &mut EmulationThread) -> Chip8 {
+        loop {
+            if let Some(emu) = emulation.frames().pop() {
+                return emu;
+            }
+            thread::sleep(FRAME);
+        }
+    }
+
+    #[test]
+    fn the_thread_runs_the_machine_and_sends_it_back() {
+        let mut emulation = EmulationThread::spawn(Chip8::default(), FRAME, running(2));
+        let emu = next_frame(&mut emulation);
+        assert!(emu.frames() >= 2);
+        assert_eq!(emu.frames() % 2, 0);
+
+        let stopped = emulation.stop();
+        assert!(stopped.frames() >= emu.frames());
+    }
+
+    #[test]
+    fn paused_it_only_runs_frames_it_is_asked_for() {
+        let paused = Pace {
+            paused: true,
+            ..running(1)
+        };
+        let mut emulation = EmulationThread::spawn(Chip8::default(), FRAME, paused);
+        emulation.advance();
+        assert_eq!(next_frame(&mut emulation).frames(), 1);
+        thread::sleep(FRAME * 5);
+        assert!(emulation.frames().is_empty());
+        assert_eq!(emulation.stop().frames(), 1);
+    }
+
+    #[test]
+    fn the_window_copy_replaces_the_machine_when_it_changes() {
+        let mut emulation = EmulationThread::spawn(Chip8::default(), FRAME, running(1));
+        let mut emu = next_frame(&mut emulation);
+        emu.poke(0x300, 0xAB);
+        emu.reset();
+        emulation.sync(&emu);
+
+        // machines run from before the reset don't come back
+        let emu = next_frame(&mut emulation);
+        assert_eq!(emu.peek(0x300), 0xAB);
+        let stopped = emulation.stop();
+        assert_eq!(stopped.peek(0x300), 0xAB);
+    }
+}
@@ -0,0 +1,201 @@
+// Turning the display into RGBA pixels, which is all most frontends need to
+// show it: a texture, an image or a canvas takes them as they are.
+use crate::{Palette, DEFAULT_PALETTE};
+#[cfg(any(feature = "image", test))]
+use crate::cpu::Chip8;
+
+/// Draws a display as RGBA pixels, 4 bytes each, a row at a time from the
+/// top, ready to upload to a texture.
+///
+/// palette: the colors lit and unlit pixels are drawn in.
+/// scale: how many pixels wide and high each of the display's is drawn.
+/// buffer: the last frame drawn, kept to draw the next one into.
+pub struct FramePresenter {
+    palette: Palette,
+    scale: usize,
+    buffer: Vec<u8>,
+}
+
+impl Default for FramePresenter {
+    fn default() -> Self {
+        FramePresenter::new(DEFAULT_PALETTE)
+    }
+}
+
+impl FramePresenter {
+    /// Draws in `palette`, a pixel for each of the display's.
+    pub fn new(palette: Palette) -> Self {
+        FramePresenter {
+            palette,
+            scale: 1,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn scale(mut self, scale: usize) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    /// Switches palette, for the next frame drawn.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// The width and height of the frames drawn for a `width` by `height`
+    /// display.
+    pub fn size(&self, width: usize, height: usize) -> (usize, usize) {
+        (width * self.scale, height * self.scale)
+    }
+
+    /// Draws `gfx`, returning the pixels.
+    pub fn present<const W: usize, const H: usize>(&mut self, gfx: &[[u8; W]; H]) -> &[u8] {
+        let (width, height) = self.size(W, H);
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.resize(width * height * 4, 0);
+        self.present_into(gfx, &mut buffer);
+        self.buffer = buffer;
+        &self.buffer
+    }
+
+    /// Draws `gfx` into `out`, e.g. straight into an image's pixels, which
+    /// should be the size `size` says. Rows that don't fit are left out.
+    pub fn present_into<const W: usize, const H: usize>(&self, gfx: &[[u8; W]; H], out: &mut [u8]) {
+        let line = W * self.scale * 4;
+        let colors = self.colors();
+        for (row, lines) in gfx.iter().zip(out.chunks_exact_mut(line * self.scale)) {
+            let (first, rest) = lines.split_at_mut(line);
+            for (&lit, pixels) in row.iter().zip(first.chunks_exact_mut(self.scale * 4)) {
+                fill(pixels, &colors[usize::from(lit != 0)]);
+            }
+            // the rest of the row's lines are the same as the first
+            for copy in rest.chunks_exact_mut(line) {
+                copy.copy_from_slice(first);
+            }
+        }
+    }
+
+    /// Draws the display as the machine keeps it, `Chip8::gfx`, a u64 to a
+    /// row with the leftmost pixel in the top bit, without making a byte of
+    /// each pixel first.
+    pub fn present_planes<const H: usize>(&mut self, planes: &[u64; H]) -> &[u8] {
+        let (width, height) = self.size(64, H);
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.resize(width * height * 4, 0);
+        self.present_planes_into(planes, &mut buffer);
+        self.buffer = buffer;
+        &self.buffer
+    }
+
+    /// Draws `planes` into `out`, like present_into.
+    pub fn present_planes_into<const H: usize>(&self, planes: &[u64; H], out: &mut [u8]) {
+        let line = 64 * self.scale * 4;
+        let colors = self.colors();
+        for (&plane, lines) in planes.iter().zip(out.chunks_exact_mut(line * self.scale)) {
+            let (first, rest) = lines.split_at_mut(line);
+            // build the row once at one pixel per pixel, each pixel's bit
+            // indexing its color, then widen it into the first line
+            let mut row = [0; 64 * 4];
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                pixel.copy_from_slice(&colors[(plane >> (63 - x) & 1) as usize]);
+            }
+            if self.scale == 1 {
+                first.copy_from_slice(&row);
+            } else {
+                let pixels = first.chunks_exact_mut(self.scale * 4);
+                for (color, pixels) in row.chunks_exact(4).zip(pixels) {
+                    fill(pixels, color);
+                }
+            }
+            for copy in rest.chunks_exact_mut(line) {
+                copy.copy_from_slice(first);
+            }
+        }
+    }
+
+    // The unlit and lit pixels' RGBA, in that order.
+    fn colors(&self) -> [[u8; 4]; 2] {
+        [self.palette.background, self.palette.foreground]
+            .map(|color| [color.r, color.g, color.b, 255])
+    }
+}
+
+// Fills a run of pixels with one color.
+fn fill(pixels: &mut [u8], color: &[u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel.copy_from_slice(color);
+    }
+}
+
+#[cfg(feature = "image")]
+impl Chip8 {
+    /// Draws the display as an image in `palette`, a pixel for each of its,
+    /// to look at without a window: in a notebook, or saved from a test.
+    pub fn render_to_image(&self, palette: &Palette) -> image::RgbaImage {
+        let presenter = FramePresenter::new(*palette);
+        let (width, height) = presenter.size(64, self.gfx.len());
+        let mut image = image::RgbaImage::new(width as u32, height as u32);
+        presenter.present_planes_into(&self.gfx, &mut image);
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BITBEE;
+
+    #[test]
+    fn pixels_are_drawn_in_the_palette() {
+        let mut gfx = [[0u8; 64]; 32];
+        gfx[0][1] = 1;
+        let mut presenter = FramePresenter::new(BITBEE);
+        let pixels = presenter.present(&gfx);
+
+        assert_eq!(pixels.len(), 64 * 32 * 4);
+        let (on, off) = (BITBEE.foreground, BITBEE.background);
+        assert_eq!(pixels[..4], [off.r, off.g, off.b, 255]);
+        assert_eq!(pixels[4..8], [on.r, on.g, on.b, 255]);
+    }
+
+    #[test]
+    fn scaled_pixels_are_squares() {
+        let mut gfx = [[0u8; 4]; 2];
+        gfx[1][0] = 1;
+        let mut presenter = FramePresenter::default().scale(3);
+        assert_eq!(presenter.size(4, 2), (12, 6));
+        let pixels = presenter.present(&gfx);
+
+        let lit = |x: usize, y: usize| pixels[(y * 12 + x) * 4] == DEFAULT_PALETTE.foreground.r;
+        for y in 0..6 {
+            for x in 0..12 {
+                assert_eq!(lit(x, y), x < 3 && y >= 3, "pixel {},{}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn planes_are_drawn_like_bytes() {
+        let mut emu = Chip8::default();
+        emu.gfx[0] = 1 << 63 | 1;
+        emu.gfx[17] = 0x00F0_0F00_A5A5_0001;
+        for scale in [1, 3] {
+            let mut presenter = FramePresenter::new(BITBEE).scale(scale);
+            let bytes = presenter.present(&emu.pixels()).to_vec();
+            assert_eq!(presenter.present_planes(&emu.gfx), bytes);
+        }
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn the_display_renders_to_an_image() {
+        let mut emu = Chip8::default();
+        emu.gfx[31] = 1;
+        let image = emu.render_to_image(&BITBEE);
+
+        assert_eq!(image.dimensions(), (64, 32));
+        let (on, off) = (BITBEE.foreground, BITBEE.background);
+        assert_eq!(image.get_pixel(63, 31).0, [on.r, on.g, on.b, 255]);
+        assert_eq!(image.get_pixel(0, 0).0, [off.r, off.g, off.b, 255]);
+    }
+}
@@ -0,0 +1,448 @@
+// A friendlier window than the SDL one, played with --gui: a menu bar for
+// opening roms and picking the quirks, palette and speed, and debug panels
+// for the registers, the code around the program counter and memory, which
+// can be moved around the window, collapsed and closed.
+//
+// The keypad is on the same keys as in the SDL window. Hotkeys, movies and
+// save states are only in the SDL window for now.
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use eframe::egui::{self, Color32, ColorImage, Key, RichText, TextureHandle, TextureOptions};
+
+use chip8::config::PaletteName;
+use chip8::cpu::{Chip8, Fault, State};
+use chip8::disasm::disassemble;
+use chip8::frame::FramePresenter;
+use chip8::library;
+use chip8::quirks::Quirks;
+use chip8::report::Failure;
+#[cfg(feature = "cpal")]
+use chip8::sound::{cpal_backend::CpalBackend, AudioBackend};
+use chip8::{Config, KEYPAD_LAYOUT, KEY_ROWS, PALETTES};
+
+use chip8::speed::{Speed, MAX_CATCH_UP};
+use crate::{EMULATOR_HEIGHT, EMULATOR_WIDTH};
+
+const FRAME: Duration = Duration::from_micros(16_667);
+// instructions shown either side of the program counter
+const DISASSEMBLY_LINES: u16 = 8;
+// bytes on a line of the memory panel
+const MEMORY_ROW: usize = 16;
+const MEMORY_SIZE: usize = 4096;
+// room for the menu bar and the status bar around the display
+const BARS_HEIGHT: f32 = 48.0;
+
+// Which windows are open besides the display.
+#[derive(Default)]
+struct Panels {
+    registers: bool,
+    disassembly: bool,
+    memory: bool,
+    about: bool,
+}
+
+struct Gui<'a> {
+    config: Config,
+    emu: Chip8,
+    keys: [u8; 16],
+    speed: Speed,
+    paused: bool,
+    presenter: FramePresenter,
+    texture: Option<TextureHandle>,
+    // the display needs uploading again, as it or the palette changed
+    stale: bool,
+    next_frame: Instant,
+    panels: Panels,
+    // why the last rom picked couldn't be opened
+    error: Option<String>,
+    // where the rom stopped, handed back once the window closes
+    fault: &'a mut Option<Fault>,
+    #[cfg(feature = "cpal")]
+    beeper: Option<CpalBackend>,
+}
+
+pub fn play(config: Config) -> Result<(), Failure> {
+    let mut emu = Chip8::with_quirks(config.quirks);
+    if config.check_invariants {
+        emu.check_invariants();
+    }
+    if let Err(e) = emu.load_game(&config.rom_path) {
+        let error = format!("Unable to load {}: {}", config.rom_path, e);
+        return Err(Failure::Load(error));
+    }
+    remember(&config.rom_path);
+
+    let size = [
+        config.display.width as f32,
+        config.display.height as f32 + BARS_HEIGHT,
+    ];
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_inner_size(size)
+            .with_title(title(&config)),
+        ..Default::default()
+    };
+    let mut fault = None;
+    let gui = Gui::new(config, emu, &mut fault);
+    eframe::run_native("chip8", options, Box::new(|_| Ok(Box::new(gui))))
+        .map_err(|e| Failure::from(format!("Unable to open the window: {}", e)))?;
+
+    match fault {
+        Some(fault) => Err(Failure::Fault(fault)),
+        None => Ok(()),
+    }
+}
+
+impl<'a> Gui<'a> {
+    fn new(config: Config, emu: Chip8, fault: &'a mut Option<Fault>) -> Gui<'a> {
+        #[cfg(feature = "cpal")]
+        let beeper = CpalBackend::new(config.audio.tone, config.audio.buffer_size())
+            .map_err(|e| eprintln!("⚠️ Unable to open audio device, sound is disabled: {}", e))
+            .ok();
+
+        Gui {
+            speed: Speed::from_hz(config.speed),
+            paused: config.start_paused,
+            config,
+            emu,
+            keys: [0; 16],
+            presenter: FramePresenter::default(),
+            texture: None,
+            stale: true,
+            next_frame: Instant::now(),
+            panels: Panels::default(),
+            error: None,
+            fault,
+            #[cfg(feature = "cpal")]
+            beeper,
+        }
+    }
+
+    // Runs the frames that are due, 60 a second.
+    fn run(&mut self) {
+        let now = Instant::now();
+        if now.saturating_duration_since(self.next_frame) > FRAME * MAX_CATCH_UP {
+            self.next_frame = now;
+        }
+        while self.next_frame <= now {
+            self.next_frame += FRAME;
+            if self.paused || self.emu.fault().is_some() {
+                continue;
+            }
+            self.emu.set_keys(&self.keys);
+            self.emu.run_frame(self.speed.cycles_in(self.emu.frames()));
+            self.stale |= self.emu.draw_flag();
+        }
+        *self.fault = self.emu.fault();
+
+        #[cfg(feature = "cpal")]
+        if let Some(beeper) = &mut self.beeper {
+            let muted = self.config.audio.muted;
+            beeper.set_playing(self.emu.sound_active() && !self.paused && !muted);
+        }
+    }
+
+    // Opens a rom picked from a file dialog in place of the one running.
+    fn open(&mut self, ctx: &egui::Context) {
+        let picked = rfd::FileDialog::new()
+            .add_filter("CHIP-8 roms", &["ch8", "c8", "zip"])
+            .pick_file();
+        let Some(path) = picked else {
+            return;
+        };
+        let path = path.to_string_lossy().into_owned();
+        let mut emu = Chip8::with_quirks(self.config.quirks);
+        if self.config.check_invariants {
+            emu.check_invariants();
+        }
+        let opened = self.config.switch_rom(path.clone()).and_then(|()| {
+            emu.set_quirks(self.config.quirks);
+            emu.load_game(&path)
+                .map_err(|e| format!("Unable to load {}: {}", path, e))
+        });
+        match opened {
+            Ok(()) => {
+                remember(&path);
+                self.emu = emu;
+                self.stale = true;
+                self.error = None;
+                ctx.send_viewport_cmd(egui::ViewportCommand::Title(title(&self.config)));
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    fn menu_bar(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::top("menu").show(ctx, |ui| {
+            egui::MenuBar::new().ui(ui, |ui| {
+                ui.menu_button("File", |ui| {
+                    if ui.button("Open ROM…").clicked() {
+                        self.open(ctx);
+                    }
+                    if ui.button("Reset").clicked() {
+                        self.emu.reset();
+                    }
+                    ui.separator();
+                    if ui.button("Quit").clicked() {
+                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                    }
+                });
+                ui.menu_button("Emulation", |ui| {
+                    ui.checkbox(&mut self.paused, "Paused");
+                    ui.label(format!("{} instructions a second", self.speed.hz()));
+                    ui.horizontal(|ui| {
+                        if ui.button("Slower").clicked() {
+                            self.speed.slower();
+                        }
+                        if ui.button("Faster").clicked() {
+                            self.speed.faster();
+                        }
+                    });
+                    ui.separator();
+                    ui.menu_button("Quirks", |ui| {
+                        if quirks_menu(ui, &mut self.config.quirks) {
+                            self.emu.set_quirks(self.config.quirks);
+                        }
+                    });
+                });
+                ui.menu_button("View", |ui| {
+                    ui.menu_button("Palette", |ui| {
+                        for name in PaletteName::ALL {
+                            let palette = &mut self.config.display.palette;
+                            if ui.radio_value(palette, name, palette_label(name)).clicked() {
+                                self.stale = true;
+                            }
+                        }
+                    });
+                    ui.separator();
+                    ui.checkbox(&mut self.panels.registers, "Registers");
+                    ui.checkbox(&mut self.panels.disassembly, "Disassembly");
+                    ui.checkbox(&mut self.panels.memory, "Memory");
+                });
+                ui.menu_button("Help", |ui| {
+                    if ui.button("About").clicked() {
+                        self.panels.about = true;
+                    }
+                });
+            });
+        });
+    }
+
+    fn status_bar(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
+            let status = match self.emu.state() {
+                State::Stopped(fault) => format!("Stopped: {}", fault),
+                _ if self.paused => "Paused".to_string(),
+                State::Finished => "Program ended, Reset to run it again".to_string(),
+                _ => format!("{} instructions a second", self.speed.hz()),
+            };
+            ui.label(status);
+        });
+    }
+
+    fn debug_panels(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Registers")
+            .open(&mut self.panels.registers)
+            .resizable(false)
+            .show(ctx, |ui| registers(ui, &self.emu));
+        egui::Window::new("Disassembly")
+            .open(&mut self.panels.disassembly)
+            .resizable(false)
+            .show(ctx, |ui| disassembly(ui, &self.emu));
+        egui::Window::new("Memory")
+            .open(&mut self.panels.memory)
+            .default_height(300.0)
+            .show(ctx, |ui| memory(ui, &self.emu));
+        egui::Window::new("About")
+            .open(&mut self.panels.about)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| about(ui, &self.config));
+
+        let mut open = self.error.is_some();
+        egui::Window::new("Unable to open the rom")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(self.error.as_deref().unwrap_or_default())
+            });
+        if !open {
+            self.error = None;
+        }
+    }
+
+    fn display(&mut self, ctx: &egui::Context) {
+        let palette = &PALETTES[self.config.display.palette.index()];
+        if self.stale {
+            self.presenter.set_palette(*palette);
+            let pixels = self.presenter.present_planes(&self.emu.gfx);
+            let size = [EMULATOR_WIDTH as usize, EMULATOR_HEIGHT as usize];
+            let image = ColorImage::from_rgba_unmultiplied(size, pixels);
+            match &mut self.texture {
+                Some(texture) => texture.set(image, TextureOptions::NEAREST),
+                None => {
+                    let texture = ctx.load_texture("display", image, TextureOptions::NEAREST);
+                    self.texture = Some(texture);
+                }
+            }
+            self.stale = false;
+        }
+
+        let gutter = palette.gutter;
+        let frame = egui::Frame::NONE.fill(Color32::from_rgb(gutter.r, gutter.g, gutter.b));
+        egui::CentralPanel::default().frame(frame).show(ctx, |ui| {
+            let Some(texture) = &self.texture else {
+                return;
+            };
+            // as big as fits, keeping the pixels square
+            let available = ui.available_size();
+            let scale = (available.x / EMULATOR_WIDTH as f32)
+                .min(available.y / EMULATOR_HEIGHT as f32)
+                .floor()
+                .max(1.0);
+            let size = egui::vec2(EMULATOR_WIDTH as f32, EMULATOR_HEIGHT as f32) * scale;
+            ui.centered_and_justified(|ui| {
+                ui.add(egui::Image::new(texture).fit_to_exact_size(size));
+            });
+        });
+    }
+}
+
+impl eframe::App for Gui<'_> {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // the keypad, unless a window is taking keys
+        if !ctx.wants_keyboard_input() {
+            ctx.input(|input| {
+                for (row, keys) in KEY_ROWS.iter().zip(KEYPAD_LAYOUT) {
+                    for (&key, keypad_key) in row.iter().zip(keys) {
+                        // egui names the keys by what's on them
+                        let key = Key::from_name(&key.to_string());
+                        let down = key.is_some_and(|key| input.key_down(key));
+                        self.keys[usize::from(keypad_key)] = if down { 255 } else { 0 };
+                    }
+                }
+            });
+        }
+
+        self.run();
+        self.menu_bar(ctx);
+        self.status_bar(ctx);
+        self.debug_panels(ctx);
+        self.display(ctx);
+        ctx.request_repaint_after(self.next_frame.saturating_duration_since(Instant::now()));
+    }
+}
+
+// Checkboxes for each quirk, returning whether any changed.
+fn quirks_menu(ui: &mut egui::Ui, quirks: &mut Quirks) -> bool {
+    let toggles = [
+        (&mut quirks.vf_reset, "VF reset by AND, OR and XOR"),
+        (&mut quirks.memory, "FX55 and FX65 move I"),
+        (&mut quirks.shifting, "Shifts ignore VY"),
+        (&mut quirks.jumping, "BNNN jumps to VX"),
+        (&mut quirks.clipping, "Sprites clip at the edges"),
+    ];
+    let mut changed = false;
+    for (quirk, label) in toggles {
+        changed |= ui.checkbox(quirk, label).changed();
+    }
+    changed
+}
+
+fn registers(ui: &mut egui::Ui, emu: &Chip8) {
+    let (delay, sound) = emu.timers();
+    egui::Grid::new("registers").striped(true).show(ui, |ui| {
+        for (row, values) in emu.registers().chunks(4).enumerate() {
+            for (column, value) in values.iter().enumerate() {
+                ui.monospace(format!("V{:X} {:02X}", row * 4 + column, value));
+            }
+            ui.end_row();
+        }
+        ui.monospace(format!("I {:03X}", emu.index()));
+        ui.monospace(format!("PC {:03X}", emu.pc()));
+        ui.monospace(format!("DT {:02X}", delay));
+        ui.monospace(format!("ST {:02X}", sound));
+        ui.end_row();
+    });
+    let stack: Vec<String> = emu
+        .stack()
+        .iter()
+        .map(|address| format!("{:03X}", address))
+        .collect();
+    ui.monospace(format!("Stack {}", stack.join(" ")));
+}
+
+fn disassembly(ui: &mut egui::Ui, emu: &Chip8) {
+    let pc = emu.pc();
+    let first = pc.saturating_sub(DISASSEMBLY_LINES * 2).max(0x200);
+    for address in (first..=pc + DISASSEMBLY_LINES * 2).step_by(2) {
+        let opcode = u16::from(emu.peek(address)) << 8 | u16::from(emu.peek(address + 1));
+        let instruction = disassemble(opcode).unwrap_or_else(|| "???".to_string());
+        let line =
+            RichText::new(format!("{:03X}  {:04X}  {}", address, opcode, instruction)).monospace();
+        if address == pc {
+            ui.label(
+                line.strong()
+                    .background_color(ui.visuals().selection.bg_fill),
+            );
+        } else {
+            ui.label(line);
+        }
+    }
+}
+
+fn memory(ui: &mut egui::Ui, emu: &Chip8) {
+    let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+    let rows = MEMORY_SIZE / MEMORY_ROW;
+    egui::ScrollArea::vertical().show_rows(ui, row_height, rows, |ui, shown| {
+        for row in shown {
+            let start = (row * MEMORY_ROW) as u16;
+            let bytes: Vec<String> = (start..start + MEMORY_ROW as u16)
+                .map(|address| format!("{:02X}", emu.peek(address)))
+                .collect();
+            ui.monospace(format!("{:03X}  {}", start, bytes.join(" ")));
+        }
+    });
+}
+
+fn about(ui: &mut egui::Ui, config: &Config) {
+    ui.heading("CHIP-8 emulator");
+    ui.label(format!("Version {}", env!("CARGO_PKG_VERSION")));
+    ui.label("A CHIP-8 emulator written in Rust.");
+    if let Some(metadata) = &config.metadata {
+        ui.separator();
+        ui.label(format!("Playing {}", metadata.credits()));
+    }
+}
+
+// The window title, the rom's title from the chip-8-database if it's there.
+fn title(config: &Config) -> String {
+    let rom = match &config.metadata {
+        Some(metadata) => metadata.title.clone(),
+        None => Path::new(&config.rom_path)
+            .file_name()
+            .map_or(config.rom_path.clone(), |name| {
+                name.to_string_lossy().into_owned()
+            }),
+    };
+    format!("CHIP-8 - {}", rom)
+}
+
+fn palette_label(name: PaletteName) -> &'static str {
+    match name {
+        PaletteName::Default => "Default",
+        PaletteName::Bitbee => "Bitbee",
+        PaletteName::NeutralGreen => "Neutral green",
+        PaletteName::MacPaint => "MacPaint",
+        PaletteName::PaperBack => "Paperback",
+    }
+}
+
+// For the launcher and --recent.
+fn remember(rom_path: &str) {
+    if let Err(e) = library::add_recent(Path::new(rom_path)) {
+        eprintln!("⚠️ Unable to remember the rom: {}", e);
+    }
+}
@@ -0,0 +1,254 @@
+// The emulator's own keys, as opposed to the chip8 keypad. Every action can
+// be rebound in the [hotkeys] section of the config file.
+use std::collections::{BTreeMap, HashMap};
+
+use chip8::config::KeymapPreset;
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Scancode};
+
+use crate::input::{Binding, Keymap};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    NextPalette,
+    ToggleKeypad,
+    ToggleKeyDisplay,
+    Counters,
+    Mute,
+    PrintScreen,
+    ToggleZoom,
+    ZoomIn,
+    ZoomOut,
+    PanLeft,
+    PanRight,
+    PanUp,
+    PanDown,
+    Pause,
+    FrameAdvance,
+    Slower,
+    Faster,
+    FastForward,
+    Rewind,
+    Turbo,
+    SoftReset,
+    HardReset,
+    SaveState,
+    LoadState,
+    PreviousSlot,
+    NextSlot,
+    SaveInputProfile,
+    NextRom,
+}
+
+// Every action with its name in the config file and its default key.
+const ACTIONS: [(Action, &str, Keycode); 29] = [
+    (Action::Quit, "quit", Keycode::Escape),
+    (Action::NextPalette, "palette", Keycode::P),
+    (Action::ToggleKeypad, "keypad", Keycode::K),
+    (Action::ToggleKeyDisplay, "key-display", Keycode::H),
+    (Action::Counters, "counters", Keycode::F1),
+    (Action::Mute, "mute", Keycode::M),
+    (Action::PrintScreen, "print-screen", Keycode::T),
+    (Action::ToggleZoom, "zoom", Keycode::O),
+    (Action::ZoomIn, "zoom-in", Keycode::PageUp),
+    (Action::ZoomOut, "zoom-out", Keycode::PageDown),
+    (Action::PanLeft, "pan-left", Keycode::Left),
+    (Action::PanRight, "pan-right", Keycode::Right),
+    (Action::PanUp, "pan-up", Keycode::Up),
+    (Action::PanDown, "pan-down", Keycode::Down),
+    (Action::Pause, "pause", Keycode::Space),
+    (Action::FrameAdvance, "frame-advance", Keycode::N),
+    (Action::Slower, "slower", Keycode::Minus),
+    (Action::Faster, "faster", Keycode::Equals),
+    (Action::FastForward, "fast-forward", Keycode::Tab),
+    (Action::Rewind, "rewind", Keycode::Backspace),
+    (Action::Turbo, "turbo", Keycode::Backquote),
+    (Action::SoftReset, "soft-reset", Keycode::F2),
+    (Action::HardReset, "hard-reset", Keycode::F3),
+    (Action::SaveState, "save-state", Keycode::F5),
+    (Action::LoadState, "load-state", Keycode::F9),
+    (Action::PreviousSlot, "previous-slot", Keycode::F6),
+    (Action::NextSlot, "next-slot", Keycode::F7),
+    (Action::SaveInputProfile, "save-input-profile", Keycode::F8),
+    (Action::NextRom, "next-rom", Keycode::F10),
+];
+
+impl Action {
+    // Whether holding the key down repeats the action.
+    fn repeats(self) -> bool {
+        matches!(
+            self,
+            Action::NextPalette
+                | Action::ZoomIn
+                | Action::ZoomOut
+                | Action::PanLeft
+                | Action::PanRight
+                | Action::PanUp
+                | Action::PanDown
+                | Action::FrameAdvance
+                | Action::Slower
+                | Action::Faster
+        )
+    }
+}
+
+// The hotkeys each keymap preset moves off the keys it takes, onto keys
+// it leaves free: C for the palette, G for the keypad, Z for zoom and V
+// for mute.
+fn moved_by(preset: KeymapPreset) -> &'static [(Action, Keycode)] {
+    match preset {
+        KeymapPreset::Dvorak => &[
+            (Action::NextPalette, Keycode::C),
+            (Action::ToggleKeypad, Keycode::G),
+            (Action::ToggleZoom, Keycode::Z),
+        ],
+        KeymapPreset::LeftHanded => &[
+            (Action::NextPalette, Keycode::C),
+            (Action::ToggleKeypad, Keycode::G),
+            (Action::ToggleZoom, Keycode::Z),
+            (Action::Mute, Keycode::V),
+        ],
+        _ => &[],
+    }
+}
+
+pub struct Hotkeys {
+    bindings: HashMap<Binding, Action>,
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Hotkeys {
+            bindings: ACTIONS
+                .iter()
+                .map(|(action, _, keycode)| (Binding::Keycode(*keycode), *action))
+                .collect(),
+        }
+    }
+}
+
+impl Hotkeys {
+    // Builds the hotkeys from the [hotkeys] section of the config file,
+    // which lists the keys for actions by name like the [keymap] section:
+    //
+    //   [hotkeys]
+    //   pause = ["P"]
+    //   palette = ["F4"]
+    //
+    // Actions that aren't listed keep their default key, or the key the
+    // keymap's preset moves them to, and an empty list turns an action off.
+    pub fn from_config(
+        config: &BTreeMap<String, Vec<String>>,
+        preset: KeymapPreset,
+    ) -> Result<Hotkeys, String> {
+        let mut hotkeys = Hotkeys::default();
+        for &(action, keycode) in moved_by(preset) {
+            hotkeys.rebind(action, vec![Binding::Keycode(keycode)]);
+        }
+        for (name, keys) in config {
+            let action = ACTIONS
+                .iter()
+                .find(|(_, action_name, _)| action_name == name)
+                .map(|(action, _, _)| *action)
+                .ok_or_else(|| format!("Unknown action '{}' in [hotkeys].", name))?;
+
+            let mut bindings = Vec::new();
+            for key in keys {
+                let binding = Binding::from_name(key)
+                    .ok_or_else(|| format!("Unknown keyboard key '{}' in [hotkeys].", key))?;
+                bindings.push(binding);
+            }
+            hotkeys.rebind(action, bindings);
+        }
+        Ok(hotkeys)
+    }
+
+    fn rebind(&mut self, action: Action, bindings: Vec<Binding>) {
+        self.bindings.retain(|_, bound| *bound != action);
+        for binding in bindings {
+            self.bindings.insert(binding, action);
+        }
+    }
+
+    // The names of the actions bound to keys the keymap also binds, in the
+    // order they're listed. The keypad gets those keys first, so the
+    // actions need rebinding to be used. The presets move the hotkeys in
+    // their way, so it's keys rebound in [keymap] that can take them.
+    pub fn taken_by(&self, keymap: &Keymap) -> Vec<&'static str> {
+        ACTIONS
+            .iter()
+            .filter(|(action, _, _)| {
+                self.bindings
+                    .iter()
+                    .any(|(binding, bound)| bound == action && keymap.binds(binding))
+            })
+            .map(|(_, name, _)| *name)
+            .collect()
+    }
+
+    // The action a keyboard event starts or stops, as (action, pressed).
+    // Key repeats only come through for actions that repeat.
+    pub fn event(&self, event: &Event) -> Option<(Action, bool)> {
+        match *event {
+            Event::KeyDown {
+                keycode,
+                scancode,
+                repeat,
+                ..
+            } => self
+                .action(keycode, scancode)
+                .filter(|action| !repeat || action.repeats())
+                .map(|action| (action, true)),
+            Event::KeyUp {
+                keycode, scancode, ..
+            } => self.action(keycode, scancode).map(|action| (action, false)),
+            _ => None,
+        }
+    }
+
+    fn action(&self, keycode: Option<Keycode>, scancode: Option<Scancode>) -> Option<Action> {
+        scancode
+            .and_then(|scancode| self.bindings.get(&Binding::Scancode(scancode)))
+            .or_else(|| keycode.and_then(|keycode| self.bindings.get(&Binding::Keycode(keycode))))
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebinding_moves_an_action() {
+        let mut hotkeys = Hotkeys::default();
+        hotkeys.rebind(Action::Pause, vec![Binding::Keycode(Keycode::F4)]);
+        assert_eq!(hotkeys.action(Some(Keycode::F4), None), Some(Action::Pause));
+        assert_eq!(hotkeys.action(Some(Keycode::Space), None), None);
+        assert_eq!(hotkeys.action(Some(Keycode::P), None), Some(Action::NextPalette));
+    }
+
+    #[test]
+    fn presets_leave_the_hotkeys_their_keys() {
+        for preset in KeymapPreset::ALL {
+            let hotkeys = Hotkeys::from_config(&BTreeMap::new(), preset).unwrap();
+            assert!(hotkeys.taken_by(&Keymap::preset(preset)).is_empty(), "{:?}", preset);
+        }
+        let dvorak = Hotkeys::from_config(&BTreeMap::new(), KeymapPreset::Dvorak).unwrap();
+        assert_eq!(dvorak.action(Some(Keycode::C), None), Some(Action::NextPalette));
+        assert_eq!(dvorak.action(Some(Keycode::P), None), None);
+        assert_eq!(dvorak.action(Some(Keycode::M), None), Some(Action::Mute));
+    }
+
+    #[test]
+    fn keys_the_keymap_takes_are_found() {
+        let dvorak = Keymap::preset(KeymapPreset::Dvorak);
+        let mut hotkeys = Hotkeys::default();
+        assert_eq!(hotkeys.taken_by(&dvorak), ["palette", "keypad", "zoom"]);
+        hotkeys.rebind(Action::ToggleZoom, vec![Binding::Keycode(Keycode::F4)]);
+        assert_eq!(hotkeys.taken_by(&dvorak), ["palette", "keypad"]);
+        assert!(Hotkeys::default()
+            .taken_by(&Keymap::preset(KeymapPreset::Qwerty))
+            .is_empty());
+    }
+}
@@ -0,0 +1,121 @@
+// The little of an HTTP server that --rpc and --metrics need: connections
+// answered a request each by a few threads, with the request's line and
+// headers read with limits on how long they can take and how big they can
+// be, so a client that never finishes, or never stops, can't tie it up.
+use std::io::{self, BufRead, Read};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// how long a client has to send its request before it's hung up on
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// the most connections answered at once, any more wait to be accepted
+const MAX_CONNECTIONS: usize = 4;
+// the longest the request line or a header can be, and the most headers
+const MAX_LINE: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 64;
+
+/// A request's line, e.g. "GET /metrics HTTP/1.1", and its headers.
+pub struct Head {
+    pub request: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Head {
+    /// The value of the header called `name`, in any case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Answers the connections to `listener` with `answer` on threads of their
+/// own, a few at a time. `what` names the requests in warnings.
+pub fn serve<F>(listener: TcpListener, what: &'static str, answer: F) -> io::Result<()>
+where
+    F: Fn(TcpStream) -> io::Result<()> + Send + Sync + 'static,
+{
+    let answer = Arc::new(answer);
+    for _ in 0..MAX_CONNECTIONS {
+        let listener = listener.try_clone()?;
+        let answer = Arc::clone(&answer);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let answered = stream
+                    .set_read_timeout(Some(REQUEST_TIMEOUT))
+                    .and_then(|()| answer(stream));
+                if let Err(e) = answered {
+                    eprintln!("⚠️ Problem answering {}: {}", what, e);
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Reads a request's line and headers, up to the blank line before its
+/// body.
+pub fn read_head(reader: &mut impl BufRead) -> io::Result<Head> {
+    let request = read_line(reader)?;
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(invalid("the request has too many headers"));
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.to_string(), value.trim().to_string()));
+        }
+    }
+    Ok(Head { request, headers })
+}
+
+// A line of the request without its line ending, read no further than
+// MAX_LINE.
+fn read_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    reader.take(MAX_LINE).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return Err(match line.len() as u64 {
+            MAX_LINE => invalid("a line of the request is too long"),
+            _ => io::Error::new(io::ErrorKind::UnexpectedEof, "the request ended early"),
+        });
+    }
+    Ok(line.trim_end().to_string())
+}
+
+fn invalid(error: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heads_are_read_within_limits() {
+        let request = "POST / HTTP/1.1\r\nContent-Length: 2\r\nHost: x\r\n\r\n{}";
+        let mut reader = request.as_bytes();
+        let head = read_head(&mut reader).unwrap();
+        assert_eq!(head.request, "POST / HTTP/1.1");
+        assert_eq!(head.header("content-length"), Some("2"));
+        assert_eq!(reader, b"{}");
+
+        let long = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(MAX_LINE as usize));
+        let error = read_head(&mut long.as_bytes()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let many = format!("GET / HTTP/1.1\r\n{}\r\n", "X: 1\r\n".repeat(MAX_HEADERS + 1));
+        let error = read_head(&mut many.as_bytes()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let error = read_head(&mut "GET / HTTP/1.1\r\nX: 1".as_bytes()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
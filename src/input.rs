@@ -0,0 +1,368 @@
+// Keyboard input for the chip8 keypad.
+use std::collections::HashMap;
+
+use chip8::config::{GpioSettings, KeymapPreset, KeymapSettings};
+#[cfg(feature = "gpio")]
+use chip8::input_source::gpio::GpioKeypad;
+use chip8::input_source::InputSource;
+use chip8::Config;
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Scancode};
+
+// The keypad is laid out as
+//
+//   1 2 3 C
+//   4 5 6 D
+//   7 8 9 E
+//   A 0 B F
+//
+// and the presets list keyboard keys in the same order.
+const KEYPAD_ORDER: [usize; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+
+// The left side of the keyboard by position, so it's the same physical keys
+// on AZERTY, QWERTZ and every other layout.
+const GRID: [Binding; 16] = [
+    Binding::Scancode(Scancode::Num1),
+    Binding::Scancode(Scancode::Num2),
+    Binding::Scancode(Scancode::Num3),
+    Binding::Scancode(Scancode::Num4),
+    Binding::Scancode(Scancode::Q),
+    Binding::Scancode(Scancode::W),
+    Binding::Scancode(Scancode::E),
+    Binding::Scancode(Scancode::R),
+    Binding::Scancode(Scancode::A),
+    Binding::Scancode(Scancode::S),
+    Binding::Scancode(Scancode::D),
+    Binding::Scancode(Scancode::F),
+    Binding::Scancode(Scancode::Z),
+    Binding::Scancode(Scancode::X),
+    Binding::Scancode(Scancode::C),
+    Binding::Scancode(Scancode::V),
+];
+
+const QWERTY: [Binding; 16] = [
+    Binding::Keycode(Keycode::Num1),
+    Binding::Keycode(Keycode::Num2),
+    Binding::Keycode(Keycode::Num3),
+    Binding::Keycode(Keycode::Num4),
+    Binding::Keycode(Keycode::Q),
+    Binding::Keycode(Keycode::W),
+    Binding::Keycode(Keycode::E),
+    Binding::Keycode(Keycode::R),
+    Binding::Keycode(Keycode::A),
+    Binding::Keycode(Keycode::S),
+    Binding::Keycode(Keycode::D),
+    Binding::Keycode(Keycode::F),
+    Binding::Keycode(Keycode::Z),
+    Binding::Keycode(Keycode::X),
+    Binding::Keycode(Keycode::C),
+    Binding::Keycode(Keycode::V),
+];
+
+// The number row needs shift on AZERTY, so it stays on the top row by
+// position.
+const AZERTY: [Binding; 16] = [
+    Binding::Scancode(Scancode::Num1),
+    Binding::Scancode(Scancode::Num2),
+    Binding::Scancode(Scancode::Num3),
+    Binding::Scancode(Scancode::Num4),
+    Binding::Keycode(Keycode::A),
+    Binding::Keycode(Keycode::Z),
+    Binding::Keycode(Keycode::E),
+    Binding::Keycode(Keycode::R),
+    Binding::Keycode(Keycode::Q),
+    Binding::Keycode(Keycode::S),
+    Binding::Keycode(Keycode::D),
+    Binding::Keycode(Keycode::F),
+    Binding::Keycode(Keycode::W),
+    Binding::Keycode(Keycode::X),
+    Binding::Keycode(Keycode::C),
+    Binding::Keycode(Keycode::V),
+];
+
+const DVORAK: [Binding; 16] = [
+    Binding::Keycode(Keycode::Num1),
+    Binding::Keycode(Keycode::Num2),
+    Binding::Keycode(Keycode::Num3),
+    Binding::Keycode(Keycode::Num4),
+    Binding::Keycode(Keycode::Quote),
+    Binding::Keycode(Keycode::Comma),
+    Binding::Keycode(Keycode::Period),
+    Binding::Keycode(Keycode::P),
+    Binding::Keycode(Keycode::A),
+    Binding::Keycode(Keycode::O),
+    Binding::Keycode(Keycode::E),
+    Binding::Keycode(Keycode::U),
+    Binding::Keycode(Keycode::Semicolon),
+    Binding::Keycode(Keycode::Q),
+    Binding::Keycode(Keycode::J),
+    Binding::Keycode(Keycode::K),
+];
+
+const LEFT_HANDED: [Binding; 16] = [
+    Binding::Scancode(Scancode::Num7),
+    Binding::Scancode(Scancode::Num8),
+    Binding::Scancode(Scancode::Num9),
+    Binding::Scancode(Scancode::Num0),
+    Binding::Scancode(Scancode::U),
+    Binding::Scancode(Scancode::I),
+    Binding::Scancode(Scancode::O),
+    Binding::Scancode(Scancode::P),
+    Binding::Scancode(Scancode::J),
+    Binding::Scancode(Scancode::K),
+    Binding::Scancode(Scancode::L),
+    Binding::Scancode(Scancode::Semicolon),
+    Binding::Scancode(Scancode::M),
+    Binding::Scancode(Scancode::Comma),
+    Binding::Scancode(Scancode::Period),
+    Binding::Scancode(Scancode::Slash),
+];
+
+// The digits on the number pad press the same digits, and the keys around
+// them stand in for A to F.
+const NUMPAD: [Binding; 16] = [
+    Binding::Scancode(Scancode::Kp1),
+    Binding::Scancode(Scancode::Kp2),
+    Binding::Scancode(Scancode::Kp3),
+    Binding::Scancode(Scancode::KpMinus),
+    Binding::Scancode(Scancode::Kp4),
+    Binding::Scancode(Scancode::Kp5),
+    Binding::Scancode(Scancode::Kp6),
+    Binding::Scancode(Scancode::KpPlus),
+    Binding::Scancode(Scancode::Kp7),
+    Binding::Scancode(Scancode::Kp8),
+    Binding::Scancode(Scancode::Kp9),
+    Binding::Scancode(Scancode::KpEnter),
+    Binding::Scancode(Scancode::KpDivide),
+    Binding::Scancode(Scancode::Kp0),
+    Binding::Scancode(Scancode::KpMultiply),
+    Binding::Scancode(Scancode::KpPeriod),
+];
+
+// Games for two players on one keyboard give each the columns on their side
+// of the keypad, Pong's paddles are 1/4 and C/D. The left half stays where
+// the grid has it and the right half moves over to the number pad.
+const TWO_PLAYER: [Binding; 16] = [
+    Binding::Scancode(Scancode::Num1),
+    Binding::Scancode(Scancode::Num2),
+    Binding::Scancode(Scancode::Kp8),
+    Binding::Scancode(Scancode::Kp9),
+    Binding::Scancode(Scancode::Q),
+    Binding::Scancode(Scancode::W),
+    Binding::Scancode(Scancode::Kp5),
+    Binding::Scancode(Scancode::Kp6),
+    Binding::Scancode(Scancode::A),
+    Binding::Scancode(Scancode::S),
+    Binding::Scancode(Scancode::Kp2),
+    Binding::Scancode(Scancode::Kp3),
+    Binding::Scancode(Scancode::Z),
+    Binding::Scancode(Scancode::X),
+    Binding::Scancode(Scancode::Kp0),
+    Binding::Scancode(Scancode::KpPeriod),
+];
+
+fn preset_bindings(preset: KeymapPreset) -> &'static [Binding; 16] {
+    match preset {
+        KeymapPreset::Grid => &GRID,
+        KeymapPreset::Qwerty => &QWERTY,
+        KeymapPreset::Azerty => &AZERTY,
+        KeymapPreset::Dvorak => &DVORAK,
+        KeymapPreset::LeftHanded => &LEFT_HANDED,
+        KeymapPreset::Numpad => &NUMPAD,
+        KeymapPreset::TwoPlayer => &TWO_PLAYER,
+    }
+}
+
+// Config names starting with this are physical keys rather than the
+// characters printed on them.
+const SCANCODE_PREFIX: &str = "scan:";
+
+// A keyboard key, either by its position or by what the layout maps it to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Scancode(Scancode),
+    Keycode(Keycode),
+}
+
+impl Binding {
+    // Parses a key name from the config file: an SDL key name like "W" or
+    // "Keypad 4", or an SDL scancode name with the "scan:" prefix.
+    pub fn from_name(name: &str) -> Option<Binding> {
+        match name.strip_prefix(SCANCODE_PREFIX) {
+            Some(name) => Scancode::from_name(name).map(Binding::Scancode),
+            None => Keycode::from_name(name).map(Binding::Keycode),
+        }
+    }
+
+    // The name of the key in the current layout.
+    fn label(&self) -> String {
+        match self {
+            Binding::Scancode(scancode) => match Keycode::from_scancode(*scancode) {
+                Some(keycode) => keycode.name(),
+                None => scancode.name().to_string(),
+            },
+            Binding::Keycode(keycode) => keycode.name(),
+        }
+    }
+}
+
+// Which keyboard keys press which keypad keys. A keypad key can have any
+// number of keyboard keys bound to it.
+pub struct Keymap {
+    bindings: HashMap<Binding, usize>,
+}
+
+impl Keymap {
+    pub fn preset(preset: KeymapPreset) -> Keymap {
+        Keymap {
+            bindings: preset_bindings(preset)
+                .iter()
+                .zip(KEYPAD_ORDER)
+                .map(|(binding, key)| (*binding, key))
+                .collect(),
+        }
+    }
+
+    // Builds a keymap from the [keymap] section of the config file, which
+    // picks a preset and lists the keyboard keys for keypad keys by name:
+    //
+    //   [keymap]
+    //   preset = "dvorak"
+    //   5 = ["W", "Up"]
+    //
+    // Keypad keys that aren't listed keep their bindings from the preset.
+    pub fn from_config(config: &KeymapSettings) -> Result<Keymap, String> {
+        let mut rebound = Vec::new();
+        for (key, names) in &config.bindings {
+            let key = usize::from_str_radix(key, 16)
+                .ok()
+                .filter(|key| *key < 16)
+                .ok_or_else(|| format!("Unknown keypad key '{}' in [keymap].", key))?;
+
+            let mut bindings = Vec::new();
+            for name in names {
+                let binding = Binding::from_name(name)
+                    .ok_or_else(|| format!("Unknown keyboard key '{}' in [keymap].", name))?;
+                bindings.push(binding);
+            }
+            rebound.push((key, bindings));
+        }
+
+        Ok(Keymap::preset(config.preset).rebind(rebound))
+    }
+
+    // Replaces all the bindings of each keypad key given.
+    fn rebind(mut self, rebound: Vec<(usize, Vec<Binding>)>) -> Keymap {
+        for (key, bindings) in rebound {
+            self.bindings.retain(|_, bound| *bound != key);
+            for binding in bindings {
+                self.bindings.insert(binding, key);
+            }
+        }
+        self
+    }
+
+    // The keypad key bound to a keyboard key, going by its position first.
+    pub fn key(&self, keycode: Option<Keycode>, scancode: Option<Scancode>) -> Option<usize> {
+        scancode
+            .and_then(|scancode| self.bindings.get(&Binding::Scancode(scancode)))
+            .or_else(|| keycode.and_then(|keycode| self.bindings.get(&Binding::Keycode(keycode))))
+            .copied()
+    }
+
+    // Whether a keyboard key presses a keypad key, whichever way it's bound.
+    pub fn binds(&self, binding: &Binding) -> bool {
+        match *binding {
+            Binding::Scancode(scancode) => {
+                self.key(Keycode::from_scancode(scancode), Some(scancode))
+            }
+            Binding::Keycode(keycode) => self.key(Some(keycode), Scancode::from_keycode(keycode)),
+        }
+        .is_some()
+    }
+
+    // The keypad key a keyboard event presses or releases, as (key, pressed).
+    pub fn event(&self, event: &Event) -> Option<(usize, bool)> {
+        match *event {
+            Event::KeyDown {
+                keycode, scancode, ..
+            } => self.key(keycode, scancode).map(|key| (key, true)),
+            Event::KeyUp {
+                keycode, scancode, ..
+            } => self.key(keycode, scancode).map(|key| (key, false)),
+            _ => None,
+        }
+    }
+
+    // A label for each keypad key: the shortest name among the keyboard
+    // keys bound to it, so it fits on the virtual keypad.
+    pub fn labels(&self) -> [String; 16] {
+        let mut labels: [String; 16] = Default::default();
+        for (binding, key) in &self.bindings {
+            let name = binding.label();
+            let label = &mut labels[*key];
+            if label.is_empty() || (name.len(), &name) < (label.len(), label) {
+                *label = name;
+            }
+        }
+        labels
+    }
+}
+
+// Opens the keypads wired up besides the keyboard.
+pub fn input_sources(config: &Config) -> Result<Vec<Box<dyn InputSource>>, String> {
+    config.gpio.iter().map(gpio_keypad).collect()
+}
+
+#[cfg(feature = "gpio")]
+fn gpio_keypad(settings: &GpioSettings) -> Result<Box<dyn InputSource>, String> {
+    let keypad = GpioKeypad::open(settings)
+        .map_err(|e| format!("Unable to open the GPIO keypad: {}", e))?;
+    Ok(Box::new(keypad))
+}
+
+#[cfg(not(feature = "gpio"))]
+fn gpio_keypad(_settings: &GpioSettings) -> Result<Box<dyn InputSource>, String> {
+    Err(
+        "A GPIO keypad is configured but this build doesn't support one, rebuild with --features gpio."
+            .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebinding_replaces_only_listed_keys() {
+        let keymap = Keymap::preset(KeymapPreset::Grid).rebind(vec![(
+            0x5,
+            vec![Binding::Keycode(Keycode::Up), Binding::Scancode(Scancode::I)],
+        )]);
+        assert_eq!(keymap.key(Some(Keycode::Up), None), Some(0x5));
+        assert_eq!(keymap.key(None, Some(Scancode::I)), Some(0x5));
+        assert_eq!(keymap.key(None, Some(Scancode::W)), None);
+        assert_eq!(keymap.key(None, Some(Scancode::Q)), Some(0x4));
+    }
+
+    #[test]
+    fn defaults_follow_position_not_layout() {
+        // the key left of T types Z on QWERTZ
+        let keymap = Keymap::preset(KeymapPreset::Grid);
+        assert_eq!(keymap.key(Some(Keycode::Z), Some(Scancode::Y)), None);
+        assert_eq!(keymap.key(Some(Keycode::Y), Some(Scancode::Z)), Some(0xA));
+    }
+
+    #[test]
+    fn presets_bind_every_key_once() {
+        for preset in KeymapPreset::ALL {
+            let keymap = Keymap::preset(preset);
+            assert_eq!(keymap.bindings.len(), 16);
+            let mut keys: Vec<usize> = keymap.bindings.values().copied().collect();
+            keys.sort();
+            assert_eq!(keys, (0..16).collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// Timed key presses read from a text file, for demos that play themselves
+// and for reproducing bugs that depend on exact input.
+//
+// Each line gives the frame (60 to a second, counted from the start) the
+// event happens on, then "down" or "up" and the keypad key in hex:
+//
+//   # wait a second, then hold 5 for half a second
+//   60 down 5
+//   90 up 5
+//
+// Blank lines and lines starting with # are ignored.
+use std::fs;
+use std::path::Path;
+
+/// A key pressed or released on a given frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScriptEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// Plays back a list of events as keypad state.
+#[derive(Debug, Default)]
+pub struct InputScript {
+    events: Vec<ScriptEvent>,
+    // the first event that hasn't happened yet
+    next: usize,
+    // keys held down by the script
+    held: [bool; 16],
+}
+
+impl InputScript {
+    pub fn new(mut events: Vec<ScriptEvent>) -> InputScript {
+        // events on the same frame keep their order
+        events.sort_by_key(|event| event.frame);
+        InputScript {
+            events,
+            ..InputScript::default()
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<InputScript, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        InputScript::parse(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(contents: &str) -> Result<InputScript, String> {
+        let mut events = Vec::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let event = parse_event(line)
+                .ok_or_else(|| format!("line {}: expected '<frame> down|up <key>'", number + 1))?;
+            events.push(event);
+        }
+        Ok(InputScript::new(events))
+    }
+
+    /// Plays every event up to and including `frame`, then marks the keys
+    /// the script is holding as pressed in `keys`.
+    pub fn apply(&mut self, frame: u64, keys: &mut [u8; 16]) {
+        while let Some(event) = self.events.get(self.next) {
+            if event.frame > frame {
+                break;
+            }
+            self.held[event.key as usize] = event.pressed;
+            self.next += 1;
+        }
+
+        for (key, held) in keys.iter_mut().zip(self.held) {
+            if held {
+                *key = 255;
+            }
+        }
+    }
+
+    /// Whether every event has been played.
+    pub fn finished(&self) -> bool {
+        self.next == self.events.len()
+    }
+}
+
+fn parse_event(line: &str) -> Option<ScriptEvent> {
+    let mut words = line.split_whitespace();
+    let frame = words.next()?.parse().ok()?;
+    let pressed = match words.next()? {
+        "down" => true,
+        "up" => false,
+        _ => return None,
+    };
+    let key = u8::from_str_radix(words.next()?, 16).ok().filter(|key| *key < 16)?;
+    if words.next().is_some() {
+        return None;
+    }
+
+    Some(ScriptEvent {
+        frame,
+        key,
+        pressed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays_events_on_their_frames() {
+        let mut script = InputScript::parse("# demo\n2 down a\n\n4 up A\n").unwrap();
+
+        let mut keys = [0; 16];
+        script.apply(1, &mut keys);
+        assert_eq!(keys[0xA], 0);
+        script.apply(2, &mut keys);
+        assert_eq!(keys[0xA], 255);
+
+        let mut keys = [0; 16];
+        script.apply(5, &mut keys);
+        assert_eq!(keys[0xA], 0);
+        assert!(script.finished());
+    }
+
+    #[test]
+    fn reports_bad_lines() {
+        let err = InputScript::parse("1 down 5\n2 hold 5\n").unwrap_err();
+        assert!(err.starts_with("line 2:"));
+        assert!(InputScript::parse("1 down 10\n").is_err());
+    }
+}
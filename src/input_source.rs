@@ -0,0 +1,110 @@
+// Keypad input from hardware other than the computer's own keyboard.
+use std::io;
+
+use crate::KEYPAD_LAYOUT;
+
+#[cfg(feature = "gpio")]
+pub mod gpio;
+
+/// Something besides the frontend's own input handling that can press
+/// keypad keys, polled once a frame.
+pub trait InputSource {
+    /// Marks the keys currently pressed on this source in `keys`.
+    fn poll(&mut self, keys: &mut [u8; 16]) -> io::Result<()>;
+}
+
+/// A digital pin a keypad is wired to.
+pub trait Pin {
+    fn write(&mut self, high: bool) -> io::Result<()>;
+    fn read(&mut self) -> io::Result<bool>;
+}
+
+/// A 4x4 matrix keypad, the kind with a row and a column wire per key.
+///
+/// Each row is driven low in turn, and a pressed key connects its row to
+/// its column, pulling the column down too. The columns need pull-up
+/// resistors so they read high otherwise. Keys are laid out like the COSMAC
+/// VIP's keypad.
+pub struct MatrixKeypad<P: Pin> {
+    rows: [P; 4],
+    columns: [P; 4],
+}
+
+impl<P: Pin> MatrixKeypad<P> {
+    /// Takes the row pins as outputs and the column pins as inputs, top to
+    /// bottom and left to right.
+    pub fn new(mut rows: [P; 4], columns: [P; 4]) -> io::Result<MatrixKeypad<P>> {
+        for row in &mut rows {
+            row.write(true)?;
+        }
+        Ok(MatrixKeypad { rows, columns })
+    }
+}
+
+impl<P: Pin> InputSource for MatrixKeypad<P> {
+    fn poll(&mut self, keys: &mut [u8; 16]) -> io::Result<()> {
+        for (row, row_pin) in self.rows.iter_mut().enumerate() {
+            row_pin.write(false)?;
+            for (col, column_pin) in self.columns.iter_mut().enumerate() {
+                if !column_pin.read()? {
+                    keys[KEYPAD_LAYOUT[row][col] as usize] = 255;
+                }
+            }
+            row_pin.write(true)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // Pins wired to an imaginary keypad with some keys held down.
+    enum FakePin {
+        Row(usize, Rc<Cell<Option<usize>>>),
+        Column(usize, Rc<Cell<Option<usize>>>, [[bool; 4]; 4]),
+    }
+
+    impl Pin for FakePin {
+        fn write(&mut self, high: bool) -> io::Result<()> {
+            if let FakePin::Row(row, driven) = self {
+                if !high {
+                    driven.set(Some(*row));
+                } else if driven.get() == Some(*row) {
+                    driven.set(None);
+                }
+            }
+            Ok(())
+        }
+
+        fn read(&mut self) -> io::Result<bool> {
+            match self {
+                FakePin::Column(col, driven, pressed) => {
+                    Ok(!driven.get().is_some_and(|row| pressed[row][*col]))
+                }
+                FakePin::Row(..) => Ok(true),
+            }
+        }
+    }
+
+    #[test]
+    fn scans_pressed_keys() {
+        let driven = Rc::new(Cell::new(None));
+        let mut pressed = [[false; 4]; 4];
+        pressed[0][3] = true;
+        pressed[3][1] = true;
+
+        let rows = [0, 1, 2, 3].map(|row| FakePin::Row(row, driven.clone()));
+        let columns = [0, 1, 2, 3].map(|col| FakePin::Column(col, driven.clone(), pressed));
+        let mut keypad = MatrixKeypad::new(rows, columns).unwrap();
+
+        let mut keys = [0; 16];
+        keypad.poll(&mut keys).unwrap();
+        assert_eq!(keys[0xC], 255);
+        assert_eq!(keys[0x0], 255);
+        assert_eq!(keys.iter().filter(|key| **key != 0).count(), 2);
+    }
+}
@@ -0,0 +1,231 @@
+// A matrix keypad on a Raspberry Pi's GPIO header, for handheld and kiosk
+// builds. The pins are requested from the kernel's GPIO character device,
+// as gpio-cdev and libgpiod do, rather than the deprecated sysfs interface:
+// the chip's lines are numbered by the header's BCM GPIO numbers on every
+// kernel, and the columns' pull-ups can be turned on here instead of in
+// config.txt.
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, FromRawFd};
+
+use super::{MatrixKeypad, Pin};
+use crate::config::GpioSettings;
+
+// The labels of the chip wired to the header on the Pi 1 to 3, the Pi 4 and
+// the Pi 5.
+const HEADER_CHIPS: [&str; 3] = ["pinctrl-bcm2835", "pinctrl-bcm2711", "pinctrl-rp1"];
+
+// What's needed of version 2 of the character device's interface, from
+// linux/gpio.h.
+const LINE_FLAG_INPUT: u64 = 1 << 2;
+const LINE_FLAG_OUTPUT: u64 = 1 << 3;
+const LINE_FLAG_BIAS_PULL_UP: u64 = 1 << 8;
+const LINE_ATTR_ID_OUTPUT_VALUES: u32 = 2;
+
+#[repr(C)]
+struct ChipInfo {
+    name: [u8; 32],
+    label: [u8; 32],
+    lines: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct LineConfigAttribute {
+    id: u32,
+    padding: u32,
+    // flags, output values or a debounce period, depending on the id
+    value: u64,
+    // the lines of the request it applies to
+    mask: u64,
+}
+
+#[repr(C)]
+struct LineConfig {
+    flags: u64,
+    num_attrs: u32,
+    padding: [u32; 5],
+    attrs: [LineConfigAttribute; 10],
+}
+
+#[repr(C)]
+struct LineRequest {
+    offsets: [u32; 64],
+    consumer: [u8; 32],
+    config: LineConfig,
+    num_lines: u32,
+    event_buffer_size: u32,
+    padding: [u32; 5],
+    fd: i32,
+}
+
+#[repr(C)]
+struct LineValues {
+    bits: u64,
+    mask: u64,
+}
+
+const fn ioctl_request(write: bool, nr: u64, size: usize) -> u64 {
+    let read = 2;
+    let direction = if write { read | 1 } else { read };
+    direction << 30 | (size as u64) << 16 | 0xB4 << 8 | nr
+}
+
+const GET_CHIPINFO: u64 = ioctl_request(false, 0x01, size_of::<ChipInfo>());
+const GET_LINE: u64 = ioctl_request(true, 0x07, size_of::<LineRequest>());
+const LINE_GET_VALUES: u64 = ioctl_request(true, 0x0E, size_of::<LineValues>());
+const LINE_SET_VALUES: u64 = ioctl_request(true, 0x0F, size_of::<LineValues>());
+
+fn ioctl<T>(file: &File, request: u64, arg: &mut T) -> io::Result<()> {
+    // the structs are laid out as the kernel's, which only reads and writes
+    // within them
+    match unsafe { libc::ioctl(file.as_raw_fd(), request as _, arg as *mut T) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+// The GPIO chip wired to the header, going by its label.
+fn header_chip() -> io::Result<File> {
+    let mut paths: Vec<_> = fs::read_dir("/dev")?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().and_then(|name| name.to_str());
+            name.is_some_and(|name| name.starts_with("gpiochip"))
+        })
+        .collect();
+    paths.sort();
+    for path in paths {
+        let chip = OpenOptions::new().read(true).write(true).open(&path)?;
+        let mut info = ChipInfo {
+            name: [0; 32],
+            label: [0; 32],
+            lines: 0,
+        };
+        ioctl(&chip, GET_CHIPINFO, &mut info)?;
+        let length = info.label.iter().position(|&b| b == 0).unwrap_or(32);
+        if HEADER_CHIPS.contains(&String::from_utf8_lossy(&info.label[..length]).as_ref()) {
+            return Ok(chip);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "there's no GPIO chip for a Raspberry Pi's header",
+    ))
+}
+
+/// A GPIO line requested from the character device.
+pub struct CdevPin {
+    line: File,
+}
+
+impl CdevPin {
+    /// Requests GPIO `gpio` of `chip` as an output, starting high.
+    pub fn output(chip: &File, gpio: u32) -> io::Result<CdevPin> {
+        let high = LineConfigAttribute {
+            id: LINE_ATTR_ID_OUTPUT_VALUES,
+            padding: 0,
+            value: 1,
+            mask: 1,
+        };
+        CdevPin::request(chip, gpio, LINE_FLAG_OUTPUT, Some(high))
+    }
+
+    /// Requests GPIO `gpio` of `chip` as an input, pulled up so a button
+    /// wired to ground reads high until it's pressed.
+    pub fn input(chip: &File, gpio: u32) -> io::Result<CdevPin> {
+        CdevPin::request(chip, gpio, LINE_FLAG_INPUT | LINE_FLAG_BIAS_PULL_UP, None)
+    }
+
+    fn request(
+        chip: &File,
+        gpio: u32,
+        flags: u64,
+        attr: Option<LineConfigAttribute>,
+    ) -> io::Result<CdevPin> {
+        let none = LineConfigAttribute {
+            id: 0,
+            padding: 0,
+            value: 0,
+            mask: 0,
+        };
+        let mut request = LineRequest {
+            offsets: [0; 64],
+            consumer: [0; 32],
+            config: LineConfig {
+                flags,
+                num_attrs: attr.is_some() as u32,
+                padding: [0; 5],
+                attrs: [attr.unwrap_or(none); 10],
+            },
+            num_lines: 1,
+            event_buffer_size: 0,
+            padding: [0; 5],
+            fd: -1,
+        };
+        request.offsets[0] = gpio;
+        request.consumer[..5].copy_from_slice(b"chip8");
+        ioctl(chip, GET_LINE, &mut request)
+            .map_err(|e| io::Error::new(e.kind(), format!("GPIO {}: {}", gpio, e)))?;
+        // the kernel hands back a new descriptor for the line, ours to close
+        let line = unsafe { File::from_raw_fd(request.fd) };
+        Ok(CdevPin { line })
+    }
+}
+
+impl Pin for CdevPin {
+    fn write(&mut self, high: bool) -> io::Result<()> {
+        let mut values = LineValues {
+            bits: u64::from(high),
+            mask: 1,
+        };
+        ioctl(&self.line, LINE_SET_VALUES, &mut values)
+    }
+
+    fn read(&mut self) -> io::Result<bool> {
+        let mut values = LineValues { bits: 0, mask: 1 };
+        ioctl(&self.line, LINE_GET_VALUES, &mut values)?;
+        Ok(values.bits & 1 != 0)
+    }
+}
+
+/// A matrix keypad wired to the GPIO pins in the [gpio] section.
+pub type GpioKeypad = MatrixKeypad<CdevPin>;
+
+impl GpioKeypad {
+    pub fn open(settings: &GpioSettings) -> io::Result<GpioKeypad> {
+        let chip = header_chip()?;
+        let [r0, r1, r2, r3] = settings.rows;
+        let [c0, c1, c2, c3] = settings.columns;
+        MatrixKeypad::new(
+            [
+                CdevPin::output(&chip, r0)?,
+                CdevPin::output(&chip, r1)?,
+                CdevPin::output(&chip, r2)?,
+                CdevPin::output(&chip, r3)?,
+            ],
+            [
+                CdevPin::input(&chip, c0)?,
+                CdevPin::input(&chip, c1)?,
+                CdevPin::input(&chip, c2)?,
+                CdevPin::input(&chip, c3)?,
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn structs_match_the_kernel() {
+        assert_eq!(size_of::<ChipInfo>(), 68);
+        assert_eq!(size_of::<LineConfig>(), 272);
+        assert_eq!(size_of::<LineRequest>(), 592);
+        assert_eq!(GET_LINE, 0xC250_B407);
+        assert_eq!(LINE_GET_VALUES, 0xC010_B40E);
+    }
+}
@@ -0,0 +1,331 @@
+// The launcher shown when the emulator is started without a rom, e.g. by
+// double-clicking it. It lists the roms played recently, the last one picked
+// out, above the rest of the roms in the rom directory and then the bundled
+// roms, and is driven by the arrow keys and Enter or by double-clicking a
+// rom.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use chip8::archive::read_rom;
+use chip8::bundled;
+use chip8::config::ConfigFile;
+use chip8::library::{find_roms, recent};
+use chip8::metadata::ProgramDatabase;
+use chip8::roms::rom_hash;
+use chip8::{Palette, PALETTES};
+
+use crate::overlay::{draw_text, text_height};
+
+// The roms on offer and which one is picked.
+struct Launcher {
+    roms: Vec<PathBuf>,
+    // how many of the roms at the top were played recently, and how many at
+    // the bottom are bundled
+    recent: usize,
+    bundled: usize,
+    selected: usize,
+    // the first rom shown, once the list is longer than the window
+    top: usize,
+}
+
+impl Launcher {
+    fn new(mut roms: Vec<PathBuf>, recent: Vec<PathBuf>) -> Launcher {
+        roms.retain(|rom| !recent.iter().any(|played| same_file(rom, played)));
+        let bundled: Vec<PathBuf> = bundled::ROMS
+            .iter()
+            .map(|rom| PathBuf::from(rom.path()))
+            .filter(|rom| !recent.contains(rom))
+            .collect();
+        Launcher {
+            recent: recent.len(),
+            bundled: bundled.len(),
+            roms: recent.into_iter().chain(roms).chain(bundled).collect(),
+            selected: 0,
+            top: 0,
+        }
+    }
+
+    // Whether a rule goes under the rom at `index`, between the recent,
+    // found and bundled roms.
+    fn rule_after(&self, index: usize) -> bool {
+        let next = index + 1;
+        next < self.roms.len() && (next == self.recent || next == self.roms.len() - self.bundled)
+    }
+
+    fn move_by(&mut self, rows: i32) {
+        let last = self.roms.len().saturating_sub(1) as i32;
+        self.selected = (self.selected as i32 + rows).clamp(0, last.max(0)) as usize;
+    }
+
+    // Scrolls so the picked rom is on screen when `rows` fit.
+    fn scroll(&mut self, rows: usize) {
+        let rows = rows.max(1);
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + rows {
+            self.top = self.selected + 1 - rows;
+        }
+    }
+
+    fn chosen(&self) -> Option<PathBuf> {
+        self.roms.get(self.selected).cloned()
+    }
+}
+
+// What a rom is listed as: its title if the chip-8-database knows it,
+// otherwise its file name, or for a bundled rom its name and what it does.
+fn label(rom: &Path, programs: &ProgramDatabase) -> String {
+    let path = rom.to_string_lossy();
+    if let Some(bundled) = bundled::find(&path) {
+        return format!("{} - {}", bundled.name, bundled.description);
+    }
+    let metadata = read_rom(&path)
+        .ok()
+        .and_then(|contents| programs.get(&rom_hash(&contents)));
+    if let Some(metadata) = metadata {
+        return metadata.credits();
+    }
+    rom.file_name().map_or_else(
+        || rom.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+// Where things go in a window of a given size.
+struct Layout {
+    scale: u32,
+    margin: i32,
+    line_height: i32,
+    list_top: i32,
+    rows: usize,
+}
+
+impl Layout {
+    fn new(width: u32, height: u32) -> Layout {
+        let scale = (width / 320).max(1);
+        let margin = (8 * scale) as i32;
+        let line_height = (text_height(scale) + 4 * scale) as i32;
+        let list_top = margin + 2 * line_height;
+        // room is left at the bottom for the hint
+        let room = height as i32 - list_top - margin - 2 * line_height;
+        Layout {
+            scale,
+            margin,
+            line_height,
+            list_top,
+            rows: (room / line_height).max(1) as usize,
+        }
+    }
+
+    // The row of the list at height `y`, counting from the top of the list.
+    fn row_at(&self, y: i32) -> Option<usize> {
+        let row = (y - self.list_top).div_euclid(self.line_height);
+        (0..self.rows as i32).contains(&row).then_some(row as usize)
+    }
+}
+
+// Shows the launcher until a rom is picked, or the window is closed.
+pub fn choose() -> Result<Option<PathBuf>, String> {
+    let file = ConfigFile::load()?;
+    let dir = file.library.directory.unwrap_or_else(|| PathBuf::from("."));
+    let roms = find_roms(&dir).unwrap_or_else(|e| {
+        eprintln!("⚠️ Unable to list the roms in {}: {}", dir.display(), e);
+        Vec::new()
+    });
+    let mut launcher = Launcher::new(roms, recent());
+    let programs = ProgramDatabase::load(file.library.database.as_deref())?;
+    let labels: Vec<String> = launcher
+        .roms
+        .iter()
+        .map(|rom| label(rom, &programs))
+        .collect();
+
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+    let window = video_subsystem
+        .window("chip-8-emu", file.display.width, file.display.height)
+        .resizable()
+        .position_centered()
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    let mut event_pump = sdl_context.event_pump()?;
+    let palette = &PALETTES[file.display.palette.index()];
+
+    loop {
+        let (width, height) = canvas.window().size();
+        let layout = Layout::new(width, height);
+        launcher.scroll(layout.rows);
+        draw(&mut canvas, palette, &layout, &launcher, &labels, &dir);
+
+        match event_pump.wait_event() {
+            Event::Quit { .. }
+            | Event::KeyDown {
+                keycode: Some(Keycode::Escape),
+                ..
+            } => return Ok(None),
+            Event::KeyDown {
+                keycode: Some(keycode),
+                ..
+            } => match keycode {
+                Keycode::Return | Keycode::KpEnter if !launcher.roms.is_empty() => {
+                    return Ok(launcher.chosen())
+                }
+                Keycode::Up => launcher.move_by(-1),
+                Keycode::Down => launcher.move_by(1),
+                Keycode::PageUp => launcher.move_by(-(layout.rows as i32)),
+                Keycode::PageDown => launcher.move_by(layout.rows as i32),
+                Keycode::Home => launcher.move_by(i32::MIN / 2),
+                Keycode::End => launcher.move_by(i32::MAX / 2),
+                _ => {}
+            },
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                y,
+                clicks,
+                ..
+            } => {
+                if let Some(row) = layout.row_at(y) {
+                    if launcher.top + row < launcher.roms.len() {
+                        launcher.selected = launcher.top + row;
+                        if clicks >= 2 {
+                            return Ok(launcher.chosen());
+                        }
+                    }
+                }
+            }
+            Event::MouseWheel { y, .. } => launcher.move_by(-y),
+            _ => {}
+        }
+    }
+}
+
+fn draw(
+    canvas: &mut Canvas<Window>,
+    palette: &Palette,
+    layout: &Layout,
+    launcher: &Launcher,
+    labels: &[String],
+    dir: &Path,
+) {
+    let (width, height) = canvas.window().size();
+    let scale = layout.scale;
+    canvas.set_draw_color(palette.background);
+    canvas.clear();
+
+    // with nothing but the bundled roms, say where else was looked
+    let title = if launcher.roms.len() == launcher.bundled {
+        format!("NO ROMS IN {}", dir.display())
+    } else {
+        "CHOOSE A ROM".to_string()
+    };
+    let (x, y) = (layout.margin, layout.margin);
+    draw_text(canvas, &title, x, y, scale, palette.foreground);
+
+    let visible = labels
+        .iter()
+        .enumerate()
+        .skip(launcher.top)
+        .take(layout.rows);
+    for (row, (index, name)) in visible.enumerate() {
+        let y = layout.list_top + row as i32 * layout.line_height;
+
+        // the picked rom is drawn inverted, like the badges
+        let mut color = palette.foreground;
+        if index == launcher.selected {
+            canvas.set_draw_color(palette.foreground);
+            let _result = canvas.fill_rect(Rect::new(
+                layout.margin - (2 * scale) as i32,
+                y - (2 * scale) as i32,
+                width - 2 * (layout.margin as u32 - 2 * scale),
+                layout.line_height as u32,
+            ));
+            color = palette.background;
+        }
+        draw_text(canvas, name, layout.margin, y, scale, color);
+
+        // rules set the recent and bundled roms apart from the rest
+        if launcher.rule_after(index) {
+            canvas.set_draw_color(palette.foreground);
+            let _result = canvas.fill_rect(Rect::new(
+                layout.margin,
+                y + layout.line_height - (3 * scale) as i32,
+                width - 2 * layout.margin as u32,
+                scale,
+            ));
+        }
+    }
+
+    let hint = "ENTER OR DOUBLE CLICK TO PLAY, ESC TO QUIT";
+    let hint_y = height as i32 - layout.margin - text_height(scale) as i32;
+    draw_text(
+        canvas,
+        hint,
+        layout.margin,
+        hint_y,
+        scale,
+        palette.foreground,
+    );
+    canvas.present();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roms(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn recent_roms_come_first() {
+        let recent = roms(&["/elsewhere/c.ch8", "b.ch8"]);
+        let launcher = Launcher::new(roms(&["a.ch8", "b.ch8"]), recent);
+        assert_eq!(
+            launcher.roms[..3],
+            roms(&["/elsewhere/c.ch8", "b.ch8", "a.ch8"])
+        );
+        assert_eq!((launcher.recent, launcher.selected), (2, 0));
+        assert!(launcher.rule_after(1));
+    }
+
+    #[test]
+    fn bundled_roms_come_last() {
+        let launcher = Launcher::new(roms(&["a.ch8"]), roms(&["bundled:bounce"]));
+        assert_eq!(launcher.roms.len(), 1 + bundled::ROMS.len());
+        assert_eq!(launcher.roms[1], PathBuf::from("a.ch8"));
+        assert!(launcher.rule_after(0) && launcher.rule_after(1));
+        let programs = ProgramDatabase::embedded();
+        assert_eq!(
+            label(&launcher.roms[0], &programs),
+            "bounce - a ball bouncing around"
+        );
+    }
+
+    #[test]
+    fn selection_stays_in_the_list_and_on_screen() {
+        let mut launcher = Launcher::new(roms(&["a", "b", "c", "d", "e"]), Vec::new());
+        let last = launcher.roms.len() - 1;
+        launcher.move_by(100);
+        assert_eq!(launcher.selected, last);
+        launcher.scroll(2);
+        assert_eq!(launcher.top, last - 1);
+        launcher.move_by(-10);
+        launcher.scroll(2);
+        assert_eq!((launcher.selected, launcher.top), (0, 0));
+    }
+}
@@ -1,30 +1,744 @@
-use sdl2::pixels::Color;
-use std::env;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+pub mod archive;
+pub mod asm;
+#[cfg(feature = "bevy_chip8")]
+pub mod bevy_plugin;
+pub mod bundled;
+pub mod chaos;
+pub mod cli;
+pub mod compat;
+pub mod config;
+pub mod corpus;
+pub mod cpu;
+#[cfg(feature = "dap")]
+pub mod dap;
+pub mod disasm;
+pub mod download;
+pub mod dump;
+pub mod frame;
+#[cfg(any(feature = "rpc", feature = "metrics"))]
+pub mod http_server;
+pub mod input_script;
+pub mod input_source;
+pub mod library;
+pub mod lockstep;
+pub mod metadata;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod movie;
+pub mod netplay;
+pub mod peripheral;
+pub mod quirks;
+pub mod report;
+pub mod roms;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod serial;
+pub mod sound;
+pub mod speed;
+pub mod speedrun;
+pub mod testrom;
+pub mod trace;
+#[cfg(feature = "ratatui")]
+pub mod widget;
+
+use archive::read_rom;
+use chaos::ChaosSettings;
+use config::{
+    AccessibilitySettings, AudioSettings, AutosaveSettings, ConfigFile, ControllerSettings,
+    DisplaySettings, GpioSettings, KeymapPreset, KeymapSettings, PaletteName,
+};
+use metadata::{ProgramDatabase, RomMetadata};
+use quirks::Quirks;
+use report::OutputFormat;
+use roms::{rom_hash, AudioOverrides, RomDatabase, RomSettings};
+use serial::SerialLink;
+use speedrun::Split;
+use trace::TraceFormat;
 
 /// Configurations for our application.
 ///
-/// filename: the path to the .c8 rom you want to run.
+/// rom_path: the path to the .c8 rom you want to run, or to a zip archive
+/// holding it. Roms given as URLs are downloaded first and this is where
+/// they were downloaded to.
+/// rom_hash: the SHA-1 of the rom, if it could be read.
+/// metadata: what the chip-8-database knows about the rom, if anything.
+/// display: the starting palette and window size.
+/// speed: instructions to run a second, to start with.
+/// start_paused: start paused before the first instruction, at 0x200.
+/// audio: how the buzzer sounds.
+/// record_audio: a WAV file to record the buzzer to.
+/// keymap: the keypad layout and any extra bindings.
+/// controller: how game controllers press keypad keys.
+/// input_script: a file of timed key presses to play back.
+/// record_movie: a movie file to record the run to.
+/// play_movie: a movie file to play back instead of taking input.
+/// export_video: a video file to render the played back movie to, instead of
+/// showing it in a window.
+/// headless: run without a window until the rom halts or runs out of
+/// instructions, instead of playing it.
+/// output: whether results and errors are printed for people or as JSON.
+/// benchmark: run headless as fast as possible for a while and report how
+/// fast that was, instead of playing the rom.
+/// compare_trace: a trace logged by another emulator to run the rom against,
+/// stopping where they differ, instead of playing it.
+/// trace_format: how the lines of that trace are laid out.
+/// terminal: play in the terminal instead of a window, drawing the display
+/// in this style.
+/// gui: play in the egui window, with menus and debug panels, instead of the
+/// plain one.
+/// serve: the address to serve the display and keypad to browsers on, over
+/// a WebSocket, instead of opening a window.
+/// rpc: the address to answer JSON-RPC requests on, for other programs to
+/// drive the emulator.
+/// metrics: the address to serve Prometheus metrics on, for monitoring.
+/// dap: the address to wait for Debug Adapter Protocol clients on.
+/// netplay: playing with someone on another machine, if asked to.
+/// serial: the other end of the serial port roms can send bytes over.
+/// script: a Rhai script to call back every frame, for HUDs and trainers.
+/// chaos: faults to cause on purpose, flipping bits of memory and dropping
+/// key presses, if asked to.
+/// check_invariants: stop the machine as soon as an instruction leaves it in
+/// a state it should never be in, for finding bugs in the emulator.
+/// threaded: run the machine on a thread of its own, so the window being
+/// slow to draw doesn't slow the rom down.
+/// watch: restart the rom whenever its file changes.
+/// playlist: the roms to take turns running, when a directory or playlist
+/// file was given instead of a rom. rom_path is the first of them.
+/// rotate: how long each rom in the playlist runs before the next one.
+/// hotkeys: keyboard keys bound to the emulator's own actions, by name.
+/// accessibility: ways to make the emulator easier to play.
+/// autosave: saving the machine on quitting and resuming it next time.
+/// gpio: the pins of a keypad wired to GPIO, if there is one.
+/// quirks: which variant of the disputed instructions to emulate, from the
+/// config file, the chip-8-database or the rom database, the latter first.
+/// splits: where the speedrun timer splits for this rom, if it has any.
 pub struct Config {
     pub rom_path: String,
+    pub rom_hash: Option<String>,
+    pub metadata: Option<RomMetadata>,
+    pub display: DisplaySettings,
+    pub speed: u32,
+    pub start_paused: bool,
+    pub audio: AudioSettings,
+    pub record_audio: Option<PathBuf>,
+    pub keymap: KeymapSettings,
+    pub controller: ControllerSettings,
+    pub input_script: Option<PathBuf>,
+    pub record_movie: Option<PathBuf>,
+    pub play_movie: Option<PathBuf>,
+    pub export_video: Option<PathBuf>,
+    pub headless: Option<Headless>,
+    pub output: OutputFormat,
+    pub benchmark: bool,
+    pub compare_trace: Option<PathBuf>,
+    pub trace_format: TraceFormat,
+    pub terminal: Option<TerminalStyle>,
+    pub gui: bool,
+    pub serve: Option<String>,
+    pub rpc: Option<String>,
+    pub metrics: Option<String>,
+    pub dap: Option<String>,
+    pub netplay: Option<NetplaySettings>,
+    pub serial: Option<SerialLink>,
+    pub script: Option<PathBuf>,
+    pub chaos: Option<ChaosSettings>,
+    pub check_invariants: bool,
+    pub threaded: bool,
+    pub watch: bool,
+    pub playlist: Vec<String>,
+    pub rotate: Option<Duration>,
+    pub hotkeys: BTreeMap<String, Vec<String>>,
+    pub accessibility: AccessibilitySettings,
+    pub autosave: AutosaveSettings,
+    pub gpio: Option<GpioSettings>,
+    pub quirks: Quirks,
+    pub splits: Vec<Split>,
 }
 
 impl Config {
-    /// Creates a new Config from env::Args.
-    pub fn new(mut args: env::Args) -> Result<Config, &'static str> {
+    /// Creates a new Config from the config file, the rom database and
+    /// env::Args, with the arguments taking priority. The arguments are
+    /// parsed and checked before anything's read, written or downloaded.
+    pub fn new(args: impl Iterator<Item = String>) -> Result<Config, String> {
+        let mut args = Args::parse(args)?;
+        let rom_arg = args.rom_path.take().ok_or("No .c8 rom was supplied.")?;
+        args.check(library::is_playlist(Path::new(&rom_arg)))?;
+
+        let file = ConfigFile::load()?;
+        let rom = FoundRom::find(rom_arg, &file)?;
+        args.configure(file, rom)
+    }
+}
+
+// The command line, before it's merged with the config file and the
+// databases. Settings that can also come from those are None when they
+// weren't given.
+#[derive(Default)]
+struct Args {
+    rom_path: Option<String>,
+    palette: Option<PaletteName>,
+    width: Option<u32>,
+    height: Option<u32>,
+    speed: Option<u32>,
+    start_paused: bool,
+    audio: AudioOverrides,
+    visual_beep: bool,
+    audio_buffer: Option<u16>,
+    record_audio: Option<PathBuf>,
+    keymap: Option<KeymapPreset>,
+    deadzone: Option<f32>,
+    input_script: Option<PathBuf>,
+    record_movie: Option<PathBuf>,
+    play_movie: Option<PathBuf>,
+    export_video: Option<PathBuf>,
+    headless: bool,
+    max_cycles: Option<u64>,
+    exit_on_halt: bool,
+    dump_screen: Option<PathBuf>,
+    dump_state: Option<PathBuf>,
+    output: OutputFormat,
+    benchmark: bool,
+    compare_trace: Option<PathBuf>,
+    trace_format: Option<TraceFormat>,
+    terminal: bool,
+    braille: bool,
+    gui: bool,
+    serve: Option<String>,
+    rpc: Option<String>,
+    metrics: Option<String>,
+    dap: Option<String>,
+    netplay_host: Option<String>,
+    netplay_join: Option<String>,
+    netplay_delay: Option<u64>,
+    serial: Option<SerialLink>,
+    script: Option<PathBuf>,
+    flip_bits: Option<f64>,
+    drop_keys: Option<f64>,
+    chaos_seed: Option<u64>,
+    check_invariants: bool,
+    threaded: bool,
+    sticky_keys: bool,
+    watch: bool,
+    rotate: Option<u64>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
         // the first arg is always the name of the command that executed
         // this program
         args.next();
 
-        if args.len() > 2 {
-            return Err("Not enough arguments");
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--palette" => parsed.palette = Some(parse_value(&arg, args.next())?),
+                "--width" => parsed.width = Some(parse_value(&arg, args.next())?),
+                "--height" => parsed.height = Some(parse_value(&arg, args.next())?),
+                "--speed" => parsed.speed = Some(parse_value(&arg, args.next())?),
+                "--start-paused" => parsed.start_paused = true,
+                "--frequency" => parsed.audio.frequency = Some(parse_value(&arg, args.next())?),
+                "--waveform" => parsed.audio.waveform = Some(parse_value(&arg, args.next())?),
+                "--volume" => parsed.audio.volume = Some(parse_value(&arg, args.next())?),
+                "--mute" => parsed.audio.muted = Some(true),
+                "--visual-beep" => parsed.visual_beep = true,
+                "--audio-buffer" => parsed.audio_buffer = Some(parse_value(&arg, args.next())?),
+                "--record-audio" => parsed.record_audio = Some(parse_value(&arg, args.next())?),
+                "--keymap" => parsed.keymap = Some(parse_value(&arg, args.next())?),
+                "--deadzone" => parsed.deadzone = Some(parse_value(&arg, args.next())?),
+                "--input-script" => parsed.input_script = Some(parse_value(&arg, args.next())?),
+                "--record-movie" => parsed.record_movie = Some(parse_value(&arg, args.next())?),
+                "--play-movie" => parsed.play_movie = Some(parse_value(&arg, args.next())?),
+                "--export-video" => parsed.export_video = Some(parse_value(&arg, args.next())?),
+                "--headless" => parsed.headless = true,
+                "--max-cycles" => parsed.max_cycles = Some(parse_value(&arg, args.next())?),
+                "--exit-on-halt" => parsed.exit_on_halt = true,
+                "--dump-screen" => parsed.dump_screen = Some(parse_value(&arg, args.next())?),
+                "--dump-state" => parsed.dump_state = Some(parse_value(&arg, args.next())?),
+                "--output" => parsed.output = parse_value(&arg, args.next())?,
+                "--benchmark" => parsed.benchmark = true,
+                "--compare-trace" => parsed.compare_trace = Some(parse_value(&arg, args.next())?),
+                "--trace-format" => parsed.trace_format = Some(parse_value(&arg, args.next())?),
+                "--terminal" => parsed.terminal = true,
+                "--braille" => parsed.braille = true,
+                "--gui" => parsed.gui = true,
+                "--serve" => parsed.serve = Some(parse_value(&arg, args.next())?),
+                "--rpc" => parsed.rpc = Some(parse_value(&arg, args.next())?),
+                "--metrics" => parsed.metrics = Some(parse_value(&arg, args.next())?),
+                "--dap" => parsed.dap = Some(parse_value(&arg, args.next())?),
+                "--netplay-host" => parsed.netplay_host = Some(parse_value(&arg, args.next())?),
+                "--netplay-join" => parsed.netplay_join = Some(parse_value(&arg, args.next())?),
+                "--netplay-delay" => parsed.netplay_delay = Some(parse_value(&arg, args.next())?),
+                "--serial" => parsed.serial = Some(parse_value(&arg, args.next())?),
+                "--script" => parsed.script = Some(parse_value(&arg, args.next())?),
+                "--flip-bits" => parsed.flip_bits = Some(parse_value(&arg, args.next())?),
+                "--drop-keys" => parsed.drop_keys = Some(parse_value(&arg, args.next())?),
+                "--chaos-seed" => parsed.chaos_seed = Some(parse_value(&arg, args.next())?),
+                "--check-invariants" => parsed.check_invariants = true,
+                "--threaded" => parsed.threaded = true,
+                "--sticky-keys" => parsed.sticky_keys = true,
+                "--watch" => parsed.watch = true,
+                "--rotate" => parsed.rotate = Some(parse_value(&arg, args.next())?),
+                flag if flag.starts_with("--") => {
+                    return Err(format!("Unknown option {}.", flag))
+                }
+                _ if parsed.rom_path.is_some() => return Err("Too many arguments.".to_string()),
+                _ => parsed.rom_path = Some(arg),
+            }
+        }
+        Ok(parsed)
+    }
+
+    // Checks the flags given make sense together, `playlist` saying whether
+    // the rom given is a directory or playlist of them.
+    fn check(&self, playlist: bool) -> Result<(), String> {
+        let movie = self.record_movie.is_some() || self.play_movie.is_some();
+        if self.watch && movie {
+            return Err("--watch can't be used with a movie.".to_string());
+        }
+        if playlist && movie {
+            return Err("A playlist can't be used with a movie.".to_string());
+        }
+        if self.rotate.is_some() && !playlist {
+            return Err("--rotate needs a directory or playlist of roms.".to_string());
+        }
+        if self.rotate == Some(0) {
+            return Err("--rotate must be at least a second.".to_string());
+        }
+        if self.record_movie.is_some() && self.play_movie.is_some() {
+            return Err("A movie can't be recorded and played at once.".to_string());
+        }
+        if self.export_video.is_some() && self.play_movie.is_none() {
+            return Err("--export-video needs a movie, given with --play-movie.".to_string());
+        }
+        let headless = self.headless;
+        let headless_flags = [
+            ("--max-cycles", self.max_cycles.is_some()),
+            ("--exit-on-halt", self.exit_on_halt),
+            ("--dump-screen", self.dump_screen.is_some()),
+            ("--dump-state", self.dump_state.is_some()),
+        ];
+        for (flag, given) in headless_flags {
+            if given && !headless {
+                return Err(format!("{} needs --headless.", flag));
+            }
+        }
+        if headless && self.max_cycles.is_none() && !self.exit_on_halt {
+            return Err("--headless needs --max-cycles, --exit-on-halt or both.".to_string());
+        }
+        let windowed = movie || self.watch;
+        if headless && windowed {
+            return Err("--headless can't be used with a movie or --watch.".to_string());
+        }
+        let benchmark = self.benchmark;
+        if benchmark && (headless || windowed) {
+            let error = "--benchmark can't be used with --headless, a movie or --watch.";
+            return Err(error.to_string());
+        }
+        if self.trace_format.is_some() && self.compare_trace.is_none() {
+            return Err("--trace-format needs --compare-trace.".to_string());
+        }
+        let comparing = self.compare_trace.is_some();
+        let (gui, terminal, serve) = (self.gui, self.terminal, self.serve.is_some());
+        let frontend = gui || terminal || serve;
+        let input_script = self.input_script.is_some();
+        if comparing && (headless || benchmark || windowed || frontend || input_script) {
+            let error = "--compare-trace can't be used with --headless, --benchmark, --gui, \
+                         --terminal, --serve, a movie, an input script or --watch.";
+            return Err(error.to_string());
+        }
+        if self.braille && !terminal {
+            return Err("--braille needs --terminal.".to_string());
+        }
+        if gui && terminal {
+            return Err("--gui and --terminal can't be used together.".to_string());
+        }
+        if gui && (headless || benchmark || windowed) {
+            let error = "--gui can't be used with --headless, --benchmark, a movie or --watch.";
+            return Err(error.to_string());
+        }
+        if terminal && (headless || benchmark || windowed) {
+            let error = "--terminal can't be used with --headless, --benchmark, a movie or \
+                         --watch.";
+            return Err(error.to_string());
+        }
+        if serve && (gui || terminal) {
+            return Err("--serve can't be used with --gui or --terminal.".to_string());
+        }
+        if serve && (headless || benchmark || windowed) {
+            let error = "--serve can't be used with --headless, --benchmark, a movie or --watch.";
+            return Err(error.to_string());
+        }
+        let (rpc, dap) = (self.rpc.is_some(), self.dap.is_some());
+        if rpc && (gui || terminal || headless || benchmark || comparing) {
+            let error = "--rpc only works with the window and --serve.";
+            return Err(error.to_string());
+        }
+        if self.metrics.is_some() && (gui || terminal || headless || benchmark || comparing) {
+            let error = "--metrics only works with the window and --serve.";
+            return Err(error.to_string());
+        }
+        if dap && (frontend || headless || benchmark || comparing) {
+            return Err("--dap only works in the window.".to_string());
+        }
+        if self.netplay_host.is_some() && self.netplay_join.is_some() {
+            return Err("--netplay-host can't be used with --netplay-join.".to_string());
+        }
+        // the machines only stay in step if nothing but the keys changes
+        // them, and they're both running the same rom
+        let netplay = self.netplay_host.is_some() || self.netplay_join.is_some();
+        let elsewhere = frontend || headless || benchmark || comparing;
+        let tampered = windowed || input_script || rpc || dap;
+        let (serial, script) = (self.serial.is_some(), self.script.is_some());
+        if netplay && (elsewhere || tampered || serial || script || playlist) {
+            let error = "Netplay only works in the window, without --watch, a movie, an input \
+                         script, --rpc, --dap, --serial, --script or a playlist.";
+            return Err(error.to_string());
+        }
+        if serial && gui {
+            return Err("--serial can't be used with --gui.".to_string());
+        }
+        if script && (frontend || headless || benchmark || comparing) {
+            return Err("--script only works in the window.".to_string());
+        }
+        let chaos = self.flip_bits.is_some() || self.drop_keys.is_some();
+        if self.chaos_seed.is_some() && !chaos {
+            return Err("--chaos-seed needs --flip-bits or --drop-keys.".to_string());
+        }
+        let rates = [self.flip_bits, self.drop_keys];
+        if !rates.iter().flatten().all(|rate| (0.0..=1.0).contains(rate)) {
+            return Err("--flip-bits and --drop-keys must be from 0 to 1.".to_string());
+        }
+        if chaos && (frontend || benchmark || comparing || windowed || netplay) {
+            let error = "--flip-bits and --drop-keys only work in the window and with \
+                         --headless, without a movie, --watch or netplay.";
+            return Err(error.to_string());
+        }
+        // the window and the thread only meet once a frame or so, which
+        // anything that has to be in step with the machine frame by frame
+        // can't work with
+        if self.threaded && (frontend || headless || benchmark || comparing) {
+            return Err("--threaded only works in the window.".to_string());
+        }
+        let in_step = movie || input_script || self.record_audio.is_some() || dap;
+        if self.threaded && (in_step || netplay || script || chaos) {
+            let error = "--threaded can't be used with a movie, an input script, --record-audio, \
+                         --dap, netplay, --script, --flip-bits or --drop-keys.";
+            return Err(error.to_string());
+        }
+        if self.netplay_delay.is_some_and(|delay| delay > netplay::MAX_DELAY) {
+            let error = format!("--netplay-delay can be at most {}.", netplay::MAX_DELAY);
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // Settles each setting from the config file, the databases' entries for
+    // the rom and the command line, the latter first, and checks what they
+    // came to.
+    fn configure(self, file: ConfigFile, rom: FoundRom) -> Result<Config, String> {
+        let mut display = file.display;
+        let mut speed = file.emulation.speed;
+        let mut audio = file.audio;
+        let mut keymap = file.keymap;
+        let mut controller = file.controller;
+        let mut quirks = file.quirks;
+        if let Some(rom_quirks) = rom.metadata.as_ref().and_then(|metadata| metadata.quirks) {
+            quirks = rom_quirks;
+        }
+        let mut splits = Vec::new();
+        if let Some(settings) = rom.settings {
+            if let Some(palette) = settings.palette {
+                display.palette = palette;
+            }
+            if let Some(rom_speed) = settings.speed {
+                speed = rom_speed;
+            }
+            if let Some(rom_quirks) = settings.quirks {
+                quirks = rom_quirks;
+            }
+            settings.audio.apply(&mut audio);
+            if let Some(profile) = settings.keymap {
+                keymap = profile;
+            }
+            if let Some(profile) = settings.controller {
+                controller = profile;
+            }
+            splits = settings.splits;
+        }
+        if let Some(palette) = self.palette {
+            display.palette = palette;
+        }
+        display.width = self.width.unwrap_or(display.width);
+        display.height = self.height.unwrap_or(display.height);
+        speed = self.speed.unwrap_or(speed);
+        self.audio.apply(&mut audio);
+        audio.visual_beep |= self.visual_beep;
+        if self.audio_buffer.is_some() {
+            audio.buffer_size = self.audio_buffer;
+        }
+        if let Some(preset) = self.keymap {
+            keymap.preset = preset;
+        }
+        controller.deadzone = self.deadzone.unwrap_or(controller.deadzone);
+        let mut accessibility = file.accessibility;
+        accessibility.sticky_keys |= self.sticky_keys;
+
+        if display.width == 0 || display.height == 0 {
+            return Err("The window must be at least a pixel wide and high.".to_string());
+        }
+        // the speed's run as instructions a frame, 60 frames a second
+        if speed < 60 {
+            return Err("The speed must be at least 60 instructions a second.".to_string());
+        }
+        if audio.tone.frequency <= 0.0 {
+            return Err("The tone frequency must be above 0.".to_string());
         }
+        audio.tone.volume = audio.tone.volume.clamp(0.0, 1.0);
+        if !audio.buffer_size().is_power_of_two() {
+            return Err("The audio buffer size must be a power of two.".to_string());
+        }
+        if !(0.0..1.0).contains(&controller.deadzone) {
+            return Err("The controller deadzone must be from 0 up to 1.".to_string());
+        }
+
+        let peer = match (self.netplay_host, self.netplay_join) {
+            (Some(address), _) => Some(Peer::Host(address)),
+            (None, Some(address)) => Some(Peer::Join(address)),
+            (None, None) => None,
+        };
+        let chaos = (self.flip_bits.is_some() || self.drop_keys.is_some()).then(|| {
+            ChaosSettings {
+                flip_bits: self.flip_bits.unwrap_or(0.0),
+                drop_keys: self.drop_keys.unwrap_or(0.0),
+                seed: self.chaos_seed.unwrap_or_else(rand::random),
+            }
+        });
+        Ok(Config {
+            rom_path: rom.path,
+            rom_hash: rom.hash,
+            metadata: rom.metadata,
+            display,
+            speed,
+            start_paused: file.emulation.start_paused || self.start_paused,
+            audio,
+            record_audio: self.record_audio,
+            keymap,
+            controller,
+            input_script: self.input_script,
+            record_movie: self.record_movie,
+            play_movie: self.play_movie,
+            export_video: self.export_video,
+            headless: self.headless.then_some(Headless {
+                max_cycles: self.max_cycles,
+                exit_on_halt: self.exit_on_halt,
+                dump_screen: self.dump_screen,
+                dump_state: self.dump_state,
+            }),
+            output: self.output,
+            benchmark: self.benchmark,
+            compare_trace: self.compare_trace,
+            trace_format: self.trace_format.unwrap_or_default(),
+            terminal: self.terminal.then_some(if self.braille {
+                TerminalStyle::Braille
+            } else {
+                TerminalStyle::HalfBlocks
+            }),
+            gui: self.gui,
+            serve: self.serve,
+            rpc: self.rpc,
+            metrics: self.metrics,
+            dap: self.dap,
+            netplay: peer.map(|peer| NetplaySettings {
+                peer,
+                delay: self.netplay_delay.unwrap_or(netplay::DEFAULT_DELAY),
+            }),
+            serial: self.serial,
+            script: self.script,
+            chaos,
+            check_invariants: self.check_invariants,
+            threaded: self.threaded,
+            watch: self.watch,
+            playlist: rom.playlist,
+            rotate: self.rotate.map(Duration::from_secs),
+            hotkeys: file.hotkeys,
+            accessibility,
+            autosave: file.autosave,
+            gpio: file.gpio,
+            quirks,
+            splits,
+        })
+    }
+}
+
+// The rom to run, found from what was given on the command line: the first
+// of a playlist, or downloaded from a URL. Along with it, what the databases
+// have to say about it.
+struct FoundRom {
+    path: String,
+    hash: Option<String>,
+    metadata: Option<RomMetadata>,
+    settings: Option<RomSettings>,
+    playlist: Vec<String>,
+}
+
+impl FoundRom {
+    fn find(arg: String, file: &ConfigFile) -> Result<FoundRom, String> {
+        let playlist = match library::is_playlist(Path::new(&arg)) {
+            true => library::read_playlist(Path::new(&arg))?,
+            false => Vec::new(),
+        };
+        let path = playlist.first().cloned().unwrap_or(arg);
+        let path = match download::is_url(&path) {
+            true => download::fetch(&path)?,
+            false => path,
+        };
 
-        let rom_path = match args.next() {
-            Some(arg) => arg,
-            None => return Err("No .c8 rom was supplied."),
+        // a rom that can't be read is reported when it's loaded
+        let hash = read_rom(&path).ok().map(|rom| rom_hash(&rom));
+        let programs = ProgramDatabase::load(file.library.database.as_deref())?;
+        let metadata = hash.as_ref().and_then(|hash| programs.get(hash));
+        let settings = match &hash {
+            Some(hash) => RomDatabase::load()?.get(hash).cloned(),
+            None => None,
         };
+        Ok(FoundRom {
+            path,
+            hash,
+            metadata,
+            settings,
+            playlist,
+        })
+    }
+}
+
+impl Config {
+    /// Switches to another rom, as when one is dropped on the window. Its
+    /// quirks and splits come from the databases, the rest of the settings
+    /// stay as they are.
+    pub fn switch_rom(&mut self, rom_path: String) -> Result<(), String> {
+        // playlists can list roms by URL
+        let rom_path = if download::is_url(&rom_path) {
+            download::fetch(&rom_path)?
+        } else {
+            rom_path
+        };
+        let rom = read_rom(&rom_path).map_err(|e| format!("Unable to read {}: {}", rom_path, e))?;
+        let hash = rom_hash(&rom);
+        let file = ConfigFile::load()?;
+        let metadata = ProgramDatabase::load(file.library.database.as_deref())?.get(&hash);
+        let quirks = metadata
+            .as_ref()
+            .and_then(|metadata| metadata.quirks)
+            .unwrap_or(file.quirks);
+        let settings = RomDatabase::load()?.get(&hash).cloned().unwrap_or_default();
+
+        self.quirks = settings.quirks.unwrap_or(quirks);
+        self.splits = settings.splits;
+        self.rom_path = rom_path;
+        self.rom_hash = Some(hash);
+        self.metadata = metadata;
+        Ok(())
+    }
+}
+
+/// How to run without a window, given with --headless.
+///
+/// max_cycles: stop after this many instructions.
+/// exit_on_halt: stop once the rom jumps to itself, or waits for a key that
+/// no input script is going to press.
+/// dump_screen: a file to write the display to at the end, as a PBM image.
+/// dump_state: a file to save the machine to at the end, as a save state.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Headless {
+    pub max_cycles: Option<u64>,
+    pub exit_on_halt: bool,
+    pub dump_screen: Option<PathBuf>,
+    pub dump_state: Option<PathBuf>,
+}
+
+/// Playing a two-player rom with someone on another machine, given with
+/// --netplay-host or --netplay-join.
+///
+/// peer: whether to wait for the other player or join them.
+/// delay: how many frames keys take to reach the rom, from --netplay-delay.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetplaySettings {
+    pub peer: Peer,
+    pub delay: u64,
+}
+
+/// Which end of a netplay session this is, and the address to wait on or
+/// join.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Peer {
+    Host(String),
+    Join(String),
+}
+
+/// How the display is drawn when playing in the terminal, given with
+/// --terminal and --braille.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TerminalStyle {
+    /// two pixels to a character, in color
+    HalfBlocks,
+    /// eight pixels to a character, for small terminals
+    Braille,
+}
+
+// Parses the value following a command line flag.
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    match value {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("Invalid value '{}' for {}.", value, flag)),
+        None => Err(format!("Missing value for {}.", flag)),
+    }
+}
+
+/// The chip8 keypad as it was laid out on the COSMAC VIP.
+pub const KEYPAD_LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+/// The keyboard keys for each row of KEYPAD_LAYOUT, by what's printed on
+/// them on a US keyboard, so the keypad is the same shape on the keyboard's
+/// left.
+pub const KEY_ROWS: [[char; 4]; 4] = [
+    ['1', '2', '3', '4'],
+    ['Q', 'W', 'E', 'R'],
+    ['A', 'S', 'D', 'F'],
+    ['Z', 'X', 'C', 'V'],
+];
+
+/// The keypad key the keyboard key with `c` on it presses, if any, in
+/// either case.
+pub fn keypad_key(c: char) -> Option<u8> {
+    let c = c.to_ascii_uppercase();
+    KEY_ROWS
+        .iter()
+        .zip(KEYPAD_LAYOUT)
+        .find_map(|(row, keys)| row.iter().position(|&key| key == c).map(|i| keys[i]))
+}
 
-        Ok(Config { rom_path })
+/// The keypad key for a KeyboardEvent.code, e.g. "KeyQ" or "Digit1", which
+/// names a key by where it is rather than what's printed on it, so the
+/// keypad stays put whatever the keyboard's layout.
+pub fn keypad_key_for_code(code: &str) -> Option<u8> {
+    let name = code.strip_prefix("Key").or_else(|| code.strip_prefix("Digit"))?;
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => keypad_key(c),
+        _ => None,
     }
 }
 
@@ -36,7 +750,36 @@ pub const PALETTES: [Palette; 5] = [
     PAPER_BACK,
 ];
 
+/// A color on screen, in red, green and blue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b }
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl From<Color> for sdl2::pixels::Color {
+    fn from(color: Color) -> Self {
+        sdl2::pixels::Color::RGB(color.r, color.g, color.b)
+    }
+}
+
+#[cfg(feature = "ratatui")]
+impl From<Color> for ratatui::style::Color {
+    fn from(color: Color) -> Self {
+        ratatui::style::Color::Rgb(color.r, color.g, color.b)
+    }
+}
+
 /// Represents a chip8 emulator color palette.
+#[derive(Clone, Copy, PartialEq)]
 pub struct Palette {
     pub background: Color,
     pub foreground: Color,
@@ -44,32 +787,71 @@ pub struct Palette {
 }
 
 pub const DEFAULT_PALETTE: Palette = Palette {
-    background: Color::RGB(34, 35, 35),
-    foreground: Color::RGB(240, 246, 240),
-    gutter: Color::RGB(255 - 34, 255 - 35, 255 - 35),
+    background: Color::rgb(34, 35, 35),
+    foreground: Color::rgb(240, 246, 240),
+    gutter: Color::rgb(255 - 34, 255 - 35, 255 - 35),
 };
 
 pub const BITBEE: Palette = Palette {
-    background: Color::RGB(41, 43, 48),
-    foreground: Color::RGB(207, 171, 74),
-    gutter: Color::RGB(255 - 41, 255 - 43, 255 - 48),
+    background: Color::rgb(41, 43, 48),
+    foreground: Color::rgb(207, 171, 74),
+    gutter: Color::rgb(255 - 41, 255 - 43, 255 - 48),
 };
 
 pub const NEUTRAL_GREEN: Palette = Palette {
-    background: Color::RGB(0, 76, 61),
-    foreground: Color::RGB(255, 234, 249),
-    gutter: Color::RGB(255, 255 - 76, 255 - 61),
+    background: Color::rgb(0, 76, 61),
+    foreground: Color::rgb(255, 234, 249),
+    gutter: Color::rgb(255, 255 - 76, 255 - 61),
 };
 
 pub const MAC_PAINT: Palette = Palette {
-    background: Color::RGB(139, 200, 254),
-    foreground: Color::RGB(5, 27, 44),
-    gutter: Color::RGB(255 - 139, 255 - 200, 255 - 254),
+    background: Color::rgb(139, 200, 254),
+    foreground: Color::rgb(5, 27, 44),
+    gutter: Color::rgb(255 - 139, 255 - 200, 255 - 254),
 };
 
 pub const PAPER_BACK: Palette = Palette {
-    background: Color::RGB(184, 194, 185),
-    foreground: Color::RGB(56, 43, 38),
-    gutter: Color::RGB(255 - 184, 255 - 194, 255 - 185),
+    background: Color::rgb(184, 194, 185),
+    foreground: Color::rgb(56, 43, 38),
+    gutter: Color::rgb(255 - 184, 255 - 194, 255 - 185),
 };
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        Args::parse(["chip8"].iter().chain(args).map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn arguments_are_checked_on_their_own() {
+        let parsed = args(&["--gui", "--speed", "900", "pong.c8"]).unwrap();
+        assert_eq!(parsed.rom_path.as_deref(), Some("pong.c8"));
+        assert_eq!(parsed.speed, Some(900));
+        assert_eq!(parsed.check(false), Ok(()));
+
+        let parsed = args(&["--rotate", "30", "pong.c8"]).unwrap();
+        assert!(parsed.check(false).is_err());
+        assert_eq!(parsed.check(true), Ok(()));
+        assert!(args(&["--netplay-host", "0.0.0.0:7000", "roms"]).unwrap().check(true).is_err());
+
+        assert!(args(&["--bogus"]).is_err());
+        assert!(args(&["a.c8", "b.c8"]).is_err());
+    }
+
+    #[test]
+    fn keys_are_laid_out_like_the_keypad() {
+        assert_eq!(keypad_key('1'), Some(0x1));
+        assert_eq!(keypad_key('r'), Some(0xD));
+        assert_eq!(keypad_key('X'), Some(0x0));
+        assert_eq!(keypad_key('v'), Some(0xF));
+        assert_eq!(keypad_key('p'), None);
+        assert_eq!(keypad_key_for_code("Digit4"), Some(0xC));
+        assert_eq!(keypad_key_for_code("KeyZ"), Some(0xA));
+        assert_eq!(keypad_key_for_code("KeyP"), None);
+        assert_eq!(keypad_key_for_code("Numpad1"), None);
+        assert_eq!(keypad_key_for_code("Q"), None);
+    }
+}
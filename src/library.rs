@@ -0,0 +1,189 @@
+// Finding roms to play when none is given: the ones in the rom directory
+// from the [library] section, and the ones played recently. Also playlists,
+// which take turns running several roms.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::archive;
+use crate::bundled;
+use crate::download;
+
+// how many roms the recent list keeps
+const RECENT_ROMS: usize = 10;
+
+/// Whether `path` looks like a chip8 rom, going by its extension.
+pub fn is_rom(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("ch8") || extension.eq_ignore_ascii_case("c8")
+        })
+}
+
+/// The roms in `dir`, sorted by name.
+pub fn find_roms(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut roms = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && is_rom(&path) {
+            roms.push(path);
+        }
+    }
+    roms.sort_by_key(|path| path.file_name().map(|name| name.to_ascii_lowercase()));
+    Ok(roms)
+}
+
+/// Whether `path` is a playlist: a directory of roms, or an `.m3u` file
+/// listing them.
+pub fn is_playlist(path: &Path) -> bool {
+    path.is_dir()
+        || path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("m3u"))
+}
+
+/// The roms in a playlist, in the order they're played. A directory plays
+/// its roms by name.
+pub fn read_playlist(path: &Path) -> Result<Vec<String>, String> {
+    let roms = if path.is_dir() {
+        find_roms(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?
+            .iter()
+            .map(|rom| rom.to_string_lossy().into_owned())
+            .collect()
+    } else {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        parse_playlist(&contents, path.parent().unwrap_or(Path::new("")))
+    };
+    if roms.is_empty() {
+        return Err(format!("No roms in {}.", path.display()));
+    }
+    Ok(roms)
+}
+
+// A rom a line, skipping blank lines and # comments. Paths are relative to
+// the playlist's directory, `base`.
+fn parse_playlist(contents: &str, base: &Path) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|rom| {
+            if download::is_url(rom) || bundled::find(rom).is_some() {
+                rom.to_string()
+            } else {
+                base.join(rom).to_string_lossy().into_owned()
+            }
+        })
+        .collect()
+}
+
+/// Where the recently played roms are listed, next to config.toml.
+pub fn recent_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("chip8").join("recent"))
+}
+
+/// The roms played recently that are still there, the last one first.
+pub fn recent() -> Vec<PathBuf> {
+    let contents = recent_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .unwrap_or_default();
+    contents
+        .lines()
+        .filter(|rom| bundled::find(rom).is_some() || Path::new(archive::split(rom).0).is_file())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Puts `rom` at the top of the recent list, by its full path so it can be
+/// found from anywhere.
+pub fn add_recent(rom: &Path) -> Result<(), String> {
+    let path = match recent_path() {
+        Some(path) => path,
+        None => return Err("No config directory to remember the rom in.".to_string()),
+    };
+
+    let rom = rom.to_string_lossy();
+    let rom = if bundled::find(&rom).is_some() {
+        PathBuf::from(&*rom)
+    } else {
+        // a rom in an archive keeps its name in it
+        let (file, name) = archive::split(&rom);
+        let file = fs::canonicalize(file).map_err(|e| e.to_string())?;
+        match name {
+            Some(name) => PathBuf::from(format!("{}:{}", file.display(), name)),
+            None => file,
+        }
+    };
+    let contents: String = push_recent(recent(), rom)
+        .iter()
+        .map(|rom| format!("{}\n", rom.display()))
+        .collect();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(&path, contents).map_err(|e| format!("Unable to write {}: {}", path.display(), e))
+}
+
+// Moves `rom` to the front of `list`, dropping the oldest roms past
+// RECENT_ROMS.
+fn push_recent(mut list: Vec<PathBuf>, rom: PathBuf) -> Vec<PathBuf> {
+    list.retain(|recent| *recent != rom);
+    list.insert(0, rom);
+    list.truncate(RECENT_ROMS);
+    list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roms_go_by_extension() {
+        assert!(is_rom(Path::new("games/PONG.ch8")));
+        assert!(is_rom(Path::new("maze.C8")));
+        assert!(!is_rom(Path::new("notes.txt")));
+        assert!(!is_rom(Path::new("ch8")));
+    }
+
+    #[test]
+    fn playlists_are_relative_to_their_directory() {
+        let contents = "# demo night
+PONG.ch8
+
+  games.zip:TETRIS.ch8
+/roms/maze.ch8
+bundled:bounce
+https://example.com/ibm.ch8
+";
+        let roms = parse_playlist(contents, Path::new("/home/demo"));
+        assert_eq!(
+            roms,
+            [
+                "/home/demo/PONG.ch8",
+                "/home/demo/games.zip:TETRIS.ch8",
+                "/roms/maze.ch8",
+                "bundled:bounce",
+                "https://example.com/ibm.ch8",
+            ]
+        );
+    }
+
+    #[test]
+    fn recent_roms_move_to_the_front() {
+        let list: Vec<PathBuf> = (0..RECENT_ROMS)
+            .map(|i| PathBuf::from(format!("{}.ch8", i)))
+            .collect();
+        let list = push_recent(list, PathBuf::from("5.ch8"));
+        assert_eq!(list.len(), RECENT_ROMS);
+        assert_eq!(list[..2], [PathBuf::from("5.ch8"), PathBuf::from("0.ch8")]);
+
+        let list = push_recent(list, PathBuf::from("new.ch8"));
+        assert_eq!(list.len(), RECENT_ROMS);
+        assert_eq!(list[0], PathBuf::from("new.ch8"));
+        assert!(!list.contains(&PathBuf::from("9.ch8")));
+    }
+}
@@ -0,0 +1,365 @@
+// Running a rom under two sets of quirks side by side, an instruction at a
+// time, to find where they part ways: the first instruction after which the
+// machines differ, and everything about them that does. Rom authors can
+// check a game plays the same on the interpreters they care about, and when
+// it doesn't, see which instruction leans on a quirk.
+//
+// The sets of quirks are named after the platforms in the chip-8-database,
+// originalChip8, modernChip8, superchip, xochip and so on, or `default` for
+// the emulator's own. Both machines get the same random numbers and the same
+// keys, so the quirks are the only thing setting them apart.
+use std::fmt;
+use std::str::FromStr;
+
+use crate::cpu::{Chip8, Fault};
+use crate::metadata::{self, PLATFORMS};
+use crate::quirks::Quirks;
+use crate::speed::Speed;
+
+/// A set of quirks to run a rom under, and what it's called.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub quirks: Quirks,
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let quirks = match s {
+            "default" => Some(Quirks::default()),
+            platform => metadata::platform_quirks(platform),
+        };
+        let quirks = quirks.ok_or_else(|| {
+            format!(
+                "Unknown quirk profile {}, it can be default, {}.",
+                s,
+                PLATFORMS.join(", ")
+            )
+        })?;
+        Ok(Profile {
+            name: s.to_string(),
+            quirks,
+        })
+    }
+}
+
+/// Something that differs between the two machines, as it is under the
+/// first profile and then the second.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Difference {
+    Pc(u16, u16),
+    Register(usize, u8, u8),
+    Index(u16, u16),
+    Stack(Vec<u16>, Vec<u16>),
+    // the delay and sound timers
+    Timers((u8, u8), (u8, u8)),
+    Memory(u16, u8, u8),
+    // how many pixels differ
+    Display(usize),
+    WaitingForKey(bool, bool),
+    Fault(Option<Fault>, Option<Fault>),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stopped = |fault: &Option<Fault>| match fault {
+            Some(fault) => fault.to_string(),
+            None => "running".to_string(),
+        };
+        match self {
+            Difference::Pc(a, b) => write!(f, "pc {:03X} / {:03X}", a, b),
+            Difference::Register(x, a, b) => write!(f, "V{:X} {:02X} / {:02X}", x, a, b),
+            Difference::Index(a, b) => write!(f, "I {:03X} / {:03X}", a, b),
+            Difference::Stack(a, b) => write!(f, "the stack {:03X?} / {:03X?}", a, b),
+            Difference::Timers(a, b) => write!(
+                f,
+                "the timers {:02X} {:02X} / {:02X} {:02X}",
+                a.0, a.1, b.0, b.1
+            ),
+            Difference::Memory(address, a, b) => {
+                write!(f, "memory at {:03X} {:02X} / {:02X}", address, a, b)
+            }
+            Difference::Display(1) => write!(f, "a pixel of the display"),
+            Difference::Display(pixels) => write!(f, "{} pixels of the display", pixels),
+            Difference::WaitingForKey(a, b) => {
+                let waiting = |waiting: &bool| if *waiting { "waiting" } else { "not waiting" };
+                write!(f, "{} / {} for a key", waiting(a), waiting(b))
+            }
+            Difference::Fault(a, b) => write!(f, "{} / {}", stopped(a), stopped(b)),
+        }
+    }
+}
+
+/// Where the two machines first differed.
+///
+/// instruction: how many instructions each had run, counting the one that
+/// set them apart.
+/// frame: the frame it ran in, from 0.
+/// address, opcode: where that instruction was and what it was.
+/// quirk: the quirk deciding how it runs, if it's one of the disputed
+/// instructions and the profiles disagree on it.
+/// differences: everything that differed once it had run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    pub instruction: u64,
+    pub frame: u64,
+    pub address: u16,
+    pub opcode: u16,
+    pub quirk: Option<&'static str>,
+    pub differences: Vec<Difference>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let differences: Vec<String> = self.differences.iter().map(|d| d.to_string()).collect();
+        write!(
+            f,
+            "instruction {} in frame {}, {:04X} at {:03X}",
+            self.instruction, self.frame, self.opcode, self.address
+        )?;
+        if let Some(quirk) = self.quirk {
+            write!(f, " (the {} quirk)", quirk)?;
+        }
+        write!(f, ": {}", differences.join(", "))
+    }
+}
+
+/// How the two machines compared.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    // they ran the same for this many frames
+    Same { frames: u64 },
+    Diverged(Divergence),
+}
+
+/// Runs `a` and `b` side by side for up to `frames` frames at `speed`,
+/// comparing them after every instruction and stopping at the first
+/// difference. `keys` gives the keys held down in each frame, pressed on
+/// both. A fault both machines come to ends the run early, leaving it for
+/// the caller.
+pub fn run(
+    a: &mut Chip8,
+    b: &mut Chip8,
+    frames: u64,
+    speed: Speed,
+    mut keys: impl FnMut(u64, &mut [u8; 16]),
+) -> Outcome {
+    a.watch_writes();
+    b.watch_writes();
+    for frame in 0..frames {
+        let mut held = [0; 16];
+        keys(frame, &mut held);
+        a.set_keys(&held);
+        b.set_keys(&held);
+        for _ in 0..speed.cycles_in(a.frames()) {
+            let (address, opcode) = (a.pc() % 0x1000, a.next_opcode());
+            a.emulate_cycle();
+            b.emulate_cycle();
+            let differences = differences(a, b);
+            if !differences.is_empty() {
+                return Outcome::Diverged(Divergence {
+                    instruction: a.cycles(),
+                    frame,
+                    address,
+                    opcode,
+                    quirk: quirk(opcode, a.quirks(), b.quirks()),
+                    differences,
+                });
+            }
+            // they're the same, so they both wait or stop
+            if a.waiting_for_key() || a.fault().is_some() {
+                break;
+            }
+        }
+        a.tick_timers();
+        b.tick_timers();
+        if a.fault().is_some() {
+            return Outcome::Same { frames: frame + 1 };
+        }
+    }
+    Outcome::Same { frames }
+}
+
+// Everything that differs between the machines, taking the writes to memory
+// since last time to know which bytes to look at.
+fn differences(a: &mut Chip8, b: &mut Chip8) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    if a.pc() != b.pc() {
+        differences.push(Difference::Pc(a.pc(), b.pc()));
+    }
+    for (x, (va, vb)) in a.registers().iter().zip(b.registers()).enumerate() {
+        if va != vb {
+            differences.push(Difference::Register(x, *va, *vb));
+        }
+    }
+    if a.index() != b.index() {
+        differences.push(Difference::Index(a.index(), b.index()));
+    }
+    if a.stack() != b.stack() {
+        differences.push(Difference::Stack(a.stack().to_vec(), b.stack().to_vec()));
+    }
+    if a.timers() != b.timers() {
+        differences.push(Difference::Timers(a.timers(), b.timers()));
+    }
+
+    let (writes_a, writes_b) = (a.take_writes(), b.take_writes());
+    let mut written: Vec<u16> = Vec::new();
+    for &(address, _) in writes_a.iter().chain(&writes_b) {
+        if !written.contains(&address) {
+            written.push(address);
+        }
+    }
+    for address in written {
+        if a.peek(address) != b.peek(address) {
+            differences.push(Difference::Memory(
+                address,
+                a.peek(address),
+                b.peek(address),
+            ));
+        }
+    }
+
+    let pixels: usize = a
+        .gfx
+        .iter()
+        .zip(&b.gfx)
+        .map(|(pa, pb)| (pa ^ pb).count_ones() as usize)
+        .sum();
+    if pixels > 0 {
+        differences.push(Difference::Display(pixels));
+    }
+    if a.waiting_for_key() != b.waiting_for_key() {
+        differences.push(Difference::WaitingForKey(
+            a.waiting_for_key(),
+            b.waiting_for_key(),
+        ));
+    }
+    if a.fault() != b.fault() {
+        differences.push(Difference::Fault(a.fault(), b.fault()));
+    }
+    differences
+}
+
+// The quirk that decides how `opcode` runs, if the two sets disagree on it.
+fn quirk(opcode: u16, a: Quirks, b: Quirks) -> Option<&'static str> {
+    let (name, differs) = match (opcode >> 12, opcode & 0xF, opcode & 0xFF) {
+        (0x8, 0x1..=0x3, _) => ("vf_reset", a.vf_reset != b.vf_reset),
+        (0x8, 0x6 | 0xE, _) => ("shifting", a.shifting != b.shifting),
+        (0xB, _, _) => ("jumping", a.jumping != b.jumping),
+        (0xD, _, _) => ("clipping", a.clipping != b.clipping),
+        (0xF, _, 0x55 | 0x65) => ("memory", a.memory != b.memory),
+        _ => return None,
+    };
+    differs.then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    fn machines(source: &str, a: &str, b: &str) -> (Chip8, Chip8) {
+        let rom = assemble(source).unwrap();
+        let power_on = |profile: &str| {
+            let mut emu = Chip8::with_quirks(profile.parse::<Profile>().unwrap().quirks);
+            emu.seed_rng(0);
+            emu.load_rom(&rom).unwrap();
+            emu
+        };
+        (power_on(a), power_on(b))
+    }
+
+    #[test]
+    fn profiles_are_named_after_platforms() {
+        assert_eq!(
+            "default".parse::<Profile>().unwrap().quirks,
+            Quirks::default()
+        );
+        let superchip: Profile = "superchip".parse().unwrap();
+        assert!(superchip.quirks.jumping && superchip.quirks.clipping);
+        for platform in PLATFORMS {
+            assert!(
+                platform.parse::<Profile>().is_ok(),
+                "{} has no quirks",
+                platform
+            );
+        }
+        assert!("megachip8".parse::<Profile>().is_err());
+    }
+
+    #[test]
+    fn the_first_difference_is_reported() {
+        let source = "
+            LD V1, 5
+            LD V2, 3
+            SHR V1, V2
+            JP 0x206
+        ";
+        let (mut a, mut b) = machines(source, "originalChip8", "superchip");
+        let speed = Speed::default();
+        let Outcome::Diverged(divergence) = run(&mut a, &mut b, 10, speed, |_, _| {}) else {
+            panic!("the machines ran the same");
+        };
+        assert_eq!(divergence.instruction, 3);
+        assert_eq!((divergence.address, divergence.opcode), (0x204, 0x8126));
+        assert_eq!(divergence.quirk, Some("shifting"));
+        assert_eq!(divergence.differences, [Difference::Register(1, 1, 2)]);
+        assert_eq!(
+            divergence.to_string(),
+            "instruction 3 in frame 0, 8126 at 204 (the shifting quirk): V1 01 / 02"
+        );
+    }
+
+    #[test]
+    fn roms_that_avoid_the_quirks_run_the_same() {
+        // random numbers and keys are the same on both
+        let source = "
+    loop:   RND V0, 0xFF
+            LD V1, K
+            ADD V0, V1
+            LD I, 0x300
+            LD [I], V1
+            JP loop
+        ";
+        let (mut a, mut b) = machines(source, "modernChip8", "xochip");
+        let outcome = run(&mut a, &mut b, 30, Speed::default(), |frame, keys| {
+            keys[5] = (frame % 2 * 255) as u8;
+        });
+        assert_eq!(outcome, Outcome::Same { frames: 30 });
+        assert_eq!(a.registers(), b.registers());
+    }
+
+    #[test]
+    fn memory_and_the_display_are_compared() {
+        let source = "
+            LD V0, 62
+            LD V1, 0
+            LD F, V1
+            DRW V0, V1, 1
+            JP 0x208
+        ";
+        let (mut a, mut b) = machines(source, "modernChip8", "xochip");
+        let speed = Speed::default();
+        let Outcome::Diverged(divergence) = run(&mut a, &mut b, 10, speed, |_, _| {}) else {
+            panic!("the machines ran the same");
+        };
+        assert_eq!(divergence.quirk, Some("clipping"));
+        assert_eq!(divergence.differences, [Difference::Display(2)]);
+
+        let source = "
+            LD V0, 7
+            LD I, 0x300
+            LD [I], V0
+            JP 0x206
+        ";
+        let (mut a, mut b) = machines(source, "originalChip8", "superchip");
+        let speed = Speed::default();
+        let Outcome::Diverged(divergence) = run(&mut a, &mut b, 10, speed, |_, _| {}) else {
+            panic!("the machines ran the same");
+        };
+        assert_eq!(divergence.quirk, Some("memory"));
+        assert_eq!(divergence.differences, [Difference::Index(0x301, 0x300)]);
+    }
+}
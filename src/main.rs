@@ -1,213 +1,277 @@
-extern crate sdl2;
-mod cpu;
+#[cfg(feature = "sdl")]
+mod audio;
+#[cfg(feature = "sdl")]
+mod controller;
+#[cfg(feature = "sdl")]
+mod emulation;
+#[cfg(feature = "gui")]
+mod gui;
+#[cfg(feature = "sdl")]
+mod hotkeys;
+#[cfg(feature = "sdl")]
+mod input;
+#[cfg(feature = "sdl")]
+mod launcher;
+#[cfg(feature = "sdl")]
+mod overlay;
+// --serve doesn't run uncapped
+#[cfg(any(feature = "sdl", feature = "serve"))]
+#[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+mod pacer;
+#[cfg(feature = "sdl")]
+mod plugin;
+#[cfg(feature = "sdl")]
+mod rewind;
+// only partly used without a window
+#[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+mod savestate;
+#[cfg(feature = "serve")]
+mod serve;
+#[cfg(feature = "terminal")]
+mod terminal;
+mod tools;
+mod video;
+#[cfg(feature = "sdl")]
+mod watch;
+#[cfg(feature = "sdl")]
+mod window;
+#[cfg(all(feature = "winit", not(feature = "sdl")))]
+mod winit_window;
+#[cfg(feature = "sdl")]
+mod zoom;
 
 use std::env;
+use std::path::Path;
 use std::process;
-use core::time::Duration;
-
-use sdl2::event::{Event, WindowEvent};
-use sdl2::keyboard::Keycode;
-use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
 
+use chip8::cli::{Command, USAGE};
+use chip8::movie::Movie;
+use chip8::report::{Failure, OutputFormat};
+#[cfg(all(feature = "metrics", any(feature = "sdl", feature = "serve")))]
+use chip8::metrics::Metrics;
+#[cfg(all(feature = "rpc", any(feature = "sdl", feature = "serve")))]
+use chip8::rpc::RpcServer;
 use chip8::Config;
-use chip8::{Palette, PALETTES, DEFAULT_PALETTE};
-use cpu::Chip8;
 
-const WINDOW_WIDTH: u16 = 800;
 const EMULATOR_WIDTH: u8 = 64;
 const EMULATOR_HEIGHT: u8 = 32;
 
 pub fn main() {
-    let config = Config::new(env::args()).unwrap_or_else(|err| {
+    let command = Command::parse(env::args()).unwrap_or_else(|err| {
         eprintln!("❌ Problem parsing arguments: {}", err);
+        eprintln!("{}", USAGE);
         process::exit(1);
     });
 
-    application(config);
-}
-
-fn application(config: Config) {
-    let pixel_size: u8 = (WINDOW_WIDTH / EMULATOR_WIDTH as u16) as u8;
-    // Initialize SDL and Input Handling
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-
-    let window = video_subsystem
-        .window("chip-8-emu", 
-            pixel_size as u32 * EMULATOR_WIDTH as u32, 
-            pixel_size as u32 * EMULATOR_HEIGHT as u32)
-        .resizable()
-        .position_centered()
-        .build()
-        .unwrap();
-    let mut canvas = window.into_canvas().build().unwrap();
-
-    // initially clear the screen
-    let mut color_palette: &Palette = &DEFAULT_PALETTE; 
-    
-    canvas.set_draw_color(color_palette.background);
-    canvas.clear();
-    canvas.present();
-
-    let mut event_pump = sdl_context.event_pump().unwrap();
-
-    // Initialize chip8 emulator
-    let mut emu = Chip8::default();
-    // copy the program into memory
-    match emu.load_game(&config.rom_path) {
-        Err(e) => {
-            eprint!("❌ Error loading ROM file {e:?}.");
-            std::process::exit(1);
-        }
-        _ => (),
+    // runs report how they went as JSON when asked, everything else as text
+    let output = match &command {
+        Command::Run(config)
+        | Command::Test { config, .. }
+        | Command::Corpus { config, .. }
+        | Command::Compare { config, .. } => config.output,
+        _ => OutputFormat::Text,
     };
-
-    'running: loop {
-        // setup keys
-        let mut keys: [u8; 16] = [0; 16];
-
-        emu.emulate_cycle(); // Emulate one cycle
-
-        if emu.draw_flag() {
-            render(&emu, &mut canvas, &color_palette);
+    let result = match command {
+        Command::Run(config) if config.benchmark => tools::benchmark(&config),
+        Command::Run(config) if config.compare_trace.is_some() => tools::compare_trace(&config),
+        Command::Run(config) => match (&config.export_video, &config.headless) {
+            (Some(path), _) => video::export(&config, path).map_err(Failure::from),
+            (None, Some(options)) => tools::headless(&config, options),
+            (None, None) => play(config),
+        },
+        Command::Disasm { rom } => tools::disasm(&rom).map_err(Failure::from),
+        Command::Asm { source, output } => tools::asm(&source, &output).map_err(Failure::from),
+        Command::Info { rom } => tools::info(&rom).map_err(Failure::from),
+        Command::Test { config, frames } => tools::test(&config, frames),
+        Command::Corpus {
+            config,
+            frames,
+            manifest,
+            update,
+        } => tools::corpus(config, frames, &manifest, update),
+        Command::Compare {
+            config,
+            profiles,
+            frames,
+        } => tools::compare_quirks(&config, &profiles, frames),
+        Command::Recent => {
+            tools::recent();
+            Ok(())
         }
-
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => break 'running,
-                Event::Window {
-                    win_event: WindowEvent::Resized(_w, _h),
-                    ..
-                } => render(&mut emu, &mut canvas, &color_palette),
-                Event::KeyDown {
-                    keycode: Some(Keycode::P),
-                    ..
-                } => {
-                    next_palette(&mut color_palette);
-                    render(&mut emu, &mut canvas, &color_palette);
-                }
-                Event::KeyDown {
-                    keycode: Some(Keycode::Num1),
-                    ..
-                } => keys[1] = 255,
-                Event::KeyDown {
-                    keycode: Some(Keycode::Num2),
-                    ..
-                } => keys[2] = 255,
-                Event::KeyDown {
-                    keycode: Some(Keycode::Num3),
-                    ..
-                } => keys[3] = 255,
-                Event::KeyDown {
-                    keycode: Some(Keycode::Num4),
-                    ..
-                } => keys[12] = 255,
-                Event::KeyDown {
-                    keycode: Some(Keycode::Q),
-                    ..
-                } => keys[4] = 255,
-                Event::KeyDown {
-                    keycode: Some(Keycode::W),
-                    ..
-                } => keys[5] = 255,
-                Event::KeyDown {
-                    keycode: Some(Keycode::E),
-                    ..
-                } => keys[6] = 255,
-                Event::KeyDown {
-                    keycode: Some(Keycode::R),
-                    ..
-                } => keys[13] = 255,
-                Event::KeyDown {
-                    keycode: Some(Keycode::A),
-                    ..
-                } => keys[7] = 255,
-                Event::KeyDown {
-                    keycode: Some(Keycode::S),
-                    ..
-                } => keys[8] = 255,
-                Event::KeyDown {
-                    keycode: Some(Keycode::D),
-                    ..
-                } => keys[9] = 255,
-                Event::KeyDown {
-                    keycode: Some(Keycode::F),
-                    ..
-                } => keys[14] = 255,
-                Event::KeyDown {
-                    keycode: Some(Keycode::Z),
-                    ..
-                } => keys[10] = 255,
-                Event::KeyDown {
-                    keycode: Some(Keycode::X),
-                    ..
-                } => keys[0] = 255,
-                Event::KeyDown {
-                    keycode: Some(Keycode::C),
-                    ..
-                } => keys[11] = 255,
-                Event::KeyDown {
-                    keycode: Some(Keycode::V),
-                    ..
-                } => keys[15] = 255,
-                _ => {}
-            }
+        Command::Launch => launch(),
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
         }
-        emu.set_keys(&keys);
-
-        ::std::thread::sleep(Duration::new(0, 100_000_000u32 / 6000));
+    };
+    if let Err(failure) = result {
+        match output {
+            OutputFormat::Text => eprintln!("❌ {}", failure),
+            OutputFormat::Json => println!("{}", failure.to_json()),
+        }
+        process::exit(failure.exit_code());
     }
 }
 
-// Draws the current gfx buffer onto the Canvas. 
-// 
-// I'm not crazy about this abstraction...
-fn render(emu: &Chip8, canvas: &mut Canvas<Window>, draw_color: &Palette) {
-    let screen_width = canvas.window().size().0;
-    let screen_height = canvas.window().size().1; 
+// Plays the rom in the terminal with --terminal, in the egui window with
+// --gui, in browsers with --serve, otherwise in the SDL window or, without
+// SDL, the winit one, as far as this build can.
+fn play(config: Config) -> Result<(), Failure> {
+    #[cfg(not(feature = "rpc"))]
+    if config.rpc.is_some() {
+        return Err(Failure::from(
+            "--rpc needs the emulator built with the rpc feature.".to_string(),
+        ));
+    }
+    #[cfg(not(feature = "metrics"))]
+    if config.metrics.is_some() {
+        return Err(Failure::from(
+            "--metrics needs the emulator built with the metrics feature.".to_string(),
+        ));
+    }
+    #[cfg(not(feature = "dap"))]
+    if config.dap.is_some() {
+        return Err(Failure::from(
+            "--dap needs the emulator built with the dap feature.".to_string(),
+        ));
+    }
+    #[cfg(not(feature = "scripting"))]
+    if config.script.is_some() {
+        return Err(Failure::from(
+            "--script needs the emulator built with the scripting feature.".to_string(),
+        ));
+    }
+    if let Some(address) = config.serve.clone() {
+        #[cfg(feature = "serve")]
+        return serve::run(config, &address);
+        #[cfg(not(feature = "serve"))]
+        return Err(Failure::from(format!(
+            "--serve {} needs the emulator built with the serve feature.",
+            address
+        )));
+    }
+    if config.terminal.is_some() {
+        #[cfg(feature = "terminal")]
+        return terminal::play(&config);
+        #[cfg(not(feature = "terminal"))]
+        return Err(Failure::from(
+            "--terminal needs the emulator built with the terminal feature.".to_string(),
+        ));
+    }
+    if config.gui {
+        #[cfg(feature = "gui")]
+        return gui::play(config);
+        #[cfg(not(feature = "gui"))]
+        return Err(Failure::from(
+            "--gui needs the emulator built with the gui feature.".to_string(),
+        ));
+    }
+    #[cfg(feature = "sdl")]
+    return window::application(config);
+    #[cfg(all(feature = "winit", not(feature = "sdl")))]
+    if config.rpc.is_some() {
+        return Err(Failure::from(
+            "--rpc needs the SDL window or --serve.".to_string(),
+        ));
+    }
+    #[cfg(all(feature = "winit", not(feature = "sdl")))]
+    if config.netplay.is_some() {
+        return Err(Failure::from("Netplay needs the SDL window.".to_string()));
+    }
+    #[cfg(all(feature = "winit", not(feature = "sdl")))]
+    if config.serial.is_some() {
+        return Err(Failure::from(
+            "--serial needs the SDL window, --terminal, --serve or --headless.".to_string(),
+        ));
+    }
+    #[cfg(all(feature = "winit", not(feature = "sdl")))]
+    if config.metrics.is_some() {
+        return Err(Failure::from(
+            "--metrics needs the SDL window or --serve.".to_string(),
+        ));
+    }
+    #[cfg(all(feature = "winit", not(feature = "sdl")))]
+    if config.dap.is_some() {
+        return Err(Failure::from("--dap needs the SDL window.".to_string()));
+    }
+    #[cfg(all(feature = "winit", not(feature = "sdl")))]
+    if config.script.is_some() {
+        return Err(Failure::from("--script needs the SDL window.".to_string()));
+    }
+    #[cfg(all(feature = "winit", not(feature = "sdl")))]
+    if config.chaos.is_some() {
+        return Err(Failure::from(
+            "--flip-bits and --drop-keys need the SDL window or --headless.".to_string(),
+        ));
+    }
+    #[cfg(all(feature = "winit", not(feature = "sdl")))]
+    if config.threaded {
+        return Err(Failure::from("--threaded needs the SDL window.".to_string()));
+    }
+    #[cfg(all(feature = "winit", not(feature = "sdl")))]
+    return winit_window::play(config);
+    #[cfg(not(any(feature = "sdl", feature = "winit")))]
+    Err(Failure::from(
+        "This build has no window, play with --terminal instead.".to_string(),
+    ))
+}
 
-    // Clear screen for gutters
-    canvas.set_draw_color(draw_color.gutter);
-    canvas.clear();
+// The JSON-RPC API, if --rpc asked for it.
+#[cfg(all(feature = "rpc", any(feature = "sdl", feature = "serve")))]
+fn rpc_server(config: &Config) -> Result<Option<RpcServer>, Failure> {
+    let Some(address) = &config.rpc else {
+        return Ok(None);
+    };
+    RpcServer::bind(address)
+        .map(Some)
+        .map_err(|e| Failure::from(format!("Unable to listen on {}: {}", address, e)))
+}
 
-    // Recalculate constants for the current window size
-    let pixel_size = screen_width / 64;
-    let gutter: i32 =
-        (screen_height as i32 - (pixel_size as i32 * EMULATOR_HEIGHT as i32)) as i32 / 2 as i32;
+// The Prometheus metrics, if --metrics asked for them.
+#[cfg(all(feature = "metrics", any(feature = "sdl", feature = "serve")))]
+fn metrics_server(config: &Config) -> Result<Option<Metrics>, Failure> {
+    let Some(address) = &config.metrics else {
+        return Ok(None);
+    };
+    Metrics::serve(address)
+        .map(Some)
+        .map_err(|e| Failure::from(format!("Unable to listen on {}: {}", address, e)))
+}
 
-    canvas.set_draw_color(draw_color.background);
-    let _result = canvas.fill_rect(Rect::new(
-        0,
-        gutter,
-        screen_width,
-        (screen_height as i32 - (2 * gutter as i32)) as u32,
-    ));
+// Lets the rom to play be picked from the launcher.
+#[cfg(feature = "sdl")]
+fn launch() -> Result<(), Failure> {
+    match launcher::choose() {
+        Ok(Some(rom)) => {
+            let args = ["chip8".to_string(), rom.to_string_lossy().into_owned()];
+            Config::new(args.into_iter())
+                .map_err(Failure::from)
+                .and_then(window::application)
+        }
+        Ok(None) => Ok(()),
+        Err(e) => Err(Failure::from(e)),
+    }
+}
 
-    // loop through the pixel array
-    for x in 0..EMULATOR_WIDTH {
-        for y in 0..EMULATOR_HEIGHT {
-            // Only draw the pixel if its on
-            if emu.gfx[y as usize][x as usize] != 0 {
-                // get the x and y coordinate in screen space
-                let screen_x: i32 = x as i32 * pixel_size as i32;
-                let screen_y: i32 = (y as i32 * pixel_size as i32) + gutter as i32;
+#[cfg(not(feature = "sdl"))]
+fn launch() -> Result<(), Failure> {
+    Err(Failure::from(
+        "This build has no launcher, give a rom to play with --terminal.".to_string(),
+    ))
+}
 
-                canvas.set_draw_color(draw_color.foreground);
-                let _result =
-                    canvas.fill_rect(Rect::new(screen_x, screen_y, pixel_size.into(), pixel_size.into()));
+// Makes sure a movie was recorded on the rom it's about to play back on,
+// exiting if not.
+fn check_movie(config: &Config, path: &Path, movie: &Movie) {
+    match movie.origin().check(config.rom_hash.as_deref(), config.quirks) {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("⚠️ {}: {}", path.display(), warning);
             }
         }
+        Err(e) => {
+            eprintln!("❌ Problem with the movie {}: {}.", path.display(), e);
+            process::exit(1);
+        }
     }
-    canvas.present();
-}
-
-//TODO: write this helper function.
-fn next_palette(mut _curr_palette: &Palette) {
-    _curr_palette = &PALETTES[0];
 }
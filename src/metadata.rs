@@ -0,0 +1,267 @@
+// What the community chip-8-database (github.com/chip-8/chip-8-database)
+// knows about roms: their titles, authors and release years, and which
+// platforms, and so which quirks, they were written for.
+//
+// A snapshot covering the bundled roms is built in. The full database can
+// be downloaded and its `database` directory, holding programs.json and
+// sha1-hashes.json, set as `database` in the [library] section.
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::quirks::Quirks;
+
+const PROGRAMS: &str = include_str!("../database/programs.json");
+const HASHES: &str = include_str!("../database/sha1-hashes.json");
+
+// One program in programs.json, which may have several versions of its rom.
+#[derive(Debug, Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    authors: Vec<String>,
+    // usually a year, sometimes a full date
+    release: Option<Value>,
+    #[serde(default)]
+    roms: HashMap<String, RomEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RomEntry {
+    // best first
+    #[serde(default)]
+    platforms: Vec<String>,
+    // quirks that differ from the platform's own for this rom
+    #[serde(default)]
+    quirky_platforms: HashMap<String, PlatformQuirks>,
+}
+
+// The quirks as the database names them. vblank has no counterpart here.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlatformQuirks {
+    shift: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    logic: Option<bool>,
+}
+
+impl PlatformQuirks {
+    // The quirks of the platforms from platforms.json that this emulator can
+    // pass for.
+    fn of(platform: &str) -> Option<PlatformQuirks> {
+        let (shift, memory_leave_i_unchanged, wrap, jump, logic) = match platform {
+            "originalChip8" | "hybridVIP" => (false, false, false, false, true),
+            "modernChip8" => (false, false, false, false, false),
+            "chip48" | "superchip1" | "superchip" => (true, true, false, true, false),
+            "xochip" => (false, false, true, false, false),
+            _ => return None,
+        };
+        Some(PlatformQuirks {
+            shift: Some(shift),
+            memory_leave_i_unchanged: Some(memory_leave_i_unchanged),
+            wrap: Some(wrap),
+            jump: Some(jump),
+            logic: Some(logic),
+        })
+    }
+
+    fn apply(self, quirks: &mut Quirks) {
+        if let Some(shift) = self.shift {
+            quirks.shifting = shift;
+        }
+        if let Some(unchanged) = self.memory_leave_i_unchanged {
+            quirks.memory = !unchanged;
+        }
+        if let Some(wrap) = self.wrap {
+            quirks.clipping = !wrap;
+        }
+        if let Some(jump) = self.jump {
+            quirks.jumping = jump;
+        }
+        if let Some(logic) = self.logic {
+            quirks.vf_reset = logic;
+        }
+    }
+}
+
+/// The platforms from platforms.json whose quirks the emulator can pass for.
+pub const PLATFORMS: [&str; 7] = [
+    "originalChip8",
+    "hybridVIP",
+    "modernChip8",
+    "chip48",
+    "superchip1",
+    "superchip",
+    "xochip",
+];
+
+/// The quirks of one of PLATFORMS.
+pub fn platform_quirks(platform: &str) -> Option<Quirks> {
+    let mut quirks = Quirks::default();
+    PlatformQuirks::of(platform)?.apply(&mut quirks);
+    Some(quirks)
+}
+
+/// What the database says about one rom.
+///
+/// title: the program's name.
+/// authors: who wrote it, if known.
+/// release: when it came out, usually a year.
+/// platform: the platform it's best played as, of the ones whose quirks can
+/// be emulated.
+/// quirks: the quirks it needs, going by that platform.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RomMetadata {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub release: Option<String>,
+    pub platform: Option<String>,
+    pub quirks: Option<Quirks>,
+}
+
+impl RomMetadata {
+    /// The title with the authors and release, e.g. "Pong by Paul Vervalin
+    /// (1990)".
+    pub fn credits(&self) -> String {
+        let mut credits = self.title.clone();
+        if !self.authors.is_empty() {
+            credits += &format!(" by {}", self.authors.join(", "));
+        }
+        if let Some(release) = &self.release {
+            credits += &format!(" ({})", release);
+        }
+        credits
+    }
+}
+
+/// The chip-8-database, looked up by rom hash.
+#[derive(Debug, Default)]
+pub struct ProgramDatabase {
+    programs: Vec<Program>,
+    hashes: HashMap<String, usize>,
+}
+
+impl ProgramDatabase {
+    /// The snapshot built into the emulator.
+    pub fn embedded() -> ProgramDatabase {
+        ProgramDatabase::parse(PROGRAMS, HASHES).expect("the built in database is valid")
+    }
+
+    /// The built in snapshot, with the database in `dir` on top if there is
+    /// one.
+    pub fn load(dir: Option<&Path>) -> Result<ProgramDatabase, String> {
+        let mut database = ProgramDatabase::embedded();
+        if let Some(dir) = dir {
+            let read = |name: &str| {
+                let path = dir.join(name);
+                fs::read_to_string(&path)
+                    .map_err(|e| format!("Unable to read {}: {}", path.display(), e))
+            };
+            let (programs, hashes) = (read("programs.json")?, read("sha1-hashes.json")?);
+            let loaded = ProgramDatabase::parse(&programs, &hashes)
+                .map_err(|e| format!("Invalid chip-8-database in {}: {}", dir.display(), e))?;
+            database.extend(loaded);
+        }
+        Ok(database)
+    }
+
+    fn parse(programs: &str, hashes: &str) -> Result<ProgramDatabase, String> {
+        let programs: Vec<Program> = serde_json::from_str(programs).map_err(|e| e.to_string())?;
+        let hashes: HashMap<String, usize> =
+            serde_json::from_str(hashes).map_err(|e| e.to_string())?;
+        if let Some(index) = hashes.values().find(|&&index| index >= programs.len()) {
+            return Err(format!("no program {}", index));
+        }
+        Ok(ProgramDatabase { programs, hashes })
+    }
+
+    // Adds another database's programs, its roms taking over from any
+    // already known.
+    fn extend(&mut self, other: ProgramDatabase) {
+        let offset = self.programs.len();
+        self.programs.extend(other.programs);
+        for (hash, index) in other.hashes {
+            self.hashes.insert(hash, offset + index);
+        }
+    }
+
+    /// Looks up a rom by the SHA-1 of its contents.
+    pub fn get(&self, hash: &str) -> Option<RomMetadata> {
+        let program = &self.programs[*self.hashes.get(hash)?];
+        let rom = program.roms.get(hash);
+        let platform = rom.and_then(|rom| {
+            rom.platforms
+                .iter()
+                .find(|platform| PlatformQuirks::of(platform).is_some())
+        });
+        let quirks = platform.map(|platform| {
+            let mut quirks = platform_quirks(platform).unwrap_or_default();
+            if let Some(quirky) = rom.and_then(|rom| rom.quirky_platforms.get(platform)) {
+                quirky.apply(&mut quirks);
+            }
+            quirks
+        });
+        let release = program.release.as_ref().map(|release| match release {
+            Value::String(release) => release.clone(),
+            release => release.to_string(),
+        });
+
+        Some(RomMetadata {
+            title: program.title.clone(),
+            authors: program.authors.clone(),
+            release,
+            platform: platform.cloned(),
+            quirks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundled;
+    use crate::roms::rom_hash;
+
+    #[test]
+    fn bundled_roms_are_in_the_snapshot() {
+        let database = ProgramDatabase::embedded();
+        for rom in &bundled::ROMS {
+            let metadata = database.get(&rom_hash(rom.rom));
+            assert!(metadata.is_some(), "{} is missing", rom.name);
+        }
+    }
+
+    #[test]
+    fn quirks_come_from_the_platform_and_the_rom() {
+        let programs = r#"[{
+            "title": "Pong",
+            "authors": ["Paul Vervalin"],
+            "release": 1990,
+            "roms": {
+                "aaaa": {
+                    "platforms": ["megachip8", "superchip", "originalChip8"],
+                    "quirkyPlatforms": { "superchip": { "shift": false } }
+                },
+                "bbbb": { "platforms": ["megachip8"] }
+            }
+        }]"#;
+        let hashes = r#"{ "aaaa": 0, "bbbb": 0 }"#;
+        let database = ProgramDatabase::parse(programs, hashes).unwrap();
+
+        let pong = database.get("aaaa").unwrap();
+        assert_eq!(pong.credits(), "Pong by Paul Vervalin (1990)");
+        assert_eq!(pong.platform.as_deref(), Some("superchip"));
+        let quirks = pong.quirks.unwrap();
+        assert!(!quirks.shifting && quirks.jumping && !quirks.memory && quirks.clipping);
+
+        // nothing that can be emulated
+        assert_eq!(database.get("bbbb").unwrap().quirks, None);
+        assert_eq!(database.get("cccc"), None);
+    }
+}
@@ -0,0 +1,231 @@
+// Runtime metrics in Prometheus's text format, for keeping an eye on
+// machines left running for a long time, like kiosks. They're served on
+// /metrics at the address given with --metrics:
+//
+//   curl localhost:9100/metrics
+//
+// The counters only go up, across roms and resets, so Prometheus's rate()
+// works on them. The per second gauges are measured over the last second,
+// for dashboards without Prometheus.
+use std::fmt::Write as _;
+use std::io::{self, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::cpu::{Chip8, Fault};
+use crate::http_server::{self, read_head};
+
+// how often the per second gauges are measured
+const SAMPLE: Duration = Duration::from_secs(1);
+
+/// The metrics, updated by the frontend every frame and read by the server.
+pub struct Metrics {
+    counters: Arc<Mutex<Counters>>,
+    // what the machine looked like last time, to count what's changed
+    cycles: u64,
+    frames: u64,
+    sounding: bool,
+    fault: Option<Fault>,
+    // what's been counted since the gauges were last measured
+    sample_start: Instant,
+    sample_cycles: u64,
+    sample_draws: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    instructions: u64,
+    frames: u64,
+    frames_drawn: u64,
+    beeps: u64,
+    unknown_opcodes: u64,
+    instructions_per_second: f64,
+    draws_per_second: f64,
+}
+
+impl Metrics {
+    /// Starts serving the metrics on `address`, e.g. 0.0.0.0:9100.
+    pub fn serve(address: &str) -> io::Result<Metrics> {
+        let listener = TcpListener::bind(address)?;
+        let counters = Arc::new(Mutex::new(Counters::default()));
+        let shared = Arc::clone(&counters);
+        http_server::serve(listener, "a metrics request", move |stream| {
+            answer(stream, &shared)
+        })?;
+        Ok(Metrics::with_counters(counters))
+    }
+
+    fn with_counters(counters: Arc<Mutex<Counters>>) -> Metrics {
+        Metrics {
+            counters,
+            cycles: 0,
+            frames: 0,
+            sounding: false,
+            fault: None,
+            sample_start: Instant::now(),
+            sample_cycles: 0,
+            sample_draws: 0,
+        }
+    }
+
+    /// Counts what `emu` did since last time, after the frontend runs its
+    /// frames. `drew` is whether the display changed, as `draw_flag` said.
+    pub fn record(&mut self, emu: &Chip8, drew: bool) {
+        // a new rom, a reset or rewinding goes back in time, which isn't
+        // counted, only what runs from there
+        let cycles = emu.cycles().saturating_sub(self.cycles);
+        let frames = emu.frames().saturating_sub(self.frames);
+        self.cycles = emu.cycles();
+        self.frames = emu.frames();
+        let beeped = emu.sound_active() && !self.sounding;
+        self.sounding = emu.sound_active();
+        let faulted = emu.fault().is_some() && emu.fault() != self.fault;
+        self.fault = emu.fault();
+
+        let Ok(mut counters) = self.counters.lock() else {
+            return;
+        };
+        counters.instructions += cycles;
+        counters.frames += frames;
+        counters.frames_drawn += u64::from(drew);
+        counters.beeps += u64::from(beeped);
+        counters.unknown_opcodes += u64::from(faulted);
+
+        self.sample_cycles += cycles;
+        self.sample_draws += u64::from(drew);
+        let elapsed = self.sample_start.elapsed();
+        if elapsed >= SAMPLE {
+            counters.instructions_per_second = self.sample_cycles as f64 / elapsed.as_secs_f64();
+            counters.draws_per_second = self.sample_draws as f64 / elapsed.as_secs_f64();
+            self.sample_start = Instant::now();
+            self.sample_cycles = 0;
+            self.sample_draws = 0;
+        }
+    }
+}
+
+// Sends the metrics for GET /metrics, and not found for anything else.
+fn answer(mut stream: TcpStream, counters: &Mutex<Counters>) -> io::Result<()> {
+    // the headers don't matter, but are read so the client sees an answer
+    // rather than a reset connection
+    let head = read_head(&mut BufReader::new(&stream))?;
+
+    let (status, body) = if head.request.starts_with("GET /metrics ") {
+        let body = match counters.lock() {
+            Ok(counters) => exposition(&counters),
+            Err(_) => String::new(),
+        };
+        ("200 OK", body)
+    } else {
+        ("404 Not Found", "Not found\n".to_string())
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+// The counters in Prometheus's text format.
+fn exposition(counters: &Counters) -> String {
+    let metrics: [(&str, &str, &str, f64); 7] = [
+        (
+            "chip8_instructions_total",
+            "counter",
+            "Instructions run.",
+            counters.instructions as f64,
+        ),
+        (
+            "chip8_frames_total",
+            "counter",
+            "Frames run, 60 a second while running.",
+            counters.frames as f64,
+        ),
+        (
+            "chip8_frames_drawn_total",
+            "counter",
+            "Times the display changed and was drawn.",
+            counters.frames_drawn as f64,
+        ),
+        (
+            "chip8_beeps_total",
+            "counter",
+            "Times the buzzer started sounding.",
+            counters.beeps as f64,
+        ),
+        (
+            "chip8_unknown_opcodes_total",
+            "counter",
+            "Unknown instructions roms stopped on.",
+            counters.unknown_opcodes as f64,
+        ),
+        (
+            "chip8_instructions_per_second",
+            "gauge",
+            "Instructions run over the last second.",
+            counters.instructions_per_second,
+        ),
+        (
+            "chip8_draws_per_second",
+            "gauge",
+            "Times the display was drawn over the last second.",
+            counters.draws_per_second,
+        ),
+    ];
+    let mut text = String::new();
+    for (name, kind, help, value) in metrics {
+        let _ = writeln!(text, "# HELP {} {}", name, help);
+        let _ = writeln!(text, "# TYPE {} {}", name, kind);
+        let _ = writeln!(text, "{} {}", name, value);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_what_changed_since_last_time() {
+        let counters = Arc::new(Mutex::new(Counters::default()));
+        let mut metrics = Metrics::with_counters(Arc::clone(&counters));
+        let mut emu = Chip8::default();
+        // LD V0, 5; LD ST, V0; JP 0x204
+        emu.load_rom(&[0x60, 0x05, 0xF0, 0x18, 0x12, 0x04]).unwrap();
+
+        emu.run_frame(10);
+        metrics.record(&emu, false);
+        emu.run_frame(10);
+        metrics.record(&emu, true);
+        // starting over isn't counted backwards
+        emu.reset();
+        metrics.record(&emu, false);
+
+        let counters = counters.lock().unwrap();
+        assert_eq!(counters.instructions, 20);
+        assert_eq!(counters.frames, 2);
+        assert_eq!(counters.frames_drawn, 1);
+        assert_eq!(counters.beeps, 1);
+        assert_eq!(counters.unknown_opcodes, 0);
+    }
+
+    #[test]
+    fn unknown_opcodes_are_counted_once() {
+        let counters = Arc::new(Mutex::new(Counters::default()));
+        let mut metrics = Metrics::with_counters(Arc::clone(&counters));
+        let mut emu = Chip8::default();
+        emu.load_rom(&[0xFF, 0xFF]).unwrap();
+        for _ in 0..3 {
+            emu.run_frame(10);
+            metrics.record(&emu, false);
+        }
+
+        let text = exposition(&counters.lock().unwrap());
+        assert!(text.contains("# TYPE chip8_unknown_opcodes_total counter\n"));
+        assert!(text.contains("\nchip8_unknown_opcodes_total 1\n"));
+    }
+}
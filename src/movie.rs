@@ -0,0 +1,237 @@
+// Movies: the keypad on every frame of a run, along with everything else it
+// takes to play the run back exactly, for tool-assisted runs and bug
+// reports.
+//
+// A movie is a text file. A header gives the version of the emulator that
+// recorded it, the rom, the seed for CXNN's random numbers, the quirks, the
+// speed and how many instructions the run took, then each line after
+// "frames" is the keypad on one frame as a hex mask, key 0 in the lowest
+// bit. Once a second the mask is followed by a checksum of the machine at
+// the end of the frame, so playback can tell when it's gone out of step:
+//
+//   version 0.1.0
+//   rom 0123456789abcdef0123456789abcdef01234567
+//   seed 8157309461
+//   quirks 04
+//   speed 600
+//   cycles 36000
+//   frames
+//   0000
+//   0020
+//   ...
+//   0020 9f3c01d2
+//
+// Blank lines and lines starting with # are ignored.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::compat::{Origin, VERSION};
+use crate::quirks::Quirks;
+
+/// How often a recording checksums the machine, in frames.
+pub const CHECKSUM_INTERVAL: u64 = 60;
+
+/// A recorded run.
+///
+/// version: the version of the emulator it was recorded by, if known.
+/// rom_hash: the SHA-1 of the rom it was recorded on, if known.
+/// seed: what the random number generator was seeded with.
+/// quirks: the quirks it was recorded with.
+/// speed: the instructions a second it was recorded at, if known.
+/// cycles: the instructions executed over the whole run, if known.
+/// frames: the keys held on each frame, one bit per key.
+/// checksums: checksums of the machine at the end of some frames, by frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Movie {
+    pub version: Option<String>,
+    pub rom_hash: Option<String>,
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub speed: Option<u32>,
+    pub cycles: Option<u64>,
+    pub frames: Vec<u16>,
+    pub checksums: BTreeMap<u64, u32>,
+}
+
+impl Movie {
+    pub fn new(
+        rom_hash: Option<String>,
+        seed: u64,
+        quirks: Quirks,
+        speed: u32,
+    ) -> Movie {
+        Movie {
+            version: Some(VERSION.to_string()),
+            rom_hash,
+            seed,
+            quirks,
+            speed: Some(speed),
+            cycles: None,
+            frames: Vec::new(),
+            checksums: BTreeMap::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Movie, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        Movie::parse(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_string())
+            .map_err(|e| format!("Unable to write {}: {}", path.display(), e))
+    }
+
+    pub fn parse(contents: &str) -> Result<Movie, String> {
+        let mut movie = Movie::default();
+        let mut seed = None;
+        let mut in_frames = false;
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |expected: &str| format!("line {}: expected {}", number + 1, expected);
+
+            if in_frames {
+                let mut words = line.split_whitespace();
+                let keys = words
+                    .next()
+                    .and_then(|keys| u16::from_str_radix(keys, 16).ok());
+                let checksum = words
+                    .next()
+                    .map(|checksum| u32::from_str_radix(checksum, 16).ok());
+                match (keys, checksum, words.next()) {
+                    (Some(keys), checksum @ (None | Some(Some(_))), None) => {
+                        if let Some(Some(checksum)) = checksum {
+                            movie.checksums.insert(movie.frames.len() as u64, checksum);
+                        }
+                        movie.frames.push(keys);
+                    }
+                    _ => return Err(error("a hex key mask and checksum")),
+                }
+                continue;
+            }
+            match line.split_once(' ') {
+                Some(("version", version)) => movie.version = Some(version.trim().to_string()),
+                Some(("rom", hash)) => movie.rom_hash = Some(hash.trim().to_string()),
+                Some(("seed", value)) => {
+                    seed = Some(value.trim().parse().map_err(|_| error("a number"))?)
+                }
+                Some(("quirks", bits)) => {
+                    let bits = u8::from_str_radix(bits.trim(), 16).map_err(|_| error("hex"))?;
+                    movie.quirks = Quirks::from_bits(bits);
+                }
+                Some(("speed", value)) => {
+                    movie.speed = Some(value.trim().parse().map_err(|_| error("a number"))?)
+                }
+                Some(("cycles", value)) => {
+                    movie.cycles = Some(value.trim().parse().map_err(|_| error("a number"))?)
+                }
+                None if line == "frames" => in_frames = true,
+                _ => {
+                    return Err(error(
+                        "version, rom, seed, quirks, speed, cycles or frames",
+                    ))
+                }
+            }
+        }
+
+        movie.seed = seed.ok_or("the movie has no seed")?;
+        if !in_frames {
+            return Err("the movie has no frames".to_string());
+        }
+        Ok(movie)
+    }
+
+    /// What the movie was recorded with, to check it against the rom.
+    pub fn origin(&self) -> Origin<'_> {
+        Origin {
+            version: self.version.as_deref(),
+            rom_hash: self.rom_hash.as_deref(),
+            quirks: self.quirks,
+        }
+    }
+
+    /// Adds a frame with `keys` held to the end of the movie.
+    pub fn record(&mut self, keys: &[u8; 16]) {
+        let mask = (0..16)
+            .filter(|key| keys[*key] != 0)
+            .fold(0, |mask, key| mask | 1 << key);
+        self.frames.push(mask);
+    }
+
+    /// The keys held on `frame`, or None once the movie is over.
+    pub fn keys(&self, frame: u64) -> Option<[u8; 16]> {
+        let mask = *self.frames.get(usize::try_from(frame).ok()?)?;
+        let mut keys = [0; 16];
+        for (key, state) in keys.iter_mut().enumerate() {
+            if mask & (1 << key) != 0 {
+                *state = 255;
+            }
+        }
+        Some(keys)
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(version) = &self.version {
+            writeln!(f, "version {}", version)?;
+        }
+        if let Some(hash) = &self.rom_hash {
+            writeln!(f, "rom {}", hash)?;
+        }
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "quirks {:02x}", self.quirks.to_bits())?;
+        if let Some(speed) = self.speed {
+            writeln!(f, "speed {}", speed)?;
+        }
+        if let Some(cycles) = self.cycles {
+            writeln!(f, "cycles {}", cycles)?;
+        }
+        writeln!(f, "frames")?;
+        for (frame, keys) in self.frames.iter().enumerate() {
+            match self.checksums.get(&(frame as u64)) {
+                Some(checksum) => writeln!(f, "{:04x} {:08x}", keys, checksum)?,
+                None => writeln!(f, "{:04x}", keys)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_text() {
+        let mut movie = Movie::new(Some("ab".repeat(20)), 42, Quirks::default(), 10);
+        movie.cycles = Some(20);
+        let mut keys = [0; 16];
+        movie.record(&keys);
+        keys[0x5] = 255;
+        keys[0xF] = 255;
+        movie.record(&keys);
+        movie.checksums.insert(1, 0xdeadbeef);
+
+        assert_eq!(movie.frames, [0x0000, 0x8020]);
+        assert!(movie.to_string().ends_with("0000\n8020 deadbeef\n"));
+        assert_eq!(Movie::parse(&movie.to_string()), Ok(movie.clone()));
+        assert_eq!(movie.keys(1), Some(keys));
+        assert_eq!(movie.keys(2), None);
+    }
+
+    #[test]
+    fn reports_bad_lines() {
+        let err = Movie::parse("seed 1\nframes\n0000\nzz\n").unwrap_err();
+        assert!(err.starts_with("line 4:"));
+        assert!(Movie::parse("seed 1\nframes\n0000 xyz\n").is_err());
+        assert!(Movie::parse("frames\n").is_err());
+    }
+}
@@ -0,0 +1,411 @@
+// Experimental netplay: two emulators running the same two-player rom in
+// lockstep over UDP. Each frame both send the keys their player is holding,
+// and a frame only runs once both sides' keys for it have arrived, so both
+// machines see the same keys on the same frames. With the same rom, quirks,
+// speed and random seed, which the host hands the one joining, they stay
+// in step without sending anything else.
+//
+// Keys take effect a few frames after they're pressed, the input delay, so
+// they've usually arrived by the time they're needed and neither side
+// waits. Packets are small and can be lost, so each one repeats the keys of
+// the last few frames too.
+//
+// The packets, with numbers little-endian:
+//
+//   'H' checksum              joining, with the machine's checksum at power
+//                             on to check both run the same rom
+//   'W' seed quirks cycles delay
+//                             welcome, with what the host runs the rom with
+//   'R'                       refused, as the roms differ
+//   'K' first count keys... checked checksum
+//                             `count` 16-bit key masks from frame `first`,
+//                             key 0 in the lowest bit, and the checksum of
+//                             the machine after the last checked frame
+//   'B'                       bye, the other player quit
+use std::collections::BTreeMap;
+use std::io::{self, ErrorKind};
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+use crate::movie::CHECKSUM_INTERVAL;
+use crate::quirks::Quirks;
+
+/// The input delay when none is given, in frames.
+pub const DEFAULT_DELAY: u64 = 3;
+/// The longest input delay, a second.
+pub const MAX_DELAY: u64 = 60;
+// how long joining keeps trying, and how often it asks
+const JOIN_TIMEOUT: Duration = Duration::from_secs(30);
+const JOIN_RETRY: Duration = Duration::from_millis(250);
+// how long to wait for the other player's keys before giving up on them
+const PEER_TIMEOUT: Duration = Duration::from_secs(15);
+// frames of keys repeated in each packet besides the delay's
+const REPEATS: u64 = 8;
+const MAX_PACKET: usize = 512;
+
+/// What both machines need to agree on to stay in step, which the host
+/// decides.
+///
+/// seed: what CXNN's random numbers are seeded with.
+/// quirks: the quirks the rom runs with.
+/// speed: the speed, in instructions a second.
+/// delay: how many frames keys take to reach the rom.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Session {
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub speed: u32,
+    pub delay: u64,
+}
+
+/// A connection to the other player.
+pub struct Netplay {
+    socket: UdpSocket,
+    delay: u64,
+    // the frame run next
+    frame: u64,
+    // key masks by frame, ours kept for a while to repeat them
+    local: BTreeMap<u64, u16>,
+    remote: BTreeMap<u64, u16>,
+    // our checksums, and the latest one checked by each side
+    checksums: BTreeMap<u64, u32>,
+    checked: Option<(u64, u32)>,
+    peer_checked: Option<(u64, u32)>,
+    desync: Option<u64>,
+    // sent again if the one joining asks again, having missed it
+    welcome: Option<Vec<u8>>,
+    heard: Instant,
+    left: bool,
+}
+
+impl Netplay {
+    /// Waits on `address` for the other player to join, running the same
+    /// rom, whose machine had `checksum` at power on.
+    pub fn host(address: &str, session: Session, checksum: u32) -> io::Result<Netplay> {
+        let socket = UdpSocket::bind(address)?;
+        let mut welcome = vec![b'W'];
+        welcome.extend(session.seed.to_le_bytes());
+        welcome.push(session.quirks.to_bits());
+        welcome.extend(session.speed.to_le_bytes());
+        welcome.push(session.delay as u8);
+
+        let mut packet = [0; MAX_PACKET];
+        loop {
+            let (read, peer) = socket.recv_from(&mut packet)?;
+            match &packet[..read] {
+                [b'H', sum @ ..] if sum == checksum.to_le_bytes() => {
+                    socket.connect(peer)?;
+                    socket.send(&welcome)?;
+                    break;
+                }
+                [b'H', ..] => {
+                    socket.send_to(b"R", peer)?;
+                }
+                _ => {}
+            }
+        }
+        Netplay::connected(socket, session.delay, Some(welcome))
+    }
+
+    /// Joins the player hosting on `address`, returning what to run the
+    /// rom with.
+    pub fn join(address: &str, checksum: u32) -> io::Result<(Netplay, Session)> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(address)?;
+        socket.set_read_timeout(Some(JOIN_RETRY))?;
+        let mut hello = vec![b'H'];
+        hello.extend(checksum.to_le_bytes());
+
+        let started = Instant::now();
+        let mut packet = [0; MAX_PACKET];
+        while started.elapsed() < JOIN_TIMEOUT {
+            socket.send(&hello)?;
+            let read = match socket.recv(&mut packet) {
+                Ok(read) => read,
+                // nobody's there yet, or the packet was lost
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    continue
+                }
+                Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                    std::thread::sleep(JOIN_RETRY);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            match &packet[..read] {
+                [b'W', seed @ .., quirks, s0, s1, s2, s3, delay] if seed.len() == 8 => {
+                    let session = Session {
+                        seed: u64::from_le_bytes(seed.try_into().unwrap_or_default()),
+                        quirks: Quirks::from_bits(*quirks),
+                        speed: u32::from_le_bytes([*s0, *s1, *s2, *s3]),
+                        delay: u64::from(*delay),
+                    };
+                    let netplay = Netplay::connected(socket, session.delay, None)?;
+                    return Ok((netplay, session));
+                }
+                [b'R'] => {
+                    let error = "The other player is running a different rom.";
+                    return Err(io::Error::new(ErrorKind::InvalidData, error));
+                }
+                _ => {}
+            }
+        }
+        Err(io::Error::new(
+            ErrorKind::TimedOut,
+            "Nobody's hosting there.",
+        ))
+    }
+
+    fn connected(socket: UdpSocket, delay: u64, welcome: Option<Vec<u8>>) -> io::Result<Netplay> {
+        socket.set_nonblocking(true)?;
+        Ok(Netplay {
+            socket,
+            delay,
+            frame: 0,
+            local: BTreeMap::new(),
+            remote: BTreeMap::new(),
+            checksums: BTreeMap::new(),
+            checked: None,
+            peer_checked: None,
+            desync: None,
+            welcome,
+            heard: Instant::now(),
+            left: false,
+        })
+    }
+
+    /// Sends the keys our player's holding and returns the keys for the
+    /// next frame, both players' together, or None if the other player's
+    /// haven't arrived yet and the frame has to wait. It's an error once
+    /// the other player's gone or the machines are out of step.
+    pub fn keys(&mut self, local: &[u8; 16]) -> io::Result<Option<[u8; 16]>> {
+        self.receive()?;
+        if self.left {
+            return Err(io::Error::new(
+                ErrorKind::ConnectionAborted,
+                "The other player left.",
+            ));
+        }
+        if let Some(frame) = self.desync {
+            let error = format!("The machines went out of step on frame {}.", frame);
+            return Err(io::Error::new(ErrorKind::InvalidData, error));
+        }
+
+        self.local
+            .entry(self.frame + self.delay)
+            .or_insert_with(|| mask(local));
+        self.send()?;
+
+        // nobody's keys reach the rom before the delay's up
+        let remote = match self.frame < self.delay {
+            true => Some(0),
+            false => self.remote.get(&self.frame).copied(),
+        };
+        let Some(remote) = remote else {
+            if self.heard.elapsed() > PEER_TIMEOUT {
+                let error = "The other player stopped answering.";
+                return Err(io::Error::new(ErrorKind::TimedOut, error));
+            }
+            return Ok(None);
+        };
+        let ours = self.local.get(&self.frame).copied().unwrap_or(0);
+        self.frame += 1;
+        // what the other player may still be missing is kept to repeat
+        let keep = self.frame.saturating_sub(self.delay + REPEATS);
+        self.local = self.local.split_off(&keep);
+        self.remote = self.remote.split_off(&self.frame);
+        Ok(Some(keys(ours | remote)))
+    }
+
+    /// Records the machine's checksum after running the frame `keys`
+    /// returned, for checking the machines are still in step.
+    pub fn ran(&mut self, checksum: u32) {
+        let frame = self.frame - 1;
+        if !(frame + 1).is_multiple_of(CHECKSUM_INTERVAL) {
+            return;
+        }
+        self.checksums.insert(frame, checksum);
+        self.checked = Some((frame, checksum));
+        self.compare();
+        // the other player is never more than the delay behind
+        self.checksums = self
+            .checksums
+            .split_off(&frame.saturating_sub(CHECKSUM_INTERVAL));
+    }
+
+    fn compare(&mut self) {
+        if let Some((frame, theirs)) = self.peer_checked {
+            if self
+                .checksums
+                .get(&frame)
+                .is_some_and(|&ours| ours != theirs)
+            {
+                self.desync.get_or_insert(frame);
+            }
+        }
+    }
+
+    fn send(&self) -> io::Result<()> {
+        let last = self.frame + self.delay;
+        let first = (last + 1).saturating_sub(self.delay + REPEATS + 1);
+        let first = first.max(self.local.keys().next().copied().unwrap_or(first));
+        let mut packet = vec![b'K'];
+        packet.extend(first.to_le_bytes());
+        packet.push((last + 1 - first) as u8);
+        for frame in first..=last {
+            let mask = self.local.get(&frame).copied().unwrap_or(0);
+            packet.extend(mask.to_le_bytes());
+        }
+        let (frame, checksum) = self.checked.unwrap_or((u64::MAX, 0));
+        packet.extend(frame.to_le_bytes());
+        packet.extend(checksum.to_le_bytes());
+        ignore_refused(self.socket.send(&packet).map(|_| ()))
+    }
+
+    fn receive(&mut self) -> io::Result<()> {
+        let mut packet = [0; MAX_PACKET];
+        loop {
+            let read = match self.socket.recv(&mut packet) {
+                Ok(read) => read,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return ignore_refused(Err(e)),
+            };
+            self.heard = Instant::now();
+            match &packet[..read] {
+                [b'K', rest @ ..] => self.read_keys(rest),
+                [b'H', ..] => {
+                    if let Some(welcome) = &self.welcome {
+                        ignore_refused(self.socket.send(welcome).map(|_| ()))?;
+                    }
+                }
+                [b'B'] => self.left = true,
+                _ => {}
+            }
+        }
+    }
+
+    fn read_keys(&mut self, packet: &[u8]) {
+        let Some((first, rest)) = split_u64(packet) else {
+            return;
+        };
+        let Some((&count, rest)) = rest.split_first() else {
+            return;
+        };
+        let (masks, rest) = rest.split_at(rest.len().min(usize::from(count) * 2));
+        for (frame, mask) in (first..).zip(masks.chunks_exact(2)) {
+            if frame >= self.frame {
+                self.remote
+                    .insert(frame, u16::from_le_bytes([mask[0], mask[1]]));
+            }
+        }
+        if let Some((frame, [c0, c1, c2, c3])) = split_u64(rest) {
+            if frame != u64::MAX {
+                self.peer_checked = Some((frame, u32::from_le_bytes([*c0, *c1, *c2, *c3])));
+                self.compare();
+            }
+        }
+    }
+}
+
+impl Drop for Netplay {
+    fn drop(&mut self) {
+        // so the other player isn't left waiting for keys that won't come
+        let _ = self.socket.send(b"B");
+    }
+}
+
+// Before the other player's there, or after they've gone, sending fails
+// with connection refused, which only means waiting longer.
+fn ignore_refused(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => Ok(()),
+        result => result,
+    }
+}
+
+fn split_u64(bytes: &[u8]) -> Option<(u64, &[u8])> {
+    let (number, rest) = bytes.split_first_chunk()?;
+    Some((u64::from_le_bytes(*number), rest))
+}
+
+fn mask(keys: &[u8; 16]) -> u16 {
+    (0..16)
+        .filter(|&key| keys[key] != 0)
+        .fold(0, |mask, key| mask | 1 << key)
+}
+
+fn keys(mask: u16) -> [u8; 16] {
+    std::array::from_fn(|key| if mask & (1 << key) != 0 { 255 } else { 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn session() -> Session {
+        Session {
+            seed: 42,
+            quirks: Quirks::default(),
+            speed: 600,
+            delay: 2,
+        }
+    }
+
+    // Waits for the frame's keys, which only cross the loopback.
+    fn next(netplay: &mut Netplay, local: &[u8; 16]) -> [u8; 16] {
+        loop {
+            if let Some(keys) = netplay.keys(local).unwrap() {
+                return keys;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn both_sides_see_both_players_keys_after_the_delay() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        drop(socket);
+        let host = thread::spawn({
+            let address = address.clone();
+            move || Netplay::host(&address, session(), 7).unwrap()
+        });
+        let (mut joined, joined_session) = Netplay::join(&address, 7).unwrap();
+        let mut host = host.join().unwrap();
+        assert_eq!(joined_session, session());
+
+        let mut one = [0; 16];
+        one[1] = 255;
+        let mut two = [0; 16];
+        two[0xC] = 255;
+        let mut both = one;
+        both[0xC] = 255;
+        for frame in 0..4 {
+            // the frames can be asked for in either order
+            let keys = next(&mut host, &one);
+            assert_eq!(next(&mut joined, &two), keys);
+            assert_eq!(keys, if frame < 2 { [0; 16] } else { both });
+        }
+
+        drop(joined);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(
+            host.keys(&one).unwrap_err().kind(),
+            ErrorKind::ConnectionAborted
+        );
+    }
+
+    #[test]
+    fn joining_with_another_rom_is_refused() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        drop(socket);
+        thread::spawn({
+            let address = address.clone();
+            move || Netplay::host(&address, session(), 7)
+        });
+        let error = Netplay::join(&address, 8).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
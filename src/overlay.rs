@@ -0,0 +1,451 @@
+// On-screen overlays drawn on top of the emulated display.
+//
+// Everything here is drawn with plain filled rects so we don't need
+// SDL_ttf: text uses a tiny 3x5 bitmap font in the same spirit as the
+// chip8 fontset.
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas};
+use sdl2::video::Window;
+
+use chip8::{Palette, KEYPAD_LAYOUT};
+
+// Width and height of a glyph in font pixels.
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+// Returns the rows of a glyph, 3 bits per row with the msb on the left.
+// Lowercase letters are drawn as uppercase and anything unknown is blank.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        ';' => [0b000, 0b010, 0b000, 0b010, 0b100],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '?' => [0b111, 0b001, 0b010, 0b000, 0b010],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        '[' => [0b110, 0b100, 0b100, 0b100, 0b110],
+        ']' => [0b011, 0b001, 0b001, 0b001, 0b011],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        '"' => [0b101, 0b101, 0b000, 0b000, 0b000],
+        _ => [0; 5],
+    }
+}
+
+// The width in screen pixels of `text` drawn at `scale`.
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    let chars = text.chars().count() as u32;
+    if chars == 0 {
+        return 0;
+    }
+
+    // every glyph is followed by a one pixel gap except the last
+    (chars * (GLYPH_WIDTH + 1) - 1) * scale
+}
+
+// The height in screen pixels of a line of text drawn at `scale`.
+pub fn text_height(scale: u32) -> u32 {
+    GLYPH_HEIGHT * scale
+}
+
+// Draws `text` with its top left corner at (x, y). Each font pixel becomes
+// a `scale` x `scale` square.
+pub fn draw_text(
+    canvas: &mut Canvas<Window>,
+    text: &str,
+    x: i32,
+    y: i32,
+    scale: u32,
+    color: impl Into<Color>,
+) {
+    canvas.set_draw_color(color);
+
+    let mut pen_x = x;
+    for c in text.chars() {
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if (bits >> (GLYPH_WIDTH - 1 - col)) & 1 == 1 {
+                    let _result = canvas.fill_rect(Rect::new(
+                        pen_x + (col * scale) as i32,
+                        y + (row as u32 * scale) as i32,
+                        scale,
+                        scale,
+                    ));
+                }
+            }
+        }
+        pen_x += ((GLYPH_WIDTH + 1) * scale) as i32;
+    }
+}
+
+// All of the overlays drawn on top of the display.
+#[derive(Default)]
+pub struct Overlays {
+    pub keypad: VirtualKeypad,
+    // lights up the keypad keys as they're pressed
+    pub show_keys: bool,
+    // shows a badge while sound is muted
+    pub muted: bool,
+    // and while emulation is paused
+    pub paused: bool,
+    // flashes the window border while the buzzer sounds
+    pub beeping: bool,
+    // show badges while running faster than real time
+    pub fast_forward: bool,
+    pub turbo: bool,
+    pub rewinding: bool,
+    // frames and instructions run since power on, when shown
+    pub counters: Option<(u64, u64)>,
+    // what the plugins have to show, like the speedrun timer
+    pub badges: Vec<String>,
+    // a short message shown for a moment, like the new speed
+    pub notice: Option<String>,
+    // keys latched down in sticky keys mode, listed in a badge
+    pub latched: [u8; 16],
+}
+
+impl Overlays {
+    pub fn draw(&self, canvas: &mut Canvas<Window>, palette: &Palette, keys: &[u8; 16]) {
+        if self.beeping {
+            draw_border(canvas, palette);
+        }
+        if self.show_keys {
+            draw_key_hud(canvas, palette, keys);
+        }
+        let badges = [
+            (self.paused, "PAUSED"),
+            (self.muted, "MUTE"),
+            (self.fast_forward, ">>"),
+            (self.turbo, "TURBO"),
+            (self.rewinding, "<<"),
+        ];
+        let latched: String = (0..16)
+            .filter(|key| self.latched[*key] != 0)
+            .map(|key| format!(" {:X}", key))
+            .collect();
+        let latched = format!("HELD{}", latched);
+
+        let mut shown: Vec<&str> = badges
+            .iter()
+            .filter(|(on, _)| *on)
+            .map(|(_, text)| *text)
+            .collect();
+        if self.latched.iter().any(|key| *key != 0) {
+            shown.push(&latched);
+        }
+        let counters = self
+            .counters
+            .map(|(frames, cycles)| [format!("FRAME {}", frames), format!("CYCLE {}", cycles)]);
+        if let Some(counters) = &counters {
+            shown.extend(counters.iter().map(String::as_str));
+        }
+        shown.extend(self.badges.iter().map(String::as_str));
+        shown.extend(self.notice.as_deref());
+        draw_badges(canvas, palette, &shown);
+        self.keypad.draw(canvas, palette, keys);
+    }
+}
+
+// Draws a thick frame around the edge of the window.
+fn draw_border(canvas: &mut Canvas<Window>, palette: &Palette) {
+    let (screen_width, screen_height) = canvas.window().size();
+    let thickness = (screen_width.min(screen_height) / 40).max(4);
+
+    canvas.set_draw_color(palette.foreground);
+    let _result = canvas.fill_rects(&[
+        Rect::new(0, 0, screen_width, thickness),
+        Rect::new(0, (screen_height - thickness) as i32, screen_width, thickness),
+        Rect::new(0, 0, thickness, screen_height),
+        Rect::new((screen_width - thickness) as i32, 0, thickness, screen_height),
+    ]);
+}
+
+// Draws short status labels down the top right corner of the window.
+fn draw_badges(canvas: &mut Canvas<Window>, palette: &Palette, texts: &[&str]) {
+    let (screen_width, _) = canvas.window().size();
+    let scale = (screen_width / 320).max(1);
+    let padding = 2 * scale;
+    let height = text_height(scale) + 2 * padding;
+
+    let mut y = (4 * scale) as i32;
+    for text in texts {
+        let width = text_width(text, scale) + 2 * padding;
+        let x = (screen_width - width - 4 * scale) as i32;
+
+        canvas.set_draw_color(palette.foreground);
+        let _result = canvas.fill_rect(Rect::new(x, y, width, height));
+        draw_text(
+            canvas,
+            text,
+            x + padding as i32,
+            y + padding as i32,
+            scale,
+            palette.background,
+        );
+        y += (height + 2 * scale) as i32;
+    }
+}
+
+// Draws a small 4x4 grid in the top left corner of the window with a cell
+// for every keypad key, filling in the cells of the keys that are down.
+fn draw_key_hud(canvas: &mut Canvas<Window>, palette: &Palette, keys: &[u8; 16]) {
+    let (screen_width, _) = canvas.window().size();
+    let scale = (screen_width / 400).max(1);
+    // a cell fits one glyph with a one font pixel border around it
+    let cell = (GLYPH_HEIGHT + 2) * scale + 2;
+    let margin = (4 * scale) as i32;
+
+    canvas.set_blend_mode(BlendMode::Blend);
+    let fg = palette.foreground;
+    let bg = palette.background;
+
+    canvas.set_draw_color(Color::RGBA(bg.r, bg.g, bg.b, 200));
+    let _result = canvas.fill_rect(Rect::new(margin, margin, cell * 4 + 2, cell * 4 + 2));
+
+    for (row, cells) in KEYPAD_LAYOUT.iter().enumerate() {
+        for (col, key) in cells.iter().enumerate() {
+            let cell_rect = Rect::new(
+                margin + 1 + (col as u32 * cell) as i32,
+                margin + 1 + (row as u32 * cell) as i32,
+                cell,
+                cell,
+            );
+
+            let pressed = keys[*key as usize] != 0;
+            let ink = if pressed {
+                canvas.set_draw_color(fg);
+                let _result = canvas.fill_rect(cell_rect);
+                bg.into()
+            } else {
+                Color::RGBA(fg.r, fg.g, fg.b, 120)
+            };
+
+            let label = format!("{:X}", key);
+            let x = cell_rect.x() + (cell - text_width(&label, scale)) as i32 / 2;
+            let y = cell_rect.y() + (cell - text_height(scale)) as i32 / 2;
+            draw_text(canvas, &label, x, y, scale, ink);
+        }
+    }
+
+    canvas.set_blend_mode(BlendMode::None);
+}
+
+// A clickable 4x4 keypad drawn over the display.
+//
+// Clicking a cell holds that chip8 key down until the mouse button is
+// released, so games are playable without learning the keyboard mapping.
+#[derive(Default)]
+pub struct VirtualKeypad {
+    pub visible: bool,
+    // the key currently held down with the mouse
+    held: Option<u8>,
+    // the keyboard keys bound to each keypad key
+    labels: [String; 16],
+    // keys held down by fingers on a touch screen, by finger id
+    touches: Vec<(i64, u8)>,
+}
+
+impl VirtualKeypad {
+    pub fn new(labels: [String; 16]) -> VirtualKeypad {
+        VirtualKeypad {
+            labels,
+            ..VirtualKeypad::default()
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.held = None;
+        self.touches.clear();
+    }
+
+    // The panel sits in the bottom right corner of the window and takes up
+    // most of its height so the cells stay big enough to hit.
+    fn panel(screen_width: u32, screen_height: u32) -> Rect {
+        let size = (screen_width.min(screen_height) * 4 / 5).max(4);
+        let margin = (screen_width.min(screen_height) - size) / 2;
+
+        Rect::new(
+            (screen_width - size - margin) as i32,
+            (screen_height - size - margin) as i32,
+            size,
+            size,
+        )
+    }
+
+    // Finds the chip8 key under the window coordinates (x, y).
+    pub fn key_at(&self, screen_width: u32, screen_height: u32, x: i32, y: i32) -> Option<u8> {
+        if !self.visible {
+            return None;
+        }
+
+        let panel = VirtualKeypad::panel(screen_width, screen_height);
+        if !panel.contains_point((x, y)) {
+            return None;
+        }
+
+        let cell = panel.width() / 4;
+        let col = ((x - panel.x()) as u32 / cell).min(3) as usize;
+        let row = ((y - panel.y()) as u32 / cell).min(3) as usize;
+
+        Some(KEYPAD_LAYOUT[row][col])
+    }
+
+    // Presses the key under the cursor, returning whether a key was hit.
+    pub fn press(&mut self, screen_width: u32, screen_height: u32, x: i32, y: i32) -> bool {
+        self.held = self.key_at(screen_width, screen_height, x, y);
+        self.held.is_some()
+    }
+
+    // Releases the key held by the mouse, returning whether one was held.
+    pub fn release(&mut self) -> bool {
+        self.held.take().is_some()
+    }
+
+    // Presses the key under a finger touching or sliding across the screen
+    // at (x, y), given as fractions of the window size. Any number of
+    // fingers can hold keys at once. Returns whether the finger's key
+    // changed.
+    pub fn touch(
+        &mut self,
+        screen_width: u32,
+        screen_height: u32,
+        finger: i64,
+        x: f32,
+        y: f32,
+    ) -> bool {
+        let before = self.lift_key(finger);
+        let key = self.key_at(
+            screen_width,
+            screen_height,
+            (x * screen_width as f32) as i32,
+            (y * screen_height as f32) as i32,
+        );
+        if let Some(key) = key {
+            self.touches.push((finger, key));
+        }
+        key != before
+    }
+
+    // Releases the key held by a finger, returning whether it held one.
+    pub fn lift(&mut self, finger: i64) -> bool {
+        self.lift_key(finger).is_some()
+    }
+
+    fn lift_key(&mut self, finger: i64) -> Option<u8> {
+        let index = self.touches.iter().position(|(id, _)| *id == finger)?;
+        Some(self.touches.remove(index).1)
+    }
+
+    // Marks the keys held with the mouse or fingers as pressed in `keys`.
+    pub fn apply(&self, keys: &mut [u8; 16]) {
+        if let Some(key) = self.held {
+            keys[key as usize] = 255;
+        }
+        for (_, key) in &self.touches {
+            keys[*key as usize] = 255;
+        }
+    }
+
+    // Draws the keypad, highlighting any keys pressed in `keys`.
+    pub fn draw(&self, canvas: &mut Canvas<Window>, palette: &Palette, keys: &[u8; 16]) {
+        if !self.visible {
+            return;
+        }
+
+        let (screen_width, screen_height) = canvas.window().size();
+        let panel = VirtualKeypad::panel(screen_width, screen_height);
+        let cell = panel.width() / 4;
+        let gap = (cell / 12).max(1);
+
+        canvas.set_blend_mode(BlendMode::Blend);
+        let fg = palette.foreground;
+        let bg = palette.background;
+
+        for (row, cells) in KEYPAD_LAYOUT.iter().enumerate() {
+            for (col, key) in cells.iter().enumerate() {
+                let cell_rect = Rect::new(
+                    panel.x() + (col as u32 * cell + gap) as i32,
+                    panel.y() + (row as u32 * cell + gap) as i32,
+                    cell - 2 * gap,
+                    cell - 2 * gap,
+                );
+
+                let pressed = keys[*key as usize] != 0 || self.held == Some(*key);
+                let (fill, ink) = if pressed { (fg, bg) } else { (bg, fg) };
+
+                canvas.set_draw_color(Color::RGBA(fill.r, fill.g, fill.b, 200));
+                let _result = canvas.fill_rect(cell_rect);
+                canvas.set_draw_color(Color::RGBA(fg.r, fg.g, fg.b, 200));
+                let _result = canvas.draw_rect(cell_rect);
+
+                // the chip8 key in the middle of the cell
+                let label = format!("{:X}", key);
+                let scale = (cell_rect.height() / 12).max(1);
+                let x = cell_rect.x() + (cell_rect.width() - text_width(&label, scale)) as i32 / 2;
+                let y = cell_rect.y() + (cell_rect.height() - text_height(scale)) as i32 / 2;
+                draw_text(canvas, &label, x, y, scale, ink);
+
+                // and the keyboard key it's bound to in the corner
+                let small = (scale / 3).max(1);
+                draw_text(
+                    canvas,
+                    &self.labels[*key as usize],
+                    cell_rect.x() + (2 * small) as i32,
+                    cell_rect.y() + (2 * small) as i32,
+                    small,
+                    Color::RGBA(ink.r, ink.g, ink.b, 160),
+                );
+            }
+        }
+
+        canvas.set_blend_mode(BlendMode::None);
+    }
+}
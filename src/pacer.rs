@@ -0,0 +1,117 @@
+// Keeps a loop running a frame every 60th of a second, for the frontends
+// that run their own loop instead of waiting on an event loop.
+//
+// Sleeping is only as precise as the OS's scheduler, which can wake a
+// thread a millisecond or more late, so most of each wait is slept and the
+// last of it spun through. A frame that ran a little long is made up for by
+// starting the next one straight away, which keeps the speed right on
+// average, but after a stall the pacer starts counting again from then
+// rather than rushing through every frame it missed.
+use std::thread;
+use std::time::{Duration, Instant};
+
+// how long before a frame's due to stop sleeping and spin instead
+const SPIN: Duration = Duration::from_millis(1);
+// how far behind the pacer gets before giving up on catching up
+const MAX_BEHIND: u32 = 4;
+
+pub struct FramePacer {
+    frame: Duration,
+    next_frame: Instant,
+}
+
+impl FramePacer {
+    pub fn new(frame: Duration) -> FramePacer {
+        FramePacer {
+            frame,
+            next_frame: Instant::now(),
+        }
+    }
+
+    // Waits until the next frame's due.
+    pub fn wait(&mut self) {
+        let Some(remaining) = self.next() else {
+            return;
+        };
+        if remaining > SPIN {
+            thread::sleep(remaining - SPIN);
+        }
+        while Instant::now() < self.next_frame {
+            thread::yield_now();
+        }
+    }
+
+    // Waits with `block` instead, for loops with something better to wait on
+    // than the time, like window events. It's given how long until the next
+    // frame's due, and called again for the rest whenever it comes back
+    // early, so a frame is never run before it's due.
+    pub fn block(&mut self, mut block: impl FnMut(Duration)) {
+        if self.next().is_none() {
+            return;
+        }
+        loop {
+            let remaining = self.next_frame.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return;
+            }
+            block(remaining);
+        }
+    }
+
+    // Moves on a frame, returning how long until it's due, or nothing if
+    // it's so far behind that it starts counting again from now.
+    fn next(&mut self) -> Option<Duration> {
+        self.next_frame += self.frame;
+        let now = Instant::now();
+        if now > self.next_frame + self.frame * MAX_BEHIND {
+            self.next_frame = now;
+            return None;
+        }
+        Some(self.next_frame.saturating_duration_since(now))
+    }
+
+    // Starts counting frames from now, after running as fast as it can.
+    pub fn restart(&mut self) {
+        self.next_frame = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_take_as_long_as_they_should() {
+        let frame = Duration::from_millis(5);
+        let mut pacer = FramePacer::new(frame);
+        let start = Instant::now();
+        for _ in 0..4 {
+            pacer.wait();
+        }
+        assert!(start.elapsed() >= frame * 4);
+
+        // after a stall it carries on from there
+        thread::sleep(frame * (MAX_BEHIND + 2));
+        let start = Instant::now();
+        pacer.wait();
+        pacer.wait();
+        assert!(start.elapsed() >= frame);
+    }
+
+    #[test]
+    fn blocking_waits_out_the_frame_however_often_it_wakes() {
+        let frame = Duration::from_millis(5);
+        let mut pacer = FramePacer::new(frame);
+        let start = Instant::now();
+        // every wait comes straight back, like a flood of mouse events
+        let mut wakeups = 0;
+        for _ in 0..3 {
+            pacer.block(|remaining| {
+                assert!(remaining <= frame);
+                wakeups += 1;
+            });
+        }
+        assert!(start.elapsed() >= frame * 3);
+        assert!(wakeups > 3);
+    }
+}
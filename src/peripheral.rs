@@ -0,0 +1,21 @@
+// Devices wired to the machine beyond what the CHIP-8 came with. The FX
+// instructions the machine doesn't know are offered to each peripheral in
+// turn before they stop the rom as unknown, so extensions like the serial
+// port don't need the CPU changing.
+use std::fmt;
+
+/// A device answering some of the FX instructions the CHIP-8 leaves unused.
+/// Peripherals aren't part of save states, and stay attached when the
+/// machine is reset.
+pub trait Peripheral: Send + Sync {
+    /// Runs FXNN with `x` as X on the registers `v`, returning whether this
+    /// peripheral knows the instruction. Ones it doesn't know must leave
+    /// the registers alone.
+    fn execute(&mut self, x: usize, nn: u8, v: &mut [u8; 16]) -> bool;
+}
+
+impl fmt::Debug for dyn Peripheral {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Peripheral")
+    }
+}
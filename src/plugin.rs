@@ -0,0 +1,286 @@
+// Plugins: optional features of the window, like the speedrun timer and
+// --script, that hook into it at a few points instead of each being woven
+// through its frame loop. The window keeps them in a Plugins registry and
+// calls them all in the order they were added:
+//
+//   init   once the rom's loaded, before the first frame
+//   frame  after each frame the machine runs
+//   keys   with the keys held, before the machine sees them
+//   event  when the machine's reset or the rom changes
+//   badge  text for a badge in the corner of the window
+//   draw   anything else, on top of the display
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use chip8::chaos::Chaos;
+use chip8::cpu::Chip8;
+#[cfg(feature = "scripting")]
+use chip8::scripting::Script;
+use chip8::speedrun::{format_time, SpeedrunTimer, Split};
+use chip8::{Config, Palette};
+
+// Something happening to the machine.
+pub enum PluginEvent<'a> {
+    // the machine started again, with the same rom
+    Reset,
+    // another rom was loaded, or the same one again after it changed, with
+    // its own settings
+    RomChanged(&'a Config),
+}
+
+pub trait Plugin {
+    // For telling the player which plugin stopped.
+    fn name(&self) -> &str;
+
+    fn init(&mut self, _emu: &mut Chip8) {}
+
+    // Returns a notice to show for a moment, or an error to stop the plugin
+    // with.
+    fn frame(&mut self, _emu: &mut Chip8) -> Result<Option<String>, String> {
+        Ok(None)
+    }
+
+    // Can change which keys the machine sees as held.
+    fn keys(&mut self, _keys: &mut [u8; 16]) {}
+
+    fn event(&mut self, _event: &PluginEvent, _emu: &mut Chip8) {}
+
+    fn badge(&self) -> Option<String> {
+        None
+    }
+
+    fn draw(&self, _canvas: &mut Canvas<Window>, _palette: &Palette) {}
+}
+
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Box<dyn Plugin>>,
+}
+
+impl Plugins {
+    // The plugins the config asks for.
+    pub fn for_config(config: &Config) -> Result<Plugins, String> {
+        let mut plugins = Plugins::default();
+        plugins.add(Box::new(Speedrun::new(config.splits.clone())));
+        #[cfg(feature = "scripting")]
+        if let Some(path) = &config.script {
+            plugins.add(Box::new(Script::load(path)?));
+        }
+        if let Some(settings) = &config.chaos {
+            println!("Chaos seed {}", settings.seed);
+            plugins.add(Box::new(Chaos::new(settings.clone())));
+        }
+        Ok(plugins)
+    }
+
+    pub fn add(&mut self, plugin: Box<dyn Plugin>) {
+        self.plugins.push(plugin);
+    }
+
+    pub fn init(&mut self, emu: &mut Chip8) {
+        for plugin in &mut self.plugins {
+            plugin.init(emu);
+        }
+    }
+
+    // Runs the plugins after a frame, returning the last notice one of them
+    // asked for. Plugins that fail are dropped.
+    pub fn frame(&mut self, emu: &mut Chip8) -> Option<String> {
+        let mut notice = None;
+        self.plugins.retain_mut(|plugin| match plugin.frame(emu) {
+            Ok(shown) => {
+                notice = shown.or(notice.take());
+                true
+            }
+            Err(e) => {
+                eprintln!("⚠️ The {} plugin stopped: {}", plugin.name(), e);
+                false
+            }
+        });
+        notice
+    }
+
+    pub fn keys(&mut self, keys: &mut [u8; 16]) {
+        for plugin in &mut self.plugins {
+            plugin.keys(keys);
+        }
+    }
+
+    // Tells every plugin about the event.
+    pub fn event(&mut self, event: &PluginEvent, emu: &mut Chip8) {
+        for plugin in &mut self.plugins {
+            plugin.event(event, emu);
+        }
+    }
+
+    pub fn badges(&self) -> Vec<String> {
+        self.plugins
+            .iter()
+            .filter_map(|plugin| plugin.badge())
+            .collect()
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, palette: &Palette) {
+        for plugin in &self.plugins {
+            plugin.draw(canvas, palette);
+        }
+    }
+}
+
+// Times runs of roms with speedrun splits, from power on or a reset.
+struct Speedrun {
+    timer: SpeedrunTimer,
+    // whether the rom has splits, and so a timer to show
+    shown: bool,
+}
+
+impl Speedrun {
+    fn new(splits: Vec<Split>) -> Speedrun {
+        let shown = !splits.is_empty();
+        let mut timer = SpeedrunTimer::new(splits);
+        timer.restart();
+        Speedrun { timer, shown }
+    }
+}
+
+impl Plugin for Speedrun {
+    fn name(&self) -> &str {
+        "speedrun timer"
+    }
+
+    fn frame(&mut self, emu: &mut Chip8) -> Result<Option<String>, String> {
+        let split = self.timer.frame(|address| emu.peek(address));
+        let Some(name) = split.map(|split| split.name.clone()) else {
+            return Ok(None);
+        };
+        let time = format_time(self.timer.elapsed());
+        println!("{} {}", time, name);
+        Ok(Some(format!("{} {}", name.to_uppercase(), time)))
+    }
+
+    fn event(&mut self, event: &PluginEvent, _emu: &mut Chip8) {
+        match event {
+            PluginEvent::Reset => self.timer.restart(),
+            PluginEvent::RomChanged(config) => *self = Speedrun::new(config.splits.clone()),
+        }
+    }
+
+    fn badge(&self) -> Option<String> {
+        self.shown.then(|| format_time(self.timer.elapsed()))
+    }
+}
+
+#[cfg(feature = "scripting")]
+impl Plugin for Script {
+    fn name(&self) -> &str {
+        "script"
+    }
+
+    fn init(&mut self, emu: &mut Chip8) {
+        if self.watches_writes() {
+            emu.watch_writes();
+        }
+    }
+
+    fn frame(&mut self, emu: &mut Chip8) -> Result<Option<String>, String> {
+        Script::frame(self, emu).map(|()| None)
+    }
+
+    fn badge(&self) -> Option<String> {
+        self.hud()
+    }
+}
+
+impl Plugin for Chaos {
+    fn name(&self) -> &str {
+        "chaos"
+    }
+
+    fn frame(&mut self, emu: &mut Chip8) -> Result<Option<String>, String> {
+        let Some((address, bit)) = Chaos::frame(self, emu) else {
+            return Ok(None);
+        };
+        println!("Flipped bit {} of {:03X}", bit, address);
+        Ok(Some(format!("FLIPPED {:03X}", address)))
+    }
+
+    fn keys(&mut self, keys: &mut [u8; 16]) {
+        Chaos::keys(self, keys)
+    }
+
+    fn badge(&self) -> Option<String> {
+        Some(format!("FLIPS {} DROPS {}", self.flipped, self.dropped))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::chaos::ChaosSettings;
+
+    // Stops on the frame it's told to.
+    struct Failing(u32);
+
+    impl Plugin for Failing {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        fn frame(&mut self, _emu: &mut Chip8) -> Result<Option<String>, String> {
+            self.0 = self.0.checked_sub(1).ok_or("out of frames")?;
+            Ok(None)
+        }
+
+        fn badge(&self) -> Option<String> {
+            Some(format!("{} LEFT", self.0))
+        }
+    }
+
+    #[test]
+    fn failing_plugins_are_dropped() {
+        let mut plugins = Plugins::default();
+        plugins.add(Box::new(Failing(1)));
+        let mut emu = Chip8::default();
+
+        assert_eq!(plugins.frame(&mut emu), None);
+        assert_eq!(plugins.badges(), ["0 LEFT"]);
+        plugins.frame(&mut emu);
+        assert!(plugins.badges().is_empty());
+    }
+
+    #[test]
+    fn the_speedrun_timer_splits_and_restarts() {
+        let mut plugins = Plugins::default();
+        plugins.add(Box::new(Speedrun::new(vec![Split {
+            name: "Start".to_string(),
+            address: 0x300,
+            value: 1,
+        }])));
+        let mut emu = Chip8::default();
+
+        assert_eq!(plugins.frame(&mut emu), None);
+        emu.poke(0x300, 1);
+        assert_eq!(plugins.frame(&mut emu).as_deref(), Some("START 0:00.03"));
+        assert_eq!(plugins.badges(), ["0:00.03"]);
+        plugins.event(&PluginEvent::Reset, &mut emu);
+        assert_eq!(plugins.badges(), ["0:00.00"]);
+    }
+
+    #[test]
+    fn chaos_drops_keys_and_counts_its_faults() {
+        let mut plugins = Plugins::default();
+        plugins.add(Box::new(Chaos::new(ChaosSettings {
+            flip_bits: 1.0,
+            drop_keys: 1.0,
+            seed: 7,
+        })));
+        let mut emu = Chip8::default();
+
+        assert!(plugins.frame(&mut emu).unwrap().starts_with("FLIPPED "));
+        let mut keys = [0; 16];
+        keys[3] = 255;
+        plugins.keys(&mut keys);
+        assert_eq!(keys, [0; 16]);
+        assert_eq!(plugins.badges(), ["FLIPS 1 DROPS 1"]);
+    }
+}
@@ -0,0 +1,72 @@
+// The behaviours chip8 interpreters disagree on. Roms written for one
+// interpreter can misbehave on another, so they can be switched to match.
+use serde::{Deserialize, Serialize};
+
+/// Which variant of each disputed instruction to emulate, set in the
+/// [quirks] section. The defaults are how this emulator has always behaved.
+///
+/// vf_reset: 8XY1, 8XY2 and 8XY3 clear VF, as on the COSMAC VIP.
+/// memory: FX55 and FX65 leave I pointing past the last register.
+/// shifting: 8XY6 and 8XYE shift VX in place instead of VY into VX.
+/// jumping: BNNN jumps to NNN plus VX, where X is NNN's top nibble, instead
+/// of plus V0.
+/// clipping: sprites are cut off at the edges of the screen instead of
+/// wrapping around.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Quirks {
+    pub vf_reset: bool,
+    pub memory: bool,
+    pub shifting: bool,
+    pub jumping: bool,
+    pub clipping: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            vf_reset: false,
+            memory: false,
+            shifting: true,
+            jumping: false,
+            clipping: false,
+        }
+    }
+}
+
+impl Quirks {
+    /// Packs the quirks into one bit each, for file headers.
+    pub fn to_bits(self) -> u8 {
+        [self.vf_reset, self.memory, self.shifting, self.jumping, self.clipping]
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, on)| bits | (*on as u8) << i)
+    }
+
+    pub fn from_bits(bits: u8) -> Quirks {
+        let bit = |i: u8| bits & (1 << i) != 0;
+        Quirks {
+            vf_reset: bit(0),
+            memory: bit(1),
+            shifting: bit(2),
+            jumping: bit(3),
+            clipping: bit(4),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bits_round_trip() {
+        let quirks = Quirks {
+            vf_reset: true,
+            clipping: true,
+            ..Quirks::default()
+        };
+        assert_eq!(quirks.to_bits(), 0b10101);
+        assert_eq!(Quirks::from_bits(quirks.to_bits()), quirks);
+    }
+}
@@ -0,0 +1,138 @@
+// How runs report how they went, for people or, with `--output json`, for
+// scripts and test harnesses. Each way a run can end badly has its own exit
+// code, which stays the same from version to version:
+//
+//   0  the run went fine
+//   1  anything else that went wrong, like a bad option
+//   2  the rom couldn't be loaded
+//   3  the rom came to an instruction the emulator doesn't know
+//   4  a test rom's check failed
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+
+use crate::cpu::{Chip8, Fault};
+use crate::testrom::TestResults;
+
+/// How results and errors are printed, picked with --output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("Unknown output format {}.", s)),
+        }
+    }
+}
+
+/// Why a run ended badly.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Failure {
+    Error(String),
+    Load(String),
+    Fault(Fault),
+    Checks(TestResults),
+}
+
+impl Failure {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Failure::Error(_) => 1,
+            Failure::Load(_) => 2,
+            Failure::Fault(_) => 3,
+            Failure::Checks(_) => 4,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self {
+            Failure::Error(error) => json!({ "status": "error", "error": error }),
+            Failure::Load(error) => json!({ "status": "load-failed", "error": error }),
+            Failure::Fault(fault) => json!({
+                "status": "fault",
+                "error": fault.to_string(),
+                "opcode": fault.opcode,
+                "address": fault.address,
+            }),
+            Failure::Checks(results) => results.to_json(),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Error(error) | Failure::Load(error) => write!(f, "{}", error),
+            Failure::Fault(fault) => write!(f, "The rom stopped: {}.", fault),
+            Failure::Checks(results) => {
+                write!(f, "The rom failed {}.", results.failure().unwrap_or_default())
+            }
+        }
+    }
+}
+
+impl From<String> for Failure {
+    fn from(error: String) -> Self {
+        Failure::Error(error)
+    }
+}
+
+/// A run that finished, as JSON: `status` with the machine's counters, where
+/// it got to and the display as rows of 0s and 1s.
+pub fn finished_json(status: &str, emu: &Chip8) -> Value {
+    let display: Vec<String> = emu
+        .pixels()
+        .iter()
+        .map(|row| row.iter().map(|&on| if on != 0 { '1' } else { '0' }).collect())
+        .collect();
+    json!({
+        "status": status,
+        "frames": emu.frames(),
+        "instructions": emu.cycles(),
+        "pc": emu.pc(),
+        "display": display,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::FaultKind;
+
+    #[test]
+    fn failures_keep_their_exit_codes() {
+        let fault = Failure::Fault(Fault {
+            opcode: 0xFFFF,
+            address: 0x204,
+            kind: FaultKind::UnknownOpcode,
+        });
+        assert_eq!(fault.exit_code(), 3);
+        assert_eq!(fault.to_json()["status"], "fault");
+        assert_eq!(fault.to_json()["address"], 0x204);
+        assert_eq!(fault.to_string(), "The rom stopped: unknown opcode FFFF at 204.");
+
+        let load = Failure::Load("Unable to load pong.ch8".to_string());
+        assert_eq!(load.exit_code(), 2);
+        assert_eq!(Failure::from("Bad option".to_string()).exit_code(), 1);
+    }
+
+    #[test]
+    fn finished_runs_include_the_display() {
+        let mut emu = Chip8::default();
+        emu.gfx[0] = 1 << 62;
+        let report = finished_json("ok", &emu);
+        assert_eq!(report["pc"], 0x200);
+        assert_eq!(report["display"].as_array().unwrap().len(), 32);
+        assert!(report["display"][0].as_str().unwrap().starts_with("010"));
+    }
+}
@@ -0,0 +1,151 @@
+// Rewinding: the machine is snapshotted every frame so that holding
+// Backspace can step back through the last few seconds of play, and the
+// arrow keys can scrub back and forth through them while paused.
+use std::collections::VecDeque;
+
+use chip8::cpu::Chip8;
+
+// five seconds at 60 frames a second
+const CAPACITY: usize = 300;
+
+#[derive(Default)]
+pub struct Rewind {
+    // oldest first
+    snapshots: VecDeque<Vec<u8>>,
+    // while scrubbing, the snapshot the machine was put back to. Snapshots
+    // after it are still there to scrub forward to until play resumes
+    scrubbed: Option<usize>,
+}
+
+impl Rewind {
+    // Snapshots the machine, forgetting the oldest snapshot once full.
+    pub fn push(&mut self, emu: &Chip8) {
+        self.stop_scrubbing();
+        // reuse the oldest snapshot's buffer rather than allocating a new
+        // one every frame
+        let mut snapshot = if self.snapshots.len() == CAPACITY {
+            self.snapshots.pop_front().unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        snapshot.clear();
+        emu.write_state(&mut snapshot)
+            .expect("writing to a Vec can't fail");
+        self.snapshots.push_back(snapshot);
+    }
+
+    // Puts the machine back to the latest snapshot, returning false when
+    // there's nothing left to rewind.
+    pub fn pop(&mut self, emu: &mut Chip8) -> bool {
+        self.stop_scrubbing();
+        match self.snapshots.pop_back() {
+            Some(snapshot) => {
+                restore(emu, &snapshot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Steps the machine back a frame without forgetting the frames after
+    // it, returning false at the oldest snapshot.
+    pub fn scrub_back(&mut self, emu: &mut Chip8) -> bool {
+        let current = match self.scrubbed {
+            Some(current) => current,
+            None => {
+                // keep the present to come back to
+                self.push(emu);
+                self.snapshots.len() - 1
+            }
+        };
+        if current == 0 {
+            self.scrubbed = Some(current);
+            return false;
+        }
+        self.scrubbed = Some(current - 1);
+        restore(emu, &self.snapshots[current - 1]);
+        true
+    }
+
+    // Steps the machine forward a frame towards where scrubbing started,
+    // returning false once it's there.
+    pub fn scrub_forward(&mut self, emu: &mut Chip8) -> bool {
+        match self.scrubbed {
+            Some(current) if current + 1 < self.snapshots.len() => {
+                self.scrubbed = Some(current + 1);
+                restore(emu, &self.snapshots[current + 1]);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // How many frames back scrubbing has gone.
+    pub fn frames_back(&self) -> usize {
+        self.scrubbed.map_or(0, |current| self.snapshots.len() - 1 - current)
+    }
+
+    // Carries on from the snapshot scrubbed to, forgetting the ones after
+    // it. The snapshot itself goes too, being where the machine is now.
+    fn stop_scrubbing(&mut self) {
+        if let Some(current) = self.scrubbed.take() {
+            self.snapshots.truncate(current);
+        }
+    }
+}
+
+fn restore(emu: &mut Chip8, snapshot: &[u8]) {
+    emu.read_state(&mut &snapshot[..])
+        .expect("snapshots are written by write_state");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewinds_newest_first_and_forgets_the_oldest() {
+        let mut emu = Chip8::default();
+        let mut rewind = Rewind::default();
+        for frame in 0..CAPACITY + 2 {
+            emu.gfx[0] = frame as u64;
+            rewind.push(&emu);
+        }
+
+        assert!(rewind.pop(&mut emu));
+        assert_eq!(emu.gfx[0], (CAPACITY + 1) as u64);
+        for _ in 1..CAPACITY {
+            assert!(rewind.pop(&mut emu));
+        }
+        // the first two frames fell off the end
+        assert_eq!(emu.gfx[0], 2);
+        assert!(!rewind.pop(&mut emu));
+    }
+
+    #[test]
+    fn scrubs_both_ways_then_resumes_from_there() {
+        let mut emu = Chip8::default();
+        let mut rewind = Rewind::default();
+        for frame in 0..5 {
+            emu.gfx[0] = frame;
+            rewind.push(&emu);
+        }
+        emu.gfx[0] = 5;
+
+        assert!(rewind.scrub_back(&mut emu));
+        assert!(rewind.scrub_back(&mut emu));
+        assert_eq!(emu.gfx[0], 3);
+        assert_eq!(rewind.frames_back(), 2);
+        assert!(rewind.scrub_forward(&mut emu));
+        assert!(rewind.scrub_forward(&mut emu));
+        assert!(!rewind.scrub_forward(&mut emu));
+        assert_eq!(emu.gfx[0], 5);
+
+        rewind.scrub_back(&mut emu);
+        // playing on from frame 4 forgets frame 5
+        rewind.push(&emu);
+        assert_eq!(rewind.snapshots.len(), 5);
+        assert!(rewind.pop(&mut emu));
+        assert_eq!(emu.gfx[0], 4);
+    }
+}
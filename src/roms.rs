@@ -0,0 +1,199 @@
+// Settings remembered for individual roms.
+//
+// Roms are identified by the SHA-1 of their contents so renaming or moving
+// a file keeps its settings. Everything lives in roms.toml next to the
+// config file, one table per rom:
+//
+//   [0123456789abcdef0123456789abcdef01234567]
+//   name = "PONG.ch8"
+//   palette = "mac-paint"
+//   speed = 900
+//
+//   [0123456789abcdef0123456789abcdef01234567.quirks]
+//   shifting = false
+//
+//   [0123456789abcdef0123456789abcdef01234567.audio]
+//   frequency = 220.0
+//   muted = true
+//
+//   [0123456789abcdef0123456789abcdef01234567.keymap]
+//   preset = "numpad"
+//
+//   [[0123456789abcdef0123456789abcdef01234567.splits]]
+//   name = "Level 1"
+//   address = 0x3F0
+//   value = 2
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{AudioSettings, ControllerSettings, KeymapSettings, PaletteName};
+use crate::quirks::Quirks;
+use crate::sound::Waveform;
+use crate::speedrun::Split;
+
+/// Returns the SHA-1 of a rom as lowercase hex.
+pub fn rom_hash(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+/// Where the rom database lives, next to config.toml.
+pub fn database_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("chip8").join("roms.toml"))
+}
+
+/// Audio settings that replace the ones from the config file when set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AudioOverrides {
+    pub frequency: Option<f32>,
+    pub waveform: Option<Waveform>,
+    pub volume: Option<f32>,
+    pub muted: Option<bool>,
+}
+
+impl AudioOverrides {
+    pub fn apply(&self, audio: &mut AudioSettings) {
+        if let Some(frequency) = self.frequency {
+            audio.tone.frequency = frequency;
+        }
+        if let Some(waveform) = self.waveform {
+            audio.tone.waveform = waveform;
+        }
+        if let Some(volume) = self.volume {
+            audio.tone.volume = volume;
+        }
+        if let Some(muted) = self.muted {
+            audio.muted = muted;
+        }
+    }
+}
+
+/// Everything remembered about one rom.
+///
+/// name: the file name the rom was last run as, to make the database
+/// readable.
+/// palette, speed: replace the config file's palette and speed.
+/// quirks: the quirks the rom needs, replacing the config file's [quirks].
+/// keymap, controller: an input profile that replaces the config file's
+/// [keymap] and [controller] sections for this rom.
+/// splits: the speedrun timer's splits, in the order they're reached.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RomSettings {
+    pub name: Option<String>,
+    pub palette: Option<PaletteName>,
+    pub speed: Option<u32>,
+    pub quirks: Option<Quirks>,
+    pub audio: AudioOverrides,
+    pub keymap: Option<KeymapSettings>,
+    pub controller: Option<ControllerSettings>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub splits: Vec<Split>,
+}
+
+/// The settings of every rom that has any, keyed by rom hash.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct RomDatabase {
+    roms: BTreeMap<String, RomSettings>,
+}
+
+impl RomDatabase {
+    /// Loads the database, which is empty until something gets saved.
+    pub fn load() -> Result<RomDatabase, String> {
+        let path = match database_path() {
+            Some(path) => path,
+            None => return Ok(RomDatabase::default()),
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| format!("Invalid rom database {}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(RomDatabase::default()),
+            Err(e) => Err(format!("Unable to read {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = match database_path() {
+            Some(path) => path,
+            None => return Err("No config directory to save the rom database in.".to_string()),
+        };
+
+        let contents = toml::to_string(self).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        fs::write(&path, contents).map_err(|e| format!("Unable to write {}: {}", path.display(), e))
+    }
+
+    /// The settings for the rom with `hash`, if there are any.
+    pub fn get(&self, hash: &str) -> Option<&RomSettings> {
+        self.roms.get(hash)
+    }
+
+    /// The settings for the rom with `hash`, creating them if needed.
+    pub fn entry(&mut self, hash: &str) -> &mut RomSettings {
+        self.roms.entry(hash.to_string()).or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_is_sha1_hex() {
+        assert_eq!(rom_hash(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+
+    #[test]
+    fn round_trips_through_toml() {
+        let mut database = RomDatabase::default();
+        let entry = database.entry("a9993e364706816aba3e25717850c26c9cd0d89d");
+        entry.name = Some("PONG.ch8".to_string());
+        entry.palette = Some(PaletteName::MacPaint);
+        entry.speed = Some(900);
+        entry.quirks = Some(Quirks {
+            shifting: false,
+            ..Quirks::default()
+        });
+        entry.audio.muted = Some(true);
+        let mut keymap = KeymapSettings::default();
+        keymap.bindings.insert("5".to_string(), vec!["Up".to_string()]);
+        entry.keymap = Some(keymap);
+        entry.controller = Some(ControllerSettings::default());
+        entry.splits = vec![Split {
+            name: "Level 1".to_string(),
+            address: 0x3F0,
+            value: 2,
+        }];
+
+        let contents = toml::to_string(&database).unwrap();
+        let loaded: RomDatabase = toml::from_str(&contents).unwrap();
+        assert_eq!(loaded.roms, database.roms);
+    }
+
+    #[test]
+    fn split_addresses_can_be_hex() {
+        let database: RomDatabase =
+            toml::from_str("[[abc.splits]]\nname = \"Win\"\naddress = 0x3F0\nvalue = 1\n").unwrap();
+        assert_eq!(database.get("abc").unwrap().splits[0].address, 0x3F0);
+    }
+
+    #[test]
+    fn overrides_only_replace_what_is_set() {
+        let mut audio = AudioSettings::default();
+        let overrides = AudioOverrides {
+            volume: Some(0.1),
+            ..AudioOverrides::default()
+        };
+        overrides.apply(&mut audio);
+        assert_eq!(audio.tone.volume, 0.1);
+        assert_eq!(audio.tone.frequency, AudioSettings::default().tone.frequency);
+    }
+}
@@ -0,0 +1,292 @@
+// A JSON-RPC 2.0 API for driving a running emulator from other programs,
+// like editors, teaching dashboards and test tools. Requests are POSTed to
+// the address given with --rpc, a request at a time:
+//
+//   curl -d '{"jsonrpc":"2.0","id":1,"method":"registers"}' localhost:7000
+//
+// The methods are:
+//
+//   status                    whether it's paused, frame and instruction
+//                             counts, the program counter and any fault
+//   pause, resume             stop and start running frames
+//   step {count}              run `count` instructions, 1 if not given,
+//                             while paused
+//   registers                 V0-VF, I, the program counter, the stack and
+//                             the timers
+//   memory {address, length}  `length` bytes from `address`, in base64
+//   framebuffer               the display, a byte a pixel and 1 if lit, a
+//                             row at a time, in base64
+//   load {path}               switch to another rom
+//
+// Requests are answered by the frontend between frames, so they always see
+// the machine as it's shown.
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::{json, Value};
+
+use crate::archive::read_rom;
+use crate::cpu::Chip8;
+use crate::http_server::{self, read_head};
+
+// how long the frontend has to answer, which it does every frame unless
+// something's wrong
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(2);
+// requests are a line of JSON, anything bigger is a mistake
+const MAX_REQUEST: usize = 64 * 1024;
+// the most instructions a step runs, so a big count can't freeze the window
+const MAX_STEP: u64 = 1_000_000;
+const MEMORY_SIZE: u64 = 4096;
+
+// JSON-RPC's error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// the method couldn't do what was asked, like stepping while running
+const FAILED: i64 = -32000;
+
+/// The API, listening for requests for the frontend to answer.
+pub struct RpcServer {
+    calls: Receiver<Call>,
+}
+
+// A request waiting for an answer, and where to send it.
+struct Call {
+    request: Value,
+    reply: Sender<Value>,
+}
+
+impl RpcServer {
+    /// Starts listening on `address`, e.g. 127.0.0.1:7000.
+    pub fn bind(address: &str) -> io::Result<RpcServer> {
+        let listener = TcpListener::bind(address)?;
+        let (sender, calls) = mpsc::channel();
+        http_server::serve(listener, "an RPC request", move |stream| {
+            serve(stream, sender.clone())
+        })?;
+        Ok(RpcServer { calls })
+    }
+
+    /// Answers the requests that came in since last time, which can pause,
+    /// resume and step `emu`. Returns the rom a `load` asked for, for the
+    /// frontend to switch to.
+    pub fn answer(&self, emu: &mut Chip8, paused: &mut bool) -> Option<String> {
+        let mut load = None;
+        for call in self.calls.try_iter() {
+            let _ = call
+                .reply
+                .send(respond(&call.request, emu, paused, &mut load));
+        }
+        load
+    }
+}
+
+// Reads an HTTP request, has it answered and sends back the answer.
+fn serve(mut stream: TcpStream, calls: Sender<Call>) -> io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let head = read_head(&mut reader)?;
+    let post = head.request.starts_with("POST ");
+    let length = head
+        .header("content-length")
+        .map_or(0, |length| length.parse().unwrap_or(0));
+
+    if !post {
+        return reply(&mut stream, "405 Method Not Allowed", "Allow: POST\r\n", "");
+    }
+    if length > MAX_REQUEST {
+        return reply(&mut stream, "413 Content Too Large", "", "");
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let answer = match serde_json::from_slice(&body) {
+        Ok(request) => {
+            let (reply, answer) = mpsc::channel();
+            let _ = calls.send(Call { request, reply });
+            answer
+                .recv_timeout(ANSWER_TIMEOUT)
+                .unwrap_or_else(|_| error(Value::Null, FAILED, "The emulator didn't answer."))
+        }
+        Err(e) => error(Value::Null, PARSE_ERROR, &e.to_string()),
+    };
+    let headers = "Content-Type: application/json\r\n";
+    reply(&mut stream, "200 OK", headers, &answer.to_string())
+}
+
+fn reply(stream: &mut TcpStream, status: &str, headers: &str, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        headers,
+        body.len(),
+        body
+    )
+}
+
+// The response to a request, setting `load` to the rom a load asked for.
+fn respond(
+    request: &Value,
+    emu: &mut Chip8,
+    paused: &mut bool,
+    load: &mut Option<String>,
+) -> Value {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let method = match (request.get("jsonrpc"), request.get("method")) {
+        (Some(version), Some(Value::String(method))) if version == "2.0" => method,
+        _ => return error(id, INVALID_REQUEST, "Not a JSON-RPC 2.0 request."),
+    };
+    let params = request.get("params").cloned().unwrap_or(json!({}));
+    match call(method, &params, emu, paused, load) {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => error(id, code, &message),
+    }
+}
+
+fn call(
+    method: &str,
+    params: &Value,
+    emu: &mut Chip8,
+    paused: &mut bool,
+    load: &mut Option<String>,
+) -> Result<Value, (i64, String)> {
+    let number = |name: &str, default: Option<u64>| match params.get(name) {
+        Some(value) => value
+            .as_u64()
+            .ok_or((INVALID_PARAMS, format!("{} must be a number.", name))),
+        None => default.ok_or((INVALID_PARAMS, format!("{} is missing.", name))),
+    };
+
+    match method {
+        "status" => Ok(json!({
+            "paused": *paused,
+            "frames": emu.frames(),
+            "cycles": emu.cycles(),
+            "pc": emu.pc(),
+            "fault": emu.fault().map(|fault| fault.to_string()),
+        })),
+        "pause" | "resume" => {
+            *paused = method == "pause";
+            Ok(Value::Null)
+        }
+        "step" => {
+            if !*paused {
+                return Err((FAILED, "Pause before stepping.".to_string()));
+            }
+            for _ in 0..number("count", Some(1))?.min(MAX_STEP) {
+                if emu.fault().is_some() {
+                    break;
+                }
+                emu.emulate_cycle();
+            }
+            Ok(json!({ "pc": emu.pc(), "cycles": emu.cycles() }))
+        }
+        "registers" => {
+            let (delay, sound) = emu.timers();
+            Ok(json!({
+                "v": emu.registers(),
+                "i": emu.index(),
+                "pc": emu.pc(),
+                "stack": emu.stack(),
+                "delay": delay,
+                "sound": sound,
+            }))
+        }
+        "memory" => {
+            let address = number("address", None)?;
+            let length = number("length", Some(1))?;
+            if address.saturating_add(length) > MEMORY_SIZE {
+                return Err((INVALID_PARAMS, "That's past the end of memory.".to_string()));
+            }
+            let bytes: Vec<u8> = (address..address + length)
+                .map(|address| emu.peek(address as u16))
+                .collect();
+            Ok(json!(STANDARD.encode(bytes)))
+        }
+        "framebuffer" => {
+            let pixels = emu.pixels().as_flattened().to_vec();
+            Ok(json!({ "width": 64, "height": 32, "pixels": STANDARD.encode(pixels) }))
+        }
+        "load" => {
+            let Some(path) = params.get("path").and_then(Value::as_str) else {
+                return Err((INVALID_PARAMS, "path is missing.".to_string()));
+            };
+            read_rom(path).map_err(|e| (FAILED, format!("Unable to read {}: {}", path, e)))?;
+            *load = Some(path.to_string());
+            Ok(Value::Null)
+        }
+        _ => Err((METHOD_NOT_FOUND, format!("There's no method {}.", method))),
+    }
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(emu: &mut Chip8, paused: &mut bool, request: Value) -> Value {
+        respond(&request, emu, paused, &mut None)
+    }
+
+    #[test]
+    fn steps_only_while_paused() {
+        let mut emu = Chip8::default();
+        // LD V3, 0x2A
+        emu.load_rom(&[0x63, 0x2A]).unwrap();
+        let mut paused = false;
+        let step = json!({ "jsonrpc": "2.0", "id": 1, "method": "step" });
+
+        let response = request(&mut emu, &mut paused, step.clone());
+        assert_eq!(response["error"]["code"], FAILED);
+
+        let pause = json!({ "jsonrpc": "2.0", "id": 2, "method": "pause" });
+        assert_eq!(request(&mut emu, &mut paused, pause)["result"], Value::Null);
+        let response = request(&mut emu, &mut paused, step);
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["pc"], 0x202);
+
+        let registers = json!({ "jsonrpc": "2.0", "id": 3, "method": "registers" });
+        let response = request(&mut emu, &mut paused, registers);
+        assert_eq!(response["result"]["v"][3], 0x2A);
+    }
+
+    #[test]
+    fn memory_is_base64() {
+        let mut emu = Chip8::default();
+        emu.load_rom(&[0x12, 0x00]).unwrap();
+        let memory = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "memory",
+            "params": { "address": 0x200, "length": 2 },
+        });
+        let response = request(&mut emu, &mut false, memory);
+        assert_eq!(response["result"], "EgA=");
+
+        let past_the_end = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "memory",
+            "params": { "address": 4095, "length": 2 },
+        });
+        let response = request(&mut emu, &mut false, past_the_end);
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[test]
+    fn bad_requests_are_errors() {
+        let mut emu = Chip8::default();
+        let response = request(&mut emu, &mut false, json!({ "id": 1, "method": "status" }));
+        assert_eq!(response["error"]["code"], INVALID_REQUEST);
+        let unknown = json!({ "jsonrpc": "2.0", "id": 1, "method": "explode" });
+        let response = request(&mut emu, &mut false, unknown);
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+    }
+}
@@ -0,0 +1,154 @@
+// Save states: the whole machine written to a file next to the rom, to pick
+// up from later. Each rom has ten slots to save to.
+//
+// A state file starts with a header saying what it was saved from, followed
+// by the machine as Chip8::write_state writes it:
+//
+//   "CH8S"    magic
+//   u8        format version
+//   [u8; 40]  the rom's SHA-1 in hex, zeros if it isn't known
+//   u8        the quirks, one bit each
+//   u8        length of the emulator's version, then the version in ASCII
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use chip8::archive;
+use chip8::bundled;
+use chip8::compat::{self, Origin};
+use chip8::quirks::Quirks;
+
+use chip8::cpu::Chip8;
+
+const MAGIC: &[u8; 4] = b"CH8S";
+const FORMAT: u8 = 1;
+
+pub const SLOTS: u8 = 10;
+
+// Where slot `slot` of the rom at `rom_path` is saved: pong.state for slot 0,
+// then pong.1.state, pong.2.state and so on. Roms in archives are saved next
+// to the archive, and bundled roms in the data directory.
+pub fn state_path(rom_path: &str, slot: u8) -> PathBuf {
+    let extension = match slot {
+        0 => "state".to_string(),
+        slot => format!("{}.state", slot),
+    };
+    if let Some(rom) = bundled::find(rom_path) {
+        let dir = dirs::data_dir().map(|dir| dir.join("chip8").join("bundled"));
+        return dir.unwrap_or_default().join(rom.name).with_extension(extension);
+    }
+    match archive::split(rom_path) {
+        (file, Some(name)) => {
+            let name = Path::new(name).file_name().unwrap_or_default();
+            Path::new(file).with_file_name(name).with_extension(extension)
+        }
+        (file, None) => Path::new(file).with_extension(extension),
+    }
+}
+
+// Where the machine is saved on quitting, by the rom's hash so it's found
+// again wherever the rom is started from, e.g.
+// ~/.local/share/chip8/autosave/<hash>.state on Linux.
+pub fn autosave_path(rom_hash: &str) -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| {
+        dir.join("chip8")
+            .join("autosave")
+            .join(format!("{}.state", rom_hash))
+    })
+}
+
+pub fn save(path: &Path, emu: &Chip8, rom_hash: Option<&str>) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&[FORMAT])?;
+
+    let mut hash = [0; 40];
+    if let Some(rom_hash) = rom_hash {
+        hash.copy_from_slice(rom_hash.as_bytes());
+    }
+    out.write_all(&hash)?;
+    out.write_all(&[emu.quirks().to_bits()])?;
+    out.write_all(&[compat::VERSION.len() as u8])?;
+    out.write_all(compat::VERSION.as_bytes())?;
+
+    emu.write_state(&mut out)?;
+    out.flush()
+}
+
+// Loads a saved machine, running with the quirks it was saved with. States
+// from another rom are refused, anything else that doesn't match what's
+// running is warned about.
+pub fn load(path: &Path, rom_hash: Option<&str>, quirks: Quirks) -> io::Result<Chip8> {
+    let mut input = BufReader::new(File::open(path)?);
+
+    let mut header = [0; 46];
+    input.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(invalid("not a save state"));
+    }
+    check_format(header[4])?;
+    let mut length = [0];
+    input.read_exact(&mut length)?;
+    let mut version = vec![0; length[0] as usize];
+    input.read_exact(&mut version)?;
+    let version = String::from_utf8_lossy(&version);
+
+    // zeros when the rom wasn't known
+    let saved_hash = std::str::from_utf8(&header[5..45])
+        .ok()
+        .filter(|hash| !hash.starts_with('\0'));
+    let origin = Origin {
+        version: Some(&version),
+        rom_hash: saved_hash,
+        quirks: Quirks::from_bits(header[45]),
+    };
+    for warning in origin.check(rom_hash, quirks).map_err(|e| invalid(&e))? {
+        eprintln!("⚠️ {}: {}", path.display(), warning);
+    }
+
+    let mut emu = Chip8::with_quirks(origin.quirks);
+    emu.read_state(&mut input)?;
+    Ok(emu)
+}
+
+// Formats count up from 1, so a 0 is something else that starts CH8S.
+fn check_format(format: u8) -> io::Result<()> {
+    match format {
+        0 => Err(invalid("not a save state, its format is 0")),
+        format if format > FORMAT => Err(invalid("saved by a newer version of the emulator")),
+        _ => Ok(()),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_share_the_rom_name() {
+        assert_eq!(state_path("roms/PONG.ch8", 0), Path::new("roms/PONG.state"));
+        assert_eq!(
+            state_path("roms/PONG.ch8", 3),
+            Path::new("roms/PONG.3.state")
+        );
+        assert_eq!(
+            state_path("roms/games.zip:games/PONG.ch8", 0),
+            Path::new("roms/PONG.state")
+        );
+    }
+
+    #[test]
+    fn only_this_format_loads() {
+        assert!(check_format(FORMAT).is_ok());
+        let message = |format| check_format(format).unwrap_err().to_string();
+        assert_eq!(message(0), "not a save state, its format is 0");
+        assert_eq!(message(FORMAT + 1), "saved by a newer version of the emulator");
+    }
+}
@@ -0,0 +1,286 @@
+// User scripts in Rhai (https://rhai.rs), run alongside the rom for HUDs,
+// trainers, auto-splitters and experiments without building the emulator.
+// A script defines the callbacks it wants, and anything outside them runs
+// once when it's loaded:
+//
+//   fn on_write(address, value) {
+//       // the rom wrote `value` to `address`
+//   }
+//   fn on_frame() {
+//       // a frame has run
+//       if reg(3) < 3 { set_reg(3, 3); }     // infinite lives
+//       this.best = max(this.best ?? 0, peek(0x2F0));
+//       hud(`BEST ${this.best}`);
+//   }
+//
+// After each frame on_write is called for each byte the rom wrote during
+// it, in order, then on_frame. Functions in Rhai can't see the script's
+// variables, so `this` is a map that's kept from call to call for
+// remembering things. Scripts read and change the machine with:
+//
+//   peek(address), poke(address, value)   memory
+//   reg(x), set_reg(x, value)             V0-VF
+//   index(), set_index(value)             I
+//   pc(), set_pc(value)                   the program counter
+//   delay(), set_delay(value)             the delay timer
+//   sound(), set_sound(value)             the sound timer
+//   key(k)                                whether keypad key k is held
+//   frame()                               frames run since power on
+//   hud(text)                             shows text in the window, or
+//                                         nothing for ""
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST, INT};
+
+use crate::cpu::Chip8;
+
+// how much a callback can do before it's stopped, so a script stuck in a
+// loop can't freeze the emulator
+const MAX_OPERATIONS: u64 = 1_000_000;
+const MEMORY_SIZE: usize = 4096;
+
+/// A loaded script, ready to be called back.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    // the script's `this`
+    state: Dynamic,
+    machine: Rc<RefCell<Machine>>,
+    on_write: bool,
+    on_frame: bool,
+}
+
+// What the script sees of the machine while it's called back, copied from
+// it beforehand and copied back afterwards if the script changed anything.
+struct Machine {
+    memory: [u8; MEMORY_SIZE],
+    v: [u8; 16],
+    i: u16,
+    pc: u16,
+    delay: u8,
+    sound: u8,
+    keys: [u8; 16],
+    frame: u64,
+    changed: bool,
+    hud: Option<String>,
+}
+
+impl Script {
+    /// Loads the script at `path`, running anything outside its callbacks.
+    pub fn load(path: &Path) -> Result<Script, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        Script::new(&source).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Compiles a script, running anything outside its callbacks.
+    pub fn new(source: &str) -> Result<Script, String> {
+        let machine = Rc::new(RefCell::new(Machine {
+            memory: [0; MEMORY_SIZE],
+            v: [0; 16],
+            i: 0,
+            pc: 0,
+            delay: 0,
+            sound: 0,
+            keys: [0; 16],
+            frame: 0,
+            changed: false,
+            hud: None,
+        }));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        register(&mut engine, &machine);
+
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        engine.run_ast(&ast).map_err(|e| e.to_string())?;
+        let has = |name: &str| ast.iter_functions().any(|function| function.name == name);
+        Ok(Script {
+            on_write: has("on_write"),
+            on_frame: has("on_frame"),
+            engine,
+            ast,
+            state: Dynamic::from_map(Map::new()),
+            machine,
+        })
+    }
+
+    /// Whether the script wants to hear about memory writes, for the
+    /// frontend to have `emu` watch them.
+    pub fn watches_writes(&self) -> bool {
+        self.on_write
+    }
+
+    /// Calls the script back for the frame `emu` just ran, letting it change
+    /// the machine.
+    pub fn frame(&mut self, emu: &mut Chip8) -> Result<(), String> {
+        let writes = emu.take_writes();
+        if !self.on_frame && writes.is_empty() {
+            return Ok(());
+        }
+        self.machine.borrow_mut().read(emu);
+        for (address, value) in writes {
+            self.call("on_write", (INT::from(address), INT::from(value)))?;
+        }
+        if self.on_frame {
+            self.call("on_frame", ())?;
+        }
+        self.machine.borrow_mut().write(emu);
+        Ok(())
+    }
+
+    /// What the script last asked to show with hud().
+    pub fn hud(&self) -> Option<String> {
+        self.machine.borrow().hud.clone()
+    }
+
+    fn call(&mut self, name: &str, args: impl rhai::FuncArgs) -> Result<(), String> {
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.state);
+        self.engine
+            .call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &self.ast, name, args)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl Machine {
+    fn read(&mut self, emu: &Chip8) {
+        for (address, byte) in self.memory.iter_mut().enumerate() {
+            *byte = emu.peek(address as u16);
+        }
+        self.v = *emu.registers();
+        self.i = emu.index();
+        self.pc = emu.pc();
+        (self.delay, self.sound) = emu.timers();
+        self.keys = *emu.keys();
+        self.frame = emu.frames();
+        self.changed = false;
+    }
+
+    fn write(&self, emu: &mut Chip8) {
+        if !self.changed {
+            return;
+        }
+        for (address, &byte) in self.memory.iter().enumerate() {
+            if emu.peek(address as u16) != byte {
+                emu.poke(address as u16, byte);
+            }
+        }
+        for (x, &value) in self.v.iter().enumerate() {
+            emu.set_register(x, value);
+        }
+        emu.set_index(self.i);
+        emu.set_pc(self.pc);
+        emu.set_timers(self.delay, self.sound);
+    }
+}
+
+// Gives the script its functions for reading and changing the machine.
+fn register(engine: &mut Engine, machine: &Rc<RefCell<Machine>>) {
+    // addresses wrap around memory, and values are cut down to size
+    let address = |address: INT| address.rem_euclid(MEMORY_SIZE as INT) as usize;
+    let register = |x: INT| x.rem_euclid(16) as usize;
+
+    let m = Rc::clone(machine);
+    engine.register_fn("peek", move |a: INT| {
+        INT::from(m.borrow().memory[address(a)])
+    });
+    let m = Rc::clone(machine);
+    engine.register_fn("poke", move |a: INT, value: INT| {
+        let mut m = m.borrow_mut();
+        m.memory[address(a)] = value as u8;
+        m.changed = true;
+    });
+    let m = Rc::clone(machine);
+    engine.register_fn("reg", move |x: INT| INT::from(m.borrow().v[register(x)]));
+    let m = Rc::clone(machine);
+    engine.register_fn("set_reg", move |x: INT, value: INT| {
+        let mut m = m.borrow_mut();
+        m.v[register(x)] = value as u8;
+        m.changed = true;
+    });
+    let m = Rc::clone(machine);
+    engine.register_fn("index", move || INT::from(m.borrow().i));
+    let m = Rc::clone(machine);
+    engine.register_fn("set_index", move |value: INT| {
+        let mut m = m.borrow_mut();
+        m.i = value as u16;
+        m.changed = true;
+    });
+    let m = Rc::clone(machine);
+    engine.register_fn("pc", move || INT::from(m.borrow().pc));
+    let m = Rc::clone(machine);
+    engine.register_fn("set_pc", move |value: INT| {
+        let mut m = m.borrow_mut();
+        m.pc = address(value) as u16;
+        m.changed = true;
+    });
+    let m = Rc::clone(machine);
+    engine.register_fn("delay", move || INT::from(m.borrow().delay));
+    let m = Rc::clone(machine);
+    engine.register_fn("set_delay", move |value: INT| {
+        let mut m = m.borrow_mut();
+        m.delay = value as u8;
+        m.changed = true;
+    });
+    let m = Rc::clone(machine);
+    engine.register_fn("sound", move || INT::from(m.borrow().sound));
+    let m = Rc::clone(machine);
+    engine.register_fn("set_sound", move |value: INT| {
+        let mut m = m.borrow_mut();
+        m.sound = value as u8;
+        m.changed = true;
+    });
+    let m = Rc::clone(machine);
+    engine.register_fn("key", move |k: INT| m.borrow().keys[register(k)] != 0);
+    let m = Rc::clone(machine);
+    engine.register_fn("frame", move || m.borrow().frame as INT);
+    let m = Rc::clone(machine);
+    engine.register_fn("hud", move |text: &str| {
+        m.borrow_mut().hud = Some(text.to_string()).filter(|text| !text.is_empty());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts_watch_and_change_the_machine() {
+        let mut script = Script::new(
+            r#"
+            fn on_write(address, value) {
+                this.writes = (this.writes ?? 0) + 1;
+            }
+            fn on_frame() {
+                set_reg(3, 9);
+                poke(0x300, reg(0) + 1);
+                hud(`WRITES ${this.writes}`);
+            }
+            "#,
+        )
+        .unwrap();
+        let mut emu = Chip8::default();
+        if script.watches_writes() {
+            emu.watch_writes();
+        }
+        // LD V0, 4; LD I, 0x310; LD [I], V0 which writes V0 and nothing else
+        emu.load_rom(&[0x60, 0x04, 0xA3, 0x10, 0xF0, 0x55]).unwrap();
+        emu.run_frame(3);
+        script.frame(&mut emu).unwrap();
+
+        assert_eq!(emu.registers()[3], 9);
+        assert_eq!(emu.peek(0x300), 5);
+        assert_eq!(emu.peek(0x310), 4);
+        assert_eq!(script.hud().as_deref(), Some("WRITES 1"));
+    }
+
+    #[test]
+    fn runaway_scripts_are_stopped() {
+        let mut script = Script::new("fn on_frame() { loop {} }").unwrap();
+        assert!(script.frame(&mut Chip8::default()).is_err());
+    }
+}
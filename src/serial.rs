@@ -0,0 +1,183 @@
+// A serial port for roms, so two emulators, or an emulator and a program on
+// the other end of a pty, can swap bytes: enough for chat programs and
+// linked play. It's a peripheral answering three FX instructions the
+// CHIP-8 leaves unused:
+//
+//   FXB0  sends VX
+//   FXB1  receives a byte into VX and sets VF to 1, or if none are waiting
+//         leaves VX alone and sets VF to 0
+//   FXB2  sets VX to how many bytes are waiting, up to 255
+//
+// Bytes are read on a thread of their own as they arrive, so a rom never
+// waits on the other end.
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::peripheral::Peripheral;
+
+const SEND: u8 = 0xB0;
+const RECEIVE: u8 = 0xB1;
+const WAITING: u8 = 0xB2;
+
+/// Where the other end of the serial port is, given with --serial as
+/// `listen:ADDRESS`, `connect:ADDRESS` or the path of a device like a pty.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SerialLink {
+    Listen(String),
+    Connect(String),
+    Device(PathBuf),
+}
+
+impl FromStr for SerialLink {
+    type Err = String;
+
+    fn from_str(link: &str) -> Result<SerialLink, String> {
+        if let Some(address) = link.strip_prefix("listen:") {
+            Ok(SerialLink::Listen(address.to_string()))
+        } else if let Some(address) = link.strip_prefix("connect:") {
+            Ok(SerialLink::Connect(address.to_string()))
+        } else if link.is_empty() {
+            Err("The serial link is empty.".to_string())
+        } else {
+            Ok(SerialLink::Device(PathBuf::from(link)))
+        }
+    }
+}
+
+/// The serial port, wired to whatever's on the other end.
+pub struct SerialPort {
+    output: Box<dyn Write + Send + Sync>,
+    received: Arc<Mutex<VecDeque<u8>>>,
+}
+
+impl SerialPort {
+    /// Opens the link, which for `listen:` waits for the other end to
+    /// connect.
+    pub fn open(link: &SerialLink) -> io::Result<SerialPort> {
+        match link {
+            SerialLink::Listen(address) => {
+                let (stream, _) = TcpListener::bind(address)?.accept()?;
+                SerialPort::over_tcp(stream)
+            }
+            SerialLink::Connect(address) => SerialPort::over_tcp(TcpStream::connect(address)?),
+            SerialLink::Device(path) => {
+                let device = OpenOptions::new().read(true).write(true).open(path)?;
+                Ok(SerialPort::new(device.try_clone()?, device))
+            }
+        }
+    }
+
+    fn over_tcp(stream: TcpStream) -> io::Result<SerialPort> {
+        // a byte at a time, each sent as soon as the rom sends it
+        stream.set_nodelay(true)?;
+        Ok(SerialPort::new(stream.try_clone()?, stream))
+    }
+
+    /// A serial port reading from `input` and writing to `output`.
+    pub fn new(
+        mut input: impl Read + Send + 'static,
+        output: impl Write + Send + Sync + 'static,
+    ) -> SerialPort {
+        let received = Arc::new(Mutex::new(VecDeque::new()));
+        let arriving = Arc::clone(&received);
+        thread::spawn(move || {
+            let mut bytes = [0; 256];
+            // once the other end's gone nothing more arrives
+            while let Ok(read @ 1..) = input.read(&mut bytes) {
+                match arriving.lock() {
+                    Ok(mut arriving) => arriving.extend(&bytes[..read]),
+                    Err(_) => return,
+                }
+            }
+        });
+        SerialPort {
+            output: Box::new(output),
+            received,
+        }
+    }
+}
+
+impl Peripheral for SerialPort {
+    fn execute(&mut self, x: usize, nn: u8, v: &mut [u8; 16]) -> bool {
+        match nn {
+            // bytes sent with nobody there are lost, as on a real wire
+            SEND => {
+                let _ = self.output.write_all(&[v[x]]);
+            }
+            RECEIVE => {
+                let byte = self
+                    .received
+                    .lock()
+                    .ok()
+                    .and_then(|mut bytes| bytes.pop_front());
+                if let Some(byte) = byte {
+                    v[x] = byte;
+                }
+                v[0xF] = u8::from(byte.is_some());
+            }
+            WAITING => {
+                let waiting = self.received.lock().map_or(0, |bytes| bytes.len());
+                v[x] = waiting.min(255) as u8;
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::Chip8;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn links_are_parsed() {
+        assert_eq!(
+            "listen:0.0.0.0:7800".parse(),
+            Ok(SerialLink::Listen("0.0.0.0:7800".to_string()))
+        );
+        assert_eq!(
+            "connect:localhost:7800".parse(),
+            Ok(SerialLink::Connect("localhost:7800".to_string()))
+        );
+        assert_eq!(
+            "/dev/pts/3".parse(),
+            Ok(SerialLink::Device(PathBuf::from("/dev/pts/3")))
+        );
+    }
+
+    #[test]
+    fn roms_swap_bytes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let connecting =
+            thread::spawn(move || SerialPort::open(&SerialLink::Connect(address)).unwrap());
+        let (stream, _) = listener.accept().unwrap();
+
+        let mut sender = Chip8::default();
+        sender.attach(Box::new(SerialPort::over_tcp(stream).unwrap()));
+        // LD V1, 0x41; FXB0 with X 1
+        sender.load_rom(&[0x61, 0x41, 0xF1, 0xB0]).unwrap();
+        let mut receiver = Chip8::default();
+        receiver.attach(Box::new(connecting.join().unwrap()));
+        // FXB1 with X 2, again until it gets something
+        receiver.load_rom(&[0xF2, 0xB1, 0x3F, 0x01, 0x12, 0x00]).unwrap();
+
+        sender.emulate_cycle();
+        sender.emulate_cycle();
+        let started = Instant::now();
+        while receiver.registers()[0xF] == 0 {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            receiver.emulate_cycle();
+        }
+        assert_eq!(receiver.registers()[2], 0x41);
+        assert_eq!(receiver.fault(), None);
+    }
+}
@@ -0,0 +1,273 @@
+// Plays a rom without a window, serving the display to browsers instead, so
+// a machine on a server or a Pi can be watched and played from anywhere:
+//
+//   chip8 run pong.ch8 --serve 0.0.0.0:8080
+//
+// Opening the address shows serve.html, which connects back to /ws over a
+// WebSocket. Whenever the display or the buzzer changes, everyone watching
+// is sent a binary message: a byte that's 1 while the buzzer sounds, then
+// the 2048 pixels 8 to a byte, a row at a time from the top left. Viewers
+// press keys with text messages, "down 5" and "up 5" for keypad key 5, and
+// a key stays down while anyone is holding it. If the rom stops at an
+// unknown instruction, viewers are sent why as a text message.
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+use tungstenite::{Message, WebSocket};
+
+use chip8::library;
+#[cfg(feature = "rpc")]
+use chip8::cpu::Chip8;
+use chip8::report::Failure;
+use chip8::Config;
+
+use crate::pacer::FramePacer;
+use chip8::speed::Speed;
+use crate::tools;
+
+const FRAME: Duration = Duration::from_micros(16_667);
+// the page that shows the display and sends keys back
+const VIEWER: &str = include_str!("serve.html");
+// how long a browser has to send its request before it's hung up on
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Someone watching, and the keys they're holding down.
+struct Viewer {
+    socket: WebSocket<TcpStream>,
+    keys: [bool; 16],
+}
+
+// the config only changes when a rom's loaded over --rpc
+#[cfg_attr(not(feature = "rpc"), allow(unused_mut))]
+pub fn run(mut config: Config, address: &str) -> Result<(), Failure> {
+    let mut emu = tools::power_on(&config)?;
+    // for the launcher and --recent
+    if let Err(e) = library::add_recent(Path::new(&config.rom_path)) {
+        eprintln!("⚠️ Unable to remember the rom: {}", e);
+    }
+
+    let listener = TcpListener::bind(address)
+        .map_err(|e| Failure::from(format!("Unable to listen on {}: {}", address, e)))?;
+    let (joined, joining) = mpsc::channel();
+    thread::spawn(move || accept(listener, joined));
+    #[cfg(feature = "rpc")]
+    let rpc = crate::rpc_server(&config)?;
+    #[cfg(feature = "metrics")]
+    let mut metrics = crate::metrics_server(&config)?;
+    println!(
+        "Serving {} on http://{}, Ctrl-C stops.",
+        config.rom_path, address
+    );
+
+    let speed = Speed::from_hz(config.speed);
+    let mut paused = config.start_paused;
+    let mut viewers: Vec<Viewer> = Vec::new();
+    let mut last = Vec::new();
+    let mut pacer = FramePacer::new(FRAME);
+    loop {
+        pacer.wait();
+
+        viewers.retain_mut(Viewer::read_keys);
+        let mut keys = [0u8; 16];
+        for viewer in &viewers {
+            for (key, &down) in keys.iter_mut().zip(&viewer.keys) {
+                if down {
+                    *key = 255;
+                }
+            }
+        }
+        // starting paused waits for someone to press a key
+        paused &= !keys.contains(&255);
+
+        #[cfg(feature = "rpc")]
+        if let Some(rom) = rpc.as_ref().and_then(|rpc| rpc.answer(&mut emu, &mut paused)) {
+            match switch(&mut config, rom) {
+                Ok(fresh) => emu.replace_with(fresh),
+                Err(e) => eprintln!("⚠️ {}", e),
+            }
+        }
+
+        let running = emu.fault().is_none();
+        if running && !paused {
+            emu.set_keys(&keys);
+            emu.run_frame(speed.cycles_in(emu.frames()));
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &mut metrics {
+            let drew = emu.draw_flag();
+            metrics.record(&emu, drew);
+        }
+        let fault = emu.fault().map(|fault| Message::text(fault.to_string()));
+
+        let message = frame_message(&emu.pixels(), emu.sound_active() && !paused);
+        if message != last {
+            let frame = Message::binary(message.clone());
+            viewers.retain_mut(|viewer| viewer.send(frame.clone()));
+            last = message;
+        }
+        if let (true, Some(fault)) = (running, &fault) {
+            viewers.retain_mut(|viewer| viewer.send(fault.clone()));
+        }
+
+        // those who just connected are sent what the others have been
+        for socket in joining.try_iter() {
+            let mut viewer = Viewer {
+                socket,
+                keys: [false; 16],
+            };
+            if viewer.send(Message::binary(last.clone()))
+                && fault.clone().is_none_or(|fault| viewer.send(fault))
+            {
+                viewers.push(viewer);
+            }
+        }
+    }
+}
+
+// A fresh machine running another rom, for one loaded over --rpc.
+#[cfg(feature = "rpc")]
+fn switch(config: &mut Config, rom_path: String) -> Result<Chip8, String> {
+    config.switch_rom(rom_path)?;
+    let mut fresh = Chip8::with_quirks(config.quirks);
+    fresh
+        .load_game(&config.rom_path)
+        .map_err(|e| format!("Unable to load {}: {}", config.rom_path, e))?;
+    if let Err(e) = library::add_recent(Path::new(&config.rom_path)) {
+        eprintln!("⚠️ Unable to remember the rom: {}", e);
+    }
+    Ok(fresh)
+}
+
+impl Viewer {
+    // Reads the keys the viewer pressed and let go of since last time,
+    // returning whether they're still connected.
+    fn read_keys(&mut self) -> bool {
+        loop {
+            match self.socket.read() {
+                Ok(Message::Text(text)) => {
+                    if let Some((key, down)) = key_message(&text) {
+                        self.keys[key] = down;
+                    }
+                }
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => return true,
+                Err(_) => return false,
+            }
+        }
+    }
+
+    // Sends a message, returning whether the viewer's still connected. One
+    // too slow to keep up misses frames rather than holding up the rest.
+    fn send(&mut self, message: Message) -> bool {
+        match self.socket.send(message) {
+            Ok(()) | Err(tungstenite::Error::WriteBufferFull(_)) => true,
+            Err(tungstenite::Error::Io(e)) => e.kind() == ErrorKind::WouldBlock,
+            Err(_) => false,
+        }
+    }
+}
+
+// Answers connections, handing WebSockets over to the emulator as they
+// open. Each is answered on a thread of its own, so a slow browser doesn't
+// hold up the others.
+fn accept(listener: TcpListener, joined: Sender<WebSocket<TcpStream>>) {
+    for stream in listener.incoming().flatten() {
+        let joined = joined.clone();
+        thread::spawn(move || match answer(stream) {
+            Ok(Some(socket)) => {
+                let _ = joined.send(socket);
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("⚠️ Problem answering a viewer: {}", e),
+        });
+    }
+}
+
+// Sends the viewer page, or for /ws opens the WebSocket it connects back on.
+fn answer(mut stream: TcpStream) -> io::Result<Option<WebSocket<TcpStream>>> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request = [0; 1024];
+    let read = stream.peek(&mut request)?;
+    let request = String::from_utf8_lossy(&request[..read]).into_owned();
+
+    if request.starts_with("GET /ws ") {
+        let socket = tungstenite::accept(stream).map_err(|e| io::Error::other(e.to_string()))?;
+        socket.get_ref().set_nonblocking(true)?;
+        return Ok(Some(socket));
+    }
+
+    // what's been peeked at still needs reading before answering
+    let _ = stream.read(&mut [0; 1024])?;
+    let (status, body) = if request.starts_with("GET / ") {
+        ("200 OK", VIEWER)
+    } else {
+        ("404 Not Found", "Not found")
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    Ok(None)
+}
+
+// What viewers are sent: whether the buzzer's sounding, then the display 8
+// pixels to a byte, the leftmost in the highest bit.
+fn frame_message(gfx: &[[u8; 64]; 32], sounding: bool) -> Vec<u8> {
+    let mut message = Vec::with_capacity(1 + 64 * 32 / 8);
+    message.push(u8::from(sounding));
+    for pixels in gfx.as_flattened().chunks(8) {
+        message.push(
+            pixels
+                .iter()
+                .fold(0, |byte, &pixel| byte << 1 | u8::from(pixel != 0)),
+        );
+    }
+    message
+}
+
+// The keypad key a viewer pressed or let go of, from "down 5" or "up 5".
+fn key_message(text: &str) -> Option<(usize, bool)> {
+    let (action, key) = text.split_once(' ')?;
+    let key = usize::from_str_radix(key, 16)
+        .ok()
+        .filter(|&key| key < 16)?;
+    match action {
+        "down" => Some((key, true)),
+        "up" => Some((key, false)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_packed_eight_pixels_a_byte() {
+        let mut gfx = [[0u8; 64]; 32];
+        gfx[0][0] = 1;
+        gfx[0][9] = 1;
+        gfx[31][63] = 1;
+        let message = frame_message(&gfx, true);
+
+        assert_eq!(message.len(), 257);
+        assert_eq!(message[..3], [1, 0b1000_0000, 0b0100_0000]);
+        assert_eq!(message[256], 1);
+    }
+
+    #[test]
+    fn keys_are_pressed_by_name() {
+        assert_eq!(key_message("down 5"), Some((5, true)));
+        assert_eq!(key_message("up F"), Some((15, false)));
+        assert_eq!(key_message("up 10"), None);
+        assert_eq!(key_message("hold 1"), None);
+    }
+}
@@ -0,0 +1,307 @@
+// Sample generation for the chip8 buzzer, independent of any audio device.
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "cpal")]
+pub mod cpal_backend;
+pub mod wav;
+
+/// Something that can sound the chip8 buzzer.
+///
+/// Frontends drive a backend from the machine state each cycle; it's up to
+/// the backend to only touch the audio device when something changes.
+pub trait AudioBackend {
+    /// Starts or stops the buzzer.
+    fn set_playing(&mut self, playing: bool);
+
+    /// Switches between the plain tone and an XO-CHIP audio pattern.
+    fn set_pattern(&mut self, pattern: Option<Pattern>);
+}
+
+/// The shape of the beep.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sine,
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sine" => Ok(Waveform::Sine),
+            _ => Err(format!(
+                "Unknown waveform '{}', expected square, triangle or sine.",
+                s
+            )),
+        }
+    }
+}
+
+/// What the buzzer sounds like.
+///
+/// frequency: pitch of the beep in hz.
+/// waveform: the shape of the wave.
+/// volume: loudness between 0 and 1.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Tone {
+    pub frequency: f32,
+    pub waveform: Waveform,
+    pub volume: f32,
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Tone {
+            frequency: 440.0,
+            waveform: Waveform::Square,
+            volume: 0.25,
+        }
+    }
+}
+
+/// An XO-CHIP audio pattern: 128 1-bit samples, msb first, looped at
+/// `rate` bits per second.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pattern {
+    pub bits: [u8; 16],
+    pub rate: f32,
+}
+
+/// Produces the samples of a `Tone` at a fixed sample rate, or of an
+/// XO-CHIP `Pattern` when one is set.
+pub struct ToneGenerator {
+    tone: Tone,
+    sample_rate: u32,
+    // position within the current period, from 0 to 1
+    phase: f32,
+    phase_inc: f32,
+    pattern: Option<Pattern>,
+    // position within the pattern in bits
+    position: f32,
+}
+
+impl ToneGenerator {
+    pub fn new(tone: Tone, sample_rate: u32) -> ToneGenerator {
+        ToneGenerator {
+            tone,
+            sample_rate,
+            phase: 0.0,
+            phase_inc: tone.frequency / sample_rate as f32,
+            pattern: None,
+            position: 0.0,
+        }
+    }
+
+    /// Plays `pattern` instead of the tone, or goes back to the tone for
+    /// `None`.
+    pub fn set_pattern(&mut self, pattern: Option<Pattern>) {
+        self.pattern = pattern;
+    }
+
+    /// Returns the next sample, between -volume and volume.
+    pub fn next_sample(&mut self) -> f32 {
+        if let Some(pattern) = self.pattern {
+            let bit = self.position as usize;
+            let on = (pattern.bits[bit / 8] >> (7 - bit % 8)) & 1 == 1;
+            self.position = (self.position + pattern.rate / self.sample_rate as f32) % 128.0;
+
+            return if on {
+                self.tone.volume
+            } else {
+                -self.tone.volume
+            };
+        }
+
+        let wave = match self.tone.waveform {
+            Waveform::Square => {
+                if self.phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            Waveform::Sine => (2.0 * PI * self.phase).sin(),
+        };
+        self.phase = (self.phase + self.phase_inc) % 1.0;
+
+        wave * self.tone.volume
+    }
+
+    /// Fills `out` with consecutive samples.
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+}
+
+// How long the buzzer takes to fade in and out, in seconds. Gating the wave
+// instantly makes an audible click.
+const ATTACK_TIME: f32 = 0.002;
+const RELEASE_TIME: f32 = 0.008;
+
+/// A linear attack/release envelope that smooths the buzzer starting and
+/// stopping.
+pub struct Envelope {
+    gate: bool,
+    level: f32,
+    attack_step: f32,
+    release_step: f32,
+}
+
+impl Envelope {
+    pub fn new(sample_rate: u32) -> Envelope {
+        Envelope {
+            gate: false,
+            level: 0.0,
+            attack_step: 1.0 / (ATTACK_TIME * sample_rate as f32),
+            release_step: 1.0 / (RELEASE_TIME * sample_rate as f32),
+        }
+    }
+
+    /// Starts the attack when opened, the release when closed.
+    pub fn set_gate(&mut self, gate: bool) {
+        self.gate = gate;
+    }
+
+    /// Returns the level for the next sample, from 0 to 1.
+    pub fn next_level(&mut self) -> f32 {
+        self.level = if self.gate {
+            (self.level + self.attack_step).min(1.0)
+        } else {
+            (self.level - self.release_step).max(0.0)
+        };
+        self.level
+    }
+}
+
+/// The buzzer as every backend plays it: a tone or pattern shaped by an
+/// envelope.
+pub struct Buzzer {
+    generator: ToneGenerator,
+    envelope: Envelope,
+}
+
+impl Buzzer {
+    pub fn new(tone: Tone, sample_rate: u32) -> Buzzer {
+        Buzzer {
+            generator: ToneGenerator::new(tone, sample_rate),
+            envelope: Envelope::new(sample_rate),
+        }
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.envelope.set_gate(playing);
+    }
+
+    pub fn set_pattern(&mut self, pattern: Option<Pattern>) {
+        self.generator.set_pattern(pattern);
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let level = self.envelope.next_level();
+        if level == 0.0 {
+            return 0.0;
+        }
+
+        self.generator.next_sample() * level
+    }
+
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn one_period(waveform: Waveform) -> Vec<f32> {
+        let tone = Tone {
+            frequency: 1.0,
+            waveform,
+            volume: 0.5,
+        };
+        let mut generator = ToneGenerator::new(tone, 8);
+        let mut out = vec![0.0; 8];
+        generator.fill(&mut out);
+        out
+    }
+
+    #[test]
+    fn square_wave_swings_between_volume() {
+        assert_eq!(
+            one_period(Waveform::Square),
+            [0.5, 0.5, 0.5, 0.5, -0.5, -0.5, -0.5, -0.5]
+        );
+    }
+
+    #[test]
+    fn triangle_wave_peaks_mid_period() {
+        assert_eq!(
+            one_period(Waveform::Triangle),
+            [-0.5, -0.25, 0.0, 0.25, 0.5, 0.25, 0.0, -0.25]
+        );
+    }
+
+    #[test]
+    fn pattern_plays_bits_at_rate() {
+        let mut generator = ToneGenerator::new(Tone::default(), 8000);
+        let mut bits = [0; 16];
+        bits[0] = 0b1010_0000;
+        generator.set_pattern(Some(Pattern { bits, rate: 4000.0 }));
+
+        let mut out = vec![0.0; 8];
+        generator.fill(&mut out);
+        // two samples per bit at half the sample rate
+        assert_eq!(out, [0.25, 0.25, -0.25, -0.25, 0.25, 0.25, -0.25, -0.25]);
+    }
+
+    #[test]
+    fn envelope_ramps_instead_of_gating() {
+        let mut envelope = Envelope::new(1000);
+        envelope.set_gate(true);
+        // 2ms of attack at 1khz
+        assert_eq!(envelope.next_level(), 0.5);
+        assert_eq!(envelope.next_level(), 1.0);
+        assert_eq!(envelope.next_level(), 1.0);
+
+        envelope.set_gate(false);
+        // and 8ms of release
+        assert_eq!(envelope.next_level(), 0.875);
+        for _ in 0..7 {
+            envelope.next_level();
+        }
+        assert_eq!(envelope.next_level(), 0.0);
+    }
+
+    #[test]
+    fn buzzer_is_silent_until_played() {
+        let mut buzzer = Buzzer::new(Tone::default(), 8000);
+        let mut out = vec![1.0; 8];
+        buzzer.fill(&mut out);
+        assert!(out.iter().all(|sample| *sample == 0.0));
+
+        buzzer.set_playing(true);
+        buzzer.fill(&mut out);
+        assert!(out[0].abs() < out[7].abs());
+    }
+
+    #[test]
+    fn waveform_from_str() {
+        assert_eq!("Sine".parse(), Ok(Waveform::Sine));
+        assert!("sawtooth".parse::<Waveform>().is_err());
+    }
+}
@@ -0,0 +1,72 @@
+// Plays the buzzer through cpal, for frontends that don't use SDL.
+use std::sync::{Arc, Mutex};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use super::{AudioBackend, Buzzer, Pattern, Tone};
+
+/// An `AudioBackend` on the host's default output device.
+pub struct CpalBackend {
+    // the stream stops playing when it's dropped
+    _stream: cpal::Stream,
+    // shared with cpal's audio thread
+    shared: Arc<Mutex<Buzzer>>,
+    playing: bool,
+    pattern: Option<Pattern>,
+}
+
+impl CpalBackend {
+    pub fn new(tone: Tone, buffer_size: u16) -> Result<CpalBackend, String> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| "No audio output device available.".to_string())?;
+        let config = device
+            .default_output_config()
+            .map_err(|e| e.to_string())?;
+        let channels = config.channels() as usize;
+        let sample_rate = config.sample_rate().0;
+        let mut config: cpal::StreamConfig = config.into();
+        config.buffer_size = cpal::BufferSize::Fixed(buffer_size as u32);
+
+        let shared = Arc::new(Mutex::new(Buzzer::new(tone, sample_rate)));
+
+        let callback_state = Arc::clone(&shared);
+        let stream = device
+            .build_output_stream(
+                &config,
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    let mut buzzer = callback_state.lock().unwrap();
+                    for frame in data.chunks_mut(channels) {
+                        frame.fill(buzzer.next_sample());
+                    }
+                },
+                |e| eprintln!("⚠️ Audio stream error: {}", e),
+                None,
+            )
+            .map_err(|e| e.to_string())?;
+        stream.play().map_err(|e| e.to_string())?;
+
+        Ok(CpalBackend {
+            _stream: stream,
+            shared,
+            playing: false,
+            pattern: None,
+        })
+    }
+}
+
+impl AudioBackend for CpalBackend {
+    fn set_playing(&mut self, playing: bool) {
+        if playing != self.playing {
+            self.shared.lock().unwrap().set_playing(playing);
+            self.playing = playing;
+        }
+    }
+
+    fn set_pattern(&mut self, pattern: Option<Pattern>) {
+        if pattern != self.pattern {
+            self.shared.lock().unwrap().set_pattern(pattern);
+            self.pattern = pattern;
+        }
+    }
+}
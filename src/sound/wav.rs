@@ -0,0 +1,108 @@
+// Records the buzzer to a WAV file.
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::{Buzzer, Pattern, Tone};
+
+/// Sample rate of recordings, which divides evenly into 60hz frames.
+pub const RECORDING_SAMPLE_RATE: u32 = 44_100;
+const SAMPLES_PER_FRAME: u32 = RECORDING_SAMPLE_RATE / 60;
+// size of the RIFF header written before the samples
+const HEADER_SIZE: u32 = 44;
+
+/// Renders the buzzer into a 16-bit mono WAV file a frame at a time.
+///
+/// Samples are generated from the sound state of each frame rather than
+/// captured from the audio device, so the recording stays in step with
+/// the emulation no matter how playback was going.
+pub struct WavRecorder<W: Write + Seek = BufWriter<File>> {
+    writer: W,
+    buzzer: Buzzer,
+    samples: u32,
+}
+
+impl WavRecorder {
+    pub fn create(path: &Path, tone: Tone) -> io::Result<WavRecorder> {
+        WavRecorder::new(BufWriter::new(File::create(path)?), tone)
+    }
+}
+
+impl<W: Write + Seek> WavRecorder<W> {
+    pub fn new(mut writer: W, tone: Tone) -> io::Result<WavRecorder<W>> {
+        // the sizes get filled in once we know them
+        write_header(&mut writer, 0)?;
+
+        Ok(WavRecorder {
+            writer,
+            buzzer: Buzzer::new(tone, RECORDING_SAMPLE_RATE),
+            samples: 0,
+        })
+    }
+
+    /// Appends one 60hz frame of audio, silent unless `playing`.
+    pub fn record_frame(&mut self, playing: bool, pattern: Option<Pattern>) -> io::Result<()> {
+        self.buzzer.set_pattern(pattern);
+        self.buzzer.set_playing(playing);
+
+        for _ in 0..SAMPLES_PER_FRAME {
+            let sample = (self.buzzer.next_sample() * i16::MAX as f32) as i16;
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.samples += SAMPLES_PER_FRAME;
+
+        Ok(())
+    }
+
+    /// Fills in the header now the length is known and flushes the file.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.writer, self.samples * 2)?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+// Writes a RIFF header for `data_size` bytes of 16-bit mono samples.
+fn write_header(writer: &mut impl Write, data_size: u32) -> io::Result<()> {
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?; // size of this chunk
+    writer.write_all(&1u16.to_le_bytes())?; // PCM
+    writer.write_all(&1u16.to_le_bytes())?; // mono
+    writer.write_all(&RECORDING_SAMPLE_RATE.to_le_bytes())?;
+    writer.write_all(&(RECORDING_SAMPLE_RATE * 2).to_le_bytes())?; // bytes per second
+    writer.write_all(&2u16.to_le_bytes())?; // bytes per sample
+    writer.write_all(&16u16.to_le_bytes())?; // bits per sample
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn header_covers_recorded_frames() {
+        let mut recorder = WavRecorder::new(Cursor::new(Vec::new()), Tone::default()).unwrap();
+        recorder.record_frame(false, None).unwrap();
+        recorder.record_frame(true, None).unwrap();
+        let wav = recorder.finish().unwrap().into_inner();
+
+        let data_size = SAMPLES_PER_FRAME * 2 * 2;
+        assert_eq!(wav.len() as u32, HEADER_SIZE + data_size);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(wav[40..44], data_size.to_le_bytes());
+        // the first frame is silent, the second isn't
+        assert!(wav[44..44 + 1470].iter().all(|b| *b == 0));
+        assert!(wav[44 + 1470..].iter().any(|b| *b != 0));
+    }
+}
@@ -0,0 +1,101 @@
+// How fast the chip8 runs, adjustable while playing since every rom wants
+// something different.
+//
+// The speed is in instructions a second and the machine runs 60 frames a
+// second, so a speed that isn't a multiple of 60 is spread over the frames:
+// each runs the whole instructions it has coming, and what's left over
+// carries to the frames after it. That's worked out from the machine's frame
+// count, so frames run again from a save state, a movie or the other end of
+// netplay run the same instructions.
+
+// The speeds - and = step through, in instructions per frame. 60 frames a
+// second makes the default 600 instructions a second.
+const STEPS: [u32; 14] = [1, 2, 3, 5, 7, 10, 12, 15, 20, 30, 50, 100, 200, 500];
+const DEFAULT_HZ: u32 = 600;
+
+/// The most frames a frontend runs at once to catch up after falling
+/// behind, after which the rest are dropped.
+pub const MAX_CATCH_UP: u32 = 10;
+
+/// How many instructions the machine runs a second.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Speed {
+    hz: u32,
+}
+
+impl Default for Speed {
+    fn default() -> Self {
+        Speed { hz: DEFAULT_HZ }
+    }
+}
+
+impl Speed {
+    /// Exactly `hz` instructions a second, as configured or as a movie was
+    /// recorded at, but no fewer than one a frame. Only - and = move it onto
+    /// the steps.
+    pub fn from_hz(hz: u32) -> Speed {
+        Speed { hz: hz.max(60) }
+    }
+
+    /// Instructions a second, for showing the speed and recording it.
+    pub fn hz(&self) -> u32 {
+        self.hz
+    }
+
+    /// The instructions to run in the machine's frame `frame`, counting from
+    /// 0: a 60th of the speed, and one more in the frames where what's left
+    /// over from those before adds up to another.
+    pub fn cycles_in(&self, frame: u64) -> u32 {
+        // it comes out the same every 60 frames
+        let frame = (frame % 60) as u32;
+        let hz = u64::from(self.hz);
+        ((u64::from(frame) + 1) * hz / 60 - u64::from(frame) * hz / 60) as u32
+    }
+
+    /// The next step up, staying put above the last of them.
+    pub fn faster(&mut self) {
+        if let Some(step) = STEPS.iter().map(|step| step * 60).find(|&step| step > self.hz) {
+            self.hz = step;
+        }
+    }
+
+    /// The next step down, staying put below the first of them.
+    pub fn slower(&mut self) {
+        if let Some(step) = STEPS.iter().rev().map(|step| step * 60).find(|&step| step < self.hz) {
+            self.hz = step;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configured_speeds_are_kept_until_stepped() {
+        let mut speed = Speed::from_hz(1000);
+        assert_eq!(speed.hz(), 1000);
+        speed.faster();
+        assert_eq!(speed.hz(), 1200);
+        speed.slower();
+        speed.slower();
+        assert_eq!(speed.hz(), 720);
+
+        let mut fast = Speed::from_hz(40000);
+        fast.faster();
+        assert_eq!(fast.hz(), 40000);
+        fast.slower();
+        assert_eq!(fast.hz(), 30000);
+    }
+
+    #[test]
+    fn every_second_runs_the_whole_speed() {
+        let speed = Speed::from_hz(1000);
+        let cycles: Vec<u32> = (0..60).map(|frame| speed.cycles_in(frame)).collect();
+        assert_eq!(cycles.iter().sum::<u32>(), 1000);
+        assert!(cycles.iter().all(|&cycles| cycles == 16 || cycles == 17));
+        // any second, not just the first
+        assert_eq!((45..105).map(|frame| speed.cycles_in(frame)).sum::<u32>(), 1000);
+        assert_eq!(speed.cycles_in(u64::MAX), speed.cycles_in(u64::MAX % 60));
+    }
+}
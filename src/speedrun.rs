@@ -0,0 +1,113 @@
+// A speedrun timer. It counts emulated frames rather than wall-clock time,
+// so a run takes the same time however fast the computer is, and splits
+// when the rom writes a given value to a given address, like a level
+// counter going up or a win flag being set.
+use serde::{Deserialize, Serialize};
+
+/// A point in a run, reached when the byte at `address` becomes `value`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Split {
+    pub name: String,
+    pub address: u16,
+    pub value: u8,
+}
+
+/// Times a run through a list of splits, stopping at the last one.
+#[derive(Debug, Default)]
+pub struct SpeedrunTimer {
+    splits: Vec<Split>,
+    // frames since the run started
+    frames: u64,
+    // when each split reached so far was reached
+    times: Vec<u64>,
+    running: bool,
+}
+
+impl SpeedrunTimer {
+    pub fn new(splits: Vec<Split>) -> SpeedrunTimer {
+        SpeedrunTimer {
+            splits,
+            ..SpeedrunTimer::default()
+        }
+    }
+
+    /// Starts the run again from nothing, as when the rom is reset.
+    pub fn restart(&mut self) {
+        self.frames = 0;
+        self.times.clear();
+        self.running = !self.splits.is_empty();
+    }
+
+    /// Counts a frame and checks the next split against memory with `read`,
+    /// returning the split if it was just reached.
+    pub fn frame(&mut self, read: impl Fn(u16) -> u8) -> Option<&Split> {
+        if !self.running {
+            return None;
+        }
+        self.frames += 1;
+
+        let split = &self.splits[self.times.len()];
+        if read(split.address) != split.value {
+            return None;
+        }
+        self.times.push(self.frames);
+        self.running = self.times.len() < self.splits.len();
+        Some(split)
+    }
+
+    /// Frames since the run started, stopping with the last split.
+    pub fn elapsed(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    /// The splits reached so far and when.
+    pub fn reached(&self) -> impl Iterator<Item = (&Split, u64)> {
+        self.splits.iter().zip(self.times.iter().copied())
+    }
+}
+
+/// Formats a number of frames as minutes, seconds and hundredths, e.g.
+/// 1:02.50.
+pub fn format_time(frames: u64) -> String {
+    let hundredths = frames * 100 / 60;
+    format!(
+        "{}:{:02}.{:02}",
+        hundredths / 6000,
+        hundredths / 100 % 60,
+        hundredths % 100
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_in_order_and_stops_at_the_last() {
+        let split = |name: &str, value| Split {
+            name: name.to_string(),
+            address: 0x300,
+            value,
+        };
+        let mut timer = SpeedrunTimer::new(vec![split("one", 1), split("two", 2)]);
+        timer.restart();
+
+        assert!(timer.frame(|_| 2).is_none());
+        assert_eq!(timer.frame(|_| 1).map(|split| split.name.as_str()), Some("one"));
+        assert_eq!(timer.frame(|_| 2).map(|split| split.name.as_str()), Some("two"));
+        assert!(!timer.running());
+        timer.frame(|_| 2);
+        assert_eq!(timer.elapsed(), 3);
+        assert_eq!(timer.reached().map(|(_, time)| time).collect::<Vec<_>>(), [2, 3]);
+    }
+
+    #[test]
+    fn formats_minutes_seconds_and_hundredths() {
+        assert_eq!(format_time(0), "0:00.00");
+        assert_eq!(format_time(3750), "1:02.50");
+    }
+}
@@ -0,0 +1,243 @@
+// Plays a rom in the terminal instead of a window, for playing over SSH or
+// on machines without SDL. The display is drawn with half blocks or, with
+// --braille, braille characters, and the keypad is the same block of keys
+// as in the window:
+//
+//   1 2 3 4        1 2 3 C
+//   Q W E R   ->   4 5 6 D
+//   A S D F        7 8 9 E
+//   Z X C V        A 0 B F
+//
+// Most terminals only report key presses, repeating them while a key is
+// held, so keys are let go of once the repeats stop. Terminals that report
+// releases too, like kitty, are asked to.
+use std::io::{self, Stdout, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use chip8::cpu::{Chip8, State};
+use chip8::dump::{framebuffer_to_ansi, framebuffer_to_braille};
+use chip8::library;
+use chip8::report::Failure;
+use chip8::{keypad_key, Config, TerminalStyle, PALETTES};
+
+use chip8::speed::Speed;
+use crate::tools;
+
+const FRAME: Duration = Duration::from_micros(16_667);
+// frames a key stays down after a press, long enough to last until the
+// terminal starts repeating it
+const FIRST_HOLD: u32 = 30;
+// frames a key stays down after each repeat
+const REPEAT_HOLD: u32 = 6;
+
+// The terminal in raw mode on the alternate screen, put back as it was when
+// dropped, even if the emulator panics.
+struct Screen {
+    // whether the terminal reports keys being let go of
+    releases: bool,
+}
+
+impl Screen {
+    fn enter() -> io::Result<Screen> {
+        terminal::enable_raw_mode()?;
+        execute!(
+            io::stdout(),
+            EnterAlternateScreen,
+            Hide,
+            Clear(ClearType::All)
+        )?;
+        let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if releases {
+            let flags = KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                | KeyboardEnhancementFlags::REPORT_EVENT_TYPES;
+            execute!(io::stdout(), PushKeyboardEnhancementFlags(flags))?;
+        }
+        Ok(Screen { releases })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        if self.releases {
+            let _ = execute!(io::stdout(), PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+pub fn play(config: &Config) -> Result<(), Failure> {
+    let mut emu = tools::power_on(config)?;
+    // for the launcher and --recent
+    if let Err(e) = library::add_recent(Path::new(&config.rom_path)) {
+        eprintln!("⚠️ Unable to remember the rom: {}", e);
+    }
+
+    let screen = Screen::enter().map_err(terminal_error)?;
+    let result = run(&mut emu, config, screen.releases);
+    drop(screen);
+    result.map_err(terminal_error)?;
+
+    match emu.fault() {
+        Some(fault) => Err(Failure::Fault(fault)),
+        None => Ok(()),
+    }
+}
+
+// Runs the rom 60 frames a second until Esc or Ctrl-C, reading keys in
+// between frames.
+fn run(emu: &mut Chip8, config: &Config, releases: bool) -> io::Result<()> {
+    let mut out = io::stdout();
+    let speed = Speed::from_hz(config.speed);
+    let title = match &config.metadata {
+        Some(metadata) => metadata.title.clone(),
+        None => Path::new(&config.rom_path)
+            .file_name()
+            .map_or(config.rom_path.clone(), |name| {
+                name.to_string_lossy().into_owned()
+            }),
+    };
+
+    let mut keys = [0u8; 16];
+    // frames left before each key is let go of, when the terminal won't say
+    let mut held = [0u32; 16];
+    let mut paused = config.start_paused;
+    let mut muted = config.audio.muted;
+    let mut beeping = false;
+    let mut redraw = true;
+    let mut next_frame = Instant::now();
+
+    'running: loop {
+        while event::poll(next_frame.saturating_duration_since(Instant::now()))? {
+            match event::read()? {
+                Event::Key(key) => {
+                    let pressed = key.kind == KeyEventKind::Press;
+                    match key.code {
+                        KeyCode::Esc => break 'running,
+                        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            break 'running
+                        }
+                        KeyCode::Char(' ') if pressed => {
+                            paused = !paused;
+                            redraw = true;
+                        }
+                        KeyCode::Char('m') if pressed => {
+                            muted = !muted;
+                            redraw = true;
+                        }
+                        KeyCode::Char(c) => {
+                            if let Some(index) = keypad_key(c).map(usize::from) {
+                                let down = key.kind != KeyEventKind::Release;
+                                press(&mut keys, &mut held, index, down, releases);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Resize(..) => {
+                    queue!(out, Clear(ClearType::All))?;
+                    redraw = true;
+                }
+                _ => {}
+            }
+        }
+        // a frame late is run straight away rather than making up for it
+        next_frame = (next_frame + FRAME).max(Instant::now());
+
+        for (key, frames) in keys.iter_mut().zip(held.iter_mut()) {
+            if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    *key = 0;
+                }
+            }
+        }
+
+        if !paused && emu.fault().is_none() {
+            // the status line changes when the rom stops or ends
+            let state = emu.state();
+            emu.set_keys(&keys);
+            emu.run_frame(speed.cycles_in(emu.frames()));
+            redraw |= emu.draw_flag() || emu.state() != state;
+        }
+
+        // the terminal bell is as close to a buzzer as there is
+        let sounding = emu.sound_active() && !paused && !muted;
+        if sounding && !beeping {
+            queue!(out, Print('\x07'))?;
+        }
+        beeping = sounding;
+
+        if redraw {
+            let status = match emu.state() {
+                State::Stopped(fault) => format!("{} stopped: {}. Esc quits.", title, fault),
+                _ if paused => format!("{} paused. Space resumes, Esc quits.", title),
+                State::Finished => format!("{} ended. Esc quits.", title),
+                _ => format!(
+                    "{}{}. Keypad 1-4 Q-R A-F Z-V, Space pauses, M mutes, Esc quits.",
+                    title,
+                    if muted { " (muted)" } else { "" }
+                ),
+            };
+            draw(&mut out, emu, config, &status)?;
+            redraw = false;
+        }
+    }
+
+    Ok(())
+}
+
+// Draws the display with the status line under it.
+fn draw(out: &mut Stdout, emu: &Chip8, config: &Config, status: &str) -> io::Result<()> {
+    let palette = &PALETTES[config.display.palette.index()];
+    let display = match config.terminal {
+        Some(TerminalStyle::Braille) => framebuffer_to_braille(&emu.pixels(), palette),
+        _ => framebuffer_to_ansi(&emu.pixels(), palette),
+    };
+
+    let mut row = 0;
+    for line in display.lines() {
+        queue!(out, MoveTo(0, row), Print(line))?;
+        row += 1;
+    }
+    queue!(
+        out,
+        MoveTo(0, row + 1),
+        Clear(ClearType::UntilNewLine),
+        Print(status)
+    )?;
+    out.flush()
+}
+
+// Presses or lets go of a keypad key. When the terminal doesn't report keys
+// being let go of, presses hold the key down for a while instead.
+fn press(keys: &mut [u8; 16], held: &mut [u32; 16], key: usize, down: bool, releases: bool) {
+    if !down {
+        keys[key] = 0;
+        held[key] = 0;
+        return;
+    }
+    if !releases {
+        held[key] = if keys[key] != 0 {
+            REPEAT_HOLD
+        } else {
+            FIRST_HOLD
+        };
+    }
+    keys[key] = 255;
+}
+
+fn terminal_error(error: io::Error) -> Failure {
+    Failure::from(format!("Problem with the terminal: {}", error))
+}
+
@@ -0,0 +1,369 @@
+// Test roms the emulator can read the results of. They finish by drawing OK,
+// or the number of the check that failed, in glyphs 4 pixels wide like the
+// font's, and `chip8 test` reads that back off the display to print which
+// checks passed instead of leaving someone to look at the screen.
+//
+// Glyphs are found by splitting the lit part of the display into lines 5
+// pixels tall and those into glyphs at the blank columns between them, so
+// it doesn't matter where on the screen the rom draws its result.
+use serde_json::{json, Value};
+
+use crate::cpu::CHIP8_FONTSET;
+use crate::roms::rom_hash;
+
+/// A test rom and how to read its result.
+///
+/// name: what it's called in the results.
+/// sha1: the rom's hash, to recognise it by.
+/// glyphs: letters it draws besides the font's digits, as 5 rows of 4
+/// pixels in the top of each byte, like the font.
+/// passed: what it draws when every check passes.
+/// checks: what its numbered checks are for, from 1, if it's known.
+pub struct TestRom {
+    pub name: &'static str,
+    pub sha1: &'static str,
+    pub glyphs: &'static [(char, [u8; 5])],
+    pub passed: &'static str,
+    pub checks: &'static [&'static str],
+}
+
+const LETTER_K: [u8; 5] = [0x90, 0xA0, 0xC0, 0xA0, 0x90];
+
+pub const TEST_ROMS: [TestRom; 2] = [
+    TestRom {
+        name: "selftest",
+        sha1: "56d7fa2bcdce389a008e844a2501979940cc6790",
+        glyphs: &[('K', LETTER_K)],
+        // its O is the font's 0
+        passed: "0K",
+        checks: &[
+            "6XNN, 3XNN",
+            "7XNN wraps around and leaves VF alone",
+            "8XY4 carries",
+            "8XY5 borrows",
+            "8XY7",
+            "8XY1, 8XY2, 8XY3",
+            "8XY6, 8XYE, shifting a register onto itself",
+            "5XY0, 9XY0",
+            "2NNN, 00EE",
+            "FX33",
+            "FX55, FX65",
+            "FX15, FX07",
+            "DXYN and collisions",
+        ],
+    },
+    TestRom {
+        name: "c8_test",
+        sha1: "8e592d3620481e00ea36d29765b95287c7349a70",
+        glyphs: &[('O', [0x60, 0x90, 0x90, 0x90, 0x60]), ('K', LETTER_K)],
+        passed: "OK",
+        checks: &[],
+    },
+];
+
+/// The test rom `rom` is, if it's one of TEST_ROMS.
+pub fn find(rom: &[u8]) -> Option<&'static TestRom> {
+    let hash = rom_hash(rom);
+    TEST_ROMS.iter().find(|test| test.sha1 == hash)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    NotRun,
+}
+
+impl Outcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Passed => "passed",
+            Outcome::Failed => "failed",
+            Outcome::NotRun => "not run",
+        }
+    }
+}
+
+/// A numbered check, and what it's for if that's known.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Check {
+    pub number: u32,
+    pub name: Option<&'static str>,
+    pub outcome: Outcome,
+}
+
+/// What a test rom found, read off the screen it left.
+///
+/// failed: the number of the check that failed, if one did.
+/// checks: every check the rom's known to have, or just the failed one if
+/// it isn't.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestResults {
+    pub rom: &'static str,
+    pub failed: Option<u32>,
+    pub checks: Vec<Check>,
+}
+
+impl TestResults {
+    pub fn passed(&self) -> bool {
+        self.failed.is_none()
+    }
+
+    /// The check that failed, by number and name if it has one.
+    pub fn failure(&self) -> Option<String> {
+        let number = self.failed?;
+        let check = self.checks.iter().find(|check| check.number == number);
+        Some(match check.and_then(|check| check.name) {
+            Some(name) => format!("check {} ({})", number, name),
+            None => format!("check {}", number),
+        })
+    }
+
+    /// The results as a table, a line for each check.
+    pub fn table(&self) -> String {
+        let mut table = match self.failure() {
+            Some(failure) => format!("{}: failed {}\n", self.rom, failure),
+            None => format!("{}: passed\n", self.rom),
+        };
+        for check in &self.checks {
+            let outcome = match check.outcome {
+                Outcome::Passed => "✅",
+                Outcome::Failed => "❌",
+                Outcome::NotRun => "  ",
+            };
+            table.push_str(&format!("{} {:>3}", outcome, check.number));
+            if let Some(name) = check.name {
+                table.push_str(&format!("  {}", name));
+            }
+            table.push('\n');
+        }
+        table
+    }
+
+    pub fn to_json(&self) -> Value {
+        let checks: Vec<Value> = self
+            .checks
+            .iter()
+            .map(|check| {
+                json!({
+                    "number": check.number,
+                    "name": check.name,
+                    "result": check.outcome.as_str(),
+                })
+            })
+            .collect();
+        json!({
+            "status": if self.passed() { "passed" } else { "failed" },
+            "rom": self.rom,
+            "failed": self.failed,
+            "checks": checks,
+        })
+    }
+}
+
+impl TestRom {
+    /// Reads the result the rom left on the screen.
+    pub fn read(&self, gfx: &[[u8; 64]; 32]) -> Result<TestResults, String> {
+        let text = read_text(gfx, self.glyphs);
+        let failed = if text == self.passed {
+            None
+        } else {
+            // the number of the check that failed, with an E in front for
+            // some roms
+            let number = text.strip_prefix('E').unwrap_or(&text).parse::<u32>();
+            let number = number.map_err(|_| {
+                format!(
+                    "The screen shows {:?}, which isn't a result {} gives.",
+                    text, self.name
+                )
+            })?;
+            Some(number)
+        };
+
+        let checks = match failed {
+            Some(number) if self.checks.is_empty() => vec![Check {
+                number,
+                name: None,
+                outcome: Outcome::Failed,
+            }],
+            _ => (1..)
+                .zip(self.checks)
+                .map(|(number, name)| Check {
+                    number,
+                    name: Some(*name),
+                    outcome: match failed {
+                        Some(failed) if number == failed => Outcome::Failed,
+                        Some(failed) if number > failed => Outcome::NotRun,
+                        _ => Outcome::Passed,
+                    },
+                })
+                .collect(),
+        };
+        Ok(TestResults {
+            rom: self.name,
+            failed,
+            checks,
+        })
+    }
+}
+
+/// Reads the glyphs drawn on the display, the font's hex digits and
+/// `glyphs`, as text. Lines are separated by newlines and anything that
+/// isn't a glyph reads as `?`.
+pub fn read_text(gfx: &[[u8; 64]; 32], glyphs: &[(char, [u8; 5])]) -> String {
+    let font = CHIP8_FONTSET
+        .chunks(5)
+        .zip("0123456789ABCDEF".chars())
+        .map(|(rows, c)| (c, [rows[0], rows[1], rows[2], rows[3], rows[4]]));
+    let known: Vec<(char, [u8; 5])> = font
+        .chain(glyphs.iter().copied())
+        .map(|(c, rows)| (c, left_aligned(rows)))
+        .collect();
+
+    let lit_row = |y: usize| gfx[y].iter().any(|&pixel| pixel != 0);
+    let mut lines = Vec::new();
+    let mut y = 0;
+    while y < gfx.len() {
+        if !lit_row(y) {
+            y += 1;
+            continue;
+        }
+        let top = y;
+        while y < gfx.len() && lit_row(y) {
+            y += 1;
+        }
+        let rows = &gfx[top..y];
+        lines.push(read_line(rows, &known));
+    }
+    lines.join("\n")
+}
+
+// Reads the glyphs in a band of lit rows, split at the blank columns.
+fn read_line(rows: &[[u8; 64]], known: &[(char, [u8; 5])]) -> String {
+    let lit_column = |x: usize| rows.iter().any(|row| row[x] != 0);
+    let mut line = String::new();
+    let mut x = 0;
+    while x < 64 {
+        if !lit_column(x) {
+            x += 1;
+            continue;
+        }
+        let left = x;
+        while x < 64 && lit_column(x) {
+            x += 1;
+        }
+        let glyph = (rows.len() == 5 && x - left <= 8).then(|| {
+            let mut glyph = [0; 5];
+            for (bits, row) in glyph.iter_mut().zip(rows) {
+                for (i, &pixel) in row[left..x].iter().enumerate() {
+                    *bits |= (pixel != 0) as u8 * (0x80 >> i);
+                }
+            }
+            glyph
+        });
+        let c = known
+            .iter()
+            .find(|(_, rows)| Some(*rows) == glyph)
+            .map_or('?', |(c, _)| *c);
+        line.push(c);
+    }
+    line
+}
+
+// Shifts a glyph as far left as it goes, as it's cut out of the screen.
+fn left_aligned(rows: [u8; 5]) -> [u8; 5] {
+    let shift = rows.iter().fold(0, |all, row| all | row).leading_zeros();
+    rows.map(|row| row.checked_shl(shift).unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundled;
+    use crate::cpu::Chip8;
+    use crate::quirks::Quirks;
+
+    fn run(rom: &[u8], quirks: Quirks) -> Chip8 {
+        let mut emu = Chip8::with_quirks(quirks);
+        emu.load_rom(rom).unwrap();
+        for _ in 0..60 {
+            emu.run_frame(10);
+        }
+        emu
+    }
+
+    #[test]
+    fn test_roms_are_recognised() {
+        let selftest = bundled::find("bundled:selftest").unwrap();
+        assert_eq!(find(selftest.rom).unwrap().name, "selftest");
+        let c8_test = std::fs::read("programs/c8_test.c8").unwrap();
+        assert_eq!(find(&c8_test).unwrap().name, "c8_test");
+        assert!(find(bundled::find("bundled:bounce").unwrap().rom).is_none());
+    }
+
+    #[test]
+    fn passes_are_read_off_the_screen() {
+        let selftest = bundled::find("bundled:selftest").unwrap().rom;
+        let results = find(selftest)
+            .unwrap()
+            .read(&run(selftest, Quirks::default()).pixels())
+            .unwrap();
+        assert!(results.passed());
+        assert_eq!(results.checks.len(), 13);
+        assert!(results
+            .checks
+            .iter()
+            .all(|check| check.outcome == Outcome::Passed));
+        assert_eq!(results.to_json()["status"], "passed");
+    }
+
+    #[test]
+    fn failures_are_read_off_the_screen() {
+        let c8_test = std::fs::read("programs/c8_test.c8").unwrap();
+        let original = Quirks::from_bits(0b10011);
+        let results = find(&c8_test)
+            .unwrap()
+            .read(&run(&c8_test, original).pixels())
+            .unwrap();
+        assert_eq!(results.failed, Some(14));
+        assert_eq!(results.failure().as_deref(), Some("check 14"));
+        assert_eq!(results.table(), "c8_test: failed check 14\n❌  14\n");
+    }
+
+    #[test]
+    fn checks_after_a_failure_were_not_run() {
+        // E07, the way selftest draws it
+        let mut emu = Chip8::default();
+        let mut draw = |digit: u16, x: u8| {
+            emu.set_index(digit * 5);
+            emu.set_register(0, x);
+            emu.set_register(1, 4);
+            emu.poke(0x200, 0xD0);
+            emu.poke(0x201, 0x15);
+            emu.set_pc(0x200);
+            emu.emulate_cycle();
+        };
+        draw(0xE, 4);
+        draw(0, 11);
+        draw(7, 16);
+
+        let results = TEST_ROMS[0].read(&emu.pixels()).unwrap();
+        let outcomes: Vec<Outcome> = results.checks.iter().map(|check| check.outcome).collect();
+        assert_eq!(
+            outcomes[5..8],
+            [Outcome::Passed, Outcome::Failed, Outcome::NotRun]
+        );
+        assert_eq!(
+            results.failure().as_deref(),
+            Some("check 7 (8XY6, 8XYE, shifting a register onto itself)")
+        );
+    }
+
+    #[test]
+    fn unreadable_screens_are_errors() {
+        let mut gfx = [[0; 64]; 32];
+        gfx[3][3] = 1;
+        assert_eq!(read_text(&gfx, &[]), "?");
+        assert!(TEST_ROMS[0].read(&gfx).is_err());
+    }
+}
@@ -0,0 +1,523 @@
+// The subcommands that don't open a window: disassembling, assembling,
+// describing roms and running them headless, including with --headless.
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chip8::archive::read_rom;
+use chip8::asm::assemble;
+use chip8::chaos::Chaos;
+use chip8::cpu::{Chip8, State};
+use chip8::disasm::disassemble_rom;
+use chip8::dump::{framebuffer_to_ansi, framebuffer_to_pbm};
+use chip8::input_script::InputScript;
+use chip8::config::ConfigFile;
+use chip8::corpus::{self, Change, Manifest};
+use chip8::library;
+use chip8::lockstep::{self, Outcome, Profile};
+use chip8::metadata::ProgramDatabase;
+use chip8::roms::{rom_hash, RomDatabase};
+use chip8::serial::{SerialLink, SerialPort};
+use chip8::testrom;
+use chip8::trace::{self, Comparison};
+use chip8::report::{finished_json, Failure, OutputFormat};
+use chip8::{Config, Headless, PALETTES};
+use serde_json::{json, Value};
+
+use crate::savestate;
+use chip8::speed::Speed;
+
+// how long --benchmark runs for, always the same so runs can be compared
+const BENCHMARK_TIME: Duration = Duration::from_secs(10);
+// one instruction in this many is timed for the breakdown by kind, a prime
+// so it doesn't keep landing on the same instruction of a loop
+const SAMPLE_EVERY: u64 = 31;
+// what --benchmark breaks the time down into
+const KINDS: [&str; 4] = [
+    "drawing",
+    "arithmetic and logic",
+    "jumps, calls and skips",
+    "timers, keys and memory",
+];
+
+// A machine with the rom loaded, ready to run.
+pub fn power_on(config: &Config) -> Result<Chip8, Failure> {
+    let mut emu = Chip8::with_quirks(config.quirks);
+    if config.check_invariants {
+        emu.check_invariants();
+    }
+    emu.load_game(&config.rom_path)
+        .map_err(|e| Failure::Load(format!("Unable to load {}: {}", config.rom_path, e)))?;
+    attach_serial(config, &mut emu)?;
+    Ok(emu)
+}
+
+// Wires up the serial port, if --serial asked for one.
+pub fn attach_serial(config: &Config, emu: &mut Chip8) -> Result<(), Failure> {
+    let Some(link) = &config.serial else {
+        return Ok(());
+    };
+    if let SerialLink::Listen(address) = link {
+        println!("Waiting for the other end of the serial port on {}.", address);
+    }
+    let port = SerialPort::open(link)
+        .map_err(|e| Failure::from(format!("Unable to open the serial port: {}", e)))?;
+    emu.attach(Box::new(port));
+    Ok(())
+}
+
+// Reads a rom, which may be in a zip archive.
+fn read(path: &Path) -> Result<Cow<'static, [u8]>, String> {
+    read_rom(&path.to_string_lossy())
+        .map_err(|e| format!("Unable to read {}: {}", path.display(), e))
+}
+
+pub fn disasm(rom: &Path) -> Result<(), String> {
+    print!("{}", disassemble_rom(&read(rom)?));
+    Ok(())
+}
+
+pub fn asm(source: &Path, output: &Path) -> Result<(), String> {
+    let source = fs::read_to_string(source)
+        .map_err(|e| format!("Unable to read {}: {}", source.display(), e))?;
+    let rom = assemble(&source)?;
+    fs::write(output, &rom).map_err(|e| format!("Unable to write {}: {}", output.display(), e))?;
+    println!("Wrote {} bytes to {}", rom.len(), output.display());
+    Ok(())
+}
+
+// Prints a rom's size and hash, what the chip-8-database knows about it and
+// whatever roms.toml remembers about it.
+pub fn info(rom: &Path) -> Result<(), String> {
+    let bytes = read(rom)?;
+    let hash = rom_hash(&bytes);
+    println!("{}", rom.display());
+    println!("size: {} bytes", bytes.len());
+    println!("sha1: {}", hash);
+    let programs = ProgramDatabase::load(ConfigFile::load()?.library.database.as_deref())?;
+    if let Some(metadata) = programs.get(&hash) {
+        println!("title: {}", metadata.title);
+        if !metadata.authors.is_empty() {
+            println!("authors: {}", metadata.authors.join(", "));
+        }
+        if let Some(release) = &metadata.release {
+            println!("release: {}", release);
+        }
+        if let Some(platform) = &metadata.platform {
+            println!("platform: {}", platform);
+        }
+    }
+    match RomDatabase::load()?.get(&hash) {
+        Some(settings) => {
+            let settings = toml::to_string(settings).map_err(|e| e.to_string())?;
+            println!("\nremembered settings:\n{}", settings.trim_end());
+        }
+        None => println!("no remembered settings"),
+    }
+    Ok(())
+}
+
+// Lists the roms played recently, numbered for --recent.
+pub fn recent() {
+    let recent = library::recent();
+    if recent.is_empty() {
+        println!("No roms have been played yet.");
+    }
+    for (number, rom) in recent.iter().enumerate() {
+        println!("{:>2}. {}", number + 1, rom.display());
+    }
+}
+
+// Runs a rom for a number of frames with no window or sound, then prints
+// the display, for checking test roms from a terminal or a script. For the
+// test roms it knows, it reads the result off the display and prints which
+// checks passed instead.
+pub fn test(config: &Config, frames: u64) -> Result<(), Failure> {
+    let mut emu = power_on(config)?;
+
+    let speed = Speed::from_hz(config.speed);
+    for _ in 0..frames {
+        emu.run_frame(speed.cycles_in(emu.frames()));
+    }
+    if let Some(fault) = emu.fault() {
+        return Err(Failure::Fault(fault));
+    }
+    // it's already been loaded once
+    let test_rom = read_rom(&config.rom_path).ok().and_then(|rom| testrom::find(&rom));
+    if let Some(test_rom) = test_rom {
+        let results = test_rom.read(&emu.pixels()).map_err(Failure::from)?;
+        match config.output {
+            OutputFormat::Text => print!("{}", results.table()),
+            OutputFormat::Json if results.passed() => println!("{}", results.to_json()),
+            // printed with the failure
+            OutputFormat::Json => {}
+        }
+        if !results.passed() {
+            return Err(Failure::Checks(results));
+        }
+        return Ok(());
+    }
+
+    match config.output {
+        OutputFormat::Text => {
+            print!(
+                "{}",
+                framebuffer_to_ansi(&emu.pixels(), &PALETTES[config.display.palette.index()])
+            );
+            println!("{} frames, {} instructions", emu.frames(), emu.cycles());
+        }
+        OutputFormat::Json => println!("{}", finished_json("ok", &emu)),
+    }
+    Ok(())
+}
+
+// Runs every rom of a directory, given as a playlist, for `frames` frames
+// with its own quirks, and compares how each ends up with the manifest from
+// last time. Writes the manifest instead when there isn't one or `update`
+// asks to, and fails if any rom changed.
+pub fn corpus(
+    mut config: Config,
+    frames: u64,
+    manifest_path: &Path,
+    update: bool,
+) -> Result<(), Failure> {
+    let speed = Speed::from_hz(config.speed);
+    let mut manifest = Manifest {
+        frames,
+        ..Manifest::default()
+    };
+    for rom_path in config.playlist.clone() {
+        config.switch_rom(rom_path)?;
+        let rom = read_rom(&config.rom_path)
+            .map_err(|e| Failure::Load(format!("Unable to load {}: {}", config.rom_path, e)))?;
+        let name = Path::new(&config.rom_path)
+            .file_name()
+            .map_or_else(|| config.rom_path.clone(), |name| name.to_string_lossy().into_owned());
+        let hash = corpus::run(&rom, config.quirks, frames, speed)
+            .map_err(|e| Failure::Load(format!("Unable to load {}: {}", config.rom_path, e)))?;
+        manifest.hashes.insert(name, hash);
+    }
+
+    let previous = match Manifest::load(manifest_path)? {
+        Some(previous) if !update => previous,
+        _ => {
+            manifest.save(manifest_path)?;
+            match config.output {
+                OutputFormat::Text => println!(
+                    "Wrote how {} roms ended up to {}.",
+                    manifest.hashes.len(),
+                    manifest_path.display()
+                ),
+                OutputFormat::Json => println!(
+                    "{}",
+                    json!({ "status": "recorded", "roms": manifest.hashes.len() })
+                ),
+            }
+            return Ok(());
+        }
+    };
+    if previous.frames != frames {
+        let error = format!(
+            "{} was made running {} frames, not {}. --update makes it again.",
+            manifest_path.display(),
+            previous.frames,
+            frames
+        );
+        return Err(Failure::from(error));
+    }
+
+    let changes = manifest.compare(&previous);
+    let changed: Vec<&str> = changes
+        .iter()
+        .filter(|(_, change)| matches!(change, Change::Changed { .. }))
+        .map(|(rom, _)| rom.as_str())
+        .collect();
+    match config.output {
+        OutputFormat::Text => {
+            for (rom, change) in &changes {
+                match change {
+                    Change::Same => println!("✅ {}", rom),
+                    Change::Changed { was } => {
+                        println!("❌ {}  changed from {:08x}", rom, was)
+                    }
+                    Change::New => println!("   {}  new", rom),
+                    Change::Missing => println!("   {}  missing", rom),
+                }
+            }
+        }
+        OutputFormat::Json if changed.is_empty() => {
+            let roms: Vec<Value> = changes
+                .iter()
+                .map(|(rom, change)| {
+                    let result = match change {
+                        Change::Same => "same",
+                        Change::Changed { .. } => "changed",
+                        Change::New => "new",
+                        Change::Missing => "missing",
+                    };
+                    json!({ "rom": rom, "result": result })
+                })
+                .collect();
+            println!("{}", json!({ "status": "passed", "roms": roms }));
+        }
+        // printed with the failure
+        OutputFormat::Json => {}
+    }
+    if !changed.is_empty() {
+        let error = format!(
+            "{} of {} roms ended up differently from {}: {}. --update records them as they \
+             are now.",
+            changed.len(),
+            manifest.hashes.len(),
+            manifest_path.display(),
+            changed.join(", ")
+        );
+        return Err(Failure::from(error));
+    }
+    Ok(())
+}
+
+// Runs a rom under two quirk profiles side by side for up to `frames`
+// frames, with the input script pressing the same keys on both, and says
+// where the machines first differ.
+pub fn compare_quirks(
+    config: &Config,
+    profiles: &[Profile; 2],
+    frames: u64,
+) -> Result<(), Failure> {
+    let [a, b] = profiles;
+    let power_on = |profile: &Profile| {
+        let mut emu = Chip8::with_quirks(profile.quirks);
+        emu.seed_rng(0);
+        emu.load_game(&config.rom_path)
+            .map_err(|e| Failure::Load(format!("Unable to load {}: {}", config.rom_path, e)))?;
+        Ok::<_, Failure>(emu)
+    };
+    let (mut emu_a, mut emu_b) = (power_on(a)?, power_on(b)?);
+    let mut script = match &config.input_script {
+        Some(path) => Some(
+            InputScript::load(path)
+                .map_err(|e| format!("Problem with the input script: {}", e))?,
+        ),
+        None => None,
+    };
+
+    let speed = Speed::from_hz(config.speed);
+    let keys = |frame, keys: &mut [u8; 16]| {
+        if let Some(script) = &mut script {
+            script.apply(frame, keys);
+        }
+    };
+    let outcome = lockstep::run(&mut emu_a, &mut emu_b, frames, speed, keys);
+    match outcome {
+        Outcome::Same { frames } => {
+            if let Some(fault) = emu_a.fault() {
+                return Err(Failure::Fault(fault));
+            }
+            match config.output {
+                OutputFormat::Text => println!(
+                    "The rom ran the same under {} and {} for {} frames.",
+                    a.name, b.name, frames
+                ),
+                OutputFormat::Json => println!("{}", finished_json("same", &emu_a)),
+            }
+            Ok(())
+        }
+        Outcome::Diverged(divergence) => {
+            let error = format!(
+                "The rom ran differently under {} / {} from {}.",
+                a.name, b.name, divergence
+            );
+            Err(Failure::from(error))
+        }
+    }
+}
+
+// Runs a rom headless as fast as it goes for BENCHMARK_TIME, then prints
+// how many instructions and frames that was and what the time went on.
+pub fn benchmark(config: &Config) -> Result<(), Failure> {
+    let mut emu = power_on(config)?;
+
+    let speed = Speed::from_hz(config.speed);
+    let mut counts = [0u64; KINDS.len()];
+    let mut sampled = [Duration::ZERO; KINDS.len()];
+    let start = Instant::now();
+    while start.elapsed() < BENCHMARK_TIME {
+        for _ in 0..speed.cycles_in(emu.frames()) {
+            let kind = kind(emu.next_opcode());
+            counts[kind] += 1;
+            if emu.cycles().is_multiple_of(SAMPLE_EVERY) {
+                let timer = Instant::now();
+                emu.emulate_cycle();
+                sampled[kind] += timer.elapsed();
+            } else {
+                emu.emulate_cycle();
+            }
+            if emu.waiting_for_key() {
+                break;
+            }
+        }
+        emu.tick_timers();
+        if let Some(fault) = emu.fault() {
+            return Err(Failure::Fault(fault));
+        }
+    }
+    let seconds = start.elapsed().as_secs_f64();
+
+    println!("{} for {:.1} seconds", config.rom_path, seconds);
+    println!(
+        "{:.0} instructions a second, {} in all",
+        emu.cycles() as f64 / seconds,
+        emu.cycles()
+    );
+    println!(
+        "{} frames emulated, {:.0} a second",
+        emu.frames(),
+        emu.frames() as f64 / seconds
+    );
+    println!("time spent, sampled every {} instructions:", SAMPLE_EVERY);
+    let total: Duration = sampled.iter().sum();
+    for ((name, count), time) in KINDS.iter().zip(counts).zip(sampled) {
+        let share = time.as_secs_f64() / total.as_secs_f64().max(f64::MIN_POSITIVE);
+        println!("  {:<24}{:>5.1}%  {} instructions", name, share * 100.0, count);
+    }
+    Ok(())
+}
+
+// Runs a rom against a trace logged by another emulator, a step for each
+// line, stopping at the first step where they differ.
+pub fn compare_trace(config: &Config) -> Result<(), Failure> {
+    let Some(path) = &config.compare_trace else {
+        return Ok(());
+    };
+    let mut emu = power_on(config)?;
+    let trace = fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read the trace {}: {}", path.display(), e))?;
+
+    let speed = Speed::from_hz(config.speed);
+    let comparison = trace::compare(&mut emu, &trace, &config.trace_format, speed)?;
+    if let Some(fault) = emu.fault() {
+        return Err(Failure::Fault(fault));
+    }
+    match comparison {
+        Comparison::Matched { steps } => match config.output {
+            OutputFormat::Text => println!("All {} steps of {} matched.", steps, path.display()),
+            OutputFormat::Json => println!("{}", finished_json("matched", &emu)),
+        },
+        Comparison::Diverged(divergence) => {
+            let error = format!("The run went its own way at {}.", divergence);
+            return Err(Failure::from(error));
+        }
+    }
+    Ok(())
+}
+
+// Which of KINDS an instruction is.
+fn kind(opcode: u16) -> usize {
+    match (opcode >> 12, opcode & 0xFF) {
+        (0x0, 0xE0) | (0xD, _) => 0,
+        (0x6 | 0x7 | 0x8 | 0xA | 0xC, _) | (0xF, 0x1E | 0x29 | 0x33) => 1,
+        (0x0, 0xEE) | (0x1..=0x5 | 0x9 | 0xB | 0xE, _) => 2,
+        _ => 3,
+    }
+}
+
+// Runs a rom with no window, sound or input besides an input script until
+// it halts or has run --max-cycles instructions, for CI and scripts. Says
+// where it stopped and dumps the display and machine if asked.
+pub fn headless(config: &Config, options: &Headless) -> Result<(), Failure> {
+    let mut emu = power_on(config)?;
+    let mut script = match &config.input_script {
+        Some(path) => Some(
+            InputScript::load(path)
+                .map_err(|e| format!("Problem with the input script: {}", e))?,
+        ),
+        None => None,
+    };
+    let mut chaos = config.chaos.clone().map(|settings| {
+        eprintln!("Chaos seed {}", settings.seed);
+        Chaos::new(settings)
+    });
+
+    let speed = Speed::from_hz(config.speed);
+    let max_cycles = options.max_cycles.unwrap_or(u64::MAX);
+    let mut halted = false;
+    'running: while emu.cycles() < max_cycles && emu.fault().is_none() {
+        let mut keys = [0; 16];
+        if let Some(script) = &mut script {
+            script.apply(emu.frames(), &mut keys);
+        }
+        if let Some(chaos) = &mut chaos {
+            chaos.keys(&mut keys);
+        }
+        if script.is_some() || chaos.is_some() {
+            emu.set_keys(&keys);
+        }
+        // without a script to press it, a key being waited for never comes
+        let stuck = emu.waiting_for_key() && script.as_ref().is_none_or(InputScript::finished);
+        if options.exit_on_halt && (emu.state() == State::Finished || stuck) {
+            halted = true;
+            break;
+        }
+        for _ in 0..speed.cycles_in(emu.frames()) {
+            if emu.cycles() >= max_cycles {
+                break 'running;
+            }
+            emu.emulate_cycle();
+            if emu.waiting_for_key() || emu.fault().is_some() {
+                break;
+            }
+            if options.exit_on_halt && emu.state() == State::Finished {
+                break;
+            }
+        }
+        emu.tick_timers();
+        if let Some((address, bit)) = chaos.as_mut().and_then(|chaos| chaos.frame(&mut emu)) {
+            eprintln!("Flipped bit {} of {:03X}", bit, address);
+        }
+    }
+
+    if let Some(path) = &options.dump_screen {
+        fs::write(path, framebuffer_to_pbm(&emu.pixels()))
+            .map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+    }
+    if let Some(path) = &options.dump_state {
+        savestate::save(path, &emu, config.rom_hash.as_deref())
+            .map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+    }
+    if let Some(fault) = emu.fault() {
+        return Err(Failure::Fault(fault));
+    }
+
+    let status = if halted { "halted" } else { "stopped" };
+    match config.output {
+        OutputFormat::Text => {
+            if halted {
+                println!("halted at {:03X}", emu.pc());
+            } else {
+                println!("stopped after {} instructions", max_cycles);
+            }
+            println!("{} frames, {} instructions", emu.frames(), emu.cycles());
+        }
+        OutputFormat::Json => println!("{}", finished_json(status, &emu)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instructions_by_kind() {
+        assert_eq!(KINDS[kind(0xD125)], "drawing");
+        assert_eq!(KINDS[kind(0x00E0)], "drawing");
+        assert_eq!(KINDS[kind(0x8124)], "arithmetic and logic");
+        assert_eq!(KINDS[kind(0xF233)], "arithmetic and logic");
+        assert_eq!(KINDS[kind(0x00EE)], "jumps, calls and skips");
+        assert_eq!(KINDS[kind(0xE19E)], "jumps, calls and skips");
+        assert_eq!(KINDS[kind(0xF30A)], "timers, keys and memory");
+        assert_eq!(KINDS[kind(0xF565)], "timers, keys and memory");
+    }
+}
@@ -0,0 +1,349 @@
+// Comparing runs with traces logged by other emulators, to find the first
+// instruction where this one goes its own way, say when checking a quirk
+// against an interpreter known to get it right. A trace has a line for each
+// instruction, giving the machine as it was before running it, in a format
+// written with the fields in braces:
+//
+//   PC={pc} OP={opcode} I={i} V={v}
+//
+// The fields are pc, opcode, i, sp, dt, st, v0 to vf, and v for all 16
+// registers in order. Numbers are hex, with or without a 0x. Fields left out
+// of the format aren't compared, and the text between them has to be there
+// but spaces around it don't matter. Blank lines are skipped.
+//
+// Random numbers can't match, so after a CXNN the register's taken from the
+// trace instead of being compared.
+use std::fmt;
+use std::str::FromStr;
+
+use crate::cpu::Chip8;
+use crate::speed::Speed;
+
+/// The format traces are in unless told otherwise: pc, the opcode, the 16
+/// registers and I, separated by spaces.
+pub const DEFAULT_FORMAT: &str = "{pc} {opcode} {v} {i}";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Pc,
+    Opcode,
+    Index,
+    StackPointer,
+    Delay,
+    Sound,
+    Register(usize),
+    // all the registers, v0 to vf
+    Registers,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        let field = match name.to_ascii_lowercase().as_str() {
+            "pc" => Field::Pc,
+            "opcode" => Field::Opcode,
+            "i" => Field::Index,
+            "sp" => Field::StackPointer,
+            "dt" => Field::Delay,
+            "st" => Field::Sound,
+            "v" => Field::Registers,
+            register => {
+                let x = register.strip_prefix('v')?;
+                Field::Register(usize::from_str_radix(x, 16).ok().filter(|x| *x < 16)?)
+            }
+        };
+        Some(field)
+    }
+
+    // What the field is on this machine now.
+    fn read(self, emu: &Chip8) -> u16 {
+        match self {
+            Field::Pc => emu.pc(),
+            Field::Opcode => emu.next_opcode(),
+            Field::Index => emu.index(),
+            Field::StackPointer => emu.stack().len() as u16,
+            Field::Delay => emu.timers().0 as u16,
+            Field::Sound => emu.timers().1 as u16,
+            Field::Register(x) => emu.registers()[x] as u16,
+            Field::Registers => unreachable!("the registers are read one at a time"),
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Field::Pc => write!(f, "pc"),
+            Field::Opcode => write!(f, "the opcode"),
+            Field::Index => write!(f, "I"),
+            Field::StackPointer => write!(f, "the stack pointer"),
+            Field::Delay => write!(f, "the delay timer"),
+            Field::Sound => write!(f, "the sound timer"),
+            Field::Register(x) => write!(f, "V{:X}", x),
+            Field::Registers => write!(f, "the registers"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Piece {
+    Text(String),
+    Field(Field),
+}
+
+/// How the lines of a trace are laid out, given with --trace-format.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceFormat {
+    pieces: Vec<Piece>,
+}
+
+impl Default for TraceFormat {
+    fn default() -> Self {
+        DEFAULT_FORMAT
+            .parse()
+            .expect("the default trace format parses")
+    }
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pieces = Vec::new();
+        let mut rest = s;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                pieces.push(Piece::Text(rest[..open].to_string()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| format!("The trace format has a {{ without a }}: {}", s))?;
+            let name = &rest[open + 1..open + close];
+            let field = Field::from_name(name)
+                .ok_or_else(|| format!("The trace format has an unknown field {{{}}}.", name))?;
+            pieces.push(Piece::Field(field));
+            rest = &rest[open + close + 1..];
+        }
+        if !rest.is_empty() {
+            pieces.push(Piece::Text(rest.to_string()));
+        }
+        if !pieces.iter().any(|piece| matches!(piece, Piece::Field(_))) {
+            return Err("The trace format needs at least one field.".to_string());
+        }
+        Ok(TraceFormat { pieces })
+    }
+}
+
+impl TraceFormat {
+    /// Reads the fields from a line of a trace.
+    pub fn parse_line(&self, line: &str) -> Result<Vec<(Field, u16)>, String> {
+        let mut values = Vec::new();
+        let mut rest = line;
+        for piece in &self.pieces {
+            rest = rest.trim_start();
+            match piece {
+                Piece::Text(text) => {
+                    let text = text.trim();
+                    rest = rest
+                        .strip_prefix(text)
+                        .ok_or_else(|| format!("expected {:?} at {:?}", text, rest))?;
+                }
+                Piece::Field(Field::Registers) => {
+                    for x in 0..16 {
+                        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+                        let (value, after) = hex(rest)?;
+                        values.push((Field::Register(x), value));
+                        rest = after;
+                    }
+                }
+                Piece::Field(field) => {
+                    let (value, after) = hex(rest)?;
+                    values.push((*field, value));
+                    rest = after;
+                }
+            }
+        }
+        Ok(values)
+    }
+}
+
+// Reads a hex number from the start of `s`, returning it and what's left.
+fn hex(s: &str) -> Result<(u16, &str), String> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    let end = digits
+        .find(|c: char| !c.is_ascii_hexdigit())
+        .unwrap_or(digits.len());
+    let value = u16::from_str_radix(&digits[..end], 16)
+        .map_err(|_| format!("expected a hex number at {:?}", s))?;
+    Ok((value, &digits[end..]))
+}
+
+/// Where a run first went a different way from its trace: the line of the
+/// trace, where the machine was and each field that didn't match, with
+/// what the trace has and then what the machine has.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    pub line: usize,
+    pub pc: u16,
+    pub opcode: u16,
+    pub fields: Vec<(Field, u16, u16)>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields: Vec<String> = self
+            .fields
+            .iter()
+            .map(|(field, traced, found)| {
+                format!(
+                    "{} is {:X} in the trace but {:X} here",
+                    field, traced, found
+                )
+            })
+            .collect();
+        write!(
+            f,
+            "line {}, about to run {:04X} at {:03X}: {}",
+            self.line,
+            self.opcode,
+            self.pc,
+            fields.join(", ")
+        )
+    }
+}
+
+/// How a run compared with its trace.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Comparison {
+    // every step of the trace matched
+    Matched { steps: usize },
+    Diverged(Divergence),
+}
+
+/// Runs the machine a step for each line of `trace`, checking its state
+/// against the line before each step, and ticking the timers after each
+/// frame's worth of steps at `speed`. A step that stops the machine ends the
+/// run early, leaving the fault for the caller.
+pub fn compare(
+    emu: &mut Chip8,
+    trace: &str,
+    format: &TraceFormat,
+    speed: Speed,
+) -> Result<Comparison, String> {
+    let mut steps = 0;
+    let mut frame_steps = 0;
+    let mut random = None;
+    for (number, line) in (1..).zip(trace.lines()) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let values = format
+            .parse_line(line)
+            .map_err(|e| format!("Unable to read line {} of the trace: {}", number, e))?;
+        if let Some(x) = random.take() {
+            if let Some((_, value)) = values
+                .iter()
+                .find(|(field, _)| *field == Field::Register(x))
+            {
+                emu.set_register(x, *value as u8);
+            }
+        }
+        let fields: Vec<(Field, u16, u16)> = values
+            .iter()
+            .map(|&(field, traced)| (field, traced, field.read(emu)))
+            .filter(|(_, traced, found)| traced != found)
+            .collect();
+        if !fields.is_empty() {
+            return Ok(Comparison::Diverged(Divergence {
+                line: number,
+                pc: emu.pc(),
+                opcode: emu.next_opcode(),
+                fields,
+            }));
+        }
+
+        let opcode = emu.next_opcode();
+        if opcode & 0xF000 == 0xC000 {
+            random = Some((opcode as usize >> 8) & 0xF);
+        }
+        emu.emulate_cycle();
+        steps += 1;
+        frame_steps += 1;
+        if frame_steps == speed.cycles_in(emu.frames()) {
+            emu.tick_timers();
+            frame_steps = 0;
+        }
+        if emu.fault().is_some() {
+            break;
+        }
+    }
+    Ok(Comparison::Matched { steps })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LD V1, 5; ADD V1, 3; RND V2, 0xFF; JP 0x206
+    const ROM: [u8; 8] = [0x61, 0x05, 0x71, 0x03, 0xC2, 0xFF, 0x12, 0x06];
+
+    fn machine() -> Chip8 {
+        let mut emu = Chip8::default();
+        emu.load_rom(&ROM).unwrap();
+        emu
+    }
+
+    #[test]
+    fn formats_parse() {
+        let format: TraceFormat = "PC={pc} OP={opcode} V1:{v1}".parse().unwrap();
+        let values = format.parse_line("PC=0x202  OP=7103 V1:05").unwrap();
+        assert_eq!(
+            values,
+            [
+                (Field::Pc, 0x202),
+                (Field::Opcode, 0x7103),
+                (Field::Register(1), 5)
+            ]
+        );
+        assert!(format.parse_line("PC=202 V1:05").is_err());
+
+        assert!("{pc} {q}".parse::<TraceFormat>().is_err());
+        assert!("{pc".parse::<TraceFormat>().is_err());
+        assert!("pc".parse::<TraceFormat>().is_err());
+    }
+
+    #[test]
+    fn matching_traces_run_to_the_end() {
+        let trace = "
+200 6105 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0000
+202 7103 00 05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0000
+204 C2FF 00 08 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0000
+206 1206 00 08 2A 00 00 00 00 00 00 00 00 00 00 00 00 00 0000
+206 1206 00 08 2A 00 00 00 00 00 00 00 00 00 00 00 00 00 0000
+";
+        let mut emu = machine();
+        let speed = Speed::default();
+        let comparison = compare(&mut emu, trace, &TraceFormat::default(), speed).unwrap();
+        assert_eq!(comparison, Comparison::Matched { steps: 5 });
+        // the random number came from the trace
+        assert_eq!(emu.registers()[2], 0x2A);
+    }
+
+    #[test]
+    fn the_first_difference_is_reported() {
+        let format: TraceFormat = "{pc} {v1}".parse().unwrap();
+        let trace = "200 00\n202 05\n204 09\n206 09\n";
+        let comparison = compare(&mut machine(), trace, &format, Speed::default()).unwrap();
+        let Comparison::Diverged(divergence) = comparison else {
+            panic!("the trace matched");
+        };
+        assert_eq!(divergence.line, 3);
+        assert_eq!(divergence.fields, [(Field::Register(1), 9, 8)]);
+        assert_eq!(
+            divergence.to_string(),
+            "line 3, about to run C2FF at 204: V1 is 9 in the trace but 8 here"
+        );
+    }
+}
@@ -0,0 +1,247 @@
+// Exporting a movie's playback as video, without opening a window, for
+// publishing runs.
+//
+// GIFs are encoded here. Anything else is piped to ffmpeg as raw frames, so
+// .mp4 and the rest work as long as ffmpeg is installed.
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+
+use gif::{Encoder, Frame, Repeat};
+
+use chip8::movie::Movie;
+use chip8::{Config, DEFAULT_PALETTE};
+
+use chip8::cpu::Chip8;
+use chip8::speed::Speed;
+use crate::{EMULATOR_HEIGHT, EMULATOR_WIDTH};
+
+// each chip8 pixel becomes a square of this many pixels
+const SCALE: usize = 8;
+const WIDTH: usize = EMULATOR_WIDTH as usize * SCALE;
+const HEIGHT: usize = EMULATOR_HEIGHT as usize * SCALE;
+
+// Plays back the movie given with --play-movie and writes it to `path`.
+pub fn export(config: &Config, path: &Path) -> Result<(), String> {
+    let movie = match &config.play_movie {
+        Some(movie_path) => {
+            let movie = Movie::load(movie_path)?;
+            crate::check_movie(config, movie_path, &movie);
+            movie
+        }
+        None => return Ok(()),
+    };
+
+    let mut emu = Chip8::with_quirks(movie.quirks);
+    emu.seed_rng(movie.seed);
+    emu.load_game(&config.rom_path)
+        .map_err(|e| format!("Unable to load {}: {}", config.rom_path, e))?;
+
+    let mut video =
+        Video::create(path).map_err(|e| format!("Unable to create {}: {}", path.display(), e))?;
+    let speed = movie.speed.map_or_else(Speed::default, Speed::from_hz);
+    let mut desynced = false;
+    for frame in 0..movie.frames.len() as u64 {
+        if let Some(keys) = movie.keys(frame) {
+            emu.set_keys(&keys);
+        }
+        emu.run_frame(speed.cycles_in(emu.frames()));
+
+        let expected = movie.checksums.get(&frame);
+        if !desynced && expected.is_some_and(|checksum| *checksum != emu.checksum()) {
+            eprintln!("⚠️ The movie went out of step on frame {}.", frame);
+            desynced = true;
+        }
+
+        video
+            .write_frame(frame, &emu.pixels())
+            .map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+    }
+    if let Some(cycles) = movie.cycles.filter(|cycles| *cycles != emu.cycles()) {
+        eprintln!(
+            "⚠️ The movie took {} instructions, {} when it was recorded.",
+            emu.cycles(),
+            cycles
+        );
+    }
+    video
+        .finish(movie.frames.len() as u64)
+        .map_err(|e| format!("Unable to write {}: {}", path.display(), e))
+}
+
+enum Video {
+    Gif {
+        encoder: Encoder<BufWriter<File>>,
+        // the last distinct picture and the frame it appeared on. It's
+        // written once the next one comes along and it's known how long it
+        // stayed up
+        pending: Option<(Vec<u8>, u64)>,
+    },
+    Ffmpeg(Child),
+}
+
+impl Video {
+    fn create(path: &Path) -> std::io::Result<Video> {
+        if path.extension().is_some_and(|extension| extension == "gif") {
+            let background = DEFAULT_PALETTE.background;
+            let foreground = DEFAULT_PALETTE.foreground;
+            let palette = [
+                background.r,
+                background.g,
+                background.b,
+                foreground.r,
+                foreground.g,
+                foreground.b,
+            ];
+            let file = BufWriter::new(File::create(path)?);
+            let mut encoder = Encoder::new(file, WIDTH as u16, HEIGHT as u16, &palette)
+                .map_err(std::io::Error::other)?;
+            encoder
+                .set_repeat(Repeat::Infinite)
+                .map_err(std::io::Error::other)?;
+            return Ok(Video::Gif {
+                encoder,
+                pending: None,
+            });
+        }
+
+        let child = Command::new("ffmpeg")
+            .args([
+                "-y",
+                "-loglevel",
+                "error",
+                "-f",
+                "rawvideo",
+                "-pix_fmt",
+                "rgb24",
+            ])
+            .args([
+                "-s",
+                &format!("{}x{}", WIDTH, HEIGHT),
+                "-r",
+                "60",
+                "-i",
+                "-",
+            ])
+            .args(["-pix_fmt", "yuv420p"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()?;
+        Ok(Video::Ffmpeg(child))
+    }
+
+    fn write_frame(&mut self, frame: u64, gfx: &[[u8; 64]; 32]) -> std::io::Result<()> {
+        match self {
+            Video::Gif { encoder, pending } => {
+                let pixels = scale(gfx, &[0], &[1]);
+                match pending {
+                    Some((last, _)) if *last == pixels => return Ok(()),
+                    // GIF delays are in hundredths of a second and players
+                    // slow down anything under two, so flickers that short
+                    // are skipped
+                    Some((_, start)) if centiseconds(frame) - centiseconds(*start) < 2 => {
+                        *pending = Some((pixels, *start));
+                        return Ok(());
+                    }
+                    _ => {}
+                }
+                if let Some((last, start)) = pending.replace((pixels, frame)) {
+                    write_gif_frame(encoder, last, start, frame)?;
+                }
+                Ok(())
+            }
+            Video::Ffmpeg(child) => {
+                let background = DEFAULT_PALETTE.background;
+                let foreground = DEFAULT_PALETTE.foreground;
+                let pixels = scale(
+                    gfx,
+                    &[background.r, background.g, background.b],
+                    &[foreground.r, foreground.g, foreground.b],
+                );
+                let stdin = child.stdin.as_mut().expect("ffmpeg's stdin is piped");
+                stdin.write_all(&pixels)
+            }
+        }
+    }
+
+    // Writes out what's left, `frames` being the length of the video.
+    fn finish(self, frames: u64) -> std::io::Result<()> {
+        match self {
+            Video::Gif {
+                mut encoder,
+                pending,
+            } => {
+                if let Some((last, start)) = pending {
+                    write_gif_frame(&mut encoder, last, start, frames)?;
+                }
+                encoder.get_mut().flush()
+            }
+            Video::Ffmpeg(mut child) => {
+                // closing stdin tells ffmpeg the video is over
+                drop(child.stdin.take());
+                let status = child.wait()?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(std::io::Error::other(format!(
+                        "ffmpeg failed with {}",
+                        status
+                    )))
+                }
+            }
+        }
+    }
+}
+
+fn write_gif_frame(
+    encoder: &mut Encoder<BufWriter<File>>,
+    pixels: Vec<u8>,
+    start: u64,
+    end: u64,
+) -> std::io::Result<()> {
+    let mut frame = Frame::from_indexed_pixels(WIDTH as u16, HEIGHT as u16, pixels, None);
+    frame.delay = (centiseconds(end) - centiseconds(start)) as u16;
+    encoder.write_frame(&frame).map_err(std::io::Error::other)
+}
+
+// When a frame starts in hundredths of a second, rounded down so the delays
+// add up to the length of the video.
+fn centiseconds(frame: u64) -> u64 {
+    frame * 100 / 60
+}
+
+// Blows the display up by SCALE, with `off` and `on` as each pixel's bytes.
+fn scale(gfx: &[[u8; 64]; 32], off: &[u8], on: &[u8]) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(WIDTH * HEIGHT * on.len());
+    for row in gfx {
+        let mut line = Vec::with_capacity(WIDTH * on.len());
+        for pixel in row {
+            let color = if *pixel != 0 { on } else { off };
+            for _ in 0..SCALE {
+                line.extend_from_slice(color);
+            }
+        }
+        for _ in 0..SCALE {
+            pixels.extend_from_slice(&line);
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_pixels_up() {
+        let mut gfx = [[0; 64]; 32];
+        gfx[0][1] = 1;
+        let pixels = scale(&gfx, &[0], &[1]);
+        assert_eq!(pixels.len(), WIDTH * HEIGHT);
+        assert_eq!(pixels[SCALE - 1], 0);
+        assert_eq!(pixels[SCALE], 1);
+        assert_eq!(pixels[WIDTH * (SCALE - 1) + SCALE], 1);
+        assert_eq!(pixels[WIDTH * SCALE + SCALE], 0);
+    }
+}
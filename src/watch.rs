@@ -0,0 +1,51 @@
+// Watching the rom's file for --watch, so a rom being written restarts as
+// soon as it's assembled again.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use chip8::archive;
+
+pub struct RomWatcher {
+    // kept alive for as long as the rom is watched
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    file: PathBuf,
+}
+
+impl RomWatcher {
+    pub fn new(rom_path: &str) -> Result<RomWatcher, String> {
+        // a rom in an archive changes along with the archive
+        let (file, _) = archive::split(rom_path);
+        let file =
+            fs::canonicalize(file).map_err(|e| format!("Unable to watch {}: {}", file, e))?;
+
+        // editors and assemblers often write a new file and rename it over
+        // the old one, so it's the directory that's watched
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(|e| e.to_string())?;
+        let dir = file.parent().unwrap_or(Path::new("/"));
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("Unable to watch {}: {}", dir.display(), e))?;
+
+        Ok(RomWatcher {
+            _watcher: watcher,
+            events,
+            file,
+        })
+    }
+
+    // Whether the rom has been written to since last asked. Saving often
+    // takes several writes, which all count as one change.
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        for event in self.events.try_iter().flatten() {
+            changed |= matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                && event.paths.contains(&self.file);
+        }
+        changed
+    }
+}
@@ -0,0 +1,124 @@
+// A ratatui widget showing a chip8 display, so terminal apps can put a
+// running machine on screen next to whatever else they show:
+//
+//   frame.render_widget(Chip8Display::new(&emu.pixels()), area);
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::widgets::Widget;
+
+use crate::dump::braille_cell;
+use crate::{Palette, TerminalStyle, DEFAULT_PALETTE};
+
+/// A chip8 display drawn into a ratatui buffer, with half blocks taking a
+/// 64x32 display to 64x16 cells or braille taking it to 32x8. Whatever
+/// doesn't fit in the area it's given is cut off.
+///
+/// gfx: the display, usually a `Chip8`'s `pixels()`.
+/// palette: the colors to draw it in.
+/// style: half blocks or braille.
+#[derive(Clone, Copy)]
+pub struct Chip8Display<'a, const W: usize = 64, const H: usize = 32> {
+    gfx: &'a [[u8; W]; H],
+    palette: &'a Palette,
+    style: TerminalStyle,
+}
+
+impl<'a, const W: usize, const H: usize> Chip8Display<'a, W, H> {
+    /// The display in the default palette, with half blocks.
+    pub fn new(gfx: &'a [[u8; W]; H]) -> Self {
+        Chip8Display {
+            gfx,
+            palette: &DEFAULT_PALETTE,
+            style: TerminalStyle::HalfBlocks,
+        }
+    }
+
+    pub fn palette(mut self, palette: &'a Palette) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    pub fn style(mut self, style: TerminalStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// The cells it takes to show the whole display, for laying it out.
+    pub fn size(&self) -> (u16, u16) {
+        let (width, height) = match self.style {
+            TerminalStyle::HalfBlocks => (W, H.div_ceil(2)),
+            TerminalStyle::Braille => (W.div_ceil(2), H.div_ceil(4)),
+        };
+        (width as u16, height as u16)
+    }
+}
+
+impl<const W: usize, const H: usize> Widget for Chip8Display<'_, W, H> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let (width, height) = self.size();
+        let area = area.intersection(Rect::new(area.x, area.y, width, height));
+        let (background, foreground) = (self.palette.background, self.palette.foreground);
+        let color = |on: u8| if on != 0 { foreground } else { background };
+
+        for row in 0..area.height {
+            for column in 0..area.width {
+                let Some(cell) = buf.cell_mut((area.x + column, area.y + row)) else {
+                    continue;
+                };
+                let (x, y) = (usize::from(column), usize::from(row));
+                match self.style {
+                    TerminalStyle::HalfBlocks => {
+                        let top = self.gfx[y * 2][x];
+                        let bottom = self.gfx.get(y * 2 + 1).map_or(0, |row| row[x]);
+                        cell.set_char('▀')
+                            .set_fg(color(top).into())
+                            .set_bg(color(bottom).into());
+                    }
+                    TerminalStyle::Braille => {
+                        cell.set_char(braille_cell(self.gfx, x * 2, y * 4))
+                            .set_fg(foreground.into())
+                            .set_bg(background.into());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BITBEE;
+
+    #[test]
+    fn half_blocks_take_two_rows_a_cell() {
+        let mut gfx = [[0u8; 64]; 32];
+        gfx[1][3] = 1;
+        let mut buf = Buffer::empty(Rect::new(0, 0, 80, 20));
+        Chip8Display::new(&gfx)
+            .palette(&BITBEE)
+            .render(buf.area, &mut buf);
+
+        let cell = &buf[(3, 0)];
+        assert_eq!(cell.symbol(), "▀");
+        assert_eq!(cell.fg, BITBEE.background.into());
+        assert_eq!(cell.bg, BITBEE.foreground.into());
+        // nothing drawn past the display
+        assert_eq!(buf[(64, 0)].symbol(), " ");
+        assert_eq!(buf[(0, 16)].symbol(), " ");
+    }
+
+    #[test]
+    fn braille_is_cut_off_at_the_area() {
+        let mut gfx = [[0u8; 64]; 32];
+        gfx[0][0] = 1;
+        let display = Chip8Display::new(&gfx).style(TerminalStyle::Braille);
+        assert_eq!(display.size(), (32, 8));
+
+        let mut buf = Buffer::empty(Rect::new(0, 0, 10, 4));
+        display.render(Rect::new(2, 1, 8, 3), &mut buf);
+        assert_eq!(buf[(2, 1)].symbol(), "\u{2801}");
+        assert_eq!(buf[(9, 3)].symbol(), "\u{2800}");
+        assert_eq!(buf[(1, 1)].symbol(), " ");
+    }
+}
@@ -0,0 +1,1484 @@
+// The windowed frontend: the rom on screen with SDL, played with the
+// keyboard, the mouse, touch or game controllers, with the emulator's own
+// hotkeys, overlays, save states, rewind and movies.
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use core::time::Duration;
+use std::time::Instant;
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::Sdl;
+
+use chip8::cpu::{Chip8, FaultKind, State};
+#[cfg(feature = "dap")]
+use chip8::dap::DapServer;
+use chip8::dump::framebuffer_to_ansi;
+use chip8::input_script::InputScript;
+use chip8::input_source::InputSource;
+use chip8::library;
+#[cfg(feature = "metrics")]
+use chip8::metrics::Metrics;
+use chip8::movie::{Movie, CHECKSUM_INTERVAL};
+use chip8::netplay::{Netplay, Session};
+use chip8::report::Failure;
+#[cfg(feature = "rpc")]
+use chip8::rpc::RpcServer;
+use chip8::roms::{RomDatabase, RomSettings};
+use chip8::sound::wav::WavRecorder;
+use chip8::sound::{AudioBackend, Pattern};
+use chip8::config::{PaletteName, Resume};
+use chip8::Config;
+use chip8::{NetplaySettings, Palette, Peer, PALETTES};
+
+use crate::audio::Beeper;
+use crate::controller::{Controllers, Stick};
+use crate::emulation::{EmulationThread, Pace};
+use crate::hotkeys::{Action, Hotkeys};
+use crate::input::{input_sources, Keymap};
+use crate::overlay::{draw_text, Overlays, VirtualKeypad};
+use crate::pacer::FramePacer;
+use crate::plugin::{PluginEvent, Plugins};
+use crate::rewind::Rewind;
+use crate::savestate;
+use chip8::speed::Speed;
+use crate::tools;
+use crate::watch::RomWatcher;
+use crate::zoom::Zoom;
+use crate::check_movie;
+
+// how often the chip8 timers count down, and how often a frame is emulated
+const TIMER_TICK: Duration = Duration::from_nanos(1_000_000_000 / 60);
+// frames emulated per frame shown while fast-forward is held
+const FAST_FORWARD_FRAMES: u32 = 8;
+// the mouse SDL pretends touches come from, which the touch events already
+// cover
+const TOUCH_MOUSE_ID: u32 = u32::MAX;
+// how long notices like the new speed stay on screen
+const NOTICE_TIME: Duration = Duration::from_secs(2);
+
+// The window, the machine playing in it and everything that goes along with
+// it, kept from one frame to the next.
+struct App {
+    config: Config,
+    canvas: Canvas<Window>,
+    color_palette: &'static Palette,
+    beeper: Option<Beeper>,
+    recording: Option<Movie>,
+    playback: Option<Movie>,
+    // set when playback stops matching the recording
+    desynced: bool,
+    emu: Chip8,
+    // with netplay the other player's machine runs in lockstep
+    netplay: Option<Netplay>,
+    // with --watch the rom restarts whenever its file changes
+    watcher: Option<RomWatcher>,
+    // the playlist's rom that's running, if there's a playlist
+    playlist_index: usize,
+    // the buzzer is recorded a frame at a time as the timers tick
+    recorder: Option<WavRecorder>,
+    keymap: Keymap,
+    hotkeys: Hotkeys,
+    // the clickable keypad and key display, hidden until toggled
+    overlays: Overlays,
+    last_keys: [u8; 16],
+    // magnifies part of the display, toggled with O
+    zoom: Zoom,
+    // where in the window the display's drawn
+    geometry: Geometry,
+    // keypad keys held down on the keyboard
+    held_keys: [u8; 16],
+    // with sticky keys a tap latches a key down instead, until it's tapped
+    // again
+    sticky: bool,
+    latched: [u8; 16],
+    // key presses played back on top of the player's own
+    script: Option<InputScript>,
+    // frames emulated so far, which is what the script's timings count
+    frame: u64,
+    // keys pressed by the player, for the script to add to each frame
+    player_keys: [u8; 16],
+    // controllers are optional extras, opened as they're plugged in
+    controllers: Option<Controllers>,
+    stick: Stick,
+    // keypads wired up some other way, like to a Raspberry Pi's GPIO pins
+    sources: Vec<Box<dyn InputSource>>,
+    // other programs driving the emulator with --rpc
+    #[cfg(feature = "rpc")]
+    rpc: Option<RpcServer>,
+    // monitoring with --metrics
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+    // debuggers attached with --dap
+    #[cfg(feature = "dap")]
+    dap: Option<DapServer>,
+    // the speedrun timer, --script and the like
+    plugins: Plugins,
+    // fast-forward while Tab is held, turbo runs uncapped until toggled off
+    fast_forward: bool,
+    turbo: bool,
+    // the last few seconds, stepped back through while Backspace is held
+    rewind: Rewind,
+    rewinding: bool,
+    // frozen with Space, the window keeps responding
+    paused: bool,
+    // run a single frame while paused, set by N
+    advance: bool,
+    // whether the rom has ended by jumping to itself, to say so only once
+    finished: bool,
+    // instructions a second, changed with - and =
+    speed: Speed,
+    notice_until: Instant,
+    // the save-state slot F5 and F9 save to and load from
+    slot: u8,
+    // the machine as it was when the rom was last quit, which is either
+    // picked up straight away or offered until Y or N is pressed
+    autosave: Option<PathBuf>,
+    resume: Option<Chip8>,
+    // with --threaded the machine runs on a thread of its own, and emu is
+    // the window's copy of it
+    emulation: Option<EmulationThread>,
+    // whether the window needs drawing again this time round
+    redraw: bool,
+    // a rom dropped on the window, the next one in the playlist or one
+    // loaded over --rpc, to take over from the running one
+    switch_to: Option<String>,
+}
+
+pub fn application(config: Config) -> Result<(), Failure> {
+    // Initialize SDL and Input Handling
+    let sdl_context = sdl2::init().unwrap();
+    let mut app = App::new(config, &sdl_context)?;
+    let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut pacer = FramePacer::new(TIMER_TICK);
+    // events that came in while waiting for the next frame, to be handled
+    // with the rest
+    let mut pending = Vec::new();
+
+    'running: loop {
+        app.run_frames();
+        app.update_overlays();
+        app.check_outside();
+        for event in pending.drain(..).chain(event_pump.poll_iter()) {
+            if app.event(event) {
+                break 'running;
+            }
+        }
+        if let Some(rom_path) = app.switch_to.take() {
+            app.switch_rom(rom_path);
+        }
+        app.set_keys();
+        if app.redraw {
+            app.draw();
+        }
+
+        // a rom waiting on FX0A with nothing new to show can wait on the
+        // window's events instead, using next to no CPU. They're kept for
+        // the next frame rather than running it early, which would tick the
+        // timers as fast as the mouse moves
+        let waiting =
+            app.emu.waiting_for_key() && !app.paused && !app.fast_forward && !app.rewinding;
+        if app.turbo && app.emulation.is_none() {
+            pacer.restart();
+        } else if waiting && !app.redraw {
+            pacer.block(|timeout| {
+                // rounded up, or the last part of a millisecond would spin
+                let timeout = timeout.as_micros().div_ceil(1000) as u32;
+                pending.extend(event_pump.wait_event_timeout(timeout));
+            });
+        } else {
+            pacer.wait();
+        }
+    }
+
+    app.finish()
+}
+
+impl App {
+    // Opens the window and powers on the machine with the rom, along with
+    // everything the config asks for around it.
+    fn new(config: Config, sdl_context: &Sdl) -> Result<App, Failure> {
+        let color_palette: &Palette = &PALETTES[config.display.palette.index()];
+        let canvas = open_window(sdl_context, &config, color_palette);
+
+        // a missing audio device shouldn't stop the game from running
+        let beeper = Beeper::new(sdl_context, config.audio.tone, config.audio.buffer_size())
+            .map_err(|e| eprintln!("⚠️ Unable to open audio device, sound is disabled: {}", e))
+            .ok();
+
+        let (recording, playback) = movies(&config);
+
+        // Initialize chip8 emulator
+        let quirks = playback.as_ref().map_or(config.quirks, |movie| movie.quirks);
+        let mut emu = Chip8::with_quirks(quirks);
+        if config.check_invariants {
+            emu.check_invariants();
+        }
+        if let Some(movie) = recording.as_ref().or(playback.as_ref()) {
+            emu.seed_rng(movie.seed);
+        }
+        // copy the program into memory
+        if let Err(e) = emu.load_game(&config.rom_path) {
+            let error = format!("Unable to load {}: {}", config.rom_path, e);
+            return Err(Failure::Load(error));
+        }
+        tools::attach_serial(&config, &mut emu)?;
+        // for the launcher and --recent
+        if let Err(e) = library::add_recent(Path::new(&config.rom_path)) {
+            eprintln!("⚠️ Unable to remember the rom: {}", e);
+        }
+        // the other player's machine runs with the quirks, speed and random
+        // numbers the host picked
+        let mut netplay = None;
+        let mut netplay_speed = None;
+        if let Some(settings) = &config.netplay {
+            let (connection, session) = start_netplay(&config, settings, &emu)?;
+            emu.set_quirks(session.quirks);
+            emu.seed_rng(session.seed);
+            netplay_speed = Some(session.speed);
+            netplay = Some(connection);
+        }
+
+        let recorder = config.record_audio.as_ref().map(|path| {
+            WavRecorder::create(path, config.audio.tone).unwrap_or_else(|e| {
+                eprintln!("❌ Unable to create {}: {}", path.display(), e);
+                process::exit(1);
+            })
+        });
+
+        let (keymap, hotkeys) = bindings(&config);
+        let mut overlays = Overlays {
+            keypad: VirtualKeypad::new(keymap.labels()),
+            muted: config.audio.muted,
+            ..Overlays::default()
+        };
+        let zoom = Zoom::default();
+        let geometry = Geometry::new(canvas.window().size(), &zoom);
+
+        let script = config.input_script.as_ref().map(|path| {
+            InputScript::load(path).unwrap_or_else(|err| {
+                eprintln!("❌ Problem with the input script: {}", err);
+                process::exit(1);
+            })
+        });
+
+        // carry on without controllers. Any there at startup are opened
+        // along with the rest as they're plugged in
+        let controllers = sdl_context
+            .game_controller()
+            .map(Controllers::new)
+            .map_err(|e| eprintln!("⚠️ Unable to use game controllers: {}", e))
+            .ok();
+
+        let sources = input_sources(&config).unwrap_or_else(|err| {
+            eprintln!("❌ {}", err);
+            process::exit(1);
+        });
+        #[cfg(feature = "rpc")]
+        let rpc = crate::rpc_server(&config)?;
+        #[cfg(feature = "metrics")]
+        let metrics = crate::metrics_server(&config)?;
+        #[cfg(feature = "dap")]
+        let dap = config
+            .dap
+            .as_deref()
+            .map(|address| {
+                DapServer::bind(address)
+                    .map_err(|e| Failure::from(format!("Unable to listen on {}: {}", address, e)))
+            })
+            .transpose()?;
+        let mut plugins = Plugins::for_config(&config).map_err(Failure::from)?;
+        plugins.init(&mut emu);
+
+        let speed = Speed::from_hz(
+            playback
+                .as_ref()
+                .and_then(|movie| movie.speed)
+                .or(netplay_speed)
+                .unwrap_or(config.speed),
+        );
+
+        let mut paused = false;
+        let autosave = autosave_path(&config);
+        let mut resume = None;
+        let offer = config.autosave.resume != Resume::Never
+            && !config.start_paused
+            && recording.is_none()
+            && playback.is_none()
+            && netplay.is_none();
+        let saved = autosave.as_ref().filter(|_| offer);
+        if let Some(state) = saved.and_then(|path| load_autosave(&config, path)) {
+            match config.autosave.resume {
+                Resume::Always => emu.replace_with(state),
+                _ => resume = Some(state),
+            }
+        }
+        if resume.is_some() {
+            paused = true;
+            overlays.paused = true;
+            overlays.notice = Some("RESUME? Y/N".to_string());
+        }
+        // with --start-paused nothing runs until it's unpaused or stepped
+        if config.start_paused && netplay.is_none() {
+            paused = true;
+            overlays.paused = true;
+        }
+
+        let emulation = config.threaded.then(|| {
+            let pace = Pace {
+                paused,
+                frames: 1,
+                speed,
+                uncapped: false,
+            };
+            let copy = emu.snapshot();
+            EmulationThread::spawn(std::mem::replace(&mut emu, copy), TIMER_TICK, pace)
+        });
+
+        Ok(App {
+            canvas,
+            color_palette,
+            beeper,
+            recording,
+            playback,
+            desynced: false,
+            emu,
+            netplay,
+            watcher: watch(&config),
+            playlist_index: 0,
+            recorder,
+            keymap,
+            hotkeys,
+            overlays,
+            last_keys: [0; 16],
+            zoom,
+            geometry,
+            held_keys: [0; 16],
+            sticky: config.accessibility.sticky_keys,
+            latched: [0; 16],
+            script,
+            frame: 0,
+            player_keys: [0; 16],
+            controllers,
+            stick: Stick::new(config.controller.deadzone),
+            sources,
+            #[cfg(feature = "rpc")]
+            rpc,
+            #[cfg(feature = "metrics")]
+            metrics,
+            #[cfg(feature = "dap")]
+            dap,
+            plugins,
+            fast_forward: false,
+            turbo: false,
+            rewind: Rewind::default(),
+            rewinding: false,
+            paused,
+            advance: false,
+            finished: false,
+            speed,
+            notice_until: Instant::now(),
+            slot: 0,
+            autosave,
+            resume,
+            emulation,
+            redraw: false,
+            switch_to: None,
+            config,
+        })
+    }
+
+    // Shows `notice` for a moment.
+    fn notify(&mut self, notice: String) {
+        self.overlays.notice = Some(notice);
+        self.notice_until = Instant::now() + NOTICE_TIME;
+    }
+
+    // Runs the machine for the frames due this time round, or steps it back
+    // while rewinding, and plays what it sounds like.
+    fn run_frames(&mut self) {
+        let frames = if self.paused {
+            std::mem::take(&mut self.advance) as u32
+        } else if self.fast_forward {
+            FAST_FORWARD_FRAMES
+        } else {
+            1
+        };
+        let mut pattern = None;
+        // whether any of the machines from the thread drew
+        let mut thread_drew = false;
+        if self.rewinding {
+            // back a frame each frame, or faster along with fast-forward
+            for _ in 0..frames {
+                self.rewind.pop(&mut self.emu);
+            }
+        } else if let Some(ran) = self.emulation.as_mut().map(EmulationThread::frames) {
+            // the thread has run the frames already, the window only
+            // catches up with it
+            for mut ran in ran {
+                thread_drew |= ran.draw_flag();
+                self.emu.replace_with(ran);
+                self.rewind.push(&self.emu);
+                if let Some(notice) = self.plugins.frame(&mut self.emu) {
+                    self.notify(notice);
+                }
+                pattern = self.emu.audio_pattern().map(|bits| Pattern {
+                    bits: *bits,
+                    rate: self.emu.playback_rate(),
+                });
+                self.frame += 1;
+            }
+        } else {
+            for _ in 0..frames {
+                if !self.start_frame() {
+                    break;
+                }
+                let cycles = self.speed.cycles_in(self.emu.frames());
+                // a debugger's breakpoints can stop the frame part way
+                #[cfg(feature = "dap")]
+                let stopped = match &mut self.dap {
+                    Some(dap) => dap.run_frame(&mut self.emu, cycles),
+                    None => {
+                        self.emu.run_frame(cycles);
+                        false
+                    }
+                };
+                #[cfg(not(feature = "dap"))]
+                self.emu.run_frame(cycles);
+                pattern = self.end_frame();
+                #[cfg(feature = "dap")]
+                if stopped {
+                    self.paused = true;
+                    self.overlays.paused = true;
+                    break;
+                }
+            }
+        }
+
+        if let Some(beeper) = &mut self.beeper {
+            let silenced = self.overlays.muted || self.paused || self.rewinding;
+            beeper.set_pattern(pattern);
+            beeper.set_playing(self.emu.sound_active() && !silenced);
+        }
+        // flash the border along with the buzzer when asked to
+        let beeping = self.config.audio.visual_beep && self.emu.sound_active();
+        self.redraw = beeping != self.overlays.beeping || (self.paused && frames > 0);
+        self.overlays.beeping = beeping;
+
+        let drew = self.emu.draw_flag() || thread_drew;
+        self.redraw |= drew;
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &mut self.metrics {
+            metrics.record(&self.emu, drew);
+        }
+    }
+
+    // Hands the machine the keys for the frame it's about to run, from the
+    // other player, the input script or the movie, and records them.
+    // Returns false when the frame can't run yet: the other player's keys
+    // haven't come, or the movie's over.
+    fn start_frame(&mut self) -> bool {
+        if let Some(connection) = &mut self.netplay {
+            match connection.keys(&self.player_keys) {
+                Ok(Some(keys)) => self.emu.set_keys(&keys),
+                // the frame waits for the other player's keys
+                Ok(None) => return false,
+                Err(e) => {
+                    eprintln!("⚠️ Netplay stopped: {}", e);
+                    self.netplay = None;
+                    self.notify("NETPLAY OVER".to_string());
+                }
+            }
+        }
+        self.rewind.push(&self.emu);
+        if let Some(script) = &mut self.script {
+            let mut keys = self.player_keys;
+            script.apply(self.frame, &mut keys);
+            self.plugins.keys(&mut keys);
+            self.emu.set_keys(&keys);
+        }
+        if let Some(movie) = &self.playback {
+            match movie.keys(self.frame) {
+                Some(keys) => self.emu.set_keys(&keys),
+                None => {
+                    let cycles = self.emu.cycles();
+                    if let Some(recorded) = movie.cycles.filter(|recorded| *recorded != cycles) {
+                        eprintln!(
+                            "⚠️ The movie took {} instructions, {} when it was recorded.",
+                            cycles, recorded
+                        );
+                    }
+                    // the player takes over where the movie ends
+                    self.playback = None;
+                    self.paused = true;
+                    self.overlays.paused = true;
+                    self.notify("MOVIE OVER".to_string());
+                    return false;
+                }
+            }
+        }
+        if let Some(movie) = &mut self.recording {
+            movie.record(self.emu.keys());
+        }
+        true
+    }
+
+    // Everything that follows a frame the machine ran: the other player and
+    // the plugins hear about it, it's recorded and the movie being played is
+    // checked it's still in step. Returns the sound the frame ended with.
+    fn end_frame(&mut self) -> Option<Pattern> {
+        if let Some(connection) = &mut self.netplay {
+            connection.ran(self.emu.checksum());
+        }
+        if let Some(notice) = self.plugins.frame(&mut self.emu) {
+            self.notify(notice);
+        }
+
+        let pattern = self.emu.audio_pattern().map(|bits| Pattern {
+            bits: *bits,
+            rate: self.emu.playback_rate(),
+        });
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.record_frame(self.emu.sound_active(), pattern) {
+                eprintln!("❌ Error recording audio: {}", e);
+                process::exit(1);
+            }
+        }
+
+        let frame = self.frame;
+        if let Some(movie) = &mut self.recording {
+            if (frame + 1).is_multiple_of(CHECKSUM_INTERVAL) {
+                movie.checksums.insert(frame, self.emu.checksum());
+            }
+        }
+        if let Some(movie) = self.playback.as_ref().filter(|_| !self.desynced) {
+            let expected = movie.checksums.get(&frame).copied();
+            if expected.is_some_and(|expected| expected != self.emu.checksum()) {
+                // later frames are bound to differ too, only the first one
+                // is any help
+                eprintln!("⚠️ The movie went out of step on frame {}.", frame);
+                self.desynced = true;
+                self.notify(format!("DESYNC AT {}", frame));
+            }
+        }
+        self.frame += 1;
+        pattern
+    }
+
+    // Keeps the overlays up to date with the machine: a fault or the rom
+    // ending, the counters and the plugins' badges, and takes notices down
+    // once they've been up long enough.
+    fn update_overlays(&mut self) {
+        // a rom that comes to an instruction it doesn't know stops there,
+        // paused so it can be rewound or reset. It's reported on quitting
+        // if it's still stuck
+        if let Some(fault) = self.emu.fault().filter(|_| !self.paused) {
+            self.paused = true;
+            self.overlays.paused = true;
+            self.notify(match fault.kind {
+                FaultKind::BrokenInvariant(_) => {
+                    format!("BROKEN INVARIANT AT {:03X}", fault.address)
+                }
+                _ => format!("UNKNOWN OPCODE {:04X}", fault.opcode),
+            });
+            self.redraw = true;
+        }
+        // a rom that has ended looks no different from one that's hung
+        // without saying so
+        let ended = self.emu.state() == State::Finished;
+        if ended && !self.finished {
+            self.notify("PROGRAM ENDED - PRESS RESET".to_string());
+            self.redraw = true;
+        }
+        self.finished = ended;
+
+        if self.overlays.counters.is_some() {
+            let counters = Some((self.emu.frames(), self.emu.cycles()));
+            self.redraw |= counters != self.overlays.counters;
+            self.overlays.counters = counters;
+        }
+        let badges = self.plugins.badges();
+        self.redraw |= badges != self.overlays.badges;
+        self.overlays.badges = badges;
+
+        let shown_long_enough = Instant::now() >= self.notice_until;
+        if self.resume.is_none() && self.overlays.notice.is_some() && shown_long_enough {
+            self.overlays.notice = None;
+            self.redraw = true;
+        }
+    }
+
+    // Picks up what's changed outside the window: the rom's file with
+    // --watch, the playlist moving on, and requests over --rpc and --dap.
+    fn check_outside(&mut self) {
+        if self.watcher.as_ref().is_some_and(RomWatcher::changed) {
+            // the rom's hash changes with it, and maybe its settings
+            let config = &mut self.config;
+            let reloaded = config.switch_rom(config.rom_path.clone()).and_then(|()| {
+                let mut fresh = Chip8::with_quirks(config.quirks);
+                fresh.load_game(&config.rom_path).map_err(|e| e.to_string())?;
+                Ok(fresh)
+            });
+            match reloaded {
+                Ok(fresh) => {
+                    self.emu.replace_with(fresh);
+                    self.rewind = Rewind::default();
+                    self.plugins.event(&PluginEvent::RomChanged(&self.config), &mut self.emu);
+                    self.notify("RELOADED".to_string());
+                    self.redraw = true;
+                }
+                Err(e) => eprintln!("⚠️ Unable to reload {}: {}", self.config.rom_path, e),
+            }
+        }
+
+        // playlists move on to the next rom every --rotate seconds of play
+        if self.config.rotate.is_some_and(|every| self.frame >= every.as_secs() * 60) {
+            self.switch_to = next_rom(&self.config, &mut self.playlist_index);
+        }
+
+        #[cfg(feature = "rpc")]
+        if let Some(rpc) = &self.rpc {
+            let was_paused = self.paused;
+            if let Some(rom) = rpc.answer(&mut self.emu, &mut self.paused) {
+                if self.recording.is_some() || self.playback.is_some() {
+                    eprintln!("⚠️ The rom can't be changed during a movie.");
+                } else {
+                    self.switch_to = Some(rom);
+                }
+            }
+            if self.paused != was_paused {
+                self.overlays.paused = self.paused;
+                self.redraw = true;
+            }
+        }
+        #[cfg(feature = "dap")]
+        if let Some(dap) = &mut self.dap {
+            let was_paused = self.paused;
+            dap.answer(&mut self.emu, &mut self.paused);
+            if self.paused != was_paused {
+                self.overlays.paused = self.paused;
+                self.redraw = true;
+            }
+        }
+    }
+
+    // Handles one of the window's events, returning whether to quit.
+    fn event(&mut self, event: Event) -> bool {
+        if let Event::KeyDown {
+            keycode: Some(answer @ (Keycode::Y | Keycode::N)),
+            ..
+        } = event
+        {
+            if let Some(state) = self.resume.take() {
+                if answer == Keycode::Y {
+                    self.emu.replace_with(state);
+                }
+                self.paused = false;
+                self.overlays.paused = false;
+                self.overlays.notice = None;
+                self.redraw = true;
+                return false;
+            }
+        }
+
+        // a rom dropped on the window takes over from the running one
+        if let Event::DropFile { filename, .. } = event {
+            if self.recording.is_some() || self.playback.is_some() || self.netplay.is_some() {
+                eprintln!("⚠️ The rom can't be changed during a movie or netplay.");
+                return false;
+            }
+            self.switch_to = Some(filename);
+            return false;
+        }
+
+        // keys bound to the keypad go to the rom, even the hotkeys
+        if let Some((key, pressed)) = self.keymap.event(&event) {
+            // key repeats find the key already held
+            if self.sticky && pressed && self.held_keys[key] == 0 {
+                self.latched[key] ^= 255;
+                self.overlays.latched = self.latched;
+                self.redraw = true;
+            }
+            self.held_keys[key] = if pressed { 255 } else { 0 };
+            return false;
+        }
+
+        if let Some((action, pressed)) = self.hotkeys.event(&event) {
+            return self.hotkey(action, pressed);
+        }
+
+        match event {
+            Event::Quit { .. } => return true,
+            Event::Window {
+                win_event: WindowEvent::Resized(..) | WindowEvent::SizeChanged(..),
+                ..
+            } => {
+                self.geometry = Geometry::new(self.canvas.window().size(), &self.zoom);
+                self.redraw = true;
+            }
+            Event::MouseWheel { y, .. } if self.zoom.enabled => {
+                if y > 0 {
+                    self.zoom.zoom_in();
+                } else if y < 0 {
+                    self.zoom.zoom_out();
+                }
+                self.redraw = true;
+            }
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                which,
+                x,
+                y,
+                ..
+            } if which != TOUCH_MOUSE_ID => {
+                let (w, h) = self.canvas.window().size();
+                if self.sticky {
+                    if let Some(key) = self.overlays.keypad.key_at(w, h, x, y) {
+                        self.latched[key as usize] ^= 255;
+                        self.overlays.latched = self.latched;
+                        self.redraw = true;
+                    }
+                } else {
+                    self.redraw |= self.overlays.keypad.press(w, h, x, y);
+                }
+            }
+            Event::MouseButtonUp {
+                mouse_btn: MouseButton::Left,
+                which,
+                ..
+            } if which != TOUCH_MOUSE_ID => self.redraw |= self.overlays.keypad.release(),
+            Event::FingerDown {
+                finger_id, x, y, ..
+            }
+            | Event::FingerMotion {
+                finger_id, x, y, ..
+            } => {
+                // there's no keyboard to speak of on a touch screen
+                if !self.overlays.keypad.visible {
+                    self.overlays.keypad.toggle();
+                    self.redraw = true;
+                }
+                let (w, h) = self.canvas.window().size();
+                self.redraw |= self.overlays.keypad.touch(w, h, finger_id, x, y);
+            }
+            Event::FingerUp { finger_id, .. } => {
+                self.redraw |= self.overlays.keypad.lift(finger_id)
+            }
+            Event::ControllerAxisMotion { axis, value, .. } => self.stick.set_axis(axis, value),
+            Event::ControllerDeviceAdded { which, .. } => {
+                if let Some(controllers) = &mut self.controllers {
+                    controllers.add(which);
+                }
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                let ours = self
+                    .controllers
+                    .as_mut()
+                    .is_some_and(|controllers| controllers.remove(which));
+                if ours {
+                    // back to the keyboard, and give the player a chance to
+                    // plug back in
+                    self.stick.center();
+                    let pause = self.config.controller.pause_on_disconnect;
+                    if pause && !self.paused && self.netplay.is_none() {
+                        self.paused = true;
+                        self.overlays.paused = true;
+                        self.redraw = true;
+                    }
+                }
+            }
+            // we won't hear about keys released while the window is in the
+            // background
+            Event::Window {
+                win_event: WindowEvent::FocusLost,
+                ..
+            } => {
+                self.held_keys = [0; 16];
+                self.fast_forward = false;
+                self.overlays.fast_forward = false;
+            }
+            _ => {}
+        }
+        false
+    }
+
+    // Does what a hotkey's pressed or released for, returning whether to
+    // quit.
+    fn hotkey(&mut self, action: Action, pressed: bool) -> bool {
+        // movies only play back the same if the run goes straight through,
+        // and netplay only stays in step that way
+        let movie = self.recording.is_some() || self.playback.is_some();
+        let netplay = self.netplay.is_some();
+        let straight_through = matches!(
+            action,
+            Action::Rewind
+                | Action::LoadState
+                | Action::SoftReset
+                | Action::HardReset
+                | Action::Slower
+                | Action::Faster
+        );
+        // and the other player would be left waiting
+        let stops = matches!(action, Action::Pause | Action::FrameAdvance);
+        if (movie || netplay) && straight_through || netplay && stops {
+            return false;
+        }
+        // fast-forward and rewind last while held
+        if action == Action::FastForward {
+            self.fast_forward = pressed;
+            self.overlays.fast_forward = pressed;
+            self.redraw = true;
+        }
+        if action == Action::Rewind {
+            self.rewinding = pressed;
+            self.overlays.rewinding = pressed;
+            self.redraw = true;
+        }
+        if !pressed {
+            return false;
+        }
+
+        match action {
+            Action::Quit => return true,
+            Action::NextPalette => {
+                next_palette(&mut self.color_palette);
+                remember_palette(&self.config, self.color_palette);
+                self.redraw = true;
+            }
+            Action::ToggleKeypad => {
+                self.overlays.keypad.toggle();
+                self.redraw = true;
+            }
+            Action::ToggleKeyDisplay => {
+                self.overlays.show_keys = !self.overlays.show_keys;
+                self.redraw = true;
+            }
+            Action::Mute => {
+                self.overlays.muted = !self.overlays.muted;
+                remember_muted(&self.config, self.overlays.muted);
+                self.redraw = true;
+            }
+            Action::PrintScreen => {
+                print!("{}", framebuffer_to_ansi(&self.emu.pixels(), self.color_palette))
+            }
+            Action::ToggleZoom => {
+                self.zoom.toggle();
+                self.redraw = true;
+            }
+            Action::ZoomIn | Action::ZoomOut if self.zoom.enabled => {
+                if action == Action::ZoomIn {
+                    self.zoom.zoom_in();
+                } else {
+                    self.zoom.zoom_out();
+                }
+                self.redraw = true;
+            }
+            Action::PanLeft | Action::PanRight | Action::PanUp | Action::PanDown
+                if self.zoom.enabled =>
+            {
+                match action {
+                    Action::PanLeft => self.zoom.pan(-1, 0),
+                    Action::PanRight => self.zoom.pan(1, 0),
+                    Action::PanUp => self.zoom.pan(0, -1),
+                    _ => self.zoom.pan(0, 1),
+                }
+                self.redraw = true;
+            }
+            // otherwise left and right scrub through the last few seconds
+            // while paused, and play resumes from there
+            Action::PanLeft | Action::PanRight if self.paused && !movie => {
+                if action == Action::PanLeft {
+                    self.rewind.scrub_back(&mut self.emu);
+                } else {
+                    self.rewind.scrub_forward(&mut self.emu);
+                }
+                let back = self.rewind.frames_back() as f32 / 60.0;
+                self.notify(format!("-{:.2} S", back));
+                self.redraw = true;
+            }
+            Action::Pause => {
+                self.paused = !self.paused;
+                self.overlays.paused = self.paused;
+                self.redraw = true;
+            }
+            Action::FrameAdvance if self.paused => self.advance = true,
+            Action::Slower | Action::Faster => {
+                if action == Action::Faster {
+                    self.speed.faster();
+                } else {
+                    self.speed.slower();
+                }
+                let hz = self.speed.hz();
+                remember_setting(&self.config, "speed", |settings| settings.speed = Some(hz));
+                self.notify(format!("{} HZ", hz));
+                self.redraw = true;
+            }
+            Action::Counters => {
+                self.overlays.counters = match self.overlays.counters {
+                    Some(_) => None,
+                    None => Some((self.emu.frames(), self.emu.cycles())),
+                };
+                self.redraw = true;
+            }
+            Action::Turbo => {
+                self.turbo = !self.turbo;
+                self.overlays.turbo = self.turbo;
+                self.redraw = true;
+            }
+            Action::SoftReset => {
+                self.emu.reset();
+                self.plugins.event(&PluginEvent::Reset, &mut self.emu);
+            }
+            Action::HardReset => {
+                // start again from the rom on disk, in case it changed
+                let mut fresh = Chip8::with_quirks(self.config.quirks);
+                match fresh.load_game(&self.config.rom_path) {
+                    Ok(()) => {
+                        self.emu.replace_with(fresh);
+                        self.plugins.event(&PluginEvent::Reset, &mut self.emu);
+                        self.redraw = true;
+                    }
+                    Err(e) => eprintln!("⚠️ Unable to reload {}: {}", self.config.rom_path, e),
+                }
+            }
+            Action::SaveState => self.save_state(),
+            Action::LoadState => self.load_state(),
+            Action::PreviousSlot | Action::NextSlot => {
+                self.slot = if action == Action::NextSlot {
+                    (self.slot + 1) % savestate::SLOTS
+                } else {
+                    (self.slot + savestate::SLOTS - 1) % savestate::SLOTS
+                };
+                self.notify(format!("SLOT {}", self.slot));
+                self.redraw = true;
+            }
+            Action::NextRom => {
+                self.switch_to = next_rom(&self.config, &mut self.playlist_index)
+            }
+            Action::SaveInputProfile => {
+                let notice = match remember_input(&self.config) {
+                    Ok(()) => "KEYS SAVED",
+                    Err(e) => {
+                        eprintln!("⚠️ Unable to save the input profile: {}", e);
+                        "KEYS NOT SAVED"
+                    }
+                };
+                self.notify(notice.to_string());
+                self.redraw = true;
+            }
+            _ => {}
+        }
+        false
+    }
+
+    // Saves the machine to the save-state slot.
+    fn save_state(&mut self) {
+        let (slot, rom_hash) = (self.slot, self.config.rom_hash.as_deref());
+        let path = savestate::state_path(&self.config.rom_path, slot);
+        let notice = match savestate::save(&path, &self.emu, rom_hash) {
+            Ok(()) => format!("SAVED SLOT {}", slot),
+            Err(e) => {
+                eprintln!("⚠️ Unable to save {}: {}", path.display(), e);
+                format!("SLOT {} NOT SAVED", slot)
+            }
+        };
+        self.notify(notice);
+        self.redraw = true;
+    }
+
+    // Loads the machine from the save-state slot.
+    fn load_state(&mut self) {
+        let (slot, rom_hash) = (self.slot, self.config.rom_hash.as_deref());
+        let path = savestate::state_path(&self.config.rom_path, slot);
+        let notice = match savestate::load(&path, rom_hash, self.config.quirks) {
+            Ok(state) => {
+                self.emu.replace_with(state);
+                format!("LOADED SLOT {}", slot)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => format!("SLOT {} EMPTY", slot),
+            Err(e) => {
+                eprintln!("⚠️ Unable to load {}: {}", path.display(), e);
+                format!("SLOT {} NOT LOADED", slot)
+            }
+        };
+        self.notify(notice);
+        self.redraw = true;
+    }
+
+    // Takes over from the running rom with `rom_path`, saving the one being
+    // left as if it was quit.
+    fn switch_rom(&mut self, rom_path: String) {
+        if let (Some(path), None) = (&self.autosave, &self.resume) {
+            if self.config.autosave.enabled {
+                if let Err(e) = savestate::save(path, &self.emu, self.config.rom_hash.as_deref()) {
+                    eprintln!("⚠️ Unable to save {}: {}", path.display(), e);
+                }
+            }
+        }
+        let config = &mut self.config;
+        let switched = config.switch_rom(rom_path).and_then(|()| {
+            let mut fresh = Chip8::with_quirks(config.quirks);
+            fresh
+                .load_game(&config.rom_path)
+                .map_err(|e| format!("Unable to load {}: {}", config.rom_path, e))?;
+            Ok(fresh)
+        });
+        let fresh = match switched {
+            Ok(fresh) => fresh,
+            Err(e) => {
+                eprintln!("⚠️ {}", e);
+                return;
+            }
+        };
+        if let Err(e) = library::add_recent(Path::new(&self.config.rom_path)) {
+            eprintln!("⚠️ Unable to remember the rom: {}", e);
+        }
+
+        self.emu.replace_with(fresh);
+        let _result = self.canvas.window_mut().set_title(&window_title(&self.config));
+        self.watcher = watch(&self.config);
+        self.rewind = Rewind::default();
+        self.frame = 0;
+        self.plugins.event(&PluginEvent::RomChanged(&self.config), &mut self.emu);
+        self.autosave = autosave_path(&self.config);
+        self.resume = None;
+        let resume = self
+            .autosave
+            .as_ref()
+            .filter(|_| self.config.autosave.resume != Resume::Never)
+            .and_then(|path| load_autosave(&self.config, path));
+        match resume {
+            Some(state) if self.config.autosave.resume == Resume::Always => {
+                self.emu.replace_with(state)
+            }
+            Some(state) => {
+                self.resume = Some(state);
+                self.paused = true;
+                self.overlays.paused = true;
+                self.overlays.notice = Some("RESUME? Y/N".to_string());
+            }
+            None => {
+                let name = Path::new(&self.config.rom_path).file_name().unwrap_or_default();
+                let name = name.to_string_lossy().into_owned();
+                self.notify(name);
+            }
+        }
+        self.redraw = true;
+    }
+
+    // Gathers the keys held everywhere they can be pressed and hands them to
+    // the machine, or to the thread running it.
+    fn set_keys(&mut self) {
+        let mut keys = if self.sticky { self.latched } else { self.held_keys };
+        self.stick.apply(&mut keys);
+        self.sources.retain_mut(|source| match source.poll(&mut keys) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("⚠️ Stopped reading a keypad: {}", e);
+                false
+            }
+        });
+        self.overlays.keypad.apply(&mut keys);
+        self.player_keys = keys;
+        if let Some(script) = &mut self.script {
+            script.apply(self.frame, &mut keys);
+        }
+        // the rom only sees keys that are still held when it's unpaused or
+        // stepped a frame
+        if !self.paused || self.advance {
+            self.plugins.keys(&mut keys);
+            self.emu.set_keys(&keys);
+        }
+        if let Some(emulation) = &mut self.emulation {
+            // whatever the window did to its copy, the thread carries on from
+            emulation.sync(&self.emu);
+            emulation.set_keys(self.emu.keys());
+            emulation.set_pace(Pace {
+                paused: self.paused || self.rewinding,
+                frames: if self.fast_forward { FAST_FORWARD_FRAMES } else { 1 },
+                speed: self.speed,
+                uncapped: self.turbo,
+            });
+            if std::mem::take(&mut self.advance) {
+                emulation.advance();
+            }
+        }
+
+        // keep the key display in step with the keys as they change
+        if self.overlays.show_keys && keys != self.last_keys {
+            self.redraw = true;
+        }
+        self.last_keys = keys;
+    }
+
+    fn draw(&mut self) {
+        // the zoom only changes along with a redraw
+        let zoom = (self.zoom.enabled, self.zoom.region());
+        if zoom != (self.geometry.zoomed, self.geometry.region) {
+            self.geometry = Geometry::new(self.canvas.window().size(), &self.zoom);
+        }
+        render(
+            &self.emu,
+            &mut self.canvas,
+            self.color_palette,
+            &self.overlays,
+            &self.plugins,
+            &self.last_keys,
+            &self.geometry,
+        );
+    }
+
+    // Stops the thread and finishes the recordings, saving the machine for
+    // next time, and reports a fault it was left stuck on.
+    fn finish(mut self) -> Result<(), Failure> {
+        // the machine as the thread left it, with anything the window did last
+        if let Some(mut emulation) = self.emulation.take() {
+            emulation.sync(&self.emu);
+            self.emu = emulation.stop();
+        }
+
+        if let Some(recorder) = self.recorder.take() {
+            if let Err(e) = recorder.finish() {
+                eprintln!("❌ Error finishing audio recording: {}", e);
+            }
+        }
+
+        if let (Some(movie), Some(path)) = (&mut self.recording, &self.config.record_movie) {
+            movie.cycles = Some(self.emu.cycles());
+            if let Err(e) = movie.save(path) {
+                eprintln!("❌ {}", e);
+            }
+        }
+
+        // an unanswered offer keeps the save it offered for next time, and a
+        // rom that stopped isn't saved stopped
+        if let (Some(path), None, None) = (&self.autosave, &self.resume, self.emu.fault()) {
+            if self.config.autosave.enabled {
+                if let Err(e) = savestate::save(path, &self.emu, self.config.rom_hash.as_deref()) {
+                    eprintln!("⚠️ Unable to save {}: {}", path.display(), e);
+                }
+            }
+        }
+
+        match self.emu.fault() {
+            Some(fault) => Err(Failure::Fault(fault)),
+            None => Ok(()),
+        }
+    }
+}
+
+// Opens the window, cleared to the palette's background.
+fn open_window(sdl_context: &Sdl, config: &Config, palette: &Palette) -> Canvas<Window> {
+    let video_subsystem = sdl_context.video().unwrap();
+
+    let window = video_subsystem
+        .window(&window_title(config), config.display.width, config.display.height)
+        .resizable()
+        .position_centered()
+        .build()
+        .unwrap();
+    let mut canvas = window.into_canvas().build().unwrap();
+
+    // initially clear the screen
+    canvas.set_draw_color(palette.background);
+    canvas.clear();
+    canvas.present();
+    canvas
+}
+
+// The movie to record with --record-movie and the one to play with
+// --play-movie. A movie records or plays back the keypad frame by frame, on
+// a machine with the same quirks and random numbers every time.
+fn movies(config: &Config) -> (Option<Movie>, Option<Movie>) {
+    let recording = config.record_movie.as_ref().map(|_| {
+        Movie::new(config.rom_hash.clone(), rand::random(), config.quirks, config.speed)
+    });
+    let playback = config.play_movie.as_ref().map(|path| {
+        let movie = Movie::load(path).unwrap_or_else(|err| {
+            eprintln!("❌ Problem with the movie: {}", err);
+            process::exit(1);
+        });
+        check_movie(config, path, &movie);
+        movie
+    });
+    (recording, playback)
+}
+
+// The keymap and hotkeys, warning about any hotkeys the keymap has the keys
+// of.
+fn bindings(config: &Config) -> (Keymap, Hotkeys) {
+    let keymap = Keymap::from_config(&config.keymap).unwrap_or_else(|err| {
+        eprintln!("❌ Problem with the keymap: {}", err);
+        process::exit(1);
+    });
+    let hotkeys = Hotkeys::from_config(&config.hotkeys, config.keymap.preset).unwrap_or_else(|err| {
+        eprintln!("❌ Problem with the hotkeys: {}", err);
+        process::exit(1);
+    });
+    let taken = hotkeys.taken_by(&keymap);
+    if !taken.is_empty() {
+        eprintln!(
+            "⚠️ The keymap has the keys of these hotkeys, rebind them in [hotkeys]: {}",
+            taken.join(", ")
+        );
+    }
+    (keymap, hotkeys)
+}
+
+// Waits for the other player with --netplay-host, or joins them with
+// --netplay-join, returning the connection and what to run the rom with.
+fn start_netplay(
+    config: &Config,
+    settings: &NetplaySettings,
+    emu: &Chip8,
+) -> Result<(Netplay, Session), Failure> {
+    let connected = match &settings.peer {
+        Peer::Host(address) => {
+            let session = Session {
+                seed: rand::random(),
+                quirks: config.quirks,
+                speed: config.speed,
+                delay: settings.delay,
+            };
+            println!("Waiting for the other player on {}, Ctrl-C stops.", address);
+            Netplay::host(address, session, emu.checksum()).map(|netplay| (netplay, session))
+        }
+        Peer::Join(address) => {
+            println!("Joining {}.", address);
+            Netplay::join(address, emu.checksum())
+        }
+    };
+    connected.map_err(|e| Failure::from(format!("Unable to start netplay: {}", e)))
+}
+
+// The rom's title and authors when the chip-8-database knows them.
+fn window_title(config: &Config) -> String {
+    match &config.metadata {
+        Some(metadata) => format!("{} - chip-8-emu", metadata.credits()),
+        None => "chip-8-emu".to_string(),
+    }
+}
+
+// Starts watching the rom's file if --watch was given.
+fn watch(config: &Config) -> Option<RomWatcher> {
+    if !config.watch {
+        return None;
+    }
+    RomWatcher::new(&config.rom_path)
+        .map_err(|e| eprintln!("⚠️ {}", e))
+        .ok()
+}
+
+// Steps on to the next rom in the playlist, back to the first after the last.
+fn next_rom(config: &Config, index: &mut usize) -> Option<String> {
+    if config.playlist.is_empty() {
+        return None;
+    }
+    *index = (*index + 1) % config.playlist.len();
+    Some(config.playlist[*index].clone())
+}
+
+// Where the rom is saved on quitting. Roms in a playlist aren't, they start
+// afresh each time around.
+fn autosave_path(config: &Config) -> Option<PathBuf> {
+    if !config.playlist.is_empty() {
+        return None;
+    }
+    config.rom_hash.as_deref().and_then(savestate::autosave_path)
+}
+
+// Loads the machine saved when the rom was last quit, if there is one.
+fn load_autosave(config: &Config, path: &Path) -> Option<Chip8> {
+    match savestate::load(path, config.rom_hash.as_deref(), config.quirks) {
+        Ok(state) => Some(state),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            eprintln!("⚠️ Unable to resume from {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+// Saves whether sound is muted for the running rom, so roms that abuse the
+// buzzer stay quiet next time.
+fn remember_muted(config: &Config, muted: bool) {
+    remember_setting(config, "mute setting", |settings| settings.audio.muted = Some(muted));
+}
+
+// Saves the palette picked for the running rom.
+fn remember_palette(config: &Config, palette: &Palette) {
+    let index = PALETTES.iter().position(|p| p == palette).unwrap_or(0);
+    remember_setting(config, "palette", |settings| {
+        settings.palette = Some(PaletteName::ALL[index])
+    });
+}
+
+// Changes the running rom's entry in the rom database, warning if that
+// doesn't work since the game can carry on without it.
+fn remember_setting(config: &Config, setting: &str, change: impl FnOnce(&mut RomSettings)) {
+    if let Err(e) = remember(config, change) {
+        eprintln!("⚠️ Unable to remember {}: {}", setting, e);
+    }
+}
+
+// Saves the key and controller mapping as the running rom's input profile,
+// used from then on whatever the config file says.
+fn remember_input(config: &Config) -> Result<(), String> {
+    remember(config, |settings| {
+        settings.keymap = Some(config.keymap.clone());
+        settings.controller = Some(config.controller);
+    })
+}
+
+// Changes the running rom's entry in the rom database.
+fn remember(config: &Config, change: impl FnOnce(&mut RomSettings)) -> Result<(), String> {
+    let hash = match &config.rom_hash {
+        Some(hash) => hash,
+        None => return Err("The rom couldn't be identified.".to_string()),
+    };
+
+    let mut database = RomDatabase::load()?;
+    let settings = database.entry(hash);
+    settings.name = Path::new(&config.rom_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned());
+    change(settings);
+    database.save()
+}
+
+// Where the display goes in the window: how big its pixels are and the
+// gutters above and below it. Only worked out again when the window's size
+// or the zoom changes, not every frame.
+#[derive(Clone, Copy)]
+struct Geometry {
+    width: u32,
+    height: u32,
+    // the part of the display shown, as Zoom::region gives it
+    region: (u8, u8, u8, u8),
+    zoomed: bool,
+    pixel_size: u32,
+    gutter: i32,
+}
+
+impl Geometry {
+    fn new((width, height): (u32, u32), zoom: &Zoom) -> Geometry {
+        let region = zoom.region();
+        let pixel_size = width / region.2 as u32;
+        let gutter = (height as i32 - (pixel_size as i32 * region.3 as i32)) / 2;
+        Geometry {
+            width,
+            height,
+            region,
+            zoomed: zoom.enabled,
+            pixel_size,
+            gutter,
+        }
+    }
+}
+
+// Draws the current gfx buffer onto the Canvas. 
+// 
+// I'm not crazy about this abstraction...
+fn render(
+    emu: &Chip8,
+    canvas: &mut Canvas<Window>,
+    draw_color: &Palette,
+    overlays: &Overlays,
+    plugins: &Plugins,
+    keys: &[u8; 16],
+    geometry: &Geometry,
+) {
+    let Geometry {
+        width: screen_width,
+        height: screen_height,
+        region: (region_x, region_y, region_width, region_height),
+        zoomed,
+        pixel_size,
+        gutter,
+    } = *geometry;
+
+    // the background all over, which the display mostly covers, then the
+    // gutters above and below it. The whole window's drawn every time, even
+    // when only the lit pixels changed: SDL leaves what's behind the canvas
+    // undefined once it's presented, so last frame's background can't be
+    // counted on to still be there
+    canvas.set_draw_color(draw_color.background);
+    canvas.clear();
+    if gutter > 0 {
+        let bottom = screen_height as i32 - gutter;
+        canvas.set_draw_color(draw_color.gutter);
+        let _result = canvas.fill_rects(&[
+            Rect::new(0, 0, screen_width, gutter as u32),
+            Rect::new(0, bottom, screen_width, gutter as u32),
+        ]);
+    }
+
+    // loop through the pixel array, gathering the lit pixels to draw all at
+    // once in the one color
+    let mut lit = Vec::with_capacity(region_width as usize * region_height as usize);
+    for x in 0..region_width {
+        for y in 0..region_height {
+            // Only draw the pixel if its on
+            if emu.pixel((region_x + x) as usize, (region_y + y) as usize) {
+                // get the x and y coordinate in screen space
+                let screen_x: i32 = x as i32 * pixel_size as i32;
+                let screen_y: i32 = (y as i32 * pixel_size as i32) + gutter;
+
+                lit.push(Rect::new(screen_x, screen_y, pixel_size, pixel_size));
+            }
+        }
+    }
+    canvas.set_draw_color(draw_color.foreground);
+    let _result = canvas.fill_rects(&lit);
+
+    if zoomed {
+        draw_zoom_grid(canvas, draw_color, geometry);
+    }
+
+    plugins.draw(canvas, draw_color);
+    overlays.draw(canvas, draw_color, keys);
+    canvas.present();
+}
+
+// Outlines every emulator pixel while zoomed in and labels which part of
+// the display is visible, so single pixel overlaps are easy to pick out.
+fn draw_zoom_grid(canvas: &mut Canvas<Window>, draw_color: &Palette, geometry: &Geometry) {
+    let (region_x, region_y, region_width, region_height) = geometry.region;
+    let (pixel_size, gutter) = (geometry.pixel_size, geometry.gutter);
+    let grid_width = pixel_size * region_width as u32;
+    let grid_height = pixel_size * region_height as u32;
+
+    canvas.set_draw_color(draw_color.gutter);
+    for x in 0..=region_width as i32 {
+        let screen_x = x * pixel_size as i32;
+        let _result = canvas.draw_line((screen_x, gutter), (screen_x, gutter + grid_height as i32));
+    }
+    for y in 0..=region_height as i32 {
+        let screen_y = gutter + y * pixel_size as i32;
+        let _result = canvas.draw_line((0, screen_y), (grid_width as i32, screen_y));
+    }
+
+    let label = format!(
+        "X {}-{} Y {}-{}",
+        region_x,
+        region_x + region_width - 1,
+        region_y,
+        region_y + region_height - 1
+    );
+    let scale = (pixel_size / 8).max(2);
+    draw_text(canvas, &label, 4, gutter + 4, scale, draw_color.gutter);
+}
+
+// Cycles to the palette after `curr_palette` in PALETTES.
+fn next_palette(curr_palette: &mut &Palette) {
+    let index = PALETTES
+        .iter()
+        .position(|palette| palette == *curr_palette)
+        .unwrap_or(0);
+    *curr_palette = &PALETTES[(index + 1) % PALETTES.len()];
+}
@@ -0,0 +1,315 @@
+// The plain window again, drawn with winit and softbuffer instead of SDL,
+// for builds with the winit feature and without SDL, so the emulator builds
+// on machines without SDL's development libraries.
+//
+// It plays a rom with the keypad on the same keys as the SDL window, Space
+// to pause and Esc to quit, and beeps when built with the cpal feature.
+// Hotkeys, overlays, movies and save states need SDL.
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use softbuffer::{Context, Surface};
+use winit::application::ApplicationHandler;
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, KeyEvent, WindowEvent};
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
+use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::window::{Window, WindowId};
+
+use chip8::cpu::{Chip8, State};
+use chip8::library;
+use chip8::report::Failure;
+#[cfg(feature = "cpal")]
+use chip8::sound::{cpal_backend::CpalBackend, AudioBackend};
+use chip8::speed::{Speed, MAX_CATCH_UP};
+use chip8::{keypad_key_for_code, Color, Config, Palette, PALETTES};
+
+use crate::{EMULATOR_HEIGHT, EMULATOR_WIDTH};
+
+const FRAME: Duration = Duration::from_micros(16_667);
+
+// The window and what it's drawn on, made once the event loop starts.
+struct Screen {
+    window: Rc<Window>,
+    surface: Surface<Rc<Window>, Rc<Window>>,
+}
+
+struct App {
+    config: Config,
+    emu: Chip8,
+    screen: Option<Screen>,
+    keys: [u8; 16],
+    speed: Speed,
+    paused: bool,
+    next_frame: Instant,
+    // why the window couldn't be drawn on, if it couldn't
+    error: Option<String>,
+    #[cfg(feature = "cpal")]
+    beeper: Option<CpalBackend>,
+}
+
+pub fn play(config: Config) -> Result<(), Failure> {
+    let mut emu = Chip8::with_quirks(config.quirks);
+    if config.check_invariants {
+        emu.check_invariants();
+    }
+    if let Err(e) = emu.load_game(&config.rom_path) {
+        let error = format!("Unable to load {}: {}", config.rom_path, e);
+        return Err(Failure::Load(error));
+    }
+    // for the launcher and --recent
+    if let Err(e) = library::add_recent(Path::new(&config.rom_path)) {
+        eprintln!("⚠️ Unable to remember the rom: {}", e);
+    }
+
+    #[cfg(feature = "cpal")]
+    let beeper = CpalBackend::new(config.audio.tone, config.audio.buffer_size())
+        .map_err(|e| eprintln!("⚠️ Unable to open audio device, sound is disabled: {}", e))
+        .ok();
+    let mut app = App {
+        speed: Speed::from_hz(config.speed),
+        paused: config.start_paused,
+        config,
+        emu,
+        screen: None,
+        keys: [0; 16],
+        next_frame: Instant::now(),
+        error: None,
+        #[cfg(feature = "cpal")]
+        beeper,
+    };
+
+    let event_loop = EventLoop::new().map_err(window_error)?;
+    event_loop.run_app(&mut app).map_err(window_error)?;
+
+    if let Some(error) = app.error {
+        return Err(Failure::from(error));
+    }
+    match app.emu.fault() {
+        Some(fault) => Err(Failure::Fault(fault)),
+        None => Ok(()),
+    }
+}
+
+impl App {
+    fn open(&self, event_loop: &ActiveEventLoop) -> Result<Screen, String> {
+        let size = LogicalSize::new(self.config.display.width, self.config.display.height);
+        let attributes = Window::default_attributes()
+            .with_title(self.title())
+            .with_inner_size(size);
+        let window = Rc::new(
+            event_loop
+                .create_window(attributes)
+                .map_err(|e| e.to_string())?,
+        );
+        let context = Context::new(window.clone()).map_err(|e| e.to_string())?;
+        let surface = Surface::new(&context, window.clone()).map_err(|e| e.to_string())?;
+        Ok(Screen { window, surface })
+    }
+
+    fn title(&self) -> String {
+        let rom = match &self.config.metadata {
+            Some(metadata) => metadata.title.clone(),
+            None => Path::new(&self.config.rom_path)
+                .file_name()
+                .map_or(self.config.rom_path.clone(), |name| {
+                    name.to_string_lossy().into_owned()
+                }),
+        };
+        match (self.emu.state(), self.paused) {
+            (State::Stopped(fault), _) => format!("CHIP-8 - {} - stopped: {}", rom, fault),
+            (_, true) => format!("CHIP-8 - {} - paused", rom),
+            (State::Finished, false) => format!("CHIP-8 - {} - ended", rom),
+            (_, false) => format!("CHIP-8 - {}", rom),
+        }
+    }
+
+    fn key(&mut self, event: KeyEvent, event_loop: &ActiveEventLoop) {
+        let PhysicalKey::Code(code) = event.physical_key else {
+            return;
+        };
+        let pressed = event.state == ElementState::Pressed;
+        match code {
+            KeyCode::Escape => event_loop.exit(),
+            KeyCode::Space if pressed && !event.repeat => {
+                self.paused = !self.paused;
+                if let Some(screen) = &self.screen {
+                    screen.window.set_title(&self.title());
+                }
+            }
+            _ => {
+                // winit's key codes are named after KeyboardEvent.code's
+                if let Some(key) = keypad_key_for_code(&format!("{:?}", code)) {
+                    self.keys[usize::from(key)] = if pressed { 255 } else { 0 };
+                }
+            }
+        }
+    }
+
+    // Runs the frames that are due, returning whether the display changed.
+    fn run(&mut self) -> bool {
+        let now = Instant::now();
+        if now.saturating_duration_since(self.next_frame) > FRAME * MAX_CATCH_UP {
+            self.next_frame = now;
+        }
+        let mut changed = false;
+        let state = self.emu.state();
+        while self.next_frame <= now {
+            self.next_frame += FRAME;
+            if self.paused || self.emu.fault().is_some() {
+                continue;
+            }
+            self.emu.set_keys(&self.keys);
+            self.emu.run_frame(self.speed.cycles_in(self.emu.frames()));
+            changed |= self.emu.draw_flag();
+        }
+        // an unknown instruction pauses the rom, saying so in the title, as
+        // does the rom ending
+        if self.emu.state() != state {
+            if let Some(screen) = &self.screen {
+                screen.window.set_title(&self.title());
+            }
+        }
+
+        #[cfg(feature = "cpal")]
+        if let Some(beeper) = &mut self.beeper {
+            let muted = self.config.audio.muted;
+            beeper.set_playing(self.emu.sound_active() && !self.paused && !muted);
+        }
+        changed
+    }
+
+    fn draw(&mut self) -> Result<(), String> {
+        let Some(screen) = &mut self.screen else {
+            return Ok(());
+        };
+        let size = screen.window.inner_size();
+        let (Some(width), Some(height)) =
+            (NonZeroU32::new(size.width), NonZeroU32::new(size.height))
+        else {
+            // minimised
+            return Ok(());
+        };
+        screen
+            .surface
+            .resize(width, height)
+            .map_err(|e| e.to_string())?;
+        let mut buffer = screen.surface.buffer_mut().map_err(|e| e.to_string())?;
+        let palette = &PALETTES[self.config.display.palette.index()];
+        scale(
+            &self.emu.pixels(),
+            palette,
+            &mut buffer,
+            size.width as usize,
+            size.height as usize,
+        );
+        buffer.present().map_err(|e| e.to_string())
+    }
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.screen.is_some() {
+            return;
+        }
+        match self.open(event_loop) {
+            Ok(screen) => self.screen = Some(screen),
+            Err(e) => {
+                self.error = Some(format!("Unable to open the window: {}", e));
+                event_loop.exit();
+            }
+        }
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
+        match event {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::KeyboardInput { event, .. } => self.key(event, event_loop),
+            WindowEvent::Resized(_) => {
+                if let Some(screen) = &self.screen {
+                    screen.window.request_redraw();
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                if let Err(e) = self.draw() {
+                    self.error = Some(format!("Unable to draw the window: {}", e));
+                    event_loop.exit();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if self.run() {
+            if let Some(screen) = &self.screen {
+                screen.window.request_redraw();
+            }
+        }
+        event_loop.set_control_flow(ControlFlow::WaitUntil(self.next_frame));
+    }
+}
+
+// Draws the display as big as it fits in a `width` by `height` buffer of
+// 0RGB pixels, keeping the pixels square, with the gutter color around it.
+fn scale(gfx: &[[u8; 64]; 32], palette: &Palette, out: &mut [u32], width: usize, height: usize) {
+    let rgb =
+        |color: Color| u32::from(color.r) << 16 | u32::from(color.g) << 8 | u32::from(color.b);
+    let (on, off, gutter) = (
+        rgb(palette.foreground),
+        rgb(palette.background),
+        rgb(palette.gutter),
+    );
+
+    let (columns, rows) = (EMULATOR_WIDTH as usize, EMULATOR_HEIGHT as usize);
+    let pixel = (width / columns).min(height / rows).max(1);
+    let (left, top) = (
+        width.saturating_sub(columns * pixel) / 2,
+        height.saturating_sub(rows * pixel) / 2,
+    );
+    for (y, line) in out.chunks_exact_mut(width).enumerate() {
+        let row = y
+            .checked_sub(top)
+            .map(|y| y / pixel)
+            .and_then(|row| gfx.get(row));
+        for (x, out) in line.iter_mut().enumerate() {
+            let lit = row.and_then(|row| x.checked_sub(left).and_then(|x| row.get(x / pixel)));
+            *out = match lit {
+                Some(&lit) if lit != 0 => on,
+                Some(_) => off,
+                None => gutter,
+            };
+        }
+    }
+}
+
+fn window_error(error: impl ToString) -> Failure {
+    Failure::from(format!("Unable to open the window: {}", error.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::DEFAULT_PALETTE;
+
+    #[test]
+    fn display_is_centred_in_the_gutter() {
+        let mut gfx = [[0u8; 64]; 32];
+        gfx[0][0] = 1;
+        // room for pixels 2 wide with 2 columns and a row of gutter
+        let (width, height) = (132, 66);
+        let mut out = vec![0; width * height];
+        scale(&gfx, &DEFAULT_PALETTE, &mut out, width, height);
+
+        let at = |x: usize, y: usize| out[y * width + x];
+        let rgb =
+            |color: Color| u32::from(color.r) << 16 | u32::from(color.g) << 8 | u32::from(color.b);
+        assert_eq!(at(0, 0), rgb(DEFAULT_PALETTE.gutter));
+        assert_eq!(at(2, 1), rgb(DEFAULT_PALETTE.foreground));
+        assert_eq!(at(3, 2), rgb(DEFAULT_PALETTE.foreground));
+        assert_eq!(at(4, 1), rgb(DEFAULT_PALETTE.background));
+        assert_eq!(at(131, 65), rgb(DEFAULT_PALETTE.gutter));
+    }
+}
@@ -0,0 +1,79 @@
+// Debug zoom: magnifies part of the 64x32 display so single pixels
+// (and the collisions between them) are easy to see on a big monitor.
+
+use crate::{EMULATOR_HEIGHT, EMULATOR_WIDTH};
+
+// The most the display can be magnified.
+const MAX_LEVEL: u8 = 8;
+
+pub struct Zoom {
+    pub enabled: bool,
+    // magnification, always a power of two so the region divides the display
+    level: u8,
+    // top left corner of the visible region in emulator pixels
+    x: u8,
+    y: u8,
+}
+
+impl Default for Zoom {
+    fn default() -> Self {
+        Zoom {
+            enabled: false,
+            level: 2,
+            x: 0,
+            y: 0,
+        }
+    }
+}
+
+impl Zoom {
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    // The visible part of the display as (x, y, width, height) in emulator
+    // pixels.
+    pub fn region(&self) -> (u8, u8, u8, u8) {
+        if !self.enabled {
+            return (0, 0, EMULATOR_WIDTH, EMULATOR_HEIGHT);
+        }
+
+        (
+            self.x,
+            self.y,
+            EMULATOR_WIDTH / self.level,
+            EMULATOR_HEIGHT / self.level,
+        )
+    }
+
+    // Doubles the magnification, keeping the center of the region in place.
+    pub fn zoom_in(&mut self) {
+        if self.level < MAX_LEVEL {
+            let (x, y, w, h) = self.region();
+            self.level *= 2;
+            self.center_on(x + w / 2, y + h / 2);
+        }
+    }
+
+    // Halves the magnification, keeping the center of the region in place.
+    pub fn zoom_out(&mut self) {
+        if self.level > 2 {
+            let (x, y, w, h) = self.region();
+            self.level /= 2;
+            self.center_on(x + w / 2, y + h / 2);
+        }
+    }
+
+    // Moves the region by (dx, dy) emulator pixels, stopping at the edges.
+    pub fn pan(&mut self, dx: i8, dy: i8) {
+        let (_, _, w, h) = self.region();
+        self.x = (self.x as i16 + dx as i16).clamp(0, (EMULATOR_WIDTH - w) as i16) as u8;
+        self.y = (self.y as i16 + dy as i16).clamp(0, (EMULATOR_HEIGHT - h) as i16) as u8;
+    }
+
+    fn center_on(&mut self, x: u8, y: u8) {
+        let (_, _, w, h) = self.region();
+        self.x = x.saturating_sub(w / 2).min(EMULATOR_WIDTH - w);
+        self.y = y.saturating_sub(h / 2).min(EMULATOR_HEIGHT - h);
+    }
+}
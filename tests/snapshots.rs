@@ -0,0 +1,140 @@
+// Golden screens: each test runs a rom and compares the screen it leaves
+// with a PBM image checked in under tests/snapshots, which diffs read and
+// image viewers open. When the display changes on purpose, run
+//
+//   UPDATE_SNAPSHOTS=1 cargo test --test snapshots
+//
+// to write the goldens from what's drawn now, and look over the diff.
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use chip8::asm::assemble;
+use chip8::bundled;
+use chip8::cpu::Chip8;
+use chip8::dump::framebuffer_to_pbm;
+use chip8::quirks::Quirks;
+
+fn assert_snapshot(name: &str, emu: &Chip8) {
+    let path = PathBuf::from("tests/snapshots").join(format!("{}.pbm", name));
+    let drawn = framebuffer_to_pbm(&emu.pixels());
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::create_dir_all("tests/snapshots").unwrap();
+        fs::write(&path, drawn).unwrap();
+        return;
+    }
+    let golden = fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!("Unable to read {}: {}, UPDATE_SNAPSHOTS=1 writes it", path.display(), e)
+    });
+    assert!(
+        drawn == golden,
+        "{} doesn't match, it was\n{}\nbut now it's\n{}",
+        path.display(),
+        picture(&golden),
+        picture(&drawn)
+    );
+}
+
+// A PBM image as rows of # and ., easier to tell apart in a terminal.
+fn picture(pbm: &str) -> String {
+    let rows = pbm.lines().skip(2).map(|row| {
+        row.split(' ')
+            .map(|pixel| if pixel == "1" { '#' } else { '.' })
+            .collect::<String>()
+    });
+    rows.collect::<Vec<_>>().join("\n")
+}
+
+// Assembles `source` and runs it for `frames` frames.
+fn run(source: &str, quirks: Quirks, frames: u32) -> Chip8 {
+    let mut emu = Chip8::with_quirks(quirks);
+    emu.load_rom(&assemble(source).unwrap()).unwrap();
+    for _ in 0..frames {
+        emu.run_frame(10);
+    }
+    emu
+}
+
+// An 8x8 box drawn across the bottom right corner, half off each edge.
+const CORNER: &str = "
+        LD V0, 60
+        LD V1, 28
+        LD I, box
+        DRW V0, V1, 8
+        JP 0x208
+box:    DB 0xFF, 0x81, 0x81, 0x81, 0x81, 0x81, 0x81, 0xFF
+";
+
+#[test]
+fn sprites_wrap_around_the_edges() {
+    assert_snapshot("wrap", &run(CORNER, Quirks::default(), 1));
+}
+
+#[test]
+fn sprites_are_clipped_at_the_edges() {
+    let quirks = Quirks {
+        clipping: true,
+        ..Quirks::default()
+    };
+    assert_snapshot("clip", &run(CORNER, quirks, 1));
+}
+
+#[test]
+fn overlapping_sprites_cancel_out() {
+    // the font's 8 drawn over its 0, leaving the middle bar
+    let source = "
+        LD V0, 0
+        LD F, V0
+        DRW V0, V0, 5
+        LD V1, 8
+        LD F, V1
+        DRW V0, V0, 5
+        JP 0x20C
+    ";
+    assert_snapshot("xor", &run(source, Quirks::default(), 1));
+}
+
+#[test]
+fn the_font_has_every_digit() {
+    let source = "
+        LD V0, 0            ; which digit
+        LD V1, 1            ; x
+        LD V2, 1            ; y
+next:   LD F, V0
+        DRW V1, V2, 5
+        ADD V0, 1
+        ADD V1, 5
+        SE V0, 8
+        JP skip
+        LD V1, 1            ; the second half goes on a line of its own
+        LD V2, 7
+skip:   SE V0, 16
+        JP next
+done:   JP done
+    ";
+    assert_snapshot("font", &run(source, Quirks::default(), 20));
+}
+
+#[test]
+fn the_screen_clears() {
+    let source = "
+        LD V0, 0
+        LD F, V0
+        DRW V0, V0, 5
+        CLS
+        JP 0x208
+    ";
+    assert_snapshot("clear", &run(source, Quirks::default(), 1));
+}
+
+#[test]
+fn bundled_roms_draw_what_they_should() {
+    for (name, frames) in [("bounce", 100), ("selftest", 60)] {
+        let mut emu = Chip8::default();
+        emu.load_rom(bundled::find(&format!("bundled:{}", name)).unwrap().rom).unwrap();
+        for _ in 0..frames {
+            emu.run_frame(10);
+        }
+        assert_snapshot(name, &emu);
+    }
+}
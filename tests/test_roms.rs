@@ -0,0 +1,127 @@
+// Community test roms run headlessly for each quirk profile, with what they
+// leave on the screen checked against a hash of the screen they're known to
+// leave. When one doesn't match, the screen it left is printed to compare.
+//
+// These are only the test roms already in the tree: the c8_test rom in
+// programs/, which checks most instructions and shows OK, or the number of
+// the first check that failed. Timendus' chip8-test-suite would cover more,
+// but it isn't vendored, and won't be until its licence has been checked.
+// Below them, the instructions emulators most often get wrong are run on
+// their own.
+//
+// The profiles are the ones `chip8 compare` takes, so the two can't disagree
+// on what a platform's quirks are.
+use chip8::cpu::Chip8;
+use chip8::lockstep::Profile;
+
+// A test rom, and the screen it's known to leave under each profile.
+struct TestRom {
+    path: &'static str,
+    // how long it needs to finish, at 10 instructions a frame
+    frames: u32,
+    screens: &'static [(&'static str, &'static str)],
+}
+
+const TEST_ROMS: [TestRom; 1] = [TestRom {
+    path: "programs/c8_test.c8",
+    frames: 60,
+    screens: &[
+        // OK
+        ("default", "6d8ef3aac30d8f0e30b5f94f797ebc1b444ca206"),
+        // 14: it expects FX55 and FX65 to leave I alone and BNNN to add V0
+        ("originalChip8", "31fffa5b1c6eff52837caf14ac1ec729cf0f5645"),
+        ("superchip", "31fffa5b1c6eff52837caf14ac1ec729cf0f5645"),
+    ],
+}];
+
+fn screen_hash(emu: &Chip8) -> String {
+    sha1_smol::Sha1::from(emu.pixels().as_flattened()).digest().to_string()
+}
+
+fn screen(emu: &Chip8) -> String {
+    let rows = emu.pixels().into_iter().map(|row| {
+        row.iter()
+            .map(|&pixel| if pixel != 0 { '#' } else { '.' })
+            .collect::<String>()
+    });
+    rows.collect::<Vec<_>>().join("\n")
+}
+
+#[test]
+fn test_roms_leave_the_screens_they_should() {
+    for test in &TEST_ROMS {
+        let rom = std::fs::read(test.path).unwrap();
+        for (name, expected) in test.screens {
+            let profile: Profile = name.parse().unwrap();
+            let mut emu = Chip8::with_quirks(profile.quirks);
+            emu.load_rom(&rom).unwrap();
+            for _ in 0..test.frames {
+                emu.run_frame(10);
+            }
+            let hash = screen_hash(&emu);
+            assert_eq!(
+                &hash,
+                expected,
+                "{} with the {} quirks left\n{}",
+                test.path,
+                name,
+                screen(&emu)
+            );
+        }
+    }
+}
+
+// Runs `rom` an instruction at a time, `cycles` of them.
+fn run(rom: &[u8], keys: &[u8; 16], cycles: u32) -> Chip8 {
+    let mut emu = Chip8::default();
+    emu.load_rom(rom).unwrap();
+    emu.set_keys(keys);
+    for _ in 0..cycles {
+        emu.emulate_cycle();
+    }
+    emu
+}
+
+#[test]
+fn se_vx_vy_skips_when_the_registers_match() {
+    // LD V0, 5; LD V1, 5; SE V0, V1; LD VA, 1; LD V1, 6; SE V0, V1; LD VB, 1
+    let rom = [
+        0x60, 0x05, 0x61, 0x05, 0x50, 0x10, 0x6A, 0x01, 0x61, 0x06, 0x50, 0x10, 0x6B, 0x01,
+    ];
+    let emu = run(&rom, &[0; 16], 6);
+    assert_eq!(emu.registers()[0xA], 0);
+    assert_eq!(emu.registers()[0xB], 1);
+}
+
+// LD V0, 3; SKP V0; LD VA, 1; SKNP V0; LD VB, 1; LD V2, K
+const KEY_ROM: [u8; 12] = [
+    0x60, 0x03, 0xE0, 0x9E, 0x6A, 0x01, 0xE0, 0xA1, 0x6B, 0x01, 0xF2, 0x0A,
+];
+
+#[test]
+fn key_ops_see_a_held_key() {
+    let mut keys = [0; 16];
+    keys[3] = 255;
+    let emu = run(&KEY_ROM, &keys, 5);
+    assert_eq!(emu.registers()[0xA], 0);
+    assert_eq!(emu.registers()[0xB], 1);
+    assert_eq!(emu.registers()[2], 3);
+    assert!(!emu.waiting_for_key());
+    assert_eq!(emu.pc(), 0x20C);
+}
+
+#[test]
+fn key_ops_wait_for_a_key() {
+    let mut emu = run(&KEY_ROM, &[0; 16], 6);
+    assert_eq!(emu.registers()[0xA], 1);
+    assert_eq!(emu.registers()[0xB], 0);
+    assert!(emu.waiting_for_key());
+    assert_eq!(emu.pc(), 0x20A);
+
+    let mut keys = [0; 16];
+    keys[0xC] = 255;
+    emu.set_keys(&keys);
+    emu.emulate_cycle();
+    assert_eq!(emu.registers()[2], 0xC);
+    assert_eq!(emu.pc(), 0x20C);
+}
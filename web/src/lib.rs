@@ -0,0 +1,139 @@
+// The emulator in a browser: the display on a canvas, the keypad on the
+// keyboard and the buzzer through WebAudio. index.html runs the frames and
+// passes the keys on; see README.md for building it with wasm-pack.
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::{Clamped, JsCast};
+use web_sys::{
+    AudioContext, CanvasRenderingContext2d, GainNode, HtmlCanvasElement, ImageData, OscillatorType,
+};
+
+use chip8::bundled;
+use chip8::cpu::Chip8;
+use chip8::frame::FramePresenter;
+use chip8::metadata::ProgramDatabase;
+use chip8::roms::rom_hash;
+use chip8::keypad_key_for_code;
+
+const WIDTH: u32 = 64;
+// the default 600 instructions a second
+const CYCLES_PER_FRAME: u32 = 10;
+const BEEP_FREQUENCY: f32 = 440.0;
+const BEEP_VOLUME: f32 = 0.1;
+
+/// A chip8 drawing to a canvas.
+#[wasm_bindgen]
+pub struct Emulator {
+    emu: Chip8,
+    keys: [u8; 16],
+    context: CanvasRenderingContext2d,
+    presenter: FramePresenter,
+    // None until a key is pressed, as browsers only allow sound after that
+    beeper: Option<Beeper>,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    /// Loads `rom`, with the quirks the chip-8-database says it needs, to
+    /// draw onto `canvas`, which should be 64x32 and scaled up with CSS.
+    #[wasm_bindgen(constructor)]
+    pub fn new(canvas: HtmlCanvasElement, rom: &[u8]) -> Result<Emulator, JsValue> {
+        let context = canvas
+            .get_context("2d")?
+            .ok_or("the canvas has no 2d context")?
+            .dyn_into::<CanvasRenderingContext2d>()?;
+        let quirks = ProgramDatabase::embedded()
+            .get(&rom_hash(rom))
+            .and_then(|metadata| metadata.quirks)
+            .unwrap_or_default();
+        let mut emu = Chip8::with_quirks(quirks);
+        emu.load_rom(rom).map_err(|e| e.to_string())?;
+
+        let mut emulator = Emulator {
+            emu,
+            keys: [0; 16],
+            context,
+            presenter: FramePresenter::default(),
+            beeper: None,
+        };
+        emulator.draw()?;
+        Ok(emulator)
+    }
+
+    /// One of the roms built into the emulator, by the name it's played as
+    /// after bundled:, e.g. "bounce".
+    pub fn bundled(canvas: HtmlCanvasElement, name: &str) -> Result<Emulator, JsValue> {
+        let rom = bundled::ROMS
+            .iter()
+            .find(|rom| rom.name == name)
+            .ok_or_else(|| format!("no bundled rom {}", name))?;
+        Emulator::new(canvas, rom.rom)
+    }
+
+    /// Runs a 60th of a second, redrawing the canvas if the display changed.
+    pub fn frame(&mut self) -> Result<(), JsValue> {
+        if let Some(fault) = self.emu.fault() {
+            return Err(fault.to_string().into());
+        }
+        self.emu.set_keys(&self.keys);
+        self.emu.run_frame(CYCLES_PER_FRAME);
+        if let Some(beeper) = &self.beeper {
+            beeper.set_playing(self.emu.sound_active());
+        }
+        if self.emu.draw_flag() {
+            self.draw()?;
+        }
+        Ok(())
+    }
+
+    /// Presses or lets go of the keypad key for a KeyboardEvent.code,
+    /// returning whether there is one so other keys can be left to the page.
+    pub fn key(&mut self, code: &str, down: bool) -> bool {
+        if down && self.beeper.is_none() {
+            self.beeper = Beeper::new().ok();
+        }
+        match keypad_key_for_code(code) {
+            Some(key) => {
+                self.keys[usize::from(key)] = if down { 255 } else { 0 };
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Copies the display onto the canvas.
+    fn draw(&mut self) -> Result<(), JsValue> {
+        let pixels = self.presenter.present_planes(&self.emu.gfx);
+        let image = ImageData::new_with_u8_clamped_array(Clamped(pixels), WIDTH)?;
+        self.context.put_image_data(&image, 0.0, 0.0)
+    }
+}
+
+// The buzzer, a square wave that's turned up while the sound timer runs.
+struct Beeper {
+    context: AudioContext,
+    gain: GainNode,
+}
+
+impl Beeper {
+    fn new() -> Result<Beeper, JsValue> {
+        let context = AudioContext::new()?;
+        let oscillator = context.create_oscillator()?;
+        oscillator.set_type(OscillatorType::Square);
+        oscillator.frequency().set_value(BEEP_FREQUENCY);
+        let gain = context.create_gain()?;
+        gain.gain().set_value(0.0);
+        oscillator.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&context.destination())?;
+        oscillator.start()?;
+        Ok(Beeper { context, gain })
+    }
+
+    fn set_playing(&self, playing: bool) {
+        let volume = if playing { BEEP_VOLUME } else { 0.0 };
+        // ramped a little so it doesn't click
+        let _ = self
+            .gain
+            .gain()
+            .set_target_at_time(volume, self.context.current_time(), 0.005);
+    }
+}